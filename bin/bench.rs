@@ -0,0 +1,508 @@
+//! Sends the same GET over and over and reports latency percentiles and
+//! throughput, to compare the backends:
+//!
+//! ```text
+//! cargo run --release --bin bench --features blocking,tokio,poll,mio,epoll,io-uring-with-dep -- \
+//!     --backend poll --concurrency 4 --requests 10000
+//! ```
+//!
+//! Without a URL the requests go to a server started in-process on a
+//! loopback port, which answers every request with the canned `RESPONSE`
+//! from a thread per connection. A new connection is opened for every
+//! request.
+//!
+//! `--backend fsm` does no I/O at all: `RESPONSE` is fed to a plain FSM
+//! that is reused for every request, which measures parsing and the FSM
+//! alone (the URL only sets the request's path and `Host`).
+use anyhow::{Result, bail};
+use https_sans_io::ClientOptions;
+use std::{
+    io::{Read, Write},
+    net::{TcpListener, TcpStream},
+    time::{Duration, Instant},
+};
+
+/// What the in-process server answers with, 12 headers and a short body.
+const RESPONSE: &[u8] = b"HTTP/1.1 200 OK\r\n\
+    Date: Thu, 01 Jan 2026 00:00:00 GMT\r\n\
+    Server: bench\r\n\
+    Content-Type: text/plain; charset=utf-8\r\n\
+    Cache-Control: no-cache\r\n\
+    ETag: \"abc123\"\r\n\
+    Last-Modified: Thu, 01 Jan 2026 00:00:00 GMT\r\n\
+    Vary: Accept-Encoding\r\n\
+    X-Request-Id: 0123456789abcdef\r\n\
+    X-Frame-Options: DENY\r\n\
+    Strict-Transport-Security: max-age=31536000\r\n\
+    Connection: keep-alive\r\n\
+    Content-Length: 5\r\n\
+    \r\n\
+    hello";
+
+struct Args {
+    url: String,
+    backend: String,
+    concurrency: usize,
+    requests: usize,
+}
+
+fn parse_args() -> Result<Args> {
+    let mut args = Args {
+        url: String::new(),
+        backend: "blocking".to_string(),
+        concurrency: 1,
+        requests: 1000,
+    };
+
+    let mut argv = std::env::args().skip(1);
+    while let Some(arg) = argv.next() {
+        let mut value = || match argv.next() {
+            Some(value) => Ok(value),
+            None => bail!("{arg} needs a value"),
+        };
+        match arg.as_str() {
+            "--backend" => args.backend = value()?,
+            "--concurrency" => args.concurrency = value()?.parse()?,
+            "--requests" => args.requests = value()?.parse()?,
+            "--help" => bail!(
+                "usage: bench [url] [--backend blocking|tokio|poll|mio|epoll|io-uring|fsm] \
+                 [--concurrency N] [--requests N]"
+            ),
+            _ if args.url.is_empty() => args.url = arg,
+            _ => bail!("unexpected argument {arg}"),
+        }
+    }
+
+    if args.url.is_empty() {
+        args.url = serve()?;
+    }
+    args.concurrency = args.concurrency.clamp(1, args.requests.max(1));
+    Ok(args)
+}
+
+fn main() -> Result<()> {
+    let args = parse_args()?;
+    let options = ClientOptions::default();
+
+    let started = Instant::now();
+    let latencies = match args.backend.as_str() {
+        "blocking" => blocking(&args, &options)?,
+        "fsm" => fsm(&args, &options)?,
+        #[cfg(feature = "tokio")]
+        "tokio" => tokio(&args, &options)?,
+        #[cfg(feature = "poll")]
+        "poll" => poll(&args, &options)?,
+        #[cfg(feature = "mio")]
+        "mio" => mio(&args, &options)?,
+        #[cfg(feature = "epoll")]
+        "epoll" => epoll(&args, &options)?,
+        #[cfg(feature = "io-uring-with-dep")]
+        "io-uring" => io_uring(&args, &options)?,
+        other => bail!("backend {other} is unknown or its feature is off"),
+    };
+    report(&args, latencies, started.elapsed());
+    Ok(())
+}
+
+/// Starts the local server, returns its URL.
+fn serve() -> Result<String> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let url = format!("http://{}/", listener.local_addr()?);
+    std::thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            std::thread::spawn(move || answer(stream));
+        }
+    });
+    Ok(url)
+}
+
+/// Answers every request on `stream` until the client closes it, requests
+/// are bodiless GETs so the end of the head is the end of the request.
+fn answer(mut stream: TcpStream) -> std::io::Result<()> {
+    stream.set_nodelay(true)?;
+    let mut buf = vec![];
+    let mut chunk = [0; 4096];
+    loop {
+        while let Some(end) = buf.windows(4).position(|window| window == b"\r\n\r\n") {
+            buf.drain(..end + 4);
+            stream.write_all(RESPONSE)?;
+        }
+        match stream.read(&mut chunk)? {
+            0 => return Ok(()),
+            read => buf.extend_from_slice(&chunk[..read]),
+        }
+    }
+}
+
+/// One thread per concurrent request.
+fn blocking(args: &Args, options: &ClientOptions) -> Result<Vec<Duration>> {
+    use https_sans_io::BlockingConnection;
+
+    let per_worker = split(args.requests, args.concurrency);
+    std::thread::scope(|scope| {
+        let workers = per_worker
+            .into_iter()
+            .map(|count| {
+                scope.spawn(move || -> Result<Vec<Duration>> {
+                    let mut latencies = Vec::with_capacity(count);
+                    for _ in 0..count {
+                        let started = Instant::now();
+                        BlockingConnection::get_url(&args.url, options)?.error_for_status()?;
+                        latencies.push(started.elapsed());
+                    }
+                    Ok(latencies)
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut latencies = vec![];
+        for worker in workers {
+            match worker.join() {
+                Ok(worker) => latencies.extend(worker?),
+                Err(_) => bail!("a worker thread panicked"),
+            }
+        }
+        Ok(latencies)
+    })
+}
+
+/// One task per concurrent request on a single-threaded runtime.
+#[cfg(feature = "tokio")]
+fn tokio(args: &Args, options: &ClientOptions) -> Result<Vec<Duration>> {
+    use https_sans_io::AsyncConnection;
+
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    runtime.block_on(async {
+        let workers = split(args.requests, args.concurrency)
+            .into_iter()
+            .map(|count| {
+                let url = args.url.clone();
+                let options = options.clone();
+                tokio::spawn(async move {
+                    let mut latencies = Vec::with_capacity(count);
+                    for _ in 0..count {
+                        let started = Instant::now();
+                        AsyncConnection::get_url(&url, &options)
+                            .await?
+                            .error_for_status()?;
+                        latencies.push(started.elapsed());
+                    }
+                    Ok::<_, https_sans_io::Error>(latencies)
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut latencies = vec![];
+        for worker in workers {
+            latencies.extend(worker.await??);
+        }
+        Ok(latencies)
+    })
+}
+
+/// All concurrent requests multiplexed over one `poll(2)` loop.
+#[cfg(feature = "poll")]
+fn poll(args: &Args, options: &ClientOptions) -> Result<Vec<Duration>> {
+    use https_sans_io::{EventsOrResponse, PollConnection};
+    use libc::{POLLERR, POLLHUP, POLLIN, POLLOUT, pollfd};
+    use std::os::fd::AsRawFd;
+
+    let mut latencies = Vec::with_capacity(args.requests);
+    let mut remaining = args.requests;
+    let mut in_flight: Vec<(PollConnection, Instant)> = vec![];
+
+    while remaining > 0 || !in_flight.is_empty() {
+        while remaining > 0 && in_flight.len() < args.concurrency {
+            in_flight.push((PollConnection::get_url(&args.url, options)?, Instant::now()));
+            remaining -= 1;
+        }
+
+        let mut fds = Vec::with_capacity(in_flight.len());
+        let mut idx = 0;
+        while idx < in_flight.len() {
+            let (conn, started) = &mut in_flight[idx];
+            match conn.events()? {
+                EventsOrResponse::Events(events) => {
+                    fds.push(pollfd {
+                        fd: conn.as_raw_fd(),
+                        events,
+                        revents: 0,
+                    });
+                    idx += 1;
+                }
+                EventsOrResponse::Response(response) => {
+                    response.error_for_status()?;
+                    latencies.push(started.elapsed());
+                    in_flight.swap_remove(idx);
+                }
+            }
+        }
+        if fds.is_empty() {
+            continue;
+        }
+
+        let res = unsafe { libc::poll(fds.as_mut_ptr(), fds.len() as libc::nfds_t, 1000) };
+        if res < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+
+        let mut idx = 0;
+        while idx < in_flight.len() {
+            let revents = fds[idx].revents;
+            let (conn, started) = &mut in_flight[idx];
+            let failed = revents & (POLLERR | POLLHUP) != 0;
+            let readable = failed || revents & POLLIN != 0;
+            let writable = failed || revents & POLLOUT != 0;
+            if let Some(response) = conn.poll(readable, writable)? {
+                response.error_for_status()?;
+                latencies.push(started.elapsed());
+                in_flight.swap_remove(idx);
+                fds.swap_remove(idx);
+                continue;
+            }
+            idx += 1;
+        }
+    }
+
+    Ok(latencies)
+}
+
+/// All concurrent requests multiplexed over one `mio::Poll`, the token of
+/// a connection is its slot in `in_flight`.
+#[cfg(feature = "mio")]
+fn mio(args: &Args, options: &ClientOptions) -> Result<Vec<Duration>> {
+    use https_sans_io::MioConnection;
+    use mio::{Events, Poll, Token};
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(args.concurrency);
+    let mut latencies = Vec::with_capacity(args.requests);
+    let mut remaining = args.requests;
+    let mut in_flight: Vec<Option<(MioConnection, Instant)>> = vec![];
+
+    while remaining > 0 && in_flight.len() < args.concurrency {
+        let mut conn = MioConnection::get_url(&args.url, options)?;
+        conn.register(poll.registry(), Token(in_flight.len()))?;
+        in_flight.push(Some((conn, Instant::now())));
+        remaining -= 1;
+    }
+
+    while in_flight.iter().any(Option::is_some) {
+        poll.poll(&mut events, Some(Duration::from_secs(1)))?;
+        if events.is_empty() {
+            // timed out, fails if a deadline has passed
+            for (conn, _) in in_flight.iter().flatten() {
+                conn.check_timeouts()?;
+            }
+        }
+        for event in events.iter() {
+            let slot = &mut in_flight[event.token().0];
+            let Some((conn, started)) = slot else {
+                continue;
+            };
+            let Some(response) = conn.handle_event(poll.registry(), event)? else {
+                continue;
+            };
+            response.error_for_status()?;
+            latencies.push(started.elapsed());
+            conn.deregister(poll.registry())?;
+            *slot = None;
+
+            if remaining > 0 {
+                let mut conn = MioConnection::get_url(&args.url, options)?;
+                conn.register(poll.registry(), event.token())?;
+                *slot = Some((conn, Instant::now()));
+                remaining -= 1;
+            }
+        }
+    }
+
+    Ok(latencies)
+}
+
+/// All concurrent requests multiplexed over one epoll instance, the token
+/// of a connection is its slot in `in_flight`.
+#[cfg(feature = "epoll")]
+fn epoll(args: &Args, options: &ClientOptions) -> Result<Vec<Duration>> {
+    use https_sans_io::EpollConnection;
+    use libc::epoll_event;
+    use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+    let epfd = unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) };
+    if epfd < 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    // closes it on every return
+    let owned = unsafe { OwnedFd::from_raw_fd(epfd) };
+    let epfd = owned.as_raw_fd();
+
+    let mut events = vec![epoll_event { events: 0, u64: 0 }; args.concurrency];
+    let mut latencies = Vec::with_capacity(args.requests);
+    let mut remaining = args.requests;
+    let mut in_flight: Vec<Option<(EpollConnection, Instant)>> = vec![];
+
+    while remaining > 0 && in_flight.len() < args.concurrency {
+        let conn = EpollConnection::get_url(&args.url, options)?;
+        conn.register(epfd, in_flight.len() as u64)?;
+        in_flight.push(Some((conn, Instant::now())));
+        remaining -= 1;
+    }
+
+    while in_flight.iter().any(Option::is_some) {
+        let count =
+            unsafe { libc::epoll_wait(epfd, events.as_mut_ptr(), events.len() as i32, 1000) };
+        if count < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        if count == 0 {
+            // timed out, fails if a deadline has passed
+            for (conn, _) in in_flight.iter().flatten() {
+                conn.check_timeouts()?;
+            }
+        }
+        for event in &events[..count as usize] {
+            let token = event.u64;
+            let slot = &mut in_flight[token as usize];
+            let Some((conn, started)) = slot else {
+                continue;
+            };
+            let Some(response) = conn.handle(event.events)? else {
+                continue;
+            };
+            response.error_for_status()?;
+            latencies.push(started.elapsed());
+            conn.deregister(epfd)?;
+            *slot = None;
+
+            if remaining > 0 {
+                let conn = EpollConnection::get_url(&args.url, options)?;
+                conn.register(epfd, token)?;
+                *slot = Some((conn, Instant::now()));
+                remaining -= 1;
+            }
+        }
+    }
+
+    Ok(latencies)
+}
+
+/// One thread per concurrent request, each sending through a `Client`
+/// with the io_uring backend and so on a ring of its own.
+#[cfg(feature = "io-uring-with-dep")]
+fn io_uring(args: &Args, options: &ClientOptions) -> Result<Vec<Duration>> {
+    use https_sans_io::{Backend, Client};
+
+    if !Backend::IoUring.is_available() {
+        bail!("io_uring is not available on this kernel");
+    }
+
+    let per_worker = split(args.requests, args.concurrency);
+    std::thread::scope(|scope| {
+        let workers = per_worker
+            .into_iter()
+            .map(|count| {
+                scope.spawn(move || -> Result<Vec<Duration>> {
+                    let mut client = Client::new(options);
+                    client.set_backend(Backend::IoUring);
+                    let mut latencies = Vec::with_capacity(count);
+                    for _ in 0..count {
+                        let started = Instant::now();
+                        client.get(&args.url)?.error_for_status()?;
+                        latencies.push(started.elapsed());
+                    }
+                    Ok(latencies)
+                })
+            })
+            .collect::<Vec<_>>();
+
+        let mut latencies = vec![];
+        for worker in workers {
+            match worker.join() {
+                Ok(worker) => latencies.extend(worker?),
+                Err(_) => bail!("a worker thread panicked"),
+            }
+        }
+        Ok(latencies)
+    })
+}
+
+/// No I/O, every exchange goes over the same keep-alive FSM.
+fn fsm(args: &Args, options: &ClientOptions) -> Result<Vec<Duration>> {
+    use https_sans_io::{FSM, Request, Url, Wants};
+
+    let url = Url::parse(&args.url)?;
+    let mut request = Request::get(&url.path);
+    request.add_header("Host", url.authority());
+    let options = ClientOptions {
+        keep_alive: true,
+        ..options.clone()
+    };
+    let mut fsm = FSM::plain(request.clone(), &options)?;
+
+    let mut latencies = Vec::with_capacity(args.requests);
+    for idx in 0..args.requests {
+        let started = Instant::now();
+        if idx > 0 {
+            fsm.reuse(request.clone())?;
+        }
+        let mut unread = RESPONSE;
+        loop {
+            match fsm.wants()? {
+                Wants::Write(bytes) => {
+                    let len = bytes.len();
+                    fsm.done_writing(len);
+                }
+                Wants::Read(buf) => {
+                    let len = buf.len().min(unread.len());
+                    buf[..len].copy_from_slice(&unread[..len]);
+                    unread = &unread[len..];
+                    fsm.done_reading(len);
+                }
+                Wants::Done(response) => {
+                    response.error_for_status()?;
+                    break;
+                }
+                Wants::Body(_) | Wants::SinkFull => bail!("no body to stream or sink"),
+            }
+        }
+        latencies.push(started.elapsed());
+    }
+    Ok(latencies)
+}
+
+/// Spreads `total` requests over `workers` as evenly as possible.
+fn split(total: usize, workers: usize) -> Vec<usize> {
+    (0..workers)
+        .map(|worker| total / workers + usize::from(worker < total % workers))
+        .collect()
+}
+
+fn report(args: &Args, mut latencies: Vec<Duration>, elapsed: Duration) {
+    latencies.sort();
+    let percentile = |p: f64| {
+        let idx = ((latencies.len() as f64 * p).ceil() as usize).saturating_sub(1);
+        latencies.get(idx).copied().unwrap_or_default()
+    };
+
+    println!(
+        "{} requests to {} over {} with concurrency {}",
+        latencies.len(),
+        args.url,
+        args.backend,
+        args.concurrency
+    );
+    println!(
+        "latency p50 {:?}, p90 {:?}, p99 {:?}, max {:?}",
+        percentile(0.5),
+        percentile(0.9),
+        percentile(0.99),
+        latencies.last().copied().unwrap_or_default()
+    );
+    println!(
+        "throughput {:.1} req/s in {:?}",
+        latencies.len() as f64 / elapsed.as_secs_f64(),
+        elapsed
+    );
+}
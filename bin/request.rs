@@ -1,23 +1,44 @@
 use anyhow::Result;
 
-#[cfg(feature = "blocking")]
+// every bin is built from this file, several at once when their features
+// are enabled together
 fn main() -> Result<()> {
+    match env!("CARGO_BIN_NAME") {
+        #[cfg(feature = "blocking")]
+        "blocking" => blocking(),
+        #[cfg(feature = "poll")]
+        "poll" => poll(),
+        #[cfg(feature = "tokio")]
+        "tokio" => tokio(),
+        #[cfg(feature = "mio")]
+        "mio" => mio(),
+        #[cfg(feature = "epoll")]
+        "epoll" => epoll(),
+        #[cfg(feature = "io-uring-with-dep")]
+        "io-uring" => io_uring(),
+        name => unreachable!("{name} is built without its feature"),
+    }
+}
+
+#[cfg(feature = "blocking")]
+fn blocking() -> Result<()> {
     println!("Blocking version");
 
-    use https_sans_io::BlockingConnection;
-    let response = BlockingConnection::get("myip.ibylich.dev", 443, "/")?;
-    println!("Response: {response:?}");
+    use https_sans_io::{BlockingConnection, ClientOptions};
+    let response =
+        BlockingConnection::get("myip.ibylich.dev", 443, "/", &ClientOptions::default())?;
+    println!("{response}");
     Ok(())
 }
 
 #[cfg(feature = "poll")]
-fn main() -> Result<()> {
+fn poll() -> Result<()> {
     println!("Poll version");
 
-    use https_sans_io::{EventsOrResponse, PollConnection};
-    let mut conn = PollConnection::get("myip.ibylich.dev", 443, "/")?;
+    use https_sans_io::{ClientOptions, EventsOrResponse, PollConnection};
+    let mut conn = PollConnection::get("myip.ibylich.dev", 443, "/", &ClientOptions::default())?;
 
-    use libc::{POLLERR, POLLIN, POLLOUT, poll, pollfd};
+    use libc::{POLLERR, POLLHUP, POLLIN, POLLOUT, poll, pollfd};
     use std::os::fd::AsRawFd;
 
     let mut fds = [pollfd {
@@ -26,55 +47,148 @@ fn main() -> Result<()> {
         revents: 0,
     }];
 
-    fn do_poll(fds: &mut [pollfd; 1]) -> (bool, bool) {
-        let res = unsafe { poll(fds.as_mut_ptr(), 1, -1) };
+    fn do_poll(fds: &mut [pollfd; 1], timeout: i32) -> (bool, bool) {
+        let res = unsafe { poll(fds.as_mut_ptr(), 1, timeout) };
+        if res == 0 {
+            return (false, false);
+        }
         assert!(res == 1);
-        let readable = fds[0].revents & POLLIN != 0;
-        let writable = fds[0].revents & POLLOUT != 0;
-        assert_eq!(fds[0].revents & POLLERR, 0);
+        // errors surface from the next read or write (or connect check)
+        let failed = fds[0].revents & (POLLERR | POLLHUP) != 0;
+        let readable = failed || fds[0].revents & POLLIN != 0;
+        let writable = failed || fds[0].revents & POLLOUT != 0;
         (readable, writable)
     }
 
     let response = loop {
         match conn.events()? {
             EventsOrResponse::Events(events) => {
+                // a new socket if connecting fell back to another address
+                fds[0].fd = conn.as_raw_fd();
                 fds[0].events = events;
             }
             EventsOrResponse::Response(response) => break response,
         }
-        let (readable, writable) = do_poll(&mut fds);
-
+        let timeout = conn
+            .timeout()
+            .map_or(-1, |timeout| timeout.as_millis() as i32);
+        // on a timeout both are false and `poll` reports the passed deadline
+        let (readable, writable) = do_poll(&mut fds, timeout);
         if let Some(response) = conn.poll(readable, writable)? {
             break response;
         };
     };
 
-    println!("Response: {response:?}");
+    println!("{response}");
+    Ok(())
+}
+
+#[cfg(feature = "tokio")]
+fn tokio() -> Result<()> {
+    println!("Tokio version");
+
+    use https_sans_io::{AsyncConnection, ClientOptions};
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    let response = runtime.block_on(AsyncConnection::get(
+        "myip.ibylich.dev",
+        443,
+        "/",
+        &ClientOptions::default(),
+    ))?;
+    println!("{response}");
+    Ok(())
+}
+
+#[cfg(feature = "mio")]
+fn mio() -> Result<()> {
+    println!("mio version");
+
+    use https_sans_io::{ClientOptions, MioConnection};
+    use mio::{Events, Poll, Token};
+
+    let mut poll = Poll::new()?;
+    let mut events = Events::with_capacity(8);
+    let mut conn = MioConnection::get("myip.ibylich.dev", 443, "/", &ClientOptions::default())?;
+    conn.register(poll.registry(), Token(0))?;
+
+    let response = 'outer: loop {
+        poll.poll(&mut events, conn.timeout())?;
+        if events.is_empty() {
+            // timed out, fails if a deadline has passed
+            conn.check_timeouts()?;
+        }
+        for event in events.iter() {
+            if let Some(response) = conn.handle_event(poll.registry(), event)? {
+                break 'outer response;
+            }
+        }
+    };
+    conn.deregister(poll.registry())?;
+
+    println!("{response}");
+    Ok(())
+}
+
+#[cfg(feature = "epoll")]
+fn epoll() -> Result<()> {
+    println!("epoll version");
+
+    use https_sans_io::{ClientOptions, EpollConnection};
+    use libc::epoll_event;
+
+    let epfd = unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) };
+    if epfd < 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    let mut conn = EpollConnection::get("myip.ibylich.dev", 443, "/", &ClientOptions::default())?;
+    conn.register(epfd, 0)?;
+
+    let mut events = [epoll_event { events: 0, u64: 0 }; 8];
+    let response = 'outer: loop {
+        let timeout = conn.timeout().map_or(-1, |timeout| {
+            timeout.as_millis().min(i32::MAX as u128) as i32
+        });
+        let count = unsafe { libc::epoll_wait(epfd, events.as_mut_ptr(), 8, timeout) };
+        if count < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        if count == 0 {
+            // timed out, fails if a deadline has passed
+            conn.check_timeouts()?;
+        }
+        for event in &events[..count as usize] {
+            if let Some(response) = conn.handle(event.events)? {
+                break 'outer response;
+            }
+        }
+    };
+    conn.deregister(epfd)?;
+    unsafe { libc::close(epfd) };
+
+    println!("{response}");
     Ok(())
 }
 
 #[cfg(feature = "io-uring-with-dep")]
-fn main() -> Result<()> {
+fn io_uring() -> Result<()> {
     println!("io_uring version");
 
-    use https_sans_io::{Cqe, IoUringConnection, Sqe};
-    use io_uring::{IoUring, opcode, types};
+    use https_sans_io::{ClientOptions, Cqe, Fd, IoUringCapabilities, IoUringConnection, Sqe};
+    use io_uring::{IoUring, opcode, squeue, types};
 
     let mut ring = IoUring::new(10)?;
 
-    const SOCKET_USER_DATA: u64 = 1;
-    const CONNECT_USER_DATA: u64 = 2;
-    const READ_USER_DATA: u64 = 3;
-    const WRITE_USER_DATA: u64 = 4;
-    let mut conn = IoUringConnection::get(
-        "myip.ibylich.dev",
-        443,
-        "/",
-        SOCKET_USER_DATA,
-        CONNECT_USER_DATA,
-        READ_USER_DATA,
-        WRITE_USER_DATA,
+    let mut conn = IoUringConnection::get_url_with_id(
+        "https://myip.ibylich.dev/",
+        0,
+        &ClientOptions::default(),
     )?;
+    // e.g. sockets come from socket(2) before Linux 5.19
+    if let Some(capabilities) = IoUringCapabilities::detect() {
+        conn.set_capabilities(capabilities)?;
+    }
 
     fn map_sqe(sqe: Sqe) -> io_uring::squeue::Entry {
         match sqe {
@@ -86,30 +200,85 @@ fn main() -> Result<()> {
             } => opcode::Socket::new(domain, socket_type, protocol)
                 .build()
                 .user_data(user_data),
+            Sqe::SocketDirect {
+                domain,
+                socket_type,
+                protocol,
+                file_index,
+                user_data,
+            } => opcode::Socket::new(domain, socket_type, protocol)
+                .file_index(Some(match file_index {
+                    Some(idx) => types::DestinationSlot::try_from_slot_target(idx).unwrap(),
+                    None => types::DestinationSlot::auto_target(),
+                }))
+                .build()
+                .user_data(user_data),
+            Sqe::Close { fd, user_data } => match fd {
+                Fd::Raw(fd) => opcode::Close::new(types::Fd(fd)).build(),
+                Fd::Fixed(idx) => opcode::Close::new(types::Fixed(idx)).build(),
+            }
+            .user_data(user_data),
             Sqe::Connect {
                 fd,
                 addr,
                 addrlen,
                 user_data,
-            } => opcode::Connect::new(types::Fd(fd), addr, addrlen)
-                .build()
-                .user_data(user_data),
+                ..
+            } => match fd {
+                Fd::Raw(fd) => opcode::Connect::new(types::Fd(fd), addr, addrlen).build(),
+                Fd::Fixed(idx) => opcode::Connect::new(types::Fixed(idx), addr, addrlen).build(),
+            }
+            .user_data(user_data),
             Sqe::Write {
                 fd,
                 buf,
                 len,
                 user_data,
-            } => opcode::Write::new(types::Fd(fd), buf, len)
-                .build()
-                .user_data(user_data),
+                ..
+            } => match fd {
+                Fd::Raw(fd) => opcode::Write::new(types::Fd(fd), buf, len).build(),
+                Fd::Fixed(idx) => opcode::Write::new(types::Fixed(idx), buf, len).build(),
+            }
+            .user_data(user_data),
             Sqe::Read {
                 fd,
                 buf,
                 len,
                 user_data,
-            } => opcode::Read::new(types::Fd(fd), buf, len)
-                .build()
-                .user_data(user_data),
+                ..
+            } => match fd {
+                Fd::Raw(fd) => opcode::Read::new(types::Fd(fd), buf, len).build(),
+                Fd::Fixed(idx) => opcode::Read::new(types::Fixed(idx), buf, len).build(),
+            }
+            .user_data(user_data),
+            Sqe::WriteFixed {
+                fd,
+                buf,
+                len,
+                buf_index,
+                user_data,
+                ..
+            } => match fd {
+                Fd::Raw(fd) => opcode::WriteFixed::new(types::Fd(fd), buf, len, buf_index).build(),
+                Fd::Fixed(idx) => {
+                    opcode::WriteFixed::new(types::Fixed(idx), buf, len, buf_index).build()
+                }
+            }
+            .user_data(user_data),
+            Sqe::ReadFixed {
+                fd,
+                buf,
+                len,
+                buf_index,
+                user_data,
+                ..
+            } => match fd {
+                Fd::Raw(fd) => opcode::ReadFixed::new(types::Fd(fd), buf, len, buf_index).build(),
+                Fd::Fixed(idx) => {
+                    opcode::ReadFixed::new(types::Fixed(idx), buf, len, buf_index).build()
+                }
+            }
+            .user_data(user_data),
         }
     }
 
@@ -122,12 +291,28 @@ fn main() -> Result<()> {
 
     let response = loop {
         let (sqe, response) = conn.next_sqe()?;
+        let timespec;
+        if let Some(sqe) = sqe {
+            match sqe.link_timeout() {
+                Some(timeout) => {
+                    timespec = types::Timespec::from(timeout.duration);
+                    let entries = [
+                        map_sqe(sqe).flags(squeue::Flags::IO_LINK),
+                        opcode::LinkTimeout::new(&timespec)
+                            .build()
+                            .user_data(timeout.user_data),
+                    ];
+                    unsafe { ring.submission().push_multiple(&entries)? };
+                }
+                None => unsafe { ring.submission().push(&map_sqe(sqe))? },
+            }
+        }
+
         if let Some(response) = response {
+            // the socket's close comes along with the response
+            ring.submit()?;
             break response;
         }
-        if let Some(sqe) = sqe {
-            unsafe { ring.submission().push(&map_sqe(sqe))? };
-        }
 
         ring.submit_and_wait(1)?;
 
@@ -136,6 +321,6 @@ fn main() -> Result<()> {
         }
     };
 
-    println!("Response: {response:?}");
+    println!("{response}");
     Ok(())
 }
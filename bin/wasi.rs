@@ -0,0 +1,51 @@
+//! A driver written against the bare FSM, the way one would be for a
+//! platform the crate has no driver for. On `wasm32-wasip2` the standard
+//! library's `TcpStream` is backed by WASI sockets:
+//!
+//! ```text
+//! cargo build --bin wasi --target wasm32-wasip2
+//! wasmtime run -S inherit-network -S allow-ip-name-lookup \
+//!     target/wasm32-wasip2/debug/wasi.wasm
+//! ```
+
+use anyhow::Result;
+use https_sans_io::{ClientOptions, FSM, Request, Wants};
+use rustls::pki_types::ServerName;
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+};
+
+fn main() -> Result<()> {
+    println!("Bare FSM version");
+
+    let hostname = "myip.ibylich.dev";
+    let mut request = Request::get("/");
+    request.add_header("Host", hostname);
+
+    let mut fsm = FSM::new(
+        ServerName::try_from(hostname)?,
+        request,
+        &ClientOptions::default(),
+    )?;
+    let mut sock = TcpStream::connect((hostname, 443))?;
+
+    let response = loop {
+        match fsm.wants()? {
+            Wants::Read(buf) => {
+                let read = sock.read(buf)?;
+                fsm.done_reading(read);
+            }
+            Wants::Write(buf) => {
+                let written = sock.write(buf)?;
+                fsm.done_writing(written);
+            }
+            Wants::Body(_) => unreachable!("the request has no streamed body"),
+            Wants::SinkFull => unreachable!("no body sink is set"),
+            Wants::Done(response) => break response,
+        }
+    };
+
+    println!("{response}");
+    Ok(())
+}
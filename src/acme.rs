@@ -0,0 +1,41 @@
+//! Request builders for ACME (RFC 8555) clients. Building and signing the
+//! JWS bodies is up to the caller.
+
+use crate::{Method, Request, Response};
+
+/// ALPN protocol of the TLS-ALPN-01 challenge (RFC 8737),
+/// for `ClientOptions::alpn_protocols`.
+pub const ACME_TLS_ALPN: &[u8] = b"acme-tls/1";
+
+/// `HEAD` of the `newNonce` URL, the nonce comes back in `Replay-Nonce`.
+pub fn new_nonce(path: impl Into<String>) -> Request {
+    Request::head(path)
+}
+
+/// `POST` of a flattened JWS, `POST-as-GET` requests pass an empty payload
+/// inside it.
+pub fn post(path: impl Into<String>, jws: impl Into<Vec<u8>>) -> Request {
+    let mut request = Request::new(Method::Post, path);
+    request.add_header("Content-Type", "application/jose+json");
+    request.set_body(jws);
+    request
+}
+
+/// Every response carries a fresh nonce for the next request.
+pub fn replay_nonce(response: &Response) -> Option<&str> {
+    response.headers.get("Replay-Nonce")
+}
+
+/// The nonce was rejected; the request can be re-signed with the nonce of
+/// this response and sent again.
+pub fn is_bad_nonce(response: &Response) -> bool {
+    response.status == 400
+        && response
+            .text()
+            .is_ok_and(|body| body.contains("urn:ietf:params:acme:error:badNonce"))
+}
+
+/// Where the HTTP-01 challenge expects the key authorization for `token`.
+pub fn http01_path(token: &str) -> String {
+    format!("/.well-known/acme-challenge/{token}")
+}
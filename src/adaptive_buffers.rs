@@ -0,0 +1,91 @@
+use crate::{BufferConfig, Response, Scheme};
+use std::collections::HashMap;
+
+/// No host gets initial buffers, or retained parsing space, bigger than
+/// this however big its responses were.
+const CEILING: usize = 1024 * 1024;
+/// The default, about one full TLS record: less than that only means
+/// growing the incoming buffer right away.
+const MIN_INCOMING: usize = 16 * 1024;
+const MIN_OUTGOING: usize = 1024;
+const MIN_SCRATCH: usize = 4 * 1024;
+const ROUND_TO: usize = 1024;
+
+/// Remembers how big each host's exchanges got and sizes the buffers of new
+/// connections there to match: small for APIs answering a few hundred bytes,
+/// large for downloads, so that they neither sit on memory they never use
+/// nor grow their buffers step by step on every connection. `record` every
+/// response, `config` for every new connection; hosts that were never
+/// recorded get the base config as is.
+///
+/// Sizes are decaying maxima, a host that stops serving big responses
+/// gets smaller buffers again after a few exchanges.
+#[derive(Debug, Clone, Default)]
+pub struct AdaptiveBuffers {
+    hosts: HashMap<(Scheme, String, u16), Observed>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Observed {
+    incoming: usize,
+    outgoing: usize,
+    response: usize,
+}
+
+impl AdaptiveBuffers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, scheme: Scheme, hostname: &str, port: u16, response: &Response) {
+        let observed = self
+            .hosts
+            .entry((scheme, hostname.to_ascii_lowercase(), port))
+            .or_default();
+        let stats = &response.stats;
+        observed.incoming = decay(observed.incoming, stats.incoming_buffer);
+        observed.outgoing = decay(observed.outgoing, stats.outgoing_buffer);
+        observed.response = decay(observed.response, response.body.len());
+    }
+
+    /// `base` with its initial sizes and retained parsing space replaced by
+    /// what the host needed so far, within `base`'s maxima.
+    pub fn config(
+        &self,
+        scheme: Scheme,
+        hostname: &str,
+        port: u16,
+        base: &BufferConfig,
+    ) -> BufferConfig {
+        let Some(observed) = self
+            .hosts
+            .get(&(scheme, hostname.to_ascii_lowercase(), port))
+        else {
+            return *base;
+        };
+
+        BufferConfig {
+            initial_incoming: size(observed.incoming, MIN_INCOMING, base.max_incoming),
+            initial_outgoing: size(observed.outgoing, MIN_OUTGOING, base.max_outgoing),
+            initial_scratch: size(observed.response, MIN_SCRATCH, None),
+            retain_scratch: size(observed.response, MIN_SCRATCH, None),
+            ..*base
+        }
+    }
+
+    /// Forgets every host.
+    pub fn clear(&mut self) {
+        self.hosts.clear();
+    }
+}
+
+/// Jumps up to a bigger observation right away, drifts down by a quarter
+/// per smaller one.
+fn decay(current: usize, observed: usize) -> usize {
+    observed.max(current - current / 4)
+}
+
+fn size(observed: usize, min: usize, max: Option<usize>) -> usize {
+    let size = observed.next_multiple_of(ROUND_TO).clamp(min, CEILING);
+    max.map_or(size, |max| size.min(max))
+}
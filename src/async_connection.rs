@@ -0,0 +1,123 @@
+use crate::{
+    ClientOptions, Error, FSM, Request, Response, Result, Scheme, TlsInfo, Wants,
+    fsm::{is_write_rejected, sink_full},
+    url::host_header,
+};
+use futures_util::io::{AsyncRead, AsyncReadExt as _, AsyncWrite, AsyncWriteExt as _};
+use std::sync::Arc;
+
+/// Runtime-agnostic driver, works with any stream implementing the
+/// `futures` IO traits (smol, async-std, or tokio through a compat layer).
+///
+/// Opening the stream is up to the caller, and so are timeouts:
+/// `ClientOptions::timeouts` is ignored here because there is no portable
+/// timer, wrap the returned future with your runtime's timeout instead.
+///
+/// Timing out (or otherwise dropping) that future loses the exchange and
+/// leaves `stream` in the middle of it, close it afterwards. To resume
+/// after a cancellation use `FuturesExchange`.
+pub struct FuturesConnection;
+
+impl FuturesConnection {
+    pub async fn get<S>(
+        stream: &mut S,
+        hostname: &str,
+        path: &str,
+        options: &ClientOptions,
+    ) -> Result<Response>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        let scheme = Scheme::Https;
+        let port = scheme.default_port();
+        Self::send(stream, scheme, hostname, port, Request::get(path), options).await
+    }
+
+    /// Sends an arbitrary request, `Host` is added unless the request
+    /// already sets it.
+    pub async fn send<S>(
+        stream: &mut S,
+        scheme: Scheme,
+        hostname: &str,
+        port: u16,
+        request: Request,
+        options: &ClientOptions,
+    ) -> Result<Response>
+    where
+        S: AsyncRead + AsyncWrite + Unpin,
+    {
+        FuturesExchange::new(stream, scheme, hostname, port, request, options)?
+            .response()
+            .await
+    }
+}
+
+/// A request in flight over a caller-owned stream that survives its
+/// `response` future being dropped: the FSM lives here, every await point
+/// is a single read or write of the stream that is handed to the FSM once
+/// it completes, so calling `response` again carries on from where the
+/// dropped one stopped. That holds as long as the stream's own reads and
+/// writes don't lose data when dropped mid-await.
+pub struct FuturesExchange<S> {
+    stream: S,
+    fsm: FSM,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> FuturesExchange<S> {
+    /// `hostname` and `port` are only used for SNI, certificate verification
+    /// and the `Host` header.
+    pub fn new(
+        stream: S,
+        scheme: Scheme,
+        hostname: &str,
+        port: u16,
+        mut request: Request,
+        options: &ClientOptions,
+    ) -> Result<Self> {
+        request.add_default_header("Host", &host_header(scheme, hostname, port));
+
+        Ok(Self {
+            stream,
+            fsm: FSM::with_scheme(scheme, hostname, request, options)?,
+        })
+    }
+
+    /// Resolves once per exchange, polling it again after that is a misuse.
+    pub async fn response(&mut self) -> Result<Response> {
+        loop {
+            match self.fsm.wants()? {
+                Wants::Read(buf) => {
+                    let read = self.stream.read(buf).await.map_err(Error::Io)?;
+                    self.fsm.done_reading(read);
+                }
+                Wants::Write(buf) => match self.stream.write(buf).await {
+                    Ok(written) => self.fsm.done_writing(written),
+                    Err(err) if is_write_rejected(&err) && self.fsm.abort_write() => {}
+                    Err(err) => return Err(Error::Io(err)),
+                },
+                Wants::Body(_) => {
+                    return Err(Error::InvalidRequest(
+                        "this driver can't stream request bodies".to_string(),
+                    ));
+                }
+                Wants::SinkFull => return Err(sink_full()),
+                Wants::Done(response) => {
+                    return Ok(response);
+                }
+            }
+        }
+    }
+
+    /// See `FSM::tls_info`, available before the response is.
+    pub fn tls_info(&self) -> Option<Arc<TlsInfo>> {
+        self.fsm.tls_info()
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
@@ -0,0 +1,363 @@
+use crate::{
+    Request, Response, Scheme, Url, base64,
+    headers::{parse_param, split_list},
+};
+use std::{collections::HashMap, fmt};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum AuthScheme {
+    Basic,
+    Bearer,
+    /// Recorded, but never sent preemptively: every request needs
+    /// a fresh response to the server's nonce.
+    Digest,
+    Other(String),
+}
+
+impl AuthScheme {
+    fn parse(name: &str) -> Self {
+        match name {
+            _ if name.eq_ignore_ascii_case("basic") => Self::Basic,
+            _ if name.eq_ignore_ascii_case("bearer") => Self::Bearer,
+            _ if name.eq_ignore_ascii_case("digest") => Self::Digest,
+            _ => Self::Other(name.to_string()),
+        }
+    }
+}
+
+/// One challenge of a `WWW-Authenticate` or `Proxy-Authenticate` field.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Challenge {
+    pub scheme: AuthScheme,
+    /// Lowercased names, unquoted values.
+    pub params: Vec<(String, String)>,
+}
+
+impl Challenge {
+    /// All challenges of a field value, a single field can carry several
+    /// (`Basic realm="a", Bearer`) with commas between both them and their
+    /// parameters.
+    pub fn parse_all(value: &str) -> Vec<Self> {
+        let mut challenges: Vec<Self> = vec![];
+        for item in split_list(value, ',') {
+            let (first, rest) = item.split_once([' ', '\t']).unwrap_or((item, ""));
+            let param = if first.contains('=') {
+                item
+            } else {
+                challenges.push(Self {
+                    scheme: AuthScheme::parse(first),
+                    params: vec![],
+                });
+                rest.trim()
+            };
+
+            if let Some(challenge) = challenges.last_mut()
+                && !param.is_empty()
+            {
+                let (name, value) = parse_param(param);
+                challenge.params.push((name, value.unwrap_or_default()));
+            }
+        }
+        challenges
+    }
+
+    /// The challenges of a `401` (from `WWW-Authenticate`) or a `407`
+    /// (from `Proxy-Authenticate`), empty for any other response.
+    pub fn from_response(response: &Response) -> Vec<Self> {
+        let Some(party) = Party::of(response) else {
+            return vec![];
+        };
+        response
+            .headers
+            .get_all(party.challenge_header())
+            .flat_map(Self::parse_all)
+            .collect()
+    }
+
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(existing, _)| existing.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn realm(&self) -> Option<&str> {
+        self.param("realm")
+    }
+}
+
+/// Who asked for credentials.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Party {
+    /// `401` with `WWW-Authenticate`, answered with `Authorization`.
+    Server,
+    /// `407` with `Proxy-Authenticate`, answered with `Proxy-Authorization`.
+    Proxy,
+}
+
+impl Party {
+    fn of(response: &Response) -> Option<Self> {
+        match response.status {
+            401 => Some(Self::Server),
+            407 => Some(Self::Proxy),
+            _ => None,
+        }
+    }
+
+    fn challenge_header(self) -> &'static str {
+        match self {
+            Self::Server => "WWW-Authenticate",
+            Self::Proxy => "Proxy-Authenticate",
+        }
+    }
+
+    fn credentials_header(self) -> &'static str {
+        match self {
+            Self::Server => "Authorization",
+            Self::Proxy => "Proxy-Authorization",
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq)]
+pub enum Credentials {
+    Basic { user: String, password: String },
+    Bearer(String),
+}
+
+impl Credentials {
+    fn scheme(&self) -> AuthScheme {
+        match self {
+            Self::Basic { .. } => AuthScheme::Basic,
+            Self::Bearer(_) => AuthScheme::Bearer,
+        }
+    }
+
+    pub(crate) fn header_value(&self) -> String {
+        match self {
+            Self::Basic { user, password } => format!(
+                "Basic {}",
+                base64::encode(format!("{user}:{password}").as_bytes())
+            ),
+            Self::Bearer(token) => format!("Bearer {token}"),
+        }
+    }
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Basic { user, .. } => f
+                .debug_struct("Basic")
+                .field("user", user)
+                .finish_non_exhaustive(),
+            Self::Bearer(_) => f.write_str("Bearer(..)"),
+        }
+    }
+}
+
+/// Remembers which scheme each origin (and the proxy in front of it) asked
+/// for in its last challenge, so that later requests there can carry
+/// credentials up front instead of taking a round trip to be challenged
+/// again: `record` every response, `apply` to every request. Origins that
+/// never challenged get nothing.
+#[derive(Debug, Clone)]
+pub struct AuthCache {
+    /// Send credentials before being challenged, on by default.
+    /// With it off the cache only tells what was asked for.
+    pub preemptive: bool,
+    challenges: HashMap<(Party, Scheme, String, u16), Challenge>,
+}
+
+impl Default for AuthCache {
+    fn default() -> Self {
+        Self {
+            preemptive: true,
+            challenges: HashMap::new(),
+        }
+    }
+}
+
+impl AuthCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Caches the challenge of a `401` or `407` that `url` got and returns
+    /// it; of several challenges the first Basic or Bearer one is kept,
+    /// the first one otherwise. Any other response is ignored.
+    pub fn record(&mut self, url: &Url, response: &Response) -> Option<&Challenge> {
+        let party = Party::of(response)?;
+        let challenges = Challenge::from_response(response);
+        let preferred = challenges
+            .iter()
+            .position(|challenge| {
+                matches!(challenge.scheme, AuthScheme::Basic | AuthScheme::Bearer)
+            })
+            .unwrap_or(0);
+        let challenge = challenges.into_iter().nth(preferred)?;
+
+        let key = key(party, url);
+        self.challenges.insert(key.clone(), challenge);
+        self.challenges.get(&key)
+    }
+
+    /// The last challenge `party` sent for `url`'s origin.
+    pub fn challenge(&self, party: Party, url: &Url) -> Option<&Challenge> {
+        self.challenges.get(&key(party, url))
+    }
+
+    /// Drops what's cached for `url`'s origin, e.g. once the credentials
+    /// got rejected.
+    pub fn forget(&mut self, party: Party, url: &Url) {
+        self.challenges.remove(&key(party, url));
+    }
+
+    /// Adds `Authorization` (or `Proxy-Authorization`) with `credentials`
+    /// if `party` challenged `url`'s origin for their scheme before and
+    /// `preemptive` is on. Returns whether it did.
+    pub fn apply(
+        &self,
+        party: Party,
+        url: &Url,
+        request: &mut Request,
+        credentials: &Credentials,
+    ) -> bool {
+        let applies = self.preemptive
+            && self
+                .challenge(party, url)
+                .is_some_and(|challenge| challenge.scheme == credentials.scheme());
+        if applies {
+            request.add_header(party.credentials_header(), credentials.header_value());
+        }
+        applies
+    }
+}
+
+fn key(party: Party, url: &Url) -> (Party, Scheme, String, u16) {
+    (party, url.scheme, url.host.to_ascii_lowercase(), url.port)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(head: &str) -> Response {
+        Response::parse(head.as_bytes(), false).unwrap()
+    }
+
+    fn params(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn parses_challenges() {
+        let challenges = Challenge::parse_all(
+            r#"Newauth realm="apps", type=1, title="Login to \"apps\"", Basic REALM="simple, really""#,
+        );
+        assert_eq!(
+            challenges,
+            [
+                Challenge {
+                    scheme: AuthScheme::Other("Newauth".to_string()),
+                    params: params(&[
+                        ("realm", "apps"),
+                        ("type", "1"),
+                        ("title", r#"Login to "apps""#)
+                    ]),
+                },
+                Challenge {
+                    scheme: AuthScheme::Basic,
+                    params: params(&[("realm", "simple, really")]),
+                },
+            ]
+        );
+        assert_eq!(challenges[1].realm(), Some("simple, really"));
+        assert_eq!(Challenge::parse_all("bearer")[0].scheme, AuthScheme::Bearer);
+    }
+
+    #[test]
+    fn tolerates_malformed_challenges() {
+        assert_eq!(Challenge::parse_all(""), []);
+        assert_eq!(Challenge::parse_all(" , ,"), []);
+        // parameters before any scheme have nothing to belong to
+        assert_eq!(Challenge::parse_all("realm=x"), []);
+        let unterminated = Challenge::parse_all(r#"Basic realm="open"#);
+        assert_eq!(unterminated[0].scheme, AuthScheme::Basic);
+        assert_eq!(unterminated.len(), 1);
+    }
+
+    #[test]
+    fn caches_the_preferred_challenge_per_origin() {
+        let mut cache = AuthCache::new();
+        let url = Url::parse("https://Example.com/a").unwrap();
+        let same_origin = Url::parse("https://example.com/b").unwrap();
+        let other_port = Url::parse("https://example.com:8443/a").unwrap();
+        let credentials = Credentials::Basic {
+            user: "user".to_string(),
+            password: "pass".to_string(),
+        };
+
+        assert!(
+            cache
+                .record(&url, &response("HTTP/1.1 200 OK\r\n\r\n"))
+                .is_none()
+        );
+        let challenge = cache
+            .record(
+                &url,
+                &response(
+                    "HTTP/1.1 401 Unauthorized\r\n\
+                     WWW-Authenticate: Digest realm=\"a\", nonce=\"n\"\r\n\
+                     WWW-Authenticate: Basic realm=\"a\"\r\n\r\n",
+                ),
+            )
+            .unwrap();
+        assert_eq!(challenge.scheme, AuthScheme::Basic);
+
+        let mut request = Request::get("/b");
+        assert!(cache.apply(Party::Server, &same_origin, &mut request, &credentials));
+        assert_eq!(
+            request.headers().get("Authorization"),
+            Some("Basic dXNlcjpwYXNz")
+        );
+
+        let mut request = Request::get("/a");
+        assert!(!cache.apply(Party::Server, &other_port, &mut request, &credentials));
+        assert!(!cache.apply(Party::Proxy, &url, &mut request, &credentials));
+        let bearer = Credentials::Bearer("token".to_string());
+        assert!(!cache.apply(Party::Server, &url, &mut request, &bearer));
+        assert_eq!(request.headers().get("Authorization"), None);
+
+        cache.preemptive = false;
+        assert!(!cache.apply(Party::Server, &url, &mut request, &credentials));
+        cache.preemptive = true;
+        cache.forget(Party::Server, &same_origin);
+        assert!(!cache.apply(Party::Server, &url, &mut request, &credentials));
+    }
+
+    #[test]
+    fn caches_proxy_challenges_apart() {
+        let mut cache = AuthCache::new();
+        let url = Url::parse("http://example.com/").unwrap();
+        cache.record(
+            &url,
+            &response(
+                "HTTP/1.1 407 Proxy Authentication Required\r\nProxy-Authenticate: Bearer\r\n\r\n",
+            ),
+        );
+        assert!(cache.challenge(Party::Server, &url).is_none());
+
+        let mut request = Request::get("/");
+        let bearer = Credentials::Bearer("token".to_string());
+        assert!(cache.apply(Party::Proxy, &url, &mut request, &bearer));
+        assert_eq!(
+            request.headers().get("Proxy-Authorization"),
+            Some("Bearer token")
+        );
+        assert_eq!(format!("{bearer:?}"), "Bearer(..)");
+    }
+}
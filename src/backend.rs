@@ -0,0 +1,242 @@
+#[cfg(feature = "io-uring-with-dep")]
+use crate::{Cqe, Error, Fd, IoUringConnection, Sqe};
+#[cfg(feature = "poll")]
+use crate::{EventsOrResponse, PollConnection};
+#[cfg(any(feature = "poll", feature = "io-uring-with-dep"))]
+use crate::{Response, Result};
+
+/// The driver a `Client` sends requests with, see `Client::set_backend`.
+/// `Poll` needs the `poll` feature and `IoUring` the `io-uring-with-dep`
+/// one, several can be compiled in and picked from at runtime.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Backend {
+    /// `BlockingConnection`s, kept open between requests.
+    #[default]
+    Blocking,
+    /// A `PollConnection` per request, waited on with `poll(2)`.
+    Poll,
+    /// An `IoUringConnection` per request, on a ring the `Client` keeps.
+    IoUring,
+}
+
+impl Backend {
+    /// Compiled in and, for `IoUring`, allowed by the kernel: rings can't
+    /// be set up before Linux 5.1, with `kernel.io_uring_disabled` set or
+    /// under seccomp profiles that block them (common in containers).
+    pub fn is_available(self) -> bool {
+        match self {
+            Self::Blocking => true,
+            Self::Poll => cfg!(feature = "poll"),
+            Self::IoUring => io_uring_available(),
+        }
+    }
+
+    /// `self` if it's available, otherwise the next one down the list:
+    /// `IoUring`, `Poll`, `Blocking`.
+    pub fn or_fallback(self) -> Self {
+        [Self::IoUring, Self::Poll, Self::Blocking]
+            .into_iter()
+            .skip_while(|backend| *backend != self)
+            .find(|backend| backend.is_available())
+            .unwrap_or(Self::Blocking)
+    }
+}
+
+#[cfg(feature = "io-uring-with-dep")]
+fn io_uring_available() -> bool {
+    crate::IoUringCapabilities::detect().is_some()
+}
+
+#[cfg(not(feature = "io-uring-with-dep"))]
+fn io_uring_available() -> bool {
+    false
+}
+
+#[cfg(feature = "poll")]
+pub(crate) fn run_poll(mut conn: PollConnection) -> Result<Response> {
+    use libc::{POLLERR, POLLHUP, POLLIN, POLLOUT, pollfd};
+    use std::{io, os::fd::AsRawFd};
+
+    let mut fd = pollfd {
+        fd: conn.as_raw_fd(),
+        events: 0,
+        revents: 0,
+    };
+    loop {
+        match conn.events()? {
+            EventsOrResponse::Events(events) => {
+                // a new socket if connecting fell back to another address
+                fd.fd = conn.as_raw_fd();
+                fd.events = events;
+            }
+            EventsOrResponse::Response(response) => return Ok(response),
+        }
+        let timeout = conn.timeout().map_or(-1, |timeout| {
+            timeout.as_millis().min(i32::MAX as u128) as i32
+        });
+
+        fd.revents = 0;
+        if unsafe { libc::poll(&mut fd, 1, timeout) } < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(err.into());
+        }
+        // errors surface from the next read or write (or connect check),
+        // on a timeout both are false and `poll` reports the passed deadline
+        let failed = fd.revents & (POLLERR | POLLHUP) != 0;
+        let readable = failed || fd.revents & POLLIN != 0;
+        let writable = failed || fd.revents & POLLOUT != 0;
+        if let Some(response) = conn.poll(readable, writable)? {
+            return Ok(response);
+        }
+    }
+}
+
+/// Drives `conn` (created with `id`) to its response. Completions of other
+/// ids are dropped, e.g. closes of earlier connections on the same ring.
+#[cfg(feature = "io-uring-with-dep")]
+pub(crate) fn run_io_uring(
+    ring: &mut io_uring::IoUring,
+    mut conn: IoUringConnection,
+    id: u64,
+) -> Result<Response> {
+    use io_uring::{opcode, squeue, types};
+
+    loop {
+        let (sqe, response) = conn.next_sqe()?;
+        let timespec;
+        if let Some(sqe) = sqe {
+            let pushed = match sqe.link_timeout() {
+                Some(timeout) => {
+                    timespec = types::Timespec::from(timeout.duration);
+                    let entries = [
+                        entry(sqe).flags(squeue::Flags::IO_LINK),
+                        opcode::LinkTimeout::new(&timespec)
+                            .build()
+                            .user_data(timeout.user_data),
+                    ];
+                    unsafe { ring.submission().push_multiple(&entries) }
+                }
+                None => unsafe { ring.submission().push(&entry(sqe)) },
+            };
+            // one operation at a time, the queue can't be full
+            pushed.map_err(|_| Error::Io(std::io::Error::other("the submission queue is full")))?;
+        }
+
+        if let Some(response) = response {
+            // the socket's close comes along with the response
+            ring.submit()?;
+            return Ok(response);
+        }
+
+        ring.submit_and_wait(1)?;
+
+        while let Some(cqe) = ring.completion().next() {
+            if IoUringConnection::id_of(cqe.user_data()) == id {
+                conn.process_cqe(Cqe {
+                    result: cqe.result(),
+                    user_data: cqe.user_data(),
+                })?;
+            }
+        }
+    }
+}
+
+#[cfg(feature = "io-uring-with-dep")]
+fn entry(sqe: Sqe) -> io_uring::squeue::Entry {
+    use io_uring::{opcode, types};
+
+    match sqe {
+        Sqe::Socket {
+            domain,
+            socket_type,
+            protocol,
+            user_data,
+        } => opcode::Socket::new(domain, socket_type, protocol)
+            .build()
+            .user_data(user_data),
+        Sqe::SocketDirect {
+            domain,
+            socket_type,
+            protocol,
+            file_index,
+            user_data,
+        } => opcode::Socket::new(domain, socket_type, protocol)
+            .file_index(Some(match file_index {
+                Some(index) => {
+                    types::DestinationSlot::try_from_slot_target(index).expect("a valid file index")
+                }
+                None => types::DestinationSlot::auto_target(),
+            }))
+            .build()
+            .user_data(user_data),
+        Sqe::Close { fd, user_data } => match fd {
+            Fd::Raw(fd) => opcode::Close::new(types::Fd(fd)).build(),
+            Fd::Fixed(index) => opcode::Close::new(types::Fixed(index)).build(),
+        }
+        .user_data(user_data),
+        Sqe::Connect {
+            fd,
+            addr,
+            addrlen,
+            user_data,
+            ..
+        } => match fd {
+            Fd::Raw(fd) => opcode::Connect::new(types::Fd(fd), addr, addrlen).build(),
+            Fd::Fixed(index) => opcode::Connect::new(types::Fixed(index), addr, addrlen).build(),
+        }
+        .user_data(user_data),
+        Sqe::Write {
+            fd,
+            buf,
+            len,
+            user_data,
+            ..
+        } => match fd {
+            Fd::Raw(fd) => opcode::Write::new(types::Fd(fd), buf, len).build(),
+            Fd::Fixed(index) => opcode::Write::new(types::Fixed(index), buf, len).build(),
+        }
+        .user_data(user_data),
+        Sqe::Read {
+            fd,
+            buf,
+            len,
+            user_data,
+            ..
+        } => match fd {
+            Fd::Raw(fd) => opcode::Read::new(types::Fd(fd), buf, len).build(),
+            Fd::Fixed(index) => opcode::Read::new(types::Fixed(index), buf, len).build(),
+        }
+        .user_data(user_data),
+        Sqe::WriteFixed {
+            fd,
+            buf,
+            len,
+            buf_index,
+            user_data,
+            ..
+        } => match fd {
+            Fd::Raw(fd) => opcode::WriteFixed::new(types::Fd(fd), buf, len, buf_index).build(),
+            Fd::Fixed(index) => {
+                opcode::WriteFixed::new(types::Fixed(index), buf, len, buf_index).build()
+            }
+        }
+        .user_data(user_data),
+        Sqe::ReadFixed {
+            fd,
+            buf,
+            len,
+            buf_index,
+            user_data,
+            ..
+        } => match fd {
+            Fd::Raw(fd) => opcode::ReadFixed::new(types::Fd(fd), buf, len, buf_index).build(),
+            Fd::Fixed(index) => {
+                opcode::ReadFixed::new(types::Fixed(index), buf, len, buf_index).build()
+            }
+        }
+        .user_data(user_data),
+    }
+}
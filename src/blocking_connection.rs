@@ -1,39 +1,428 @@
-use crate::{FSM, Request, Response, Wants};
-use anyhow::Result;
-use rustls::pki_types::ServerName;
+use crate::{
+    BodySink, ClientOptions, Error, FSM, Request, Response, Result, Scheme, TimeoutKind, Url,
+    Wants,
+    deadline::{deadline, remaining},
+    fsm::{is_write_rejected, sink_full},
+    net,
+    url::host_header,
+};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
 use std::{
-    io::{Read as _, Write as _},
+    io::{ErrorKind, Read, Write},
     net::TcpStream,
+    time::{Duration, Instant},
 };
 
 pub struct BlockingConnection;
 
+/// An already connected stream to send a request over.
+pub trait Stream: Read + Write {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()>;
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()>;
+}
+
+impl Stream for TcpStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        TcpStream::set_read_timeout(self, timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        TcpStream::set_write_timeout(self, timeout)
+    }
+}
+
+#[cfg(unix)]
+impl Stream for UnixStream {
+    fn set_read_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        UnixStream::set_read_timeout(self, timeout)
+    }
+
+    fn set_write_timeout(&self, timeout: Option<Duration>) -> std::io::Result<()> {
+        UnixStream::set_write_timeout(self, timeout)
+    }
+}
+
+// `Full` keeps the response's first headers inline, like `Wants::Done`
+#[allow(clippy::large_enum_variant)]
+pub enum Fetched {
+    Full(Response),
+    /// The body is over the threshold (or its size is unknown), it's
+    /// only read when asked for, see `LargeBody`.
+    Streaming(Box<LargeBody>),
+}
+
+/// A body `fetch_if_large` left for the caller to stream: `copy_to` sends
+/// the GET over the connection of the HEAD request (a new one if the
+/// server closed it) and writes the body out as it arrives, without
+/// holding it in memory. `options.limits.max_body_size` still applies,
+/// the threshold doesn't. Only a successful (2xx) response's body is
+/// written, that of any other is dropped.
+pub struct LargeBody {
+    /// The response to the HEAD request.
+    pub head: Response,
+    scheme: Scheme,
+    hostname: String,
+    port: u16,
+    path: String,
+    options: ClientOptions,
+    conn: Option<(FSM, TcpStream)>,
+}
+
+impl LargeBody {
+    /// Returns the response of the GET request, with the body already
+    /// written to `out`.
+    pub fn copy_to(self, out: &mut impl Write) -> Result<Response> {
+        let Self {
+            scheme,
+            hostname,
+            port,
+            path,
+            options,
+            conn,
+            ..
+        } = self;
+        let mut request = Request::get(&path);
+        request.add_default_header("Host", &host_header(scheme, &hostname, port));
+        let deadline = deadline(&options.timeouts);
+
+        if let Some((mut fsm, mut sock)) = conn {
+            fsm.reuse_with(request.clone(), &options)?;
+            match stream_body(&mut sock, &mut fsm, out, &options, deadline) {
+                Ok(response) => return Ok(response),
+                // closed in the meantime, nothing was written yet
+                Err(err) if !matches!(err, Error::Timeout(_)) && !fsm.has_received_response() => {}
+                Err(err) => return Err(err),
+            }
+        }
+
+        let mut fsm = FSM::with_scheme(scheme, &hostname, request, &options)?;
+        let mut sock = net::connect(
+            options.connect_host(&hostname),
+            port,
+            &options.timeouts,
+            deadline,
+            options.ip_preference,
+            options.net_observer.as_ref(),
+        )?;
+        stream_body(&mut sock, &mut fsm, out, &options, deadline)
+    }
+}
+
+/// Holds a piece of a streamed body on its way to a writer.
+struct Spool {
+    buf: Box<[u8]>,
+    len: usize,
+}
+
+impl BodySink for Spool {
+    fn spare(&mut self) -> &mut [u8] {
+        &mut self.buf[self.len..]
+    }
+
+    fn filled(&mut self, len: usize) {
+        self.len += len;
+    }
+}
+
+/// Exchanges the FSM's request, writing the body of a 2xx response to
+/// `out` as it arrives and dropping that of any other (e.g. a redirect's).
+/// `Response::body` is empty then. The FSM is left without a body sink.
+pub(crate) fn stream_body<S: Stream>(
+    sock: &mut S,
+    fsm: &mut FSM,
+    out: &mut dyn Write,
+    options: &ClientOptions,
+    deadline: Option<Instant>,
+) -> Result<Response> {
+    fsm.set_body_sink(Spool {
+        buf: vec![0; 64 * 1024].into_boxed_slice(),
+        len: 0,
+    });
+    let mut flush = |fsm: &mut FSM, success: bool| -> Result<()> {
+        if let Some(spool) = fsm.body_sink::<Spool>() {
+            if success {
+                out.write_all(&spool.buf[..spool.len])?;
+            }
+            spool.len = 0;
+        }
+        Ok(())
+    };
+    let exchanged = BlockingConnection::exchange_with(
+        sock,
+        fsm,
+        None,
+        options,
+        deadline,
+        &mut |fsm: &mut FSM| {
+            let success = fsm
+                .response_status()
+                .is_some_and(|status| (200..300).contains(&status));
+            flush(fsm, success)
+        },
+    )
+    .and_then(|response| {
+        // the status is gone from the FSM once the response is complete
+        flush(fsm, response.is_success())?;
+        Ok(response)
+    });
+    fsm.take_body_sink();
+    let mut response = exchanged?;
+    // what the spool had no room for when the response completed
+    if response.is_success() {
+        out.write_all(&response.body)?;
+    }
+    response.body.clear();
+    Ok(response)
+}
+
 impl BlockingConnection {
-    pub fn get(hostname: &str, port: u16, path: &str) -> Result<Response> {
-        let mut fsm = {
-            let server_name = ServerName::try_from(hostname)?.to_owned();
+    pub fn get(hostname: &str, port: u16, path: &str, options: &ClientOptions) -> Result<Response> {
+        Self::perform(Scheme::Https, hostname, port, Request::get(path), options)
+    }
 
-            let mut request = Request::get(path);
-            request.add_header("Host", hostname);
-            request.add_header("Connection", "close");
+    pub fn get_url(url: &str, options: &ClientOptions) -> Result<Response> {
+        let url = Url::parse(url)?;
+        Self::perform(url.scheme, &url.host, url.port, url.get_request(), options)
+    }
+
+    /// Sends an arbitrary request, `Host` is added unless the request
+    /// already sets it. Repeated as `options.retry` allows.
+    pub fn send(
+        scheme: Scheme,
+        hostname: &str,
+        port: u16,
+        request: Request,
+        options: &ClientOptions,
+    ) -> Result<Response> {
+        Self::perform(scheme, hostname, port, request, options)
+    }
 
-            FSM::new(server_name, request)?
+    /// Asks for the size with a HEAD request first and only downloads the body
+    /// if its `Content-Length` is below `threshold`, over the same connection
+    /// if the server keeps it open. A body that turns out to be larger than
+    /// announced fails with `LimitExceeded(LimitKind::BodySize)`. Larger
+    /// bodies are left to stream with `LargeBody::copy_to`.
+    pub fn fetch_if_large(
+        scheme: Scheme,
+        hostname: &str,
+        port: u16,
+        path: &str,
+        threshold: u64,
+        options: &ClientOptions,
+    ) -> Result<Fetched> {
+        let unbounded = ClientOptions {
+            keep_alive: true,
+            ..options.clone()
         };
+        let mut options = unbounded.clone();
+        let below_threshold = usize::try_from(threshold.saturating_sub(1)).unwrap_or(usize::MAX);
+        options.limits.max_body_size = Some(match options.limits.max_body_size {
+            Some(max) => max.min(below_threshold),
+            None => below_threshold,
+        });
 
-        let mut sock = TcpStream::connect(format!("{hostname}:{port}"))?;
+        let mut fsm = Self::fsm(scheme, hostname, port, Request::head(path), &options)?;
+        let deadline = deadline(&options.timeouts);
+        let mut sock = net::connect(
+            options.connect_host(hostname),
+            port,
+            &options.timeouts,
+            deadline,
+            options.ip_preference,
+            options.net_observer.as_ref(),
+        )?;
+        let head = Self::exchange(&mut sock, &mut fsm, None, &options, deadline)?;
+
+        if !matches!(head.content_length(), Some(len) if len < threshold) {
+            return Ok(Fetched::Streaming(Box::new(LargeBody {
+                head,
+                scheme,
+                hostname: hostname.to_string(),
+                port,
+                path: path.to_string(),
+                options: unbounded,
+                conn: fsm.is_reusable().then_some((fsm, sock)),
+            })));
+        }
+
+        if fsm.is_reusable() {
+            let mut request = Request::get(path);
+            request.add_default_header("Host", &host_header(scheme, hostname, port));
+            fsm.reuse(request)?;
+            match Self::exchange(&mut sock, &mut fsm, None, &options, deadline) {
+                Ok(response) => return Ok(Fetched::Full(response)),
+                // closed in the meantime, GET is safe to send again
+                Err(err) if !matches!(err, Error::Timeout(_)) && !fsm.has_received_response() => {}
+                Err(err) => return Err(err),
+            }
+        }
+
+        Self::perform(scheme, hostname, port, Request::get(path), &options).map(Fetched::Full)
+    }
+
+    /// Sends `request` over a stream the caller has connected (e.g. a unix
+    /// socket or a socket bound to a specific interface), `hostname` and `port`
+    /// are only used for SNI, certificate verification and the `Host` header.
+    /// `connect_to`, `ip_preference` and the connect timeout don't apply.
+    pub fn send_over<S: Stream>(
+        stream: &mut S,
+        scheme: Scheme,
+        hostname: &str,
+        port: u16,
+        request: Request,
+        options: &ClientOptions,
+    ) -> Result<Response> {
+        let mut fsm = Self::fsm(scheme, hostname, port, request, options)?;
+        let deadline = deadline(&options.timeouts);
+
+        Self::exchange(stream, &mut fsm, None, options, deadline)
+    }
+
+    /// Sends a request set up with `Request::stream_body`, reading the body
+    /// from `body` as it goes out. Not retried, the body can't be replayed.
+    pub fn upload(
+        scheme: Scheme,
+        hostname: &str,
+        port: u16,
+        request: Request,
+        body: &mut impl Read,
+        options: &ClientOptions,
+    ) -> Result<Response> {
+        Self::connect_and_exchange(scheme, hostname, port, request, Some(body), options)
+    }
+
+    /// Sends the request, repeating it as `options.retry` allows.
+    pub(crate) fn perform(
+        scheme: Scheme,
+        hostname: &str,
+        port: u16,
+        request: Request,
+        options: &ClientOptions,
+    ) -> Result<Response> {
+        if options.retry.max_attempts <= 1 {
+            return Self::send_once(scheme, hostname, port, request, options);
+        }
+
+        let mut attempt = 1;
+        loop {
+            let outcome = Self::send_once(scheme, hostname, port, request.clone(), options);
+            match options.retry.delay(attempt, request.method(), &outcome) {
+                Some(delay) => std::thread::sleep(delay),
+                None => return outcome,
+            }
+            attempt += 1;
+        }
+    }
+
+    fn send_once(
+        scheme: Scheme,
+        hostname: &str,
+        port: u16,
+        request: Request,
+        options: &ClientOptions,
+    ) -> Result<Response> {
+        Self::connect_and_exchange(scheme, hostname, port, request, None, options)
+    }
+
+    fn connect_and_exchange(
+        scheme: Scheme,
+        hostname: &str,
+        port: u16,
+        request: Request,
+        body: Option<&mut dyn Read>,
+        options: &ClientOptions,
+    ) -> Result<Response> {
+        let mut fsm = Self::fsm(scheme, hostname, port, request, options)?;
+        let deadline = deadline(&options.timeouts);
+
+        let mut sock = net::connect(
+            options.connect_host(hostname),
+            port,
+            &options.timeouts,
+            deadline,
+            options.ip_preference,
+            options.net_observer.as_ref(),
+        )?;
+
+        Self::exchange(&mut sock, &mut fsm, body, options, deadline)
+    }
+
+    pub(crate) fn fsm(
+        scheme: Scheme,
+        hostname: &str,
+        port: u16,
+        mut request: Request,
+        options: &ClientOptions,
+    ) -> Result<FSM> {
+        request.add_default_header("Host", &host_header(scheme, hostname, port));
+
+        FSM::with_scheme(scheme, hostname, request, options)
+    }
+
+    pub(crate) fn exchange<S: Stream>(
+        sock: &mut S,
+        fsm: &mut FSM,
+        body: Option<&mut dyn Read>,
+        options: &ClientOptions,
+        deadline: Option<Instant>,
+    ) -> Result<Response> {
+        Self::exchange_with(
+            sock,
+            fsm,
+            body,
+            options,
+            deadline,
+            &mut |_| Err(sink_full()),
+        )
+    }
+
+    /// `on_sink_full` has to make room in the FSM's `BodySink`.
+    fn exchange_with<S: Stream>(
+        sock: &mut S,
+        fsm: &mut FSM,
+        mut body: Option<&mut dyn Read>,
+        options: &ClientOptions,
+        deadline: Option<Instant>,
+        on_sink_full: &mut dyn FnMut(&mut FSM) -> Result<()>,
+    ) -> Result<Response> {
+        let timeouts = &options.timeouts;
 
         loop {
             let action = fsm.wants()?;
 
             match action {
                 Wants::Read(buf) => {
-                    let read = sock.read(buf)?;
+                    sock.set_read_timeout(remaining(timeouts.read, deadline)?)?;
+                    let read = match sock.read(buf) {
+                        Ok(read) => read,
+                        Err(err) if is_timeout(&err) => {
+                            return Err(Error::Timeout(TimeoutKind::Read));
+                        }
+                        Err(err) => return Err(Error::Io(err)),
+                    };
                     fsm.done_reading(read);
                 }
                 Wants::Write(buf) => {
-                    let written = sock.write(buf)?;
+                    sock.set_write_timeout(remaining(timeouts.write, deadline)?)?;
+                    let written = match sock.write(buf) {
+                        Ok(written) => written,
+                        Err(err) if is_timeout(&err) => {
+                            return Err(Error::Timeout(TimeoutKind::Write));
+                        }
+                        Err(err) if is_write_rejected(&err) && fsm.abort_write() => continue,
+                        Err(err) => return Err(Error::Io(err)),
+                    };
                     fsm.done_writing(written);
                 }
+                Wants::Body(buf) => {
+                    let Some(body) = body.as_mut() else {
+                        return Err(no_body_source());
+                    };
+                    let filled = body.read(buf).map_err(Error::Io)?;
+                    fsm.done_body(filled)?;
+                }
+                Wants::SinkFull => on_sink_full(fsm)?,
                 Wants::Done(response) => {
                     return Ok(response);
                 }
@@ -41,3 +430,123 @@ impl BlockingConnection {
         }
     }
 }
+
+fn no_body_source() -> Error {
+    Error::InvalidRequest("the request streams its body, send it with upload".to_string())
+}
+
+pub(crate) fn is_timeout(err: &std::io::Error) -> bool {
+    matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{LimitKind, testing::read_request};
+    use std::{net::TcpListener, thread};
+
+    #[test]
+    fn fetch_if_large_reuses_the_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let (mut conn, _) = listener.accept().unwrap();
+            let head = read_request(&mut conn);
+            assert!(head.starts_with(b"HEAD /file HTTP/1.1\r\n"));
+            conn.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\n")
+                .unwrap();
+            let get = read_request(&mut conn);
+            assert!(get.starts_with(b"GET /file HTTP/1.1\r\n"));
+            conn.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nsmall")
+                .unwrap();
+        });
+
+        let options = ClientOptions::default();
+        let fetched = BlockingConnection::fetch_if_large(
+            Scheme::Http,
+            "127.0.0.1",
+            port,
+            "/file",
+            10,
+            &options,
+        )
+        .unwrap();
+        let Fetched::Full(response) = fetched else {
+            panic!("the body is below the threshold");
+        };
+        assert_eq!(response.body, b"small");
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn fetch_if_large_bounds_the_body_by_the_threshold() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let (mut conn, _) = listener.accept().unwrap();
+            read_request(&mut conn);
+            conn.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\n")
+                .unwrap();
+            read_request(&mut conn);
+            // grew since the HEAD request
+            conn.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 20\r\n\r\n")
+                .unwrap();
+            conn.write_all(&[b'x'; 20]).ok();
+        });
+
+        let options = ClientOptions::default();
+        let fetched = BlockingConnection::fetch_if_large(
+            Scheme::Http,
+            "127.0.0.1",
+            port,
+            "/file",
+            10,
+            &options,
+        );
+        assert!(
+            matches!(fetched, Err(Error::LimitExceeded(LimitKind::BodySize))),
+            "{:?}",
+            fetched.as_ref().err()
+        );
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn fetch_if_large_streams_large_bodies() {
+        let body = (0..200_000).map(|i| i as u8).collect::<Vec<_>>();
+        let sent = body.clone();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let (mut conn, _) = listener.accept().unwrap();
+            read_request(&mut conn);
+            let head = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", sent.len());
+            conn.write_all(head.as_bytes()).unwrap();
+            let get = read_request(&mut conn);
+            assert!(get.starts_with(b"GET /file HTTP/1.1\r\n"));
+            conn.write_all(head.as_bytes()).unwrap();
+            conn.write_all(&sent).unwrap();
+        });
+
+        let options = ClientOptions::default();
+        let fetched = BlockingConnection::fetch_if_large(
+            Scheme::Http,
+            "127.0.0.1",
+            port,
+            "/file",
+            10,
+            &options,
+        )
+        .unwrap();
+        let Fetched::Streaming(large) = fetched else {
+            panic!("the body is over the threshold");
+        };
+        assert_eq!(large.head.content_length(), Some(body.len() as u64));
+        let mut out = vec![];
+        let response = large.copy_to(&mut out).unwrap();
+        assert_eq!(response.status, 200);
+        assert!(response.body.is_empty());
+        assert_eq!(out, body);
+        server.join().unwrap();
+    }
+}
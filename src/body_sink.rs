@@ -0,0 +1,112 @@
+use std::{any::Any, collections::VecDeque, ops::DerefMut};
+
+/// Takes the response body as it arrives, see `FSM::set_body_sink`.
+/// Bodies with a `Content-Length` or read until close are copied straight
+/// from the decrypted record (or the read buffer for plain HTTP) into
+/// `spare`, chunked ones go through the FSM's parsing space to remove the
+/// framing. Content codings aren't undone: with the `decompression`
+/// feature on, set the sink before the first `FSM::wants` so that the
+/// request doesn't ask for them.
+pub trait BodySink: Any + Send {
+    /// Room for the next bytes of the body. An empty slice holds them back
+    /// in the FSM until there's room again, up to what the incoming buffer
+    /// holds: past that `FSM::wants` returns `Wants::SinkFull` instead of
+    /// reading more. Whatever is still held back once the response is
+    /// complete ends up in `Response::body`.
+    fn spare(&mut self) -> &mut [u8];
+
+    /// `len` bytes at the start of the last `spare` were filled.
+    fn filled(&mut self, len: usize);
+}
+
+/// A `BodySink` over a fixed set of buffers, e.g. ones registered with
+/// io_uring. Each buffer is filled completely before the next free one is
+/// started; take full ones with `pop_filled` and hand them back with
+/// `release` once they're processed. With every buffer waiting to be
+/// released the body is held back, see `BodySink::spare`.
+pub struct BufferRing<B> {
+    buffers: Vec<B>,
+    lens: Vec<usize>,
+    free: VecDeque<usize>,
+    current: Option<usize>,
+    full: VecDeque<usize>,
+}
+
+impl<B: DerefMut<Target = [u8]>> BufferRing<B> {
+    pub fn new(buffers: Vec<B>) -> Self {
+        Self {
+            lens: vec![0; buffers.len()],
+            free: (0..buffers.len()).collect(),
+            current: None,
+            full: VecDeque::new(),
+            buffers,
+        }
+    }
+
+    /// The index of the oldest filled buffer, in body order.
+    pub fn pop_filled(&mut self) -> Option<usize> {
+        self.full.pop_front()
+    }
+
+    /// The filled part of a buffer.
+    pub fn get(&self, index: usize) -> &[u8] {
+        &self.buffers[index][..self.lens[index]]
+    }
+
+    /// Makes a buffer taken with `pop_filled` available for filling again.
+    pub fn release(&mut self, index: usize) {
+        self.lens[index] = 0;
+        self.free.push_back(index);
+    }
+
+    /// Queues the buffer being filled for `pop_filled` even though it's
+    /// not full, e.g. once the response is complete.
+    pub fn flush(&mut self) {
+        if let Some(index) = self.current.take() {
+            self.full.push_back(index);
+        }
+    }
+
+    pub fn into_buffers(self) -> Vec<B> {
+        self.buffers
+    }
+}
+
+impl<B: DerefMut<Target = [u8]> + Send + 'static> BodySink for BufferRing<B> {
+    fn spare(&mut self) -> &mut [u8] {
+        if self.current.is_none() {
+            self.current = self.free.pop_front();
+        }
+        match self.current {
+            Some(index) => &mut self.buffers[index][self.lens[index]..],
+            None => &mut [],
+        }
+    }
+
+    fn filled(&mut self, len: usize) {
+        let Some(index) = self.current else {
+            return;
+        };
+        self.lens[index] += len;
+        if self.lens[index] == self.buffers[index].len() {
+            self.flush();
+        }
+    }
+}
+
+/// Copies as much of `bytes` as fits, returns how much that was.
+pub(crate) fn fill(sink: &mut dyn BodySink, mut bytes: &[u8]) -> usize {
+    let mut copied = 0;
+    while !bytes.is_empty() {
+        let spare = sink.spare();
+        if spare.is_empty() {
+            break;
+        }
+        let len = spare.len().min(bytes.len());
+        spare[..len].copy_from_slice(&bytes[..len]);
+        sink.filled(len);
+        copied += len;
+        bytes = &bytes[len..];
+    }
+    copied
+}
@@ -0,0 +1,147 @@
+use crate::{Error, LimitKind, Result};
+use std::ops::{Deref, DerefMut};
+
+/// Sizes of the FSM's internal TLS buffers.
+///
+/// The incoming buffer must fit at least one TLS record (a bit over 16 KiB),
+/// the outgoing one holds the whole encrypted request.
+#[derive(Debug, Clone, Copy)]
+pub struct BufferConfig {
+    pub initial_incoming: usize,
+    pub initial_outgoing: usize,
+    /// Going over it fails with `Error::LimitExceeded(LimitKind::IncomingBuffer)`.
+    pub max_incoming: Option<usize>,
+    /// Going over it fails with `Error::LimitExceeded(LimitKind::OutgoingBuffer)`.
+    pub max_outgoing: Option<usize>,
+    pub growth: Growth,
+
+    /// Bytes of already processed TLS data allowed to pile up at the front of
+    /// the incoming buffer before the rest is moved back to its start.
+    /// Lower values trade extra copying for a smaller buffer.
+    pub compact_incoming_after: usize,
+
+    /// Bytes of response parsing space allocated up front, none by default:
+    /// it grows as the response comes in.
+    pub initial_scratch: usize,
+
+    /// Bytes of response parsing space (the received response, chunk offsets)
+    /// a connection keeps allocated between requests, whatever a bigger
+    /// response needed on top is freed once the next request is queued.
+    pub retain_scratch: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Growth {
+    /// Grow by a fixed number of bytes.
+    Linear(usize),
+    Double,
+}
+
+impl Default for BufferConfig {
+    fn default() -> Self {
+        Self {
+            initial_incoming: 16 * 1024,
+            initial_outgoing: 1024,
+            max_incoming: None,
+            max_outgoing: None,
+            growth: Growth::Linear(16 * 1024),
+            compact_incoming_after: 8 * 1024,
+            initial_scratch: 0,
+            retain_scratch: 64 * 1024,
+        }
+    }
+}
+
+impl Growth {
+    fn next_len(self, len: usize) -> usize {
+        match self {
+            Self::Linear(step) => len.saturating_add(step.max(1)),
+            Self::Double => len.saturating_mul(2).max(1),
+        }
+    }
+}
+
+/// Storage for the FSM's TLS buffers.
+///
+/// `Vec<u8>` grows as `BufferConfig` allows, a borrowed `&mut [u8]` never
+/// does: running out of it fails with `Error::LimitExceeded` instead of
+/// allocating.
+pub trait Buffer: DerefMut<Target = [u8]> {
+    /// Makes the buffer at least `min_len` bytes long,
+    /// returns `false` if it can't.
+    fn grow(&mut self, min_len: usize, growth: Growth, max: Option<usize>) -> bool;
+}
+
+impl Buffer for Vec<u8> {
+    fn grow(&mut self, min_len: usize, growth: Growth, max: Option<usize>) -> bool {
+        let mut new_len = growth.next_len(self.len()).max(min_len);
+        if let Some(max) = max {
+            if min_len > max {
+                return false;
+            }
+            new_len = new_len.min(max);
+        }
+        self.resize(new_len, 0);
+        true
+    }
+}
+
+impl Buffer for &mut [u8] {
+    fn grow(&mut self, min_len: usize, _growth: Growth, _max: Option<usize>) -> bool {
+        min_len <= self.len()
+    }
+}
+
+/// Inline storage of a capacity fixed at compile time, for targets where
+/// the buffers must not be allocated at all. Running out of it fails with
+/// `Error::LimitExceeded` like a borrowed slice does.
+#[derive(Debug, Clone)]
+pub struct FixedBuffer<const N: usize> {
+    bytes: [u8; N],
+}
+
+impl<const N: usize> FixedBuffer<N> {
+    pub const fn new() -> Self {
+        Self { bytes: [0; N] }
+    }
+}
+
+impl<const N: usize> Default for FixedBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Deref for FixedBuffer<N> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.bytes
+    }
+}
+
+impl<const N: usize> DerefMut for FixedBuffer<N> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.bytes
+    }
+}
+
+impl<const N: usize> Buffer for FixedBuffer<N> {
+    fn grow(&mut self, min_len: usize, _growth: Growth, _max: Option<usize>) -> bool {
+        min_len <= N
+    }
+}
+
+pub(crate) fn grow<B: Buffer>(
+    buf: &mut B,
+    min_len: usize,
+    growth: Growth,
+    max: Option<usize>,
+    kind: LimitKind,
+) -> Result<()> {
+    if buf.grow(min_len, growth, max) {
+        Ok(())
+    } else {
+        Err(Error::LimitExceeded(kind))
+    }
+}
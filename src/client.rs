@@ -0,0 +1,626 @@
+#[cfg(feature = "poll")]
+use crate::PollConnection;
+#[cfg(any(feature = "poll", feature = "io-uring-with-dep"))]
+use crate::backend;
+use crate::{
+    Backend, BlockingConnection, ClientOptions, ConnectionPool, Credentials, Download,
+    DownloadSettings, Error, FSM, Headers, LimitKind, Method, Progress, Redirect, Request,
+    Response, Result, Scheme, SchemePolicy, TimeoutKind, Url,
+    blocking_connection::{is_timeout, stream_body},
+    deadline::{deadline, remaining},
+    download::{Metered, RateLimit},
+    net,
+    redact::redact_url,
+    url::{host_literal, is_valid_host},
+};
+#[cfg(feature = "io-uring-with-dep")]
+use crate::{IoUringCapabilities, IoUringConnection};
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    sync::{Arc, Mutex},
+};
+
+/// An HTTP proxy requests are tunneled through with `CONNECT`, for plain
+/// http targets too.
+#[derive(Debug, Clone)]
+pub struct Proxy {
+    pub host: String,
+    pub port: u16,
+    /// Sent as `Proxy-Authorization` with every `CONNECT`.
+    pub credentials: Option<Credentials>,
+}
+
+impl Proxy {
+    pub fn new(host: impl Into<String>, port: u16) -> Self {
+        Self {
+            host: host.into(),
+            port,
+            credentials: None,
+        }
+    }
+}
+
+/// Sends requests with the blocking driver and shared settings: the
+/// `ClientOptions` (TLS, timeouts, limits...) of every request, headers
+/// added to every request that doesn't set them itself, redirects to
+/// follow and an optional proxy. With the default `Backend::Blocking`
+/// direct connections are kept open between requests, see `ConnectionPool`.
+pub struct Client {
+    options: Arc<ClientOptions>,
+    default_headers: Headers,
+    max_redirects: usize,
+    scheme_policy: SchemePolicy,
+    proxy: Option<Proxy>,
+    backend: Backend,
+    pool: ConnectionPool,
+    #[cfg(feature = "io-uring-with-dep")]
+    ring: Option<io_uring::IoUring>,
+    #[cfg(feature = "io-uring-with-dep")]
+    next_id: u64,
+}
+
+impl Default for Client {
+    fn default() -> Self {
+        Self::new(&ClientOptions::default())
+    }
+}
+
+impl Client {
+    pub fn new(options: &ClientOptions) -> Self {
+        Self {
+            options: Arc::new(options.clone()),
+            default_headers: Headers::new(),
+            max_redirects: 10,
+            scheme_policy: SchemePolicy::default(),
+            proxy: None,
+            backend: Backend::Blocking,
+            pool: ConnectionPool::new(options),
+            #[cfg(feature = "io-uring-with-dep")]
+            ring: None,
+            #[cfg(feature = "io-uring-with-dep")]
+            next_id: 0,
+        }
+    }
+
+    pub fn options(&self) -> &ClientOptions {
+        &self.options
+    }
+
+    /// Added to every request that doesn't set the header itself,
+    /// e.g. `User-Agent` or `Accept`.
+    pub fn set_default_header(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        self.default_headers.insert(name, value);
+    }
+
+    /// Redirects followed per request, 10 by default. Once they're used up
+    /// the last redirect is returned as is, 0 returns every redirect.
+    pub fn set_max_redirects(&mut self, max: usize) {
+        self.max_redirects = max;
+    }
+
+    pub fn set_scheme_policy(&mut self, policy: SchemePolicy) {
+        self.scheme_policy = policy;
+    }
+
+    pub fn set_proxy(&mut self, proxy: Option<Proxy>) {
+        self.proxy = proxy;
+    }
+
+    /// Falls back to the next available backend if `backend` isn't, see
+    /// `Backend::or_fallback`. Requests through a proxy always use the
+    /// blocking driver.
+    pub fn set_backend(&mut self, backend: Backend) {
+        self.backend = backend.or_fallback();
+    }
+
+    /// The backend in use, after any fallback.
+    pub fn backend(&self) -> Backend {
+        self.backend
+    }
+
+    pub fn get(&mut self, url: &str) -> Result<Response> {
+        let url = Url::parse(url)?;
+        self.send(url.scheme, &url.host, url.port, Request::get(&url.path))
+    }
+
+    pub fn post(&mut self, url: &str, body: impl Into<Vec<u8>>) -> Result<Response> {
+        let url = Url::parse(url)?;
+        let mut request = Request::new(Method::Post, &url.path);
+        request.set_body(body);
+        self.send(url.scheme, &url.host, url.port, request)
+    }
+
+    /// Sends an arbitrary request, following redirects.
+    pub fn send(
+        &mut self,
+        scheme: Scheme,
+        hostname: &str,
+        port: u16,
+        request: Request,
+    ) -> Result<Response> {
+        let options = Arc::clone(&self.options);
+        self.dispatch(scheme, hostname, port, request, &options, None)
+    }
+
+    /// Like `send` but with other options for this request only,
+    /// e.g. longer timeouts for a big download.
+    pub fn send_with(
+        &mut self,
+        scheme: Scheme,
+        hostname: &str,
+        port: u16,
+        request: Request,
+        options: &ClientOptions,
+    ) -> Result<Response> {
+        self.dispatch(scheme, hostname, port, request, options, None)
+    }
+
+    /// GETs `url`, following redirects, and writes the body to `out` as it
+    /// arrives instead of collecting it in `Response::body`. A final
+    /// response other than 2xx fails with `Error::Status` (its body
+    /// dropped). Always goes through the blocking driver's pool, whatever
+    /// the backend. See `download_many` for several files at once.
+    pub fn download(&mut self, url: &str, out: &mut dyn Write) -> Result<Response> {
+        let url = Url::parse(url)?;
+        let options = Arc::clone(&self.options);
+        let request = Request::get(&url.path);
+        let response = self.dispatch(
+            url.scheme,
+            &url.host,
+            url.port,
+            request,
+            &options,
+            Some(out),
+        )?;
+        if !response.is_success() {
+            return Err(Error::Status(Box::new(response)));
+        }
+        Ok(response)
+    }
+
+    /// Downloads every file into its `Download::out`, at most
+    /// `settings.max_concurrent` at a time (each over its own connections)
+    /// and all of them together within `settings.max_bytes_per_sec`.
+    /// `on_progress` is called from the downloading threads whenever a
+    /// piece of a body was written. The results are in the order of
+    /// `downloads`, one failing doesn't stop the others.
+    pub fn download_many(
+        &self,
+        downloads: &mut [Download],
+        settings: &DownloadSettings,
+        on_progress: &(dyn Fn(Progress) + Sync),
+    ) -> Vec<Result<Response>> {
+        let rate = settings.max_bytes_per_sec.map(RateLimit::new);
+        let queue = Mutex::new(downloads.iter_mut().enumerate());
+        let results = Mutex::new(
+            std::iter::repeat_with(|| None)
+                .take(queue.lock().unwrap().len())
+                .collect::<Vec<_>>(),
+        );
+
+        std::thread::scope(|scope| {
+            for _ in 0..settings.max_concurrent.max(1) {
+                let mut client = self.worker();
+                let (queue, results, rate) = (&queue, &results, rate.as_ref());
+                scope.spawn(move || {
+                    loop {
+                        let Some((index, download)) = queue.lock().unwrap().next() else {
+                            break;
+                        };
+                        let mut out = Metered::new(index, &mut download.out, rate, on_progress);
+                        let result = client.download(&download.url, &mut out);
+                        results.lock().unwrap()[index] = Some(result);
+                    }
+                });
+            }
+        });
+
+        results
+            .into_inner()
+            .unwrap()
+            .into_iter()
+            .map(|result| result.expect("every download ran"))
+            .collect()
+    }
+
+    /// The same settings with connections of its own.
+    pub(crate) fn worker(&self) -> Self {
+        Self {
+            default_headers: self.default_headers.clone(),
+            max_redirects: self.max_redirects,
+            scheme_policy: self.scheme_policy,
+            proxy: self.proxy.clone(),
+            ..Self::new(&self.options)
+        }
+    }
+
+    fn dispatch(
+        &mut self,
+        scheme: Scheme,
+        hostname: &str,
+        port: u16,
+        mut request: Request,
+        options: &ClientOptions,
+        mut out: Option<&mut dyn Write>,
+    ) -> Result<Response> {
+        for (name, value) in self.default_headers.iter() {
+            request.add_default_header(name, value);
+        }
+        let mut url = Url {
+            scheme,
+            host: hostname.to_string(),
+            port,
+            path: request.path().to_string(),
+        };
+
+        let mut redirects = 0;
+        loop {
+            let out = out.as_mut().map(|out| &mut **out as &mut dyn Write);
+            let response = self.send_once(&url, request.clone(), options, out)?;
+
+            let redirect = Redirect::classify(request.method(), &response);
+            if redirects == self.max_redirects {
+                return Ok(response);
+            }
+            let Some(to) = redirect.target(&url, self.scheme_policy)? else {
+                return Ok(response);
+            };
+            let Redirect::Follow {
+                method, keep_body, ..
+            } = redirect
+            else {
+                return Ok(response);
+            };
+
+            request = follow(&request, &url, &to, method, keep_body);
+            url = to;
+            redirects += 1;
+        }
+    }
+
+    fn send_once(
+        &mut self,
+        url: &Url,
+        mut request: Request,
+        options: &ClientOptions,
+        out: Option<&mut dyn Write>,
+    ) -> Result<Response> {
+        request.add_default_header("Host", &url.authority());
+
+        if let Some(proxy) = &self.proxy {
+            let mut sock = tunnel(proxy, url, options)?;
+            let Some(out) = out else {
+                return BlockingConnection::send_over(
+                    &mut sock, url.scheme, &url.host, url.port, request, options,
+                );
+            };
+            let mut fsm = FSM::with_scheme(url.scheme, &url.host, request, options)?;
+            return stream_body(
+                &mut sock,
+                &mut fsm,
+                out,
+                options,
+                deadline(&options.timeouts),
+            );
+        }
+        if let Some(out) = out {
+            return self
+                .pool
+                .stream_with(url.scheme, &url.host, url.port, request, options, out);
+        }
+
+        match self.backend {
+            Backend::Blocking => self
+                .pool
+                .send_with(url.scheme, &url.host, url.port, request, options),
+            #[cfg(feature = "poll")]
+            Backend::Poll => backend::run_poll(PollConnection::new(
+                url.scheme, &url.host, url.port, request, options,
+            )?),
+            #[cfg(feature = "io-uring-with-dep")]
+            Backend::IoUring => {
+                let ring = match &mut self.ring {
+                    Some(ring) => ring,
+                    None => self.ring.insert(io_uring::IoUring::new(8)?),
+                };
+                // the closes of earlier connections complete on the same ring
+                let id = self.next_id;
+                self.next_id += 1;
+                let mut conn =
+                    IoUringConnection::new(url.scheme, &url.host, url.port, request, id, options)?;
+                conn.set_capabilities(IoUringCapabilities::detect().unwrap_or_default())?;
+                backend::run_io_uring(ring, conn, id)
+            }
+            // `set_backend` only picks compiled in ones
+            #[cfg(not(feature = "poll"))]
+            Backend::Poll => unreachable!("the poll backend isn't compiled in"),
+            #[cfg(not(feature = "io-uring-with-dep"))]
+            Backend::IoUring => unreachable!("the io_uring backend isn't compiled in"),
+        }
+    }
+}
+
+/// The request to repeat against `to`, credentials and cookies are only
+/// kept on the same origin.
+fn follow(request: &Request, from: &Url, to: &Url, method: Method, keep_body: bool) -> Request {
+    let mut next = Request::new(method, &to.path);
+    *next.headers_mut() = request.headers().clone();
+    next.headers_mut().remove("Host");
+
+    let same_origin = from.scheme == to.scheme
+        && from.host.eq_ignore_ascii_case(&to.host)
+        && from.port == to.port;
+    if !same_origin {
+        for name in ["Authorization", "Cookie"] {
+            next.headers_mut().remove(name);
+        }
+    }
+
+    if keep_body {
+        next.set_body(request.body());
+    } else {
+        for name in ["Content-Length", "Content-Type", "Content-Encoding"] {
+            next.headers_mut().remove(name);
+        }
+    }
+    next
+}
+
+/// Connects to `proxy` and asks it to open a tunnel to `url`'s host,
+/// a refusal fails with `Error::Status` holding the proxy's response.
+fn tunnel(proxy: &Proxy, url: &Url, options: &ClientOptions) -> Result<TcpStream> {
+    if !is_valid_host(&url.host) {
+        return Err(Error::InvalidUrl(redact_url(&url.to_string()).into_owned()));
+    }
+    let deadline = deadline(&options.timeouts);
+    let mut sock = net::connect(
+        &proxy.host,
+        proxy.port,
+        &options.timeouts,
+        deadline,
+        options.ip_preference,
+        options.net_observer.as_ref(),
+    )?;
+    sock.set_write_timeout(remaining(options.timeouts.write, deadline)?)?;
+
+    let authority = format!("{}:{}", host_literal(&url.host), url.port);
+    let mut head = format!("CONNECT {authority} HTTP/1.1\r\nHost: {authority}\r\n");
+    if let Some(credentials) = &proxy.credentials {
+        head.push_str(&format!(
+            "Proxy-Authorization: {}\r\n",
+            credentials.header_value()
+        ));
+    }
+    head.push_str("\r\n");
+    sock.write_all(head.as_bytes())
+        .map_err(|err| io_error(err, TimeoutKind::Write))?;
+
+    // byte by byte, whatever follows the head already belongs to the tunnel
+    let mut received = vec![];
+    let mut byte = [0];
+    while !received.ends_with(b"\r\n\r\n") {
+        if received.len() >= options.limits.max_head_size {
+            return Err(Error::LimitExceeded(LimitKind::HeadSize));
+        }
+        sock.set_read_timeout(remaining(options.timeouts.read, deadline)?)?;
+        let read = sock
+            .read(&mut byte)
+            .map_err(|err| io_error(err, TimeoutKind::Read))?;
+        if read == 0 {
+            return Err(Error::Protocol(
+                "the proxy closed the connection before answering CONNECT".to_string(),
+            ));
+        }
+        received.push(byte[0]);
+    }
+
+    let response = Response::parse(&received, options.preserve_header_case)?;
+    if !response.is_success() {
+        return Err(Error::Status(Box::new(response)));
+    }
+    Ok(sock)
+}
+
+fn io_error(err: std::io::Error, kind: TimeoutKind) -> Error {
+    if is_timeout(&err) {
+        Error::Timeout(kind)
+    } else {
+        Error::Io(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request() -> Request {
+        let mut request = Request::new(Method::Post, "/");
+        request.add_header("Authorization", "Bearer secret");
+        request.add_header("Cookie", "session=1");
+        request.add_header("Content-Type", "text/plain");
+        request.add_header("Accept", "*/*");
+        request.set_body("body");
+        request
+    }
+
+    #[test]
+    fn follow_keeps_credentials_on_the_same_origin() {
+        let from = Url::parse("https://example.com/a").unwrap();
+        let to = Url::parse("https://EXAMPLE.com/b").unwrap();
+        let next = follow(&request(), &from, &to, Method::Post, true);
+        assert_eq!(next.headers().get("Authorization"), Some("Bearer secret"));
+        assert_eq!(next.headers().get("Cookie"), Some("session=1"));
+        assert_eq!(next.body(), b"body");
+    }
+
+    #[test]
+    fn follow_strips_credentials_across_origins() {
+        let from = Url::parse("https://example.com/a").unwrap();
+        for to in [
+            "https://other.com/b",
+            "https://example.com:8443/b",
+            "http://example.com/b",
+        ] {
+            let to = Url::parse(to).unwrap();
+            let next = follow(&request(), &from, &to, Method::Post, true);
+            assert_eq!(next.headers().get("Authorization"), None);
+            assert_eq!(next.headers().get("Cookie"), None);
+            assert_eq!(next.headers().get("Accept"), Some("*/*"));
+        }
+    }
+
+    #[test]
+    fn follow_drops_the_body_and_its_headers() {
+        let from = Url::parse("https://example.com/a").unwrap();
+        let to = Url::parse("https://example.com/b").unwrap();
+        let next = follow(&request(), &from, &to, Method::Get, false);
+        assert_eq!(next.method(), &Method::Get);
+        assert!(next.body().is_empty());
+        assert_eq!(next.headers().get("Content-Type"), None);
+        assert_eq!(next.path(), "/b");
+    }
+
+    /// Answers `requests` requests, one per connection, with a body of
+    /// `len` bytes, the path's first letter repeated, or 404 for `/missing`.
+    fn file_server(requests: usize, len: usize) -> (u16, std::thread::JoinHandle<()>) {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = std::thread::spawn(move || {
+            for _ in 0..requests {
+                let (mut conn, _) = listener.accept().unwrap();
+                let head = crate::testing::read_request(&mut conn);
+                let path = head.split(|&byte| byte == b' ').nth(1).unwrap().to_vec();
+                if path == b"/missing" {
+                    conn.write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 4\r\nConnection: close\r\n\r\nnope")
+                        .unwrap();
+                    continue;
+                }
+                let head = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {len}\r\nConnection: close\r\n\r\n"
+                );
+                conn.write_all(head.as_bytes()).unwrap();
+                conn.write_all(&vec![path[1]; len]).unwrap();
+            }
+        });
+        (port, server)
+    }
+
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn download_many_writes_each_body_out() {
+        let (port, server) = file_server(4, 100_000);
+        let outs = (0..4).map(|_| Shared::default()).collect::<Vec<_>>();
+        let mut downloads = ["a", "b", "missing", "c"]
+            .iter()
+            .zip(&outs)
+            .map(|(name, out)| {
+                Download::new(format!("http://127.0.0.1:{port}/{name}"), out.clone())
+            })
+            .collect::<Vec<_>>();
+        let progress = Mutex::new(vec![0; 4]);
+
+        let results = Client::default().download_many(
+            &mut downloads,
+            &DownloadSettings {
+                max_concurrent: 2,
+                ..DownloadSettings::default()
+            },
+            &|progress_of: Progress| {
+                progress.lock().unwrap()[progress_of.index] = progress_of.received;
+            },
+        );
+        server.join().unwrap();
+
+        for (idx, name) in [(0, b'a'), (1, b'b'), (3, b'c')] {
+            assert!(results[idx].as_ref().unwrap().body.is_empty());
+            let out = outs[idx].0.lock().unwrap();
+            assert_eq!(out.len(), 100_000);
+            assert!(out.iter().all(|&byte| byte == name));
+            assert_eq!(progress.lock().unwrap()[idx], 100_000);
+        }
+        assert!(
+            matches!(&results[2], Err(Error::Status(response)) if response.status == 404),
+            "{:?}",
+            results[2].as_ref().err()
+        );
+        assert!(outs[2].0.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn download_many_shares_the_rate_limit() {
+        let (port, server) = file_server(2, 10_000);
+        let mut downloads = (0..2)
+            .map(|_| Download::new(format!("http://127.0.0.1:{port}/x"), std::io::sink()))
+            .collect::<Vec<_>>();
+
+        let started = std::time::Instant::now();
+        let results = Client::default().download_many(
+            &mut downloads,
+            &DownloadSettings {
+                max_concurrent: 2,
+                max_bytes_per_sec: Some(10_000),
+            },
+            &|_| {},
+        );
+        server.join().unwrap();
+
+        assert!(results.iter().all(Result::is_ok));
+        // a second's worth goes out at once, the rest at the rate
+        assert!(started.elapsed() >= std::time::Duration::from_millis(900));
+    }
+
+    #[test]
+    fn tunnel_rejects_hosts_that_would_break_the_connect_line() {
+        let url = Url {
+            scheme: Scheme::Https,
+            host: "example.com:443 HTTP/1.1\r\nX-Injected: 1\r\n".to_string(),
+            port: 443,
+            path: "/".to_string(),
+        };
+        // fails before connecting anywhere
+        let err = tunnel(&Proxy::new("127.0.0.1", 1), &url, &ClientOptions::default()).unwrap_err();
+        assert!(matches!(err, Error::InvalidUrl(_)), "{err:?}");
+    }
+
+    #[test]
+    fn tunnel_keeps_to_the_total_timeout() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let proxy = std::thread::spawn(move || {
+            let (mut conn, _) = listener.accept().unwrap();
+            crate::testing::read_request(&mut conn);
+            // never quite gets to the end of the head
+            for _ in 0..40 {
+                if conn.write_all(b"X").is_err() {
+                    break;
+                }
+                std::thread::sleep(std::time::Duration::from_millis(25));
+            }
+        });
+
+        let mut options = ClientOptions::default();
+        options.timeouts.read = Some(std::time::Duration::from_secs(10));
+        options.timeouts.total = Some(std::time::Duration::from_millis(200));
+        let url = Url::parse("https://example.com/").unwrap();
+        let started = std::time::Instant::now();
+        let err = tunnel(&Proxy::new("127.0.0.1", port), &url, &options).unwrap_err();
+
+        assert!(matches!(err, Error::Timeout(_)), "{err:?}");
+        assert!(started.elapsed() < std::time::Duration::from_millis(800));
+        proxy.join().unwrap();
+    }
+}
@@ -1,21 +1,210 @@
-use std::sync::{Arc, LazyLock};
+use crate::{
+    ClientIdentity, ClientOptions, Error, Pin, Result, RootCerts, pinning::PinningVerifier,
+};
+use std::{
+    collections::HashMap,
+    sync::{Arc, LazyLock, Mutex},
+};
 
-use rustls::{ClientConfig, RootCertStore, version::TLS13};
+use rustls::{
+    ClientConfig, ConfigBuilder, RootCertStore,
+    client::{Resumption, WantsClientCert, WebPkiServerVerifier, danger::ServerCertVerifier},
+    crypto::CryptoProvider,
+    pki_types::ServerName,
+    version::TLS13,
+};
 
-static ROOT_CERT_STORE: LazyLock<Arc<RootCertStore>> = LazyLock::new(|| {
+pub(crate) static ROOT_CERT_STORE: LazyLock<Arc<RootCertStore>> = LazyLock::new(|| {
     Arc::new(RootCertStore {
         roots: webpki_roots::TLS_SERVER_ROOTS.into(),
     })
 });
 
-static CLIENT_CONFIG: LazyLock<Arc<ClientConfig>> = LazyLock::new(|| {
-    Arc::new(
-        ClientConfig::builder_with_protocol_versions(&[&TLS13])
-            .with_root_certificates(Arc::clone(&*ROOT_CERT_STORE))
-            .with_no_client_auth(),
-    )
+static CLIENT_CONFIG: LazyLock<Arc<ClientConfig>> =
+    LazyLock::new(|| Arc::new(build_client_config()));
+
+static CLIENT_CONFIG_WITHOUT_SNI: LazyLock<Arc<ClientConfig>> = LazyLock::new(|| {
+    let mut config = build_client_config();
+    config.enable_sni = false;
+    Arc::new(config)
 });
 
-pub(crate) fn get_client_config() -> Arc<ClientConfig> {
-    Arc::clone(&*CLIENT_CONFIG)
+/// Configs for everything but the defaults, by what they were built from.
+/// Built once, they keep their verifier: rustls only resumes a session
+/// with the verifier (the same `Arc`) that checked the server the first
+/// time around.
+static CUSTOM_CLIENT_CONFIGS: LazyLock<Mutex<HashMap<TlsParams, Arc<ClientConfig>>>> =
+    LazyLock::new(Default::default);
+
+/// Configs past this many are rebuilt rather than cached.
+const MAX_CUSTOM_CLIENT_CONFIGS: usize = 64;
+
+/// What a connection was established with beyond its address, connections
+/// are only reused for requests that would have set them up the same way.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub(crate) struct TlsParams {
+    sni: bool,
+    alpn_protocols: Vec<Vec<u8>>,
+    resumption: bool,
+    identity: Option<ClientIdentity>,
+    pins: Vec<Pin>,
+    roots: Option<RootCerts>,
+    accepts_invalid_certs: bool,
+    /// By address, providers are the same if they're clones of each other.
+    time_provider: Option<usize>,
+}
+
+impl TlsParams {
+    pub(crate) fn of(options: &ClientOptions, host: &str) -> Self {
+        Self {
+            sni: options.sni,
+            alpn_protocols: options.alpn_protocols.clone(),
+            resumption: options.resumption,
+            identity: options.client_identity(host).cloned(),
+            pins: options.pins(host),
+            roots: options.roots.clone(),
+            accepts_invalid_certs: options.accepts_invalid_certs(),
+            time_provider: options
+                .time_provider
+                .as_ref()
+                .map(|provider| Arc::as_ptr(provider).cast::<()>() as usize),
+        }
+    }
+}
+
+pub(crate) fn base_config() -> ConfigBuilder<ClientConfig, WantsClientCert> {
+    ClientConfig::builder_with_protocol_versions(&[&TLS13])
+        .with_root_certificates(Arc::clone(&*ROOT_CERT_STORE))
+}
+
+fn build_client_config() -> ClientConfig {
+    base_config().with_no_client_auth()
+}
+
+pub(crate) fn get_client_config(
+    options: &ClientOptions,
+    server_name: &ServerName,
+) -> Result<Arc<ClientConfig>> {
+    let host = host_of(server_name);
+    let identity = options.client_identity(&host);
+    let pins = options.pins(&host);
+    let insecure = options.accepts_invalid_certs();
+    let config = match identity {
+        Some(identity) => identity.config(),
+        None if options.sni => &*CLIENT_CONFIG,
+        None => &*CLIENT_CONFIG_WITHOUT_SNI,
+    };
+
+    let sni_differs = identity.is_some() && !options.sni;
+    if options.alpn_protocols.is_empty()
+        && options.resumption
+        && !sni_differs
+        && options.time_provider.is_none()
+        && pins.is_empty()
+        && !insecure
+        && options.roots.is_none()
+    {
+        return Ok(Arc::clone(config));
+    }
+
+    let params = TlsParams::of(options, &host);
+    let mut configs = CUSTOM_CLIENT_CONFIGS
+        .lock()
+        .unwrap_or_else(|err| err.into_inner());
+    if let Some(config) = configs.get(&params) {
+        return Ok(Arc::clone(config));
+    }
+    // the config holds on to the time provider and `params` to the roots,
+    // their addresses aren't reused while they're in here
+    let config = Arc::new(custom_config(config, options, pins, insecure)?);
+    if configs.len() < MAX_CUSTOM_CLIENT_CONFIGS {
+        configs.insert(params, Arc::clone(&config));
+    }
+    Ok(config)
+}
+
+fn custom_config(
+    config: &ClientConfig,
+    options: &ClientOptions,
+    pins: Vec<Pin>,
+    insecure: bool,
+) -> Result<ClientConfig> {
+    let mut config = ClientConfig::clone(config);
+    config.enable_sni = options.sni;
+    config.alpn_protocols = options.alpn_protocols.clone();
+    if !options.resumption {
+        config.resumption = Resumption::disabled();
+    }
+    if let Some(time_provider) = &options.time_provider {
+        config.time_provider = Arc::clone(time_provider);
+    }
+    if insecure || !pins.is_empty() || options.roots.is_some() {
+        let provider = Arc::clone(config.crypto_provider());
+        let roots = (!insecure).then(|| Arc::clone(roots(options)));
+        let mut verifier = verifier(options, &provider)?;
+        if !pins.is_empty() {
+            verifier = Arc::new(PinningVerifier::new(verifier, provider, roots, pins));
+            // a resumed session skips certificate verification
+            config.resumption = Resumption::disabled();
+        }
+        config.dangerous().set_certificate_verifier(verifier);
+    }
+    Ok(config)
+}
+
+/// What checks the chain (against `ClientOptions::roots`) before any pins are.
+fn verifier(
+    options: &ClientOptions,
+    provider: &Arc<CryptoProvider>,
+) -> Result<Arc<dyn ServerCertVerifier>> {
+    #[cfg(feature = "insecure")]
+    if options.danger_accept_invalid_certs {
+        return Ok(Arc::new(crate::insecure::AcceptInvalidCerts::new(
+            Arc::clone(provider),
+        )));
+    }
+    let verifier = WebPkiServerVerifier::builder_with_provider(
+        Arc::clone(roots(options)),
+        Arc::clone(provider),
+    )
+    .build()
+    .map_err(|err| Error::InvalidRequest(format!("can't verify certificates: {err}")))?;
+    Ok(verifier)
+}
+
+fn roots(options: &ClientOptions) -> &Arc<RootCertStore> {
+    match &options.roots {
+        Some(roots) => roots.store(),
+        None => &ROOT_CERT_STORE,
+    }
+}
+
+/// The host as matched against `ClientOptions::client_identities`.
+pub(crate) fn host_of(server_name: &ServerName) -> String {
+    match server_name {
+        ServerName::DnsName(name) => name.as_ref().to_string(),
+        ServerName::IpAddress(ip) => std::net::IpAddr::from(*ip).to_string(),
+        _ => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn custom_configs_are_built_once() {
+        let mut options = ClientOptions::default();
+        options.add_root_pem(crate::testing::UNRELATED_CA).unwrap();
+        let name = ServerName::try_from("localhost").unwrap();
+
+        let first = get_client_config(&options, &name).unwrap();
+        let again = get_client_config(&options.clone(), &name).unwrap();
+        assert!(Arc::ptr_eq(&first, &again));
+
+        let mut other = ClientOptions::default();
+        other.add_root_pem(crate::testing::UNRELATED_CA).unwrap();
+        let other = get_client_config(&other, &name).unwrap();
+        assert!(!Arc::ptr_eq(&first, &other));
+    }
 }
@@ -0,0 +1,77 @@
+use crate::{Error, Result, client_config::base_config};
+use rustls::{
+    ClientConfig,
+    pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+};
+use std::{
+    hash::{Hash, Hasher},
+    sync::Arc,
+};
+
+/// A client certificate chain and its key for mutual TLS, see
+/// `ClientOptions::client_identities` for picking one per host.
+#[derive(Debug, Clone)]
+pub struct ClientIdentity {
+    /// Built once, connections using the identity share its session cache.
+    config: Arc<ClientConfig>,
+}
+
+impl ClientIdentity {
+    /// The chain starts with the client's own certificate,
+    /// fails if the key doesn't fit it or isn't supported.
+    pub fn new(
+        cert_chain: Vec<CertificateDer<'static>>,
+        key: PrivateKeyDer<'static>,
+    ) -> Result<Self> {
+        let config = base_config().with_client_auth_cert(cert_chain, key)?;
+        Ok(Self {
+            config: Arc::new(config),
+        })
+    }
+
+    /// PEM certificates (leaf first) and a PKCS#8, PKCS#1 or SEC1 PEM key.
+    pub fn from_pem(cert_chain: &[u8], key: &[u8]) -> Result<Self> {
+        let cert_chain = CertificateDer::pem_slice_iter(cert_chain)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| Error::InvalidRequest(format!("invalid client certificate: {err}")))?;
+        if cert_chain.is_empty() {
+            return Err(Error::InvalidRequest(
+                "no client certificate in the PEM".to_string(),
+            ));
+        }
+        let key = PrivateKeyDer::from_pem_slice(key)
+            .map_err(|err| Error::InvalidRequest(format!("invalid client key: {err}")))?;
+
+        Self::new(cert_chain, key)
+    }
+
+    pub(crate) fn config(&self) -> &Arc<ClientConfig> {
+        &self.config
+    }
+}
+
+/// Identities are the same if they're clones of each other.
+impl PartialEq for ClientIdentity {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.config, &other.config)
+    }
+}
+
+impl Eq for ClientIdentity {}
+
+impl Hash for ClientIdentity {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Arc::as_ptr(&self.config).hash(state);
+    }
+}
+
+/// `*.example.com` matches any subdomain of `example.com` (but not
+/// `example.com` itself), anything else only the exact host.
+pub(crate) fn host_matches(pattern: &str, host: &str) -> bool {
+    match pattern.strip_prefix("*.") {
+        Some(domain) => host.len().checked_sub(domain.len() + 1).is_some_and(|dot| {
+            host.as_bytes()[dot] == b'.' && host[dot + 1..].eq_ignore_ascii_case(domain)
+        }),
+        None => pattern.eq_ignore_ascii_case(host),
+    }
+}
@@ -0,0 +1,338 @@
+use crate::{
+    BufferConfig, ClientIdentity, Growth, IpPreference, Limits, Location, NetObserver, Pin, Result,
+    RetryPolicy, RootCerts, Timeouts, client_identity::host_matches, validation::Problems,
+};
+use rustls::time_provider::TimeProvider;
+use std::{collections::HashMap, sync::Arc, time::Duration};
+
+#[derive(Debug, Clone)]
+pub struct ClientOptions {
+    /// Send the Server Name Indication extension in the ClientHello.
+    ///
+    /// Turning it off hides the hostname from on-path observers, but the
+    /// server then has to pick a certificate without knowing which host is
+    /// being requested. The presented certificate is still verified against
+    /// the hostname, so servers hosting several names behind one address will
+    /// usually fail verification. IP address targets never carry SNI,
+    /// regardless of this setting.
+    pub sni: bool,
+
+    /// Protocols offered via ALPN, none by default. Only HTTP/1.1 is
+    /// spoken, other values are for probing servers (e.g. ACME's
+    /// `acme-tls/1`).
+    pub alpn_protocols: Vec<Vec<u8>>,
+
+    /// Resume earlier TLS sessions with the same server, on by default.
+    /// Off forces a full handshake for every connection, e.g. to check a
+    /// freshly rotated certificate.
+    pub resumption: bool,
+
+    /// Client certificates for mutual TLS by host pattern, either an exact
+    /// hostname (or IP address) or `*.example.com` for its subdomains. The
+    /// first matching entry is presented when the server asks for one,
+    /// hosts without a match connect without a certificate.
+    pub client_identities: Vec<(String, ClientIdentity)>,
+
+    /// Certificate or public key fingerprints by host pattern (as in
+    /// `client_identities`). A host with pins only connects if its chain
+    /// passes the usual verification and one of the certificates on the
+    /// path to the trusted root (the root included) matches one of the pins,
+    /// others the server sends along don't count. A certificate pin of a
+    /// root only matches if the server sends that root too, pin its key
+    /// otherwise. Connections to it never resume sessions so that the chain
+    /// is checked every time. Hosts without pins aren't affected.
+    pub pins: Vec<(String, Pin)>,
+
+    /// What server chains are verified against, the built-in Mozilla roots
+    /// when `None`. See `add_root_pem` for trusting an internal CA too.
+    pub roots: Option<RootCerts>,
+
+    /// The time certificates are checked to be valid at, the system clock
+    /// by default. For devices whose clock can't be trusted (or that have
+    /// none), e.g. time from GNSS or a trusted time server.
+    pub time_provider: Option<Arc<dyn TimeProvider>>,
+
+    pub timeouts: Timeouts,
+
+    pub limits: Limits,
+
+    /// Applied by the drivers that connect themselves, off by default.
+    pub retry: RetryPolicy,
+
+    pub buffers: BufferConfig,
+
+    /// Dials another address (an IP literal or a different hostname) instead
+    /// of the requested hostname, e.g. to reach a specific backend node.
+    /// SNI, the `Host` header and certificate verification keep using the
+    /// requested hostname.
+    pub connect_to: HashMap<String, String>,
+
+    pub ip_preference: IpPreference,
+
+    /// Told about every resolution and connect attempt, e.g. to see which
+    /// addresses `ip_preference` picked and which of them failed.
+    pub net_observer: Option<NetObserver>,
+
+    /// Accepts any certificate chain, expired, self-signed or for another
+    /// host, only the handshake signature is still checked. Anyone on the
+    /// path can impersonate the server then: for dev servers and tests,
+    /// never in production. Pins still apply but, with no verified path to
+    /// match them against, only to the server's own certificate: pinning
+    /// a self-signed certificate (or its key) trusts just that one, pins of
+    /// a CA never match.
+    #[cfg(feature = "insecure")]
+    pub danger_accept_invalid_certs: bool,
+
+    /// Permits sending TRACE requests. Off by default: TRACE echoes the
+    /// request back, credentials included, and many servers block it.
+    pub allow_trace: bool,
+
+    /// Leaves the connection open after the response so that `FSM::reuse`
+    /// can send another request over it. When off, requests carry
+    /// `Connection: close`.
+    pub keep_alive: bool,
+
+    /// How much of a response body `FSM::abort_response` still reads (and
+    /// throws away) to keep a kept-alive connection reusable, bodies with
+    /// more left than that close it instead.
+    pub drain_budget: usize,
+
+    /// Records what the FSM asks for and what it's told back into
+    /// `Response::trace`, off by default. See `Trace`.
+    pub trace: bool,
+
+    /// Keeps response header names as the server sent them, see
+    /// `Headers::preserving_case`. Off by default, names are lowercased.
+    pub preserve_header_case: bool,
+}
+
+impl Default for ClientOptions {
+    fn default() -> Self {
+        Self {
+            sni: true,
+            alpn_protocols: vec![],
+            resumption: true,
+            client_identities: vec![],
+            pins: vec![],
+            roots: None,
+            time_provider: None,
+            timeouts: Timeouts::default(),
+            limits: Limits::default(),
+            retry: RetryPolicy::default(),
+            buffers: BufferConfig::default(),
+            connect_to: HashMap::new(),
+            ip_preference: IpPreference::default(),
+            net_observer: None,
+            #[cfg(feature = "insecure")]
+            danger_accept_invalid_certs: false,
+            allow_trace: false,
+            keep_alive: false,
+            drain_budget: 64 * 1024,
+            trace: false,
+            preserve_header_case: false,
+        }
+    }
+}
+
+impl ClientOptions {
+    #[cfg_attr(
+        not(any(
+            feature = "blocking",
+            feature = "poll",
+            feature = "io-uring",
+            feature = "tokio",
+            feature = "mio",
+            feature = "epoll"
+        )),
+        allow(dead_code)
+    )]
+    pub(crate) fn connect_host<'a>(&'a self, hostname: &'a str) -> &'a str {
+        self.connect_to
+            .get(hostname)
+            .map_or(hostname, String::as_str)
+    }
+
+    /// Reports every conflicting setting in `Error::Validation`,
+    /// FSMs check this when they're created.
+    pub fn validate(&self) -> Result<()> {
+        let mut problems = Problems::default();
+        self.check(&mut problems);
+        problems.into_result()
+    }
+
+    pub(crate) fn check(&self, problems: &mut Problems) {
+        let timeouts = [
+            ("timeouts.connect", self.timeouts.connect),
+            ("timeouts.read", self.timeouts.read),
+            ("timeouts.write", self.timeouts.write),
+            ("timeouts.total", self.timeouts.total),
+        ];
+        for (name, timeout) in timeouts {
+            if timeout.is_some_and(|timeout| timeout.is_zero()) {
+                problems.push(
+                    Location::Option(name),
+                    "a zero timeout would expire at once, use None for no timeout",
+                );
+            }
+        }
+
+        let buffers = &self.buffers;
+        if buffers
+            .max_incoming
+            .is_some_and(|max| max < buffers.initial_incoming)
+        {
+            problems.push(
+                Location::Option("buffers.max_incoming"),
+                "is below buffers.initial_incoming",
+            );
+        }
+        if buffers
+            .max_outgoing
+            .is_some_and(|max| max < buffers.initial_outgoing)
+        {
+            problems.push(
+                Location::Option("buffers.max_outgoing"),
+                "is below buffers.initial_outgoing",
+            );
+        }
+
+        let limits = &self.limits;
+        if limits.max_status_line_length > limits.max_head_size {
+            problems.push(
+                Location::Option("limits.max_status_line_length"),
+                "is above limits.max_head_size",
+            );
+        }
+        if limits.max_header_size > limits.max_head_size {
+            problems.push(
+                Location::Option("limits.max_header_size"),
+                "is above limits.max_head_size",
+            );
+        }
+
+        for (pattern, _) in &self.client_identities {
+            if pattern.is_empty() || pattern == "*." {
+                problems.push(
+                    Location::Option("client_identities"),
+                    format!("{pattern:?} matches no host"),
+                );
+            }
+        }
+        for (pattern, _) in &self.pins {
+            if pattern.is_empty() || pattern == "*." {
+                problems.push(
+                    Location::Option("pins"),
+                    format!("{pattern:?} matches no host"),
+                );
+            }
+        }
+    }
+
+    pub(crate) fn client_identity(&self, host: &str) -> Option<&ClientIdentity> {
+        self.client_identities
+            .iter()
+            .find(|(pattern, _)| host_matches(pattern, host))
+            .map(|(_, identity)| identity)
+    }
+
+    /// Turns on `danger_accept_invalid_certs`, see there.
+    #[cfg(feature = "insecure")]
+    pub fn danger_accept_invalid_certs(mut self) -> Self {
+        self.danger_accept_invalid_certs = true;
+        self
+    }
+
+    /// Trusts the certificates of a PEM bundle on top of `roots` (or the
+    /// built-in roots).
+    pub fn add_root_pem(&mut self, pem: &[u8]) -> Result<()> {
+        self.roots.get_or_insert_with(RootCerts::new).add_pem(pem)
+    }
+
+    pub(crate) fn accepts_invalid_certs(&self) -> bool {
+        #[cfg(feature = "insecure")]
+        return self.danger_accept_invalid_certs;
+        #[cfg(not(feature = "insecure"))]
+        false
+    }
+
+    pub(crate) fn pins(&self, host: &str) -> Vec<Pin> {
+        self.pins
+            .iter()
+            .filter(|(pattern, _)| host_matches(pattern, host))
+            .map(|(_, pin)| *pin)
+            .collect()
+    }
+
+    /// For constrained devices: everything is bounded so that a stuck
+    /// connection releases its memory quickly.
+    pub fn low_memory() -> Self {
+        Self {
+            timeouts: Timeouts {
+                connect: Some(Duration::from_secs(10)),
+                read: Some(Duration::from_secs(15)),
+                write: Some(Duration::from_secs(15)),
+                total: Some(Duration::from_secs(60)),
+            },
+            limits: Limits {
+                max_head_size: 16 * 1024,
+                max_status_line_length: 1024,
+                max_header_size: 8 * 1024,
+                max_header_count: 32,
+                max_body_size: Some(1024 * 1024),
+            },
+            buffers: BufferConfig {
+                initial_incoming: 17 * 1024,
+                initial_outgoing: 1024,
+                max_incoming: Some(64 * 1024),
+                max_outgoing: Some(64 * 1024),
+                growth: Growth::Linear(16 * 1024),
+                compact_incoming_after: 0,
+                initial_scratch: 0,
+                retain_scratch: 16 * 1024,
+            },
+            ..Self::default()
+        }
+    }
+
+    /// For large downloads: generous per-operation timeouts and no cap
+    /// on the total duration of a request.
+    pub fn high_throughput() -> Self {
+        Self {
+            timeouts: Timeouts {
+                connect: Some(Duration::from_secs(10)),
+                read: Some(Duration::from_secs(60)),
+                write: Some(Duration::from_secs(60)),
+                total: None,
+            },
+            buffers: BufferConfig {
+                initial_incoming: 64 * 1024,
+                initial_outgoing: 16 * 1024,
+                growth: Growth::Double,
+                ..BufferConfig::default()
+            },
+            ..Self::default()
+        }
+    }
+
+    /// For talking to untrusted servers: tight timeouts so that a
+    /// slow-dripping peer can't hold a connection open indefinitely.
+    pub fn strict_security() -> Self {
+        Self {
+            sni: true,
+            timeouts: Timeouts {
+                connect: Some(Duration::from_secs(5)),
+                read: Some(Duration::from_secs(10)),
+                write: Some(Duration::from_secs(10)),
+                total: Some(Duration::from_secs(30)),
+            },
+            limits: Limits {
+                max_head_size: 32 * 1024,
+                max_status_line_length: 1024,
+                max_header_size: 16 * 1024,
+                max_header_count: 64,
+                max_body_size: Some(16 * 1024 * 1024),
+            },
+            ..Self::default()
+        }
+    }
+}
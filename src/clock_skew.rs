@@ -0,0 +1,169 @@
+use crate::{Response, headers};
+use std::time::{Duration, SystemTime};
+
+/// Offset between the local clock and a server's, for signing schemes that
+/// reject stale timestamps (e.g. S3's `RequestTimeTooSkewed`).
+///
+/// Sign with `now()` instead of `SystemTime::now()`; when the server refuses
+/// the request with 403 pass the response to `update` and, if it returns
+/// `true`, sign and send the request once more.
+#[derive(Debug, Clone, Copy)]
+pub struct ClockSkew {
+    /// Server time minus local time, in seconds.
+    offset: i64,
+    tolerance: Duration,
+}
+
+impl Default for ClockSkew {
+    fn default() -> Self {
+        Self {
+            offset: 0,
+            tolerance: Duration::from_secs(60),
+        }
+    }
+}
+
+impl ClockSkew {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Starts from a known offset, e.g. one kept from a previous run.
+    pub fn with_offset(offset: i64) -> Self {
+        Self {
+            offset,
+            ..Self::default()
+        }
+    }
+
+    /// Differences below it aren't worth a retry, 1 minute by default.
+    pub fn set_tolerance(&mut self, tolerance: Duration) {
+        self.tolerance = tolerance;
+    }
+
+    /// Server time minus local time, in seconds.
+    pub fn offset(&self) -> i64 {
+        self.offset
+    }
+
+    /// The local time moved to the server's clock.
+    pub fn now(&self) -> SystemTime {
+        self.adjust(SystemTime::now())
+    }
+
+    pub fn adjust(&self, local: SystemTime) -> SystemTime {
+        let offset = Duration::from_secs(self.offset.unsigned_abs());
+        if self.offset >= 0 {
+            local + offset
+        } else {
+            local - offset
+        }
+    }
+
+    /// Learns the offset from the `Date` of a 403 response, `true` means it
+    /// has changed by more than the tolerance and a retry may succeed.
+    pub fn update(&mut self, response: &Response) -> bool {
+        if response.status != 403 {
+            return false;
+        }
+        let Some(server) = response.headers.get("Date").and_then(headers::date::parse) else {
+            return false;
+        };
+
+        let local = SystemTime::now();
+        let offset = match server.duration_since(local) {
+            Ok(ahead) => ahead.as_secs() as i64,
+            Err(behind) => -(behind.duration().as_secs() as i64),
+        };
+        if offset.abs_diff(self.offset) <= self.tolerance.as_secs() {
+            return false;
+        }
+        self.offset = offset;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(status: u16, server_ahead: i64) -> Response {
+        let offset = Duration::from_secs(server_ahead.unsigned_abs());
+        let server = if server_ahead >= 0 {
+            SystemTime::now() + offset
+        } else {
+            SystemTime::now() - offset
+        };
+        let head = format!(
+            "HTTP/1.1 {status} Status\r\nDate: {}\r\n\r\n",
+            headers::date::format(server)
+        );
+        Response::parse(head.as_bytes(), false).unwrap()
+    }
+
+    #[test]
+    fn adjusts_both_ways() {
+        let local = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000);
+        assert_eq!(
+            ClockSkew::with_offset(300).adjust(local),
+            local + Duration::from_secs(300)
+        );
+        assert_eq!(
+            ClockSkew::with_offset(-300).adjust(local),
+            local - Duration::from_secs(300)
+        );
+        assert_eq!(ClockSkew::new().adjust(local), local);
+    }
+
+    #[test]
+    fn learns_a_server_ahead() {
+        let mut skew = ClockSkew::new();
+        assert!(skew.update(&response(403, 3600)));
+        // the Date has whole seconds
+        assert!((3598..=3600).contains(&skew.offset()), "{}", skew.offset());
+        assert!(skew.now() > SystemTime::now() + Duration::from_secs(3500));
+        // nothing new the second time
+        assert!(!skew.update(&response(403, 3600)));
+    }
+
+    #[test]
+    fn learns_a_server_behind() {
+        let mut skew = ClockSkew::new();
+        assert!(skew.update(&response(403, -3600)));
+        assert!(
+            (-3601..=-3599).contains(&skew.offset()),
+            "{}",
+            skew.offset()
+        );
+        assert!(skew.now() < SystemTime::now() - Duration::from_secs(3500));
+    }
+
+    #[test]
+    fn ignores_differences_within_the_tolerance() {
+        let mut skew = ClockSkew::new();
+        assert!(!skew.update(&response(403, 30)));
+        assert_eq!(skew.offset(), 0);
+
+        skew.set_tolerance(Duration::from_secs(10));
+        assert!(skew.update(&response(403, 30)));
+
+        let mut skew = ClockSkew::with_offset(-100);
+        assert!(!skew.update(&response(403, -120)));
+        assert_eq!(skew.offset(), -100);
+        assert!(skew.update(&response(403, 100)));
+    }
+
+    #[test]
+    fn ignores_other_responses() {
+        let mut skew = ClockSkew::new();
+        for status in [200, 400, 401, 503] {
+            assert!(!skew.update(&response(status, 3600)));
+        }
+        let without_date = Response::parse(b"HTTP/1.1 403 Forbidden\r\n\r\n", false).unwrap();
+        assert!(!skew.update(&without_date));
+        let bad_date =
+            Response::parse(b"HTTP/1.1 403 Forbidden\r\nDate: yesterday\r\n\r\n", false).unwrap();
+        assert!(!skew.update(&bad_date));
+        assert_eq!(skew.offset(), 0);
+    }
+}
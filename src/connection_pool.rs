@@ -0,0 +1,546 @@
+use crate::{
+    AdaptiveBuffers, BlockingConnection, ClientOptions, Error, FSM, IpPreference, Request,
+    Response, Result, Scheme, Url, blocking_connection::stream_body, client_config::TlsParams,
+    deadline::deadline, net, url::host_header,
+};
+use std::{
+    collections::HashMap,
+    io::{ErrorKind, Write},
+    net::TcpStream,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+type Key = (Scheme, String, u16, Dial, TlsParams);
+type Host = (Scheme, String, u16);
+
+/// Where a connection was dialed, connections to one host aren't reused
+/// for requests that would have picked another node of it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Dial {
+    /// From `connect_to`, `None` when the hostname itself was resolved.
+    connect_to: Option<String>,
+    ip_preference: IpPreference,
+}
+
+impl Dial {
+    fn of(options: &ClientOptions, hostname: &str) -> Self {
+        Self {
+            connect_to: options.connect_to.get(hostname).cloned(),
+            ip_preference: options.ip_preference,
+        }
+    }
+}
+
+struct Idle {
+    fsm: FSM,
+    sock: TcpStream,
+    since: Instant,
+    opened: Instant,
+    /// Sent over it so far.
+    requests: u64,
+}
+
+/// What the pool holds and has done for one (scheme, host, port), see
+/// `ConnectionPool::stats`. The counters run since the pool was created.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HostStats {
+    pub scheme: Scheme,
+    pub host: String,
+    pub port: u16,
+    /// Most recently used last, the next request takes that one.
+    pub idle: Vec<IdleStats>,
+    /// New connections, retries included.
+    pub opened: u64,
+    /// Requests sent over an idle connection.
+    pub reused: u64,
+    /// Reused connections that turned out to be closed by the server, the
+    /// request went out again over a new one.
+    pub retried: u64,
+    /// Connections closed by the pool: failed, not reusable after their
+    /// response, over `set_max_idle_per_host`, expired or found dead.
+    pub closed: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IdleStats {
+    /// Since the connection was opened.
+    pub age: Duration,
+    /// Since its last response.
+    pub idle_for: Duration,
+    pub requests: u64,
+}
+
+#[derive(Default)]
+struct Counters {
+    opened: u64,
+    reused: u64,
+    retried: u64,
+    closed: u64,
+}
+
+/// Keeps connections open between requests to the same host, on top of
+/// `BlockingConnection`. `keep_alive` is always on for pooled requests.
+///
+/// New connections get their buffers sized by `AdaptiveBuffers` from what
+/// earlier exchanges with the host needed, `options.buffers` only bounds
+/// them (and sizes connections to hosts not seen yet).
+pub struct ConnectionPool {
+    options: Arc<ClientOptions>,
+    max_idle_per_host: usize,
+    idle_timeout: Duration,
+    idle: HashMap<Key, Vec<Idle>>,
+    counters: HashMap<Host, Counters>,
+    buffers: Option<AdaptiveBuffers>,
+}
+
+impl ConnectionPool {
+    pub fn new(options: &ClientOptions) -> Self {
+        Self {
+            options: pooled(options),
+            max_idle_per_host: 4,
+            idle_timeout: Duration::from_secs(90),
+            idle: HashMap::new(),
+            counters: HashMap::new(),
+            buffers: Some(AdaptiveBuffers::new()),
+        }
+    }
+
+    /// Turns the per-host buffer sizing off (or back on), with it off every
+    /// connection is set up with `options.buffers` as is.
+    pub fn set_adaptive_buffers(&mut self, enabled: bool) {
+        self.buffers = enabled.then(|| self.buffers.take().unwrap_or_default());
+    }
+
+    /// How many idle connections to keep per (scheme, host, port),
+    /// connections over the limit are closed once their response is read.
+    pub fn set_max_idle_per_host(&mut self, max: usize) {
+        self.max_idle_per_host = max;
+    }
+
+    /// Idle connections older than this are closed instead of reused,
+    /// servers usually drop them after a similar period anyway.
+    pub fn set_idle_timeout(&mut self, timeout: Duration) {
+        self.idle_timeout = timeout;
+    }
+
+    pub fn get_url(&mut self, url: &str) -> Result<Response> {
+        let url = Url::parse(url)?;
+        self.send(url.scheme, &url.host, url.port, url.get_request())
+    }
+
+    /// Sends `request` over an idle connection to the host if there's one
+    /// that's still alive, and over a new one otherwise. If the idle one
+    /// fails before any of the response arrived (the server closed it in the
+    /// meantime), idempotent requests are sent again over a new one.
+    pub fn send(
+        &mut self,
+        scheme: Scheme,
+        hostname: &str,
+        port: u16,
+        request: Request,
+    ) -> Result<Response> {
+        let options = Arc::clone(&self.options);
+        self.dispatch(scheme, hostname, port, request, &options, None)
+    }
+
+    /// Like `send` but with other options for this request only, e.g. TLS
+    /// settings such as `resumption` or `alpn_protocols`. Idle connections
+    /// set up with different TLS settings, or dialed through another
+    /// `connect_to` address or `ip_preference`, aren't reused for it. The
+    /// rest (limits, `trace`...) applies to a reused one too, see
+    /// `FSM::reuse_with`.
+    pub fn send_with(
+        &mut self,
+        scheme: Scheme,
+        hostname: &str,
+        port: u16,
+        request: Request,
+        options: &ClientOptions,
+    ) -> Result<Response> {
+        let options = pooled(options);
+        self.dispatch(scheme, hostname, port, request, &options, None)
+    }
+
+    /// Like `send_with` but writes the body of a 2xx response to `out` as
+    /// it arrives, see `blocking_connection::stream_body`.
+    pub(crate) fn stream_with(
+        &mut self,
+        scheme: Scheme,
+        hostname: &str,
+        port: u16,
+        request: Request,
+        options: &ClientOptions,
+        out: &mut dyn Write,
+    ) -> Result<Response> {
+        let options = pooled(options);
+        self.dispatch(scheme, hostname, port, request, &options, Some(out))
+    }
+
+    fn dispatch(
+        &mut self,
+        scheme: Scheme,
+        hostname: &str,
+        port: u16,
+        request: Request,
+        options: &ClientOptions,
+        mut out: Option<&mut dyn Write>,
+    ) -> Result<Response> {
+        self.evict_expired();
+
+        let key = (
+            scheme,
+            hostname.to_string(),
+            port,
+            Dial::of(options, hostname),
+            TlsParams::of(options, hostname),
+        );
+        let deadline = deadline(&options.timeouts);
+
+        if let Some(Idle {
+            mut fsm,
+            mut sock,
+            opened,
+            requests,
+            ..
+        }) = self.checkout(&key)
+        {
+            self.counters(&key).reused += 1;
+            let mut reused = request.clone();
+            reused.add_default_header("Host", &host_header(scheme, hostname, port));
+            fsm.reuse_with(reused, options)?;
+            let response = exchange(
+                &mut sock,
+                &mut fsm,
+                out.as_mut().map(|out| &mut **out as &mut dyn Write),
+                options,
+                deadline,
+            );
+            match response {
+                Ok(response) => {
+                    return Ok(self.checkin(key, fsm, sock, opened, requests + 1, response));
+                }
+                // the server closed it before reading the request, try
+                // again on a new connection if that's safe
+                Err(err)
+                    if !matches!(err, Error::Timeout(_))
+                        && request.method().is_idempotent()
+                        && !fsm.has_received_response() =>
+                {
+                    let counters = self.counters(&key);
+                    counters.retried += 1;
+                    counters.closed += 1;
+                }
+                Err(err) => {
+                    self.counters(&key).closed += 1;
+                    return Err(err);
+                }
+            }
+        }
+
+        let mut fsm = match &self.buffers {
+            Some(buffers) => {
+                let options = ClientOptions {
+                    buffers: buffers.config(scheme, hostname, port, &options.buffers),
+                    ..options.clone()
+                };
+                BlockingConnection::fsm(scheme, hostname, port, request, &options)?
+            }
+            None => BlockingConnection::fsm(scheme, hostname, port, request, options)?,
+        };
+        let mut sock = net::connect(
+            options.connect_host(hostname),
+            port,
+            &options.timeouts,
+            deadline,
+            options.ip_preference,
+            options.net_observer.as_ref(),
+        )?;
+        let opened = Instant::now();
+        self.counters(&key).opened += 1;
+
+        match exchange(&mut sock, &mut fsm, out, options, deadline) {
+            Ok(response) => Ok(self.checkin(key, fsm, sock, opened, 1, response)),
+            Err(err) => {
+                self.counters(&key).closed += 1;
+                Err(err)
+            }
+        }
+    }
+
+    /// Keeps the connection for the next request to the host if it can be
+    /// reused and there's room.
+    fn checkin(
+        &mut self,
+        key: Key,
+        fsm: FSM,
+        sock: TcpStream,
+        opened: Instant,
+        requests: u64,
+        response: Response,
+    ) -> Response {
+        let (scheme, hostname, port, _, _) = &key;
+        if let Some(buffers) = &mut self.buffers {
+            buffers.record(*scheme, hostname, *port, &response);
+        }
+
+        let idle = self.idle.entry(key.clone()).or_default();
+        if fsm.is_reusable() && idle.len() < self.max_idle_per_host {
+            idle.push(Idle {
+                fsm,
+                sock,
+                since: Instant::now(),
+                opened,
+                requests,
+            });
+        } else {
+            self.counters(&key).closed += 1;
+        }
+
+        response
+    }
+
+    /// What the pool holds and has done per host, ordered by host and port.
+    pub fn stats(&self) -> Vec<HostStats> {
+        let now = Instant::now();
+        let mut hosts: HashMap<&Host, HostStats> = HashMap::new();
+        for (host @ (scheme, hostname, port), counters) in &self.counters {
+            hosts.insert(
+                host,
+                HostStats {
+                    scheme: *scheme,
+                    host: hostname.clone(),
+                    port: *port,
+                    idle: vec![],
+                    opened: counters.opened,
+                    reused: counters.reused,
+                    retried: counters.retried,
+                    closed: counters.closed,
+                },
+            );
+        }
+        for ((scheme, hostname, port, _, _), idle) in &self.idle {
+            let host = (*scheme, hostname.clone(), *port);
+            let Some(stats) = hosts.get_mut(&host) else {
+                continue;
+            };
+            stats.idle.extend(idle.iter().map(|conn| IdleStats {
+                age: now.duration_since(conn.opened),
+                idle_for: now.duration_since(conn.since),
+                requests: conn.requests,
+            }));
+        }
+
+        let mut hosts = hosts.into_values().collect::<Vec<_>>();
+        for stats in &mut hosts {
+            // connections set up with different settings are mixed
+            stats
+                .idle
+                .sort_by_key(|conn| std::cmp::Reverse(conn.idle_for));
+        }
+        hosts.sort_by(|a, b| {
+            (&a.host, a.port, a.scheme.as_str()).cmp(&(&b.host, b.port, b.scheme.as_str()))
+        });
+        hosts
+    }
+
+    fn counters(&mut self, key: &Key) -> &mut Counters {
+        let (scheme, hostname, port, _, _) = key;
+        self.counters
+            .entry((*scheme, hostname.clone(), *port))
+            .or_default()
+    }
+
+    /// Closes every idle connection.
+    pub fn clear(&mut self) {
+        for (key, idle) in std::mem::take(&mut self.idle) {
+            self.counters(&key).closed += idle.len() as u64;
+        }
+    }
+
+    fn checkout(&mut self, key: &Key) -> Option<Idle> {
+        let idle = self.idle.get_mut(key)?;
+        let mut dead = 0;
+        // most recently used first, it's the least likely to be closed by now
+        let found = std::iter::from_fn(|| idle.pop()).find(|conn| {
+            let alive = is_alive(&conn.sock);
+            dead += u64::from(!alive);
+            alive
+        });
+        self.counters(key).closed += dead;
+        found
+    }
+
+    fn evict_expired(&mut self) {
+        let now = Instant::now();
+        for ((scheme, hostname, port, _, _), idle) in &mut self.idle {
+            let before = idle.len();
+            idle.retain(|conn| now.duration_since(conn.since) < self.idle_timeout);
+            let expired = (before - idle.len()) as u64;
+            if expired > 0 {
+                self.counters
+                    .entry((*scheme, hostname.clone(), *port))
+                    .or_default()
+                    .closed += expired;
+            }
+        }
+        self.idle.retain(|_, idle| !idle.is_empty());
+    }
+}
+
+fn exchange(
+    sock: &mut TcpStream,
+    fsm: &mut FSM,
+    out: Option<&mut dyn Write>,
+    options: &ClientOptions,
+    deadline: Option<Instant>,
+) -> Result<Response> {
+    match out {
+        Some(out) => stream_body(sock, fsm, out, options, deadline),
+        None => BlockingConnection::exchange(sock, fsm, None, options, deadline),
+    }
+}
+
+fn pooled(options: &ClientOptions) -> Arc<ClientOptions> {
+    Arc::new(ClientOptions {
+        keep_alive: true,
+        ..options.clone()
+    })
+}
+
+/// An idle connection has nothing to read: EOF means the peer has closed it,
+/// and any data (e.g. a TLS alert) means it's about to.
+fn is_alive(sock: &TcpStream) -> bool {
+    if sock.set_nonblocking(true).is_err() {
+        return false;
+    }
+    let alive = matches!(sock.peek(&mut [0; 1]), Err(err) if err.kind() == ErrorKind::WouldBlock);
+    sock.set_nonblocking(false).is_ok() && alive
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::read_request;
+    use std::{io::Write, net::TcpListener, thread};
+
+    #[test]
+    fn retries_on_a_new_connection_when_the_idle_one_was_closed() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let (mut first, _) = listener.accept().unwrap();
+            read_request(&mut first);
+            first
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nfirst")
+                .unwrap();
+            // closed once the next request arrives, without an answer
+            read_request(&mut first);
+            drop(first);
+
+            let (mut second, _) = listener.accept().unwrap();
+            read_request(&mut second);
+            second
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 6\r\n\r\nsecond")
+                .unwrap();
+        });
+
+        let mut pool = ConnectionPool::new(&ClientOptions::default());
+        let first = pool
+            .send(Scheme::Http, "127.0.0.1", port, Request::get("/"))
+            .unwrap();
+        assert_eq!(first.body, b"first");
+        let second = pool
+            .send(Scheme::Http, "127.0.0.1", port, Request::get("/"))
+            .unwrap();
+        assert_eq!(second.body, b"second");
+        server.join().unwrap();
+
+        let [stats] = &pool.stats()[..] else {
+            panic!("one host");
+        };
+        assert_eq!(
+            (stats.opened, stats.reused, stats.retried, stats.closed),
+            (2, 1, 1, 1)
+        );
+        assert_eq!(stats.idle.len(), 1);
+        assert_eq!(stats.idle[0].requests, 1);
+    }
+
+    #[test]
+    fn stats_count_reuses() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let (mut conn, _) = listener.accept().unwrap();
+            for _ in 0..3 {
+                read_request(&mut conn);
+                conn.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok")
+                    .unwrap();
+            }
+        });
+
+        let mut pool = ConnectionPool::new(&ClientOptions::default());
+        assert!(pool.stats().is_empty());
+        for _ in 0..3 {
+            pool.send(Scheme::Http, "127.0.0.1", port, Request::get("/"))
+                .unwrap();
+        }
+        server.join().unwrap();
+
+        let stats = pool.stats();
+        assert_eq!(stats.len(), 1);
+        assert_eq!((stats[0].host.as_str(), stats[0].port), ("127.0.0.1", port));
+        assert_eq!(
+            (stats[0].opened, stats[0].reused, stats[0].closed),
+            (1, 2, 0)
+        );
+        assert_eq!(stats[0].idle.len(), 1);
+        assert_eq!(stats[0].idle[0].requests, 3);
+        assert!(stats[0].idle[0].age >= stats[0].idle[0].idle_for);
+
+        pool.clear();
+        let stats = pool.stats();
+        assert!(stats[0].idle.is_empty());
+        assert_eq!(stats[0].closed, 1);
+    }
+
+    #[test]
+    fn connections_to_another_node_are_not_reused() {
+        let listener = TcpListener::bind("0.0.0.0:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let mut conns = vec![];
+            for _ in 0..2 {
+                let (mut conn, _) = listener.accept().unwrap();
+                read_request(&mut conn);
+                conn.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok")
+                    .unwrap();
+                conns.push(conn);
+            }
+        });
+
+        let mut pool = ConnectionPool::new(&ClientOptions::default());
+        for node in ["127.0.0.1", "127.0.0.2"] {
+            let options = ClientOptions {
+                connect_to: HashMap::from([("backend.test".to_string(), node.to_string())]),
+                ..ClientOptions::default()
+            };
+            pool.send_with(
+                Scheme::Http,
+                "backend.test",
+                port,
+                Request::get("/"),
+                &options,
+            )
+            .unwrap();
+        }
+        server.join().unwrap();
+
+        let [stats] = &pool.stats()[..] else {
+            panic!("one host");
+        };
+        assert_eq!((stats.opened, stats.reused), (2, 0));
+        assert_eq!(stats.idle.len(), 2);
+    }
+}
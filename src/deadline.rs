@@ -0,0 +1,35 @@
+use crate::{Error, Result, TimeoutKind, Timeouts};
+use std::time::{Duration, Instant};
+
+pub(crate) fn deadline(timeouts: &Timeouts) -> Option<Instant> {
+    timeouts.total.map(|total| Instant::now() + total)
+}
+
+/// Narrows `timeout` down to whatever is left until `deadline`,
+/// failing if the deadline has already passed.
+#[cfg_attr(
+    not(any(
+        feature = "blocking",
+        feature = "poll",
+        feature = "io-uring",
+        feature = "tokio",
+        feature = "mio",
+        feature = "epoll"
+    )),
+    allow(dead_code)
+)]
+pub(crate) fn remaining(
+    timeout: Option<Duration>,
+    deadline: Option<Instant>,
+) -> Result<Option<Duration>> {
+    let Some(deadline) = deadline else {
+        return Ok(timeout);
+    };
+
+    let left = deadline
+        .checked_duration_since(Instant::now())
+        .filter(|left| !left.is_zero())
+        .ok_or(Error::Timeout(TimeoutKind::Total))?;
+
+    Ok(Some(timeout.map_or(left, |timeout| timeout.min(left))))
+}
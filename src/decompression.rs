@@ -0,0 +1,87 @@
+use crate::{Error, LimitKind, Result};
+use brotli_decompressor::Decompressor;
+use flate2::read::{DeflateDecoder, MultiGzDecoder, ZlibDecoder};
+use std::io::Read;
+
+pub(crate) const ACCEPT_ENCODING: &str = "gzip, deflate, br";
+
+pub(crate) fn is_supported(encodings: &str) -> bool {
+    encodings.split(',').all(|encoding| {
+        matches!(
+            encoding.trim().to_ascii_lowercase().as_str(),
+            "" | "identity" | "gzip" | "x-gzip" | "deflate" | "br"
+        )
+    })
+}
+
+/// Undoes every coding listed in `encodings`,
+/// which are listed in the order they were applied.
+/// No output may get over `max` bytes, so that a small bomb can't take
+/// all the memory.
+pub(crate) fn decompress(
+    encodings: &str,
+    mut body: Vec<u8>,
+    max: Option<usize>,
+) -> Result<Vec<u8>> {
+    for encoding in encodings.split(',').rev() {
+        body = match encoding.trim().to_ascii_lowercase().as_str() {
+            "gzip" | "x-gzip" => read_all(MultiGzDecoder::new(body.as_slice()), max)?,
+            // "deflate" is supposed to be zlib-wrapped,
+            // but plenty of servers send a raw deflate stream
+            "deflate" => match read_all(ZlibDecoder::new(body.as_slice()), max) {
+                Err(Error::Parse(_)) => read_all(DeflateDecoder::new(body.as_slice()), max)?,
+                decoded => decoded?,
+            },
+            "br" => read_all(Decompressor::new(body.as_slice(), 4096), max)?,
+            _ => body,
+        };
+    }
+    Ok(body)
+}
+
+fn read_all(reader: impl Read, max: Option<usize>) -> Result<Vec<u8>> {
+    let limit = max.map_or(u64::MAX, |max| max as u64 + 1);
+    let mut out = vec![];
+    reader
+        .take(limit)
+        .read_to_end(&mut out)
+        .map_err(|err| Error::Parse(format!("failed to decompress response body: {err}")))?;
+    if max.is_some_and(|max| out.len() > max) {
+        return Err(Error::LimitExceeded(LimitKind::BodySize));
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{Compression, write::GzEncoder};
+    use std::io::Write;
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(vec![], Compression::best());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn bounds_the_output() {
+        let bomb = gzip(&vec![0; 10 * 1024 * 1024]);
+        assert!(bomb.len() < 64 * 1024);
+
+        let err = decompress("gzip", bomb, Some(1024 * 1024)).unwrap_err();
+        assert!(matches!(err, Error::LimitExceeded(LimitKind::BodySize)));
+    }
+
+    #[test]
+    fn output_at_the_limit() {
+        let body = decompress("gzip", gzip(b"hello"), Some(5)).unwrap();
+        assert_eq!(body, b"hello");
+    }
+
+    #[test]
+    fn corrupt_input() {
+        let err = decompress("gzip", b"not gzip".to_vec(), None).unwrap_err();
+        assert!(matches!(err, Error::Parse(_)), "{err:?}");
+    }
+}
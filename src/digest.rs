@@ -0,0 +1,174 @@
+use crate::base64;
+use md5::Md5;
+use sha2::{Digest as _, Sha256, Sha512};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DigestAlgorithm {
+    /// `Repr-Digest: sha-256=:...:`
+    Sha256,
+    /// `Repr-Digest: sha-512=:...:`
+    Sha512,
+    /// `Content-MD5: ...`, still required by some storage APIs
+    Md5,
+}
+
+enum BodyHasher {
+    Sha256(Sha256),
+    Sha512(Sha512),
+    Md5(Md5),
+}
+
+impl BodyHasher {
+    fn new(algorithm: DigestAlgorithm) -> Self {
+        match algorithm {
+            DigestAlgorithm::Sha256 => Self::Sha256(Sha256::new()),
+            DigestAlgorithm::Sha512 => Self::Sha512(Sha512::new()),
+            DigestAlgorithm::Md5 => Self::Md5(Md5::new()),
+        }
+    }
+
+    fn update(&mut self, chunk: &[u8]) {
+        match self {
+            Self::Sha256(hasher) => hasher.update(chunk),
+            Self::Sha512(hasher) => hasher.update(chunk),
+            Self::Md5(hasher) => hasher.update(chunk),
+        }
+    }
+
+    /// Header name and value carrying the digest.
+    fn finish(self) -> (&'static str, String) {
+        match self {
+            Self::Sha256(hasher) => (
+                "Repr-Digest",
+                format!("sha-256=:{}:", base64::encode(&hasher.finalize())),
+            ),
+            Self::Sha512(hasher) => (
+                "Repr-Digest",
+                format!("sha-512=:{}:", base64::encode(&hasher.finalize())),
+            ),
+            Self::Md5(hasher) => ("Content-MD5", base64::encode(&hasher.finalize())),
+        }
+    }
+}
+
+/// The digests asked for of one body, fed as it goes out.
+pub(crate) struct BodyDigests(Vec<BodyHasher>);
+
+impl BodyDigests {
+    pub(crate) fn new(algorithms: &[DigestAlgorithm]) -> Self {
+        Self(
+            algorithms
+                .iter()
+                .map(|algorithm| BodyHasher::new(*algorithm))
+                .collect(),
+        )
+    }
+
+    pub(crate) fn update(&mut self, chunk: &[u8]) {
+        for hasher in &mut self.0 {
+            hasher.update(chunk);
+        }
+    }
+
+    /// Fields in the order the digests were asked for, several
+    /// `Repr-Digest` algorithms share one dictionary field.
+    pub(crate) fn finish(self) -> Vec<(&'static str, String)> {
+        let mut out: Vec<(&'static str, String)> = vec![];
+        for hasher in self.0 {
+            let (name, value) = hasher.finish();
+            match out.iter_mut().find(|(existing, _)| *existing == name) {
+                Some((_, existing)) => {
+                    existing.push_str(", ");
+                    existing.push_str(&value);
+                }
+                None => out.push((name, value)),
+            }
+        }
+        out
+    }
+}
+
+/// The names `finish` will give, for the `Trailer` header announcing them.
+pub(crate) fn field_names(algorithms: &[DigestAlgorithm]) -> Vec<&'static str> {
+    let mut names = vec![];
+    for algorithm in algorithms {
+        let name = match algorithm {
+            DigestAlgorithm::Sha256 | DigestAlgorithm::Sha512 => "Repr-Digest",
+            DigestAlgorithm::Md5 => "Content-MD5",
+        };
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    names
+}
+
+#[cfg(all(test, feature = "digest"))]
+mod tests {
+    use super::*;
+
+    // RFC 9530, appendix B
+    const RFC_9530_BODY: &[u8] = br#"{"hello": "world"}"#;
+    const RFC_9530_SHA256: &str = "sha-256=:X48E9qOokqqrvdts8nOJRJN3OWDUoyWxBf7kbu9DBPE=:";
+    const RFC_9530_SHA512: &str = "sha-512=:WZDPaVn/7XgHaAy8pmojAkGWoRx2UFChF41A2svX+TaPm+AbwAgBWnrIiYllu7BNNyealdVLvRwEmTHWXvJwew==:";
+
+    fn digests(algorithms: &[DigestAlgorithm], chunks: &[&[u8]]) -> Vec<(&'static str, String)> {
+        let mut digests = BodyDigests::new(algorithms);
+        for chunk in chunks {
+            digests.update(chunk);
+        }
+        digests.finish()
+    }
+
+    #[test]
+    fn repr_digest_known_answers() {
+        assert_eq!(
+            digests(&[DigestAlgorithm::Sha256], &[RFC_9530_BODY]),
+            [("Repr-Digest", RFC_9530_SHA256.to_string())]
+        );
+        assert_eq!(
+            digests(&[DigestAlgorithm::Sha512], &[RFC_9530_BODY]),
+            [("Repr-Digest", RFC_9530_SHA512.to_string())]
+        );
+    }
+
+    #[test]
+    fn content_md5_known_answers() {
+        // RFC 1321's test suite, base64-encoded as RFC 1864 has it
+        for (body, expected) in [
+            (&b""[..], "1B2M2Y8AsgTpgAmY7PhCfg=="),
+            (b"abc", "kAFQmDzST7DWlj99KOF/cg=="),
+            (b"message digest", "+WtpfXy3k41SWi8xqvFh0A=="),
+        ] {
+            assert_eq!(
+                digests(&[DigestAlgorithm::Md5], &[body]),
+                [("Content-MD5", expected.to_string())]
+            );
+        }
+    }
+
+    #[test]
+    fn chunks_hash_like_the_whole_body() {
+        let (head, tail) = RFC_9530_BODY.split_at(7);
+        let algorithms = [
+            DigestAlgorithm::Sha512,
+            DigestAlgorithm::Md5,
+            DigestAlgorithm::Sha256,
+        ];
+        assert_eq!(
+            digests(&algorithms, &[head, b"", tail]),
+            digests(&algorithms, &[RFC_9530_BODY])
+        );
+        assert_eq!(
+            digests(&algorithms, &[RFC_9530_BODY]),
+            [
+                (
+                    "Repr-Digest",
+                    format!("{RFC_9530_SHA512}, {RFC_9530_SHA256}")
+                ),
+                ("Content-MD5", "Sd/dVLAcvNLSq16eXua5uQ==".to_string()),
+            ]
+        );
+        assert_eq!(field_names(&algorithms), ["Repr-Digest", "Content-MD5"]);
+    }
+}
@@ -0,0 +1,129 @@
+use std::{
+    io::{self, Write},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// A file for `Client::download_many` to fetch.
+pub struct Download {
+    pub url: String,
+    /// Gets the body as it arrives, e.g. a `File`.
+    pub out: Box<dyn Write + Send>,
+}
+
+impl Download {
+    pub fn new(url: impl Into<String>, out: impl Write + Send + 'static) -> Self {
+        Self {
+            url: url.into(),
+            out: Box::new(out),
+        }
+    }
+}
+
+/// What `Client::download_many` holds all of its downloads to.
+#[derive(Debug, Clone, Copy)]
+pub struct DownloadSettings {
+    /// Downloads running at a time, 4 by default.
+    pub max_concurrent: usize,
+    /// Shared by all of them, unlimited by default. Bodies are read only
+    /// as fast as this lets them be written out, so the connections slow
+    /// down too.
+    pub max_bytes_per_sec: Option<u64>,
+}
+
+impl Default for DownloadSettings {
+    fn default() -> Self {
+        Self {
+            max_concurrent: 4,
+            max_bytes_per_sec: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Progress {
+    /// Of the download in the slice given to `download_many`.
+    pub index: usize,
+    /// Body bytes written out so far.
+    pub received: u64,
+}
+
+/// A token bucket holding up to a second's worth of bytes.
+pub(crate) struct RateLimit {
+    bytes_per_sec: f64,
+    /// Bytes that can go out right away (negative when writers are already
+    /// waiting for them) and when that was.
+    state: Mutex<(f64, Instant)>,
+}
+
+impl RateLimit {
+    pub(crate) fn new(bytes_per_sec: u64) -> Self {
+        let bytes_per_sec = bytes_per_sec.max(1) as f64;
+        Self {
+            bytes_per_sec,
+            state: Mutex::new((bytes_per_sec, Instant::now())),
+        }
+    }
+
+    /// Takes `len` bytes out of the bucket, waiting until they're in it.
+    fn take(&self, len: usize) {
+        let wait = {
+            let mut state = self.state.lock().unwrap_or_else(|err| err.into_inner());
+            let (tokens, since) = &mut *state;
+            let now = Instant::now();
+            let refill = now.duration_since(*since).as_secs_f64() * self.bytes_per_sec;
+            *tokens = (*tokens + refill).min(self.bytes_per_sec) - len as f64;
+            *since = now;
+            (*tokens < 0.0).then(|| Duration::from_secs_f64(-*tokens / self.bytes_per_sec))
+        };
+        if let Some(wait) = wait {
+            std::thread::sleep(wait);
+        }
+    }
+}
+
+/// Counts what's written to a download's `out` and holds it to the
+/// shared rate.
+pub(crate) struct Metered<'a> {
+    index: usize,
+    out: &'a mut (dyn Write + Send),
+    received: u64,
+    rate: Option<&'a RateLimit>,
+    on_progress: &'a (dyn Fn(Progress) + Sync),
+}
+
+impl<'a> Metered<'a> {
+    pub(crate) fn new(
+        index: usize,
+        out: &'a mut (dyn Write + Send),
+        rate: Option<&'a RateLimit>,
+        on_progress: &'a (dyn Fn(Progress) + Sync),
+    ) -> Self {
+        Self {
+            index,
+            out,
+            received: 0,
+            rate,
+            on_progress,
+        }
+    }
+}
+
+impl Write for Metered<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(rate) = self.rate {
+            rate.take(buf.len());
+        }
+        self.out.write_all(buf)?;
+        self.received += buf.len() as u64;
+        (self.on_progress)(Progress {
+            index: self.index,
+            received: self.received,
+        });
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
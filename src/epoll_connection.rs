@@ -0,0 +1,259 @@
+use crate::{
+    ClientOptions, Error, FSM, Request, Response, Result, Scheme, TimeoutKind, Timeouts, TlsInfo,
+    Url, Wants,
+    deadline::deadline,
+    fsm::{is_write_rejected, sink_full},
+    net,
+    socket::Socket,
+    url::host_header,
+};
+use libc::{
+    EPOLL_CTL_ADD, EPOLL_CTL_DEL, EPOLLERR, EPOLLET, EPOLLHUP, EPOLLIN, EPOLLOUT, EPOLLRDHUP,
+    epoll_event,
+};
+use std::{
+    io::{self, ErrorKind, Read, Write},
+    os::fd::{AsRawFd, OwnedFd, RawFd},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// A connection for edge-triggered epoll. It's registered once for both
+/// directions and every event, whichever flags it carries, is handled by
+/// doing IO until the socket would block, so no readiness edge gets lost.
+pub struct EpollConnection {
+    fsm: FSM,
+    sock: Socket,
+    /// The peer has shut down its side (EPOLLRDHUP or worse).
+    peer_closed: bool,
+
+    timeouts: Timeouts,
+    deadline: Option<Instant>,
+    io_deadline: Option<(Instant, TimeoutKind)>,
+}
+
+impl EpollConnection {
+    /// Flags the socket is registered with.
+    pub const EVENTS: u32 = (EPOLLIN | EPOLLOUT | EPOLLRDHUP | EPOLLET) as u32;
+
+    pub fn get(hostname: &str, port: u16, path: &str, options: &ClientOptions) -> Result<Self> {
+        Self::send(Scheme::Https, hostname, port, Request::get(path), options)
+    }
+
+    pub fn get_url(url: &str, options: &ClientOptions) -> Result<Self> {
+        let url = Url::parse(url)?;
+        Self::send(url.scheme, &url.host, url.port, url.get_request(), options)
+    }
+
+    /// Connects (blocking, bounded by the connect timeout) and prepares
+    /// `request`, register the connection to start sending it.
+    pub fn send(
+        scheme: Scheme,
+        hostname: &str,
+        port: u16,
+        request: Request,
+        options: &ClientOptions,
+    ) -> Result<Self> {
+        let fsm = Self::fsm(scheme, hostname, port, request, options)?;
+        let deadline = deadline(&options.timeouts);
+
+        let sock = net::connect(
+            options.connect_host(hostname),
+            port,
+            &options.timeouts,
+            deadline,
+            options.ip_preference,
+            options.net_observer.as_ref(),
+        )?;
+
+        Self::with_socket(fsm, Socket(sock.into()), options, deadline)
+    }
+
+    /// Sends `request` over a socket the caller has connected, `hostname` and
+    /// `port` are only used for SNI, certificate verification and the `Host`
+    /// header. The socket is switched to non-blocking mode and closed when the
+    /// connection is dropped.
+    pub fn from_stream(
+        stream: impl Into<OwnedFd>,
+        scheme: Scheme,
+        hostname: &str,
+        port: u16,
+        request: Request,
+        options: &ClientOptions,
+    ) -> Result<Self> {
+        let fsm = Self::fsm(scheme, hostname, port, request, options)?;
+        Self::with_socket(
+            fsm,
+            Socket(stream.into()),
+            options,
+            deadline(&options.timeouts),
+        )
+    }
+
+    fn fsm(
+        scheme: Scheme,
+        hostname: &str,
+        port: u16,
+        mut request: Request,
+        options: &ClientOptions,
+    ) -> Result<FSM> {
+        request.add_default_header("Host", &host_header(scheme, hostname, port));
+
+        FSM::with_scheme(scheme, hostname, request, options)
+    }
+
+    fn with_socket(
+        fsm: FSM,
+        sock: Socket,
+        options: &ClientOptions,
+        deadline: Option<Instant>,
+    ) -> Result<Self> {
+        sock.set_nonblocking()?;
+
+        Ok(Self {
+            fsm,
+            sock,
+            peer_closed: false,
+
+            timeouts: options.timeouts,
+            deadline,
+            io_deadline: None,
+        })
+    }
+
+    /// The event to pass to `epoll_ctl(EPOLL_CTL_ADD)`
+    /// for callers that register the socket themselves.
+    pub fn epoll_event(&self, token: u64) -> epoll_event {
+        epoll_event {
+            events: Self::EVENTS,
+            u64: token,
+        }
+    }
+
+    /// Adds the socket to `epfd`, its events then carry `token`.
+    pub fn register(&self, epfd: RawFd, token: u64) -> Result<()> {
+        let mut event = self.epoll_event(token);
+        let res = unsafe { libc::epoll_ctl(epfd, EPOLL_CTL_ADD, self.as_raw_fd(), &mut event) };
+        if res < 0 {
+            return Err(Error::Io(io::Error::last_os_error()));
+        }
+        Ok(())
+    }
+
+    pub fn deregister(&self, epfd: RawFd) -> Result<()> {
+        let res =
+            unsafe { libc::epoll_ctl(epfd, EPOLL_CTL_DEL, self.as_raw_fd(), std::ptr::null_mut()) };
+        if res < 0 {
+            return Err(Error::Io(io::Error::last_os_error()));
+        }
+        Ok(())
+    }
+
+    /// Handles the `events` of an `epoll_event` for this connection, `Some`
+    /// once the response is complete.
+    ///
+    /// The flags only matter for hang-ups: if the peer has shut down its side
+    /// while the request can't be written any further, nobody is going to read
+    /// the rest and the FSM switches to reading the (early) response instead.
+    pub fn handle(&mut self, events: u32) -> Result<Option<Response>> {
+        self.check_timeouts()?;
+        if events & (EPOLLRDHUP | EPOLLHUP | EPOLLERR) as u32 != 0 {
+            self.peer_closed = true;
+        }
+
+        loop {
+            match self.fsm.wants()? {
+                Wants::Read(buf) => match self.sock.read(buf) {
+                    Ok(read) => {
+                        self.io_deadline = None;
+                        self.fsm.done_reading(read);
+                    }
+                    Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                        self.arm_io_deadline(self.timeouts.read, TimeoutKind::Read);
+                        return Ok(None);
+                    }
+                    Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                    Err(err) => return Err(Error::Io(err)),
+                },
+                Wants::Write(buf) => match self.sock.write(buf) {
+                    Ok(written) => {
+                        self.io_deadline = None;
+                        self.fsm.done_writing(written);
+                    }
+                    Err(err)
+                        if err.kind() == ErrorKind::WouldBlock
+                            && self.peer_closed
+                            && self.fsm.abort_write() =>
+                    {
+                        self.io_deadline = None;
+                    }
+                    Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                        self.arm_io_deadline(self.timeouts.write, TimeoutKind::Write);
+                        return Ok(None);
+                    }
+                    Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                    Err(err) if is_write_rejected(&err) && self.fsm.abort_write() => {
+                        self.io_deadline = None;
+                    }
+                    Err(err) => return Err(Error::Io(err)),
+                },
+                Wants::Body(_) => {
+                    return Err(Error::InvalidRequest(
+                        "this driver can't stream request bodies".to_string(),
+                    ));
+                }
+                Wants::SinkFull => return Err(sink_full()),
+                Wants::Done(response) => return Ok(Some(response)),
+            }
+        }
+    }
+
+    /// See `FSM::tls_info`, available before the response is.
+    pub fn tls_info(&self) -> Option<Arc<TlsInfo>> {
+        self.fsm.tls_info()
+    }
+
+    /// Time left until the nearest configured deadline,
+    /// suitable as the timeout of `epoll_wait`.
+    pub fn timeout(&self) -> Option<Duration> {
+        [
+            self.deadline,
+            self.io_deadline.map(|(deadline, _)| deadline),
+        ]
+        .into_iter()
+        .flatten()
+        .min()
+        .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+    }
+
+    /// Fails with `Error::Timeout` once a deadline has passed, call it
+    /// when `epoll_wait` returns without events for this connection.
+    pub fn check_timeouts(&self) -> Result<()> {
+        let now = Instant::now();
+
+        if self.deadline.is_some_and(|deadline| now >= deadline) {
+            return Err(Error::Timeout(TimeoutKind::Total));
+        }
+        if let Some((deadline, kind)) = self.io_deadline
+            && now >= deadline
+        {
+            return Err(Error::Timeout(kind));
+        }
+
+        Ok(())
+    }
+
+    fn arm_io_deadline(&mut self, timeout: Option<Duration>, kind: TimeoutKind) {
+        if self.io_deadline.is_none()
+            && let Some(timeout) = timeout
+        {
+            self.io_deadline = Some((Instant::now() + timeout, kind));
+        }
+    }
+}
+
+impl AsRawFd for EpollConnection {
+    fn as_raw_fd(&self) -> RawFd {
+        self.sock.0.as_raw_fd()
+    }
+}
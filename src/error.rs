@@ -0,0 +1,167 @@
+use crate::{Response, Url, ValidationError, redact::redact_url};
+use rustls::{
+    CertificateError,
+    pki_types::InvalidDnsNameError,
+    unbuffered::{EncodeError, EncryptError},
+};
+use std::{fmt, io};
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The hostname is invalid or couldn't be resolved.
+    Dns(String),
+    /// The TLS handshake or record layer failed.
+    Tls(rustls::Error),
+    /// The server presented a certificate that didn't pass verification.
+    Certificate(CertificateError),
+    /// The underlying transport failed.
+    Io(io::Error),
+    /// Query parameter values and userinfo are masked.
+    InvalidUrl(String),
+    /// The request can't be sent as built.
+    InvalidRequest(String),
+    /// Everything wrong with a request or `ClientOptions`, see `Request::validate`.
+    Validation(Vec<ValidationError>),
+    /// The response is not valid HTTP.
+    Parse(String),
+    Timeout(TimeoutKind),
+    /// The response is bigger than `ClientOptions::limits` allow.
+    LimitExceeded(LimitKind),
+    /// The peer broke the expected flow of the exchange,
+    /// e.g. closed the connection before responding.
+    Protocol(String),
+    /// A conditional request got 412 Precondition Failed.
+    PreconditionFailed(Box<Response>),
+    /// `Response::error_for_status` got a 4xx or 5xx.
+    Status(Box<Response>),
+    /// A redirect points to a scheme its `SchemePolicy` refuses,
+    /// by default from https to plain http.
+    DowngradeBlocked(Box<Url>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TimeoutKind {
+    Connect,
+    Read,
+    Write,
+    /// The whole request took longer than `Timeouts::total`.
+    Total,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LimitKind {
+    HeadSize,
+    StatusLineLength,
+    HeaderSize,
+    HeaderCount,
+    IncomingBuffer,
+    OutgoingBuffer,
+    BodySize,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Dns(message) => write!(f, "DNS error: {message}"),
+            Self::Tls(err) => write!(f, "TLS error: {err}"),
+            Self::Certificate(err) => write!(f, "invalid certificate: {err:?}"),
+            Self::Io(err) => write!(f, "IO error: {err}"),
+            Self::InvalidUrl(url) => write!(f, "invalid URL: {url}"),
+            Self::InvalidRequest(message) => write!(f, "invalid request: {message}"),
+            Self::Validation(problems) => {
+                write!(f, "validation failed: ")?;
+                for (idx, problem) in problems.iter().enumerate() {
+                    if idx > 0 {
+                        write!(f, "; ")?;
+                    }
+                    write!(f, "{problem}")?;
+                }
+                Ok(())
+            }
+            Self::Parse(message) => write!(f, "malformed response: {message}"),
+            Self::Timeout(kind) => write!(f, "{kind} timed out"),
+            Self::LimitExceeded(kind) => write!(f, "{kind} limit exceeded"),
+            Self::Protocol(message) => write!(f, "protocol error: {message}"),
+            Self::PreconditionFailed(_) => write!(f, "412 Precondition Failed"),
+            Self::Status(response) => match response.status_text() {
+                Some(text) => write!(f, "HTTP status {} {text}", response.status),
+                None => write!(f, "HTTP status {}", response.status),
+            },
+            Self::DowngradeBlocked(to) => write!(
+                f,
+                "refused to follow a redirect to {}",
+                redact_url(&to.to_string())
+            ),
+        }
+    }
+}
+
+impl fmt::Display for TimeoutKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Connect => write!(f, "connect"),
+            Self::Read => write!(f, "read"),
+            Self::Write => write!(f, "write"),
+            Self::Total => write!(f, "request"),
+        }
+    }
+}
+
+impl fmt::Display for LimitKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::HeadSize => write!(f, "response head size"),
+            Self::StatusLineLength => write!(f, "status line length"),
+            Self::HeaderSize => write!(f, "header size"),
+            Self::HeaderCount => write!(f, "header count"),
+            Self::IncomingBuffer => write!(f, "incoming buffer size"),
+            Self::OutgoingBuffer => write!(f, "outgoing buffer size"),
+            Self::BodySize => write!(f, "response body size"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Tls(err) => Some(err),
+            Self::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<rustls::Error> for Error {
+    fn from(err: rustls::Error) -> Self {
+        match err {
+            rustls::Error::InvalidCertificate(err) => Self::Certificate(err),
+            err => Self::Tls(err),
+        }
+    }
+}
+
+impl From<EncodeError> for Error {
+    fn from(err: EncodeError) -> Self {
+        Self::Protocol(format!("failed to encode TLS data: {err}"))
+    }
+}
+
+impl From<EncryptError> for Error {
+    fn from(err: EncryptError) -> Self {
+        Self::Protocol(format!("failed to encrypt TLS data: {err}"))
+    }
+}
+
+impl From<InvalidDnsNameError> for Error {
+    fn from(err: InvalidDnsNameError) -> Self {
+        Self::Dns(err.to_string())
+    }
+}
@@ -1,5 +1,11 @@
-use crate::{Request, Response, client_config::get_client_config};
-use anyhow::{Context as _, Result};
+#[cfg(feature = "digest")]
+use crate::digest::BodyDigests;
+use crate::{
+    BodySink, Buffer, BufferConfig, ClientOptions, Error, HttpVersion, LimitKind, Location, Method,
+    Request, Response, Result, Scheme, Stats, TlsInfo, Trace, TraceEvent, buffer_config::grow,
+    client_config::get_client_config, request::Streaming, response_reader::ResponseReader,
+    url::split_zone, validation::Problems,
+};
 use rustls::{
     client::UnbufferedClientConnection,
     pki_types::ServerName,
@@ -8,73 +14,417 @@ use rustls::{
         UnbufferedStatus,
     },
 };
+use std::{io, sync::Arc};
+
+enum Transport {
+    Tls(Box<UnbufferedClientConnection>),
+    /// HTTP framed directly over the stream, `incoming_tls`/`outgoing_tls`
+    /// then hold plaintext.
+    Plain,
+}
 
-pub struct FSM {
-    conn: UnbufferedClientConnection,
+pub struct FSM<B: Buffer = Vec<u8>> {
+    transport: Transport,
+    /// The serialized head (and body, unless it's streamed),
+    /// then each framed chunk of a streamed body in turn.
     request: Vec<u8>,
-    response: Vec<u8>,
+    /// Checked but only serialized by the next `wants`, so that a body
+    /// sink set before that can still keep it from asking for compression.
+    queued: Option<Request>,
+    body: Option<BodyStream>,
+    pending_body: bool,
+    reader: ResponseReader,
 
-    incoming_tls: Vec<u8>,
+    incoming_tls: B,
     incoming_start: usize,
     incoming_end: usize,
 
-    outgoing_tls: Vec<u8>,
+    outgoing_tls: B,
     outgoing_start: usize,
     outgoing_end: usize,
 
     we_closed: bool,
+    write_aborted: bool,
+    peer_eof: bool,
+    /// The peer sent close_notify.
+    peer_closed: bool,
     sent_request: bool,
     received_response: bool,
+    reusable: bool,
+    stats: Stats,
+    trace: Option<Trace>,
+    body_sink: Option<Box<dyn BodySink>>,
+    /// Set aside by `abort_response`, the drained body doesn't go to it.
+    parked_sink: Option<Box<dyn BodySink>>,
+    draining: Option<Drain>,
+    /// Taken once per connection, every response shares it.
+    tls_info: Option<Arc<TlsInfo>>,
+
+    keep_alive: bool,
+    drain_budget: usize,
+    allow_trace: bool,
+    buffers: BufferConfig,
 }
 
+// `Done` keeps the response's first headers inline, boxing it would bring
+// back the allocation that saves
+#[allow(clippy::large_enum_variant)]
 pub enum Wants<'a> {
     Read(&'a mut [u8]),
     Write(&'a [u8]),
+    /// The next piece of a body set up with `Request::stream_body`,
+    /// fill the buffer and report how much of it was used via `done_body`.
+    Body(&'a mut [u8]),
+    /// The `BodySink` has no room and more of the body than the incoming
+    /// buffer holds is waiting for it, make room before calling `wants`
+    /// again. Nothing more is read until then.
+    SinkFull,
     Done(Response),
 }
 
+/// What `abort_response` left to read.
+#[derive(Clone, Copy)]
+struct Drain {
+    /// Of the body, when it was called.
+    received_before: usize,
+    /// `None` without keep-alive, the connection is closed anyway.
+    budget: Option<usize>,
+}
+
+impl Drain {
+    /// Whether to give up on the rest of the body.
+    fn is_over(self, reader: &ResponseReader) -> bool {
+        // the head tells how much there is
+        if reader.is_complete() || !reader.has_head() {
+            return false;
+        }
+        match (self.budget, reader.body_left()) {
+            (Some(budget), Some(left)) => {
+                reader.body_received() - self.received_before + left > budget
+            }
+            _ => true,
+        }
+    }
+}
+
+struct BodyStream {
+    chunked: bool,
+    /// Bytes left until `Content-Length` is reached.
+    remaining: u64,
+    finished: bool,
+    /// Of the body so far, sent as trailers after the last chunk.
+    #[cfg(feature = "digest")]
+    digests: Option<BodyDigests>,
+}
+
 impl FSM {
-    pub fn new(server_name: ServerName<'static>, request: Request) -> Result<Self> {
-        Ok(Self {
-            conn: UnbufferedClientConnection::new(get_client_config(), server_name)?,
-            request: request.into_bytes(),
-            response: vec![],
+    pub fn new(
+        server_name: ServerName<'static>,
+        request: Request,
+        options: &ClientOptions,
+    ) -> Result<Self> {
+        let (incoming, outgoing) = initial_buffers(options);
+        Self::new_with_buffers(server_name, request, options, incoming, outgoing)
+    }
 
-            incoming_tls: vec![0; INCOMING_TLS_BUFSIZE],
+    /// Plain HTTP without TLS, for `http://` endpoints.
+    pub fn plain(request: Request, options: &ClientOptions) -> Result<Self> {
+        let (incoming, outgoing) = initial_buffers(options);
+        Self::plain_with_buffers(request, options, incoming, outgoing)
+    }
+
+    pub fn with_scheme(
+        scheme: Scheme,
+        hostname: &str,
+        request: Request,
+        options: &ClientOptions,
+    ) -> Result<Self> {
+        match scheme {
+            Scheme::Https => {
+                // a zone ID only means something to the resolver
+                let (hostname, _) = split_zone(hostname);
+                let server_name = ServerName::try_from(hostname)?.to_owned();
+                Self::new(server_name, request, options)
+            }
+            Scheme::Http => Self::plain(request, options),
+        }
+    }
+}
+
+impl<B: Buffer> FSM<B> {
+    /// Works on caller-provided buffers, e.g. `&mut [u8]` slices of a
+    /// static arena or `FixedBuffer`s, `BufferConfig` sizes are ignored then.
+    pub fn new_with_buffers(
+        server_name: ServerName<'static>,
+        request: Request,
+        options: &ClientOptions,
+        incoming: B,
+        outgoing: B,
+    ) -> Result<Self> {
+        let conn = UnbufferedClientConnection::new(
+            get_client_config(options, &server_name)?,
+            server_name,
+        )?;
+        Self::build(
+            Transport::Tls(Box::new(conn)),
+            request,
+            options,
+            incoming,
+            outgoing,
+        )
+    }
+
+    pub fn plain_with_buffers(
+        request: Request,
+        options: &ClientOptions,
+        incoming: B,
+        outgoing: B,
+    ) -> Result<Self> {
+        Self::build(Transport::Plain, request, options, incoming, outgoing)
+    }
+
+    fn build(
+        transport: Transport,
+        request: Request,
+        options: &ClientOptions,
+        incoming: B,
+        outgoing: B,
+    ) -> Result<Self> {
+        let mut problems = Problems::default();
+        options.check(&mut problems);
+
+        let mut fsm = Self {
+            transport,
+            request: vec![],
+            queued: None,
+            body: None,
+            pending_body: false,
+            reader: ResponseReader::new(
+                options.limits,
+                options.buffers.initial_scratch,
+                options.buffers.retain_scratch,
+                options.preserve_header_case,
+            ),
+
+            incoming_tls: incoming,
             incoming_start: 0,
             incoming_end: 0,
 
-            outgoing_tls: vec![0; OUTGOING_TLS_INITIAL_BUFSIZE],
+            outgoing_tls: outgoing,
             outgoing_start: 0,
             outgoing_end: 0,
 
             we_closed: false,
+            write_aborted: false,
+            peer_eof: false,
+            peer_closed: false,
             sent_request: false,
             received_response: false,
-        })
+            reusable: false,
+            stats: Stats::default(),
+            trace: options.trace.then(Trace::default),
+            body_sink: None,
+            parked_sink: None,
+            draining: None,
+            tls_info: None,
+
+            keep_alive: options.keep_alive,
+            drain_budget: options.drain_budget,
+            allow_trace: options.allow_trace,
+            buffers: options.buffers,
+        };
+        fsm.queue(request, problems)?;
+        Ok(fsm)
+    }
+
+    /// Sends `request` if neither it nor anything in `problems` is wrong.
+    fn queue(&mut self, request: Request, mut problems: Problems) -> Result<()> {
+        request.check(&mut problems);
+        if *request.method() == Method::Trace && !self.allow_trace {
+            problems.push(
+                Location::Method,
+                "TRACE is disabled, see ClientOptions::allow_trace",
+            );
+        }
+        problems.into_result()?;
+
+        self.reader.reset(*request.method() == Method::Head);
+        self.body = request.streaming().map(|streaming| match streaming {
+            Streaming::Sized(len) => BodyStream {
+                chunked: false,
+                remaining: len,
+                finished: len == 0,
+                #[cfg(feature = "digest")]
+                digests: None,
+            },
+            // `Request::check` only lets chunked bodies have digests
+            Streaming::Chunked => BodyStream {
+                chunked: true,
+                remaining: 0,
+                finished: false,
+                #[cfg(feature = "digest")]
+                digests: (!request.digests().is_empty())
+                    .then(|| BodyDigests::new(request.digests())),
+            },
+        });
+        self.pending_body = false;
+        self.queued = Some(request);
+        Ok(())
+    }
+
+    fn serialize(&mut self, mut request: Request) -> Result<()> {
+        if !self.keep_alive {
+            request.add_default_header("Connection", "close");
+        }
+        // a sink gets the body as it was sent, there's nothing to undo
+        // the compression on the way
+        #[cfg(feature = "decompression")]
+        if self.body_sink.is_none() {
+            request.accept_compressed();
+        }
+
+        // the buffer of the previous request on this connection is reused
+        self.request.clear();
+        request.write_into(&mut self.request);
+
+        if let Transport::Plain = self.transport {
+            self.queue_plain()?;
+        }
+        Ok(())
+    }
+
+    /// Plaintext goes straight to the outgoing buffer, it's empty by now.
+    fn queue_plain(&mut self) -> Result<()> {
+        let len = self.request.len();
+        if self.outgoing_tls.len() < len {
+            grow(
+                &mut self.outgoing_tls,
+                len,
+                self.buffers.growth,
+                self.buffers.max_outgoing,
+                LimitKind::OutgoingBuffer,
+            )?;
+        }
+        self.outgoing_tls[..len].copy_from_slice(&self.request);
+        self.outgoing_start = 0;
+        self.outgoing_end = len;
+        self.sent_request = true;
+        self.pending_body = false;
+        Ok(())
+    }
+
+    /// Whether the last response was complete and both sides agreed to keep
+    /// the connection open, so that `reuse` can send another request.
+    pub fn is_reusable(&self) -> bool {
+        self.reusable
+    }
+
+    /// Sends the next request over the same connection,
+    /// only valid once `Wants::Done` was returned and `is_reusable` holds.
+    pub fn reuse(&mut self, request: Request) -> Result<()> {
+        self.restart(request, Problems::default())
+    }
+
+    /// Like `reuse` but with the options of this request: its limits,
+    /// `keep_alive`, `drain_budget`, `allow_trace`, `trace`,
+    /// `preserve_header_case` and how the buffers grow apply from now on.
+    /// What the connection was set up with (TLS settings, its buffers as
+    /// they are) stays.
+    pub fn reuse_with(&mut self, request: Request, options: &ClientOptions) -> Result<()> {
+        if !self.reusable {
+            return Err(not_reusable());
+        }
+        let mut problems = Problems::default();
+        options.check(&mut problems);
+
+        self.reader
+            .configure(options.limits, options.preserve_header_case);
+        self.trace = options.trace.then(Trace::default);
+        self.keep_alive = options.keep_alive;
+        self.drain_budget = options.drain_budget;
+        self.allow_trace = options.allow_trace;
+        self.buffers = BufferConfig {
+            initial_incoming: self.buffers.initial_incoming,
+            initial_outgoing: self.buffers.initial_outgoing,
+            ..options.buffers
+        };
+        self.restart(request, problems)
+    }
+
+    fn restart(&mut self, request: Request, problems: Problems) -> Result<()> {
+        if !self.reusable {
+            return Err(not_reusable());
+        }
+
+        self.reusable = false;
+        self.sent_request = false;
+        self.pending_body = false;
+        self.received_response = false;
+        self.draining = None;
+        self.stats = Stats::default();
+        self.queue(request, problems)
     }
 
     pub fn wants(&mut self) -> Result<Wants<'_>> {
+        self.stats.wants_calls += 1;
+        if let Some(request) = self.queued.take() {
+            self.serialize(request)?;
+        }
+        if self.reader.is_body_too_large() {
+            return Err(Error::LimitExceeded(LimitKind::BodySize));
+        }
+        if self.is_over_drain_budget() {
+            return self.done();
+        }
+        if let Some(sink) = &mut self.body_sink {
+            // room may have been made since the last call
+            self.reader.deliver(sink.as_mut());
+        }
+        if self.is_sink_full() {
+            return Ok(self.wants_sink());
+        }
+
+        let Transport::Tls(conn) = &mut self.transport else {
+            return self.wants_plain();
+        };
+
         loop {
-            let UnbufferedStatus { discard, state } = self.conn.process_tls_records(
+            let hold_back = self.incoming_tls.len();
+            let UnbufferedStatus { discard, state } = conn.process_tls_records(
                 &mut self.incoming_tls[self.incoming_start..self.incoming_end],
             );
 
             self.incoming_start += discard;
 
-            let state = state.context("malformed internal state")?;
+            let state = state?;
 
             match state {
                 ConnectionState::ReadTraffic(mut state) => {
-                    while let Some(res) = state.next_record() {
-                        let AppDataRecord { discard, payload } =
-                            res.context("failed to get AppDataRecord")?;
+                    // the rest stays encrypted in the incoming buffer
+                    while !is_sink_full(&self.body_sink, &self.reader, hold_back)
+                        && let Some(res) = state.next_record()
+                    {
+                        let AppDataRecord { discard, payload } = res?;
 
                         self.incoming_start += discard;
+                        self.received_response = true;
 
-                        self.response.extend_from_slice(payload);
+                        match &mut self.body_sink {
+                            Some(sink) => self.reader.feed_to(payload, sink.as_mut())?,
+                            None => self.reader.feed(payload)?,
+                        }
+                    }
 
-                        self.received_response = true;
+                    if self.reader.is_complete()
+                        || self
+                            .draining
+                            .is_some_and(|drain| drain.is_over(&self.reader))
+                    {
+                        return self.done();
+                    }
+                    if is_sink_full(&self.body_sink, &self.reader, hold_back) {
+                        self.record(TraceEvent::WantsSinkFull);
+                        return Ok(Wants::SinkFull);
                     }
                 }
 
@@ -85,8 +435,13 @@ impl FSM {
                         Err(EncodeError::InsufficientSize(InsufficientSizeError {
                             required_size,
                         })) => {
-                            let new_len = self.outgoing_end + required_size;
-                            self.outgoing_tls.resize(new_len, 0);
+                            grow(
+                                &mut self.outgoing_tls,
+                                self.outgoing_end + required_size,
+                                self.buffers.growth,
+                                self.buffers.max_outgoing,
+                                LimitKind::OutgoingBuffer,
+                            )?;
                             state.encode(&mut self.outgoing_tls[self.outgoing_end..])?
                         }
 
@@ -100,13 +455,36 @@ impl FSM {
 
                 ConnectionState::TransmitTlsData(mut state) => {
                     if let Some(mut may_encrypt) = state.may_encrypt_app_data()
-                        && !self.sent_request
+                        && (!self.sent_request || self.pending_body)
                     {
-                        let written = may_encrypt
+                        let written = match may_encrypt
                             .encrypt(&self.request, &mut self.outgoing_tls[self.outgoing_end..])
-                            .context("encrypted request does not fit in `outgoing_tls`")?;
+                        {
+                            Ok(written) => written,
+
+                            Err(EncryptError::InsufficientSize(InsufficientSizeError {
+                                required_size,
+                            })) => {
+                                grow(
+                                    &mut self.outgoing_tls,
+                                    self.outgoing_end + required_size,
+                                    self.buffers.growth,
+                                    self.buffers.max_outgoing,
+                                    LimitKind::OutgoingBuffer,
+                                )?;
+                                may_encrypt.encrypt(
+                                    &self.request,
+                                    &mut self.outgoing_tls[self.outgoing_end..],
+                                )?
+                            }
+
+                            Err(e) => {
+                                return Err(e.into());
+                            }
+                        };
                         self.outgoing_end += written;
                         self.sent_request = true;
+                        self.pending_body = false;
                     }
 
                     if self.outgoing_start == self.outgoing_end {
@@ -117,33 +495,61 @@ impl FSM {
                 }
 
                 ConnectionState::BlockedHandshake { .. } => {
-                    self.resize_incoming_if_needed();
-                    return Ok(self.wants_read());
+                    self.make_room_for_incoming()?;
+                    return self.wants_read();
                 }
 
                 ConnectionState::WriteTraffic(mut may_encrypt) => {
-                    if !self.sent_request {
-                        panic!("dead branch hit");
-                        // let request = make_request();
-                        // let written = may_encrypt
-                        //     .encrypt(&request, &mut self.outgoing_tls[self.outgoing_end..])
-                        //     .context("encrypted request does not fit in `outgoing_tls`")?;
-                        // self.outgoing_end += written;
-                        // self.sent_request = true;
-
-                        // todo!();
-                        // // send_tls(&mut sock, outgoing_tls, &mut outgoing_end)?;
-                        // self.resize_incoming_if_needed();
-                        // todo!()
-                        // // recv_tls(&mut sock, incoming_tls, &mut incoming_end)?;
-                    } else if !self.received_response {
+                    if !self.sent_request || self.pending_body {
+                        // the next request on a reused connection, or a body chunk
+                        let written = match may_encrypt
+                            .encrypt(&self.request, &mut self.outgoing_tls[self.outgoing_end..])
+                        {
+                            Ok(written) => written,
+
+                            Err(EncryptError::InsufficientSize(InsufficientSizeError {
+                                required_size,
+                            })) => {
+                                grow(
+                                    &mut self.outgoing_tls,
+                                    self.outgoing_end + required_size,
+                                    self.buffers.growth,
+                                    self.buffers.max_outgoing,
+                                    LimitKind::OutgoingBuffer,
+                                )?;
+                                may_encrypt.encrypt(
+                                    &self.request,
+                                    &mut self.outgoing_tls[self.outgoing_end..],
+                                )?
+                            }
+
+                            Err(e) => {
+                                return Err(e.into());
+                            }
+                        };
+                        self.outgoing_end += written;
+                        self.sent_request = true;
+                        self.pending_body = false;
+
+                        return Ok(self.wants_write());
+                    } else if self.outgoing_start != self.outgoing_end {
+                        // a partial write of the request
+                        return Ok(self.wants_write());
+                    } else if self.body.as_ref().is_some_and(|body| !body.finished) {
+                        return Ok(self.wants_body());
+                    } else if !self.reader.is_complete() && !self.peer_closed {
                         // this happens in the TLS 1.3 case. the app-data was sent in the preceding
                         // `TransmitTlsData` state. the server should have already written a
-                        // response which we can read out from the socket
-                        self.resize_incoming_if_needed();
+                        // response (or the rest of it) which we can read out from the socket
+                        self.make_room_for_incoming()?;
 
-                        return Ok(self.wants_read());
-                    } else if !self.we_closed {
+                        return self.wants_read();
+                    } else if !self.we_closed && (self.peer_closed || !self.keep_alive) {
+                        // answering close_notify, the response is complete
+                        // (or runs until close) once it's delivered
+                        if self.peer_closed {
+                            check_closed(self.received_response, &self.reader)?;
+                        }
                         let written = match may_encrypt
                             .queue_close_notify(&mut self.outgoing_tls[self.outgoing_end..])
                         {
@@ -152,8 +558,13 @@ impl FSM {
                             Err(EncryptError::InsufficientSize(InsufficientSizeError {
                                 required_size,
                             })) => {
-                                let new_len = self.outgoing_end + required_size;
-                                self.outgoing_tls.resize(new_len, 0);
+                                grow(
+                                    &mut self.outgoing_tls,
+                                    self.outgoing_end + required_size,
+                                    self.buffers.growth,
+                                    self.buffers.max_outgoing,
+                                    LimitKind::OutgoingBuffer,
+                                )?;
                                 may_encrypt.queue_close_notify(
                                     &mut self.outgoing_tls[self.outgoing_end..],
                                 )?
@@ -169,22 +580,19 @@ impl FSM {
                         self.we_closed = true;
                         return Ok(self.wants_write());
                     } else {
-                        self.resize_incoming_if_needed();
+                        self.make_room_for_incoming()?;
 
-                        return Ok(self.wants_read());
+                        return self.wants_read();
                     }
                 }
 
-                ConnectionState::PeerClosed => {}
+                ConnectionState::PeerClosed => {
+                    self.peer_closed = true;
+                }
 
                 ConnectionState::Closed => {
-                    assert!(self.sent_request);
-                    assert!(self.received_response);
-                    assert_eq!(self.incoming_start, self.incoming_end);
-
-                    let response = std::mem::take(&mut self.response);
-                    let response = Response::parse(response)?;
-                    return Ok(Wants::Done(response));
+                    check_closed(self.received_response, &self.reader)?;
+                    return self.done();
                 }
 
                 _ => unreachable!(),
@@ -192,34 +600,879 @@ impl FSM {
         }
     }
 
-    fn resize_incoming_if_needed(&mut self) {
+    fn wants_plain(&mut self) -> Result<Wants<'_>> {
+        if self.incoming_start != self.incoming_end {
+            let received = &self.incoming_tls[self.incoming_start..self.incoming_end];
+            self.received_response = true;
+            match &mut self.body_sink {
+                Some(sink) => self.reader.feed_to(received, sink.as_mut())?,
+                None => self.reader.feed(received)?,
+            }
+            self.incoming_start = 0;
+            self.incoming_end = 0;
+
+            if self.reader.is_complete() || self.is_over_drain_budget() {
+                return self.done();
+            }
+            if self.is_sink_full() {
+                return Ok(self.wants_sink());
+            }
+        }
+
+        if self.outgoing_start != self.outgoing_end {
+            return Ok(self.wants_write());
+        }
+        if self.pending_body {
+            self.queue_plain()?;
+            return Ok(self.wants_write());
+        }
+        if self.needs_body() {
+            return Ok(self.wants_body());
+        }
+
+        self.make_room_for_incoming()?;
+        self.wants_read()
+    }
+
+    fn needs_body(&self) -> bool {
+        self.sent_request && self.body.as_ref().is_some_and(|body| !body.finished)
+    }
+
+    fn wants_body(&mut self) -> Wants<'_> {
+        let len = match &self.body {
+            Some(body) if !body.chunked => body.remaining.min(BODY_CHUNK as u64) as usize,
+            _ => BODY_CHUNK,
+        };
+        self.request.clear();
+        self.request.resize(len, 0);
+        self.record(TraceEvent::WantsBody(len));
+        Wants::Body(&mut self.request)
+    }
+
+    /// Reports how many bytes of the `Wants::Body` buffer were filled, 0 ends
+    /// a chunked body. A sized body ends once `Content-Length` bytes are in,
+    /// ending it earlier is an error.
+    pub fn done_body(&mut self, filled: usize) -> Result<()> {
+        self.record(TraceEvent::DoneBody(filled));
+        let Some(body) = &mut self.body else {
+            return Err(Error::InvalidRequest(
+                "the request has no streamed body".to_string(),
+            ));
+        };
+        if filled > self.request.len() {
+            return Err(Error::InvalidRequest(format!(
+                "filled {filled} bytes of a {} byte body buffer",
+                self.request.len()
+            )));
+        }
+
+        self.request.truncate(filled);
+        #[cfg(feature = "digest")]
+        if let Some(digests) = &mut body.digests {
+            digests.update(&self.request);
+        }
+        if body.chunked {
+            if filled == 0 {
+                self.request.extend_from_slice(b"0\r\n");
+                #[cfg(feature = "digest")]
+                for (name, value) in body
+                    .digests
+                    .take()
+                    .map(BodyDigests::finish)
+                    .unwrap_or_default()
+                {
+                    self.request.extend_from_slice(
+                        format!("{}: {value}\r\n", name.to_ascii_lowercase()).as_bytes(),
+                    );
+                }
+                self.request.extend_from_slice(b"\r\n");
+                body.finished = true;
+            } else {
+                let size = format!("{filled:x}\r\n");
+                self.request.splice(0..0, size.bytes());
+                self.request.extend_from_slice(b"\r\n");
+            }
+        } else {
+            if filled == 0 {
+                return Err(Error::InvalidRequest(format!(
+                    "the body ended {} bytes short of its Content-Length",
+                    body.remaining
+                )));
+            }
+            body.remaining -= filled as u64;
+            body.finished = body.remaining == 0;
+        }
+
+        self.pending_body = true;
+        Ok(())
+    }
+
+    fn make_room_for_incoming(&mut self) -> Result<()> {
+        if self.incoming_start == self.incoming_end {
+            self.incoming_start = 0;
+            self.incoming_end = 0;
+        } else if self.incoming_start > self.buffers.compact_incoming_after {
+            // rustls reports positions relative to the slice it's given,
+            // so moving unprocessed bytes to the front between calls is safe
+            self.incoming_tls
+                .copy_within(self.incoming_start..self.incoming_end, 0);
+            self.incoming_end -= self.incoming_start;
+            self.incoming_start = 0;
+        }
+
         if self.incoming_end == self.incoming_tls.len() {
-            let new_len = self.incoming_tls.len() + INCOMING_TLS_BUFSIZE;
-            self.incoming_tls.resize(new_len, 0);
+            grow(
+                &mut self.incoming_tls,
+                self.incoming_end + 1,
+                self.buffers.growth,
+                self.buffers.max_incoming,
+                LimitKind::IncomingBuffer,
+            )?;
         }
+
+        Ok(())
     }
 
-    fn wants_write(&self) -> Wants<'_> {
+    fn is_sink_full(&self) -> bool {
+        is_sink_full(&self.body_sink, &self.reader, self.incoming_tls.len())
+    }
+
+    fn wants_sink(&mut self) -> Wants<'_> {
+        self.record(TraceEvent::WantsSinkFull);
+        Wants::SinkFull
+    }
+
+    fn wants_write(&mut self) -> Wants<'_> {
+        self.record(TraceEvent::WantsWrite(
+            self.outgoing_end - self.outgoing_start,
+        ));
         Wants::Write(&self.outgoing_tls[self.outgoing_start..self.outgoing_end])
     }
 
-    fn wants_read(&mut self) -> Wants<'_> {
-        Wants::Read(&mut self.incoming_tls[self.incoming_end..])
+    fn wants_read(&mut self) -> Result<Wants<'_>> {
+        if self.peer_eof {
+            // The peer closed TCP without sending close_notify. HTTP/1.0 servers
+            // do exactly that to delimit a body that has no Content-Length.
+            check_closed(self.received_response, &self.reader)?;
+            return self.done();
+        }
+
+        self.record(TraceEvent::WantsRead(
+            self.incoming_tls.len() - self.incoming_end,
+        ));
+        Ok(Wants::Read(&mut self.incoming_tls[self.incoming_end..]))
+    }
+
+    fn done(&mut self) -> Result<Wants<'_>> {
+        let complete = self.reader.is_complete();
+        let mut response = self.reader.finish()?;
+        response.stats = Stats {
+            incoming_buffer: self.incoming_tls.len(),
+            outgoing_buffer: self.outgoing_tls.len(),
+            ..self.stats
+        };
+        self.record(TraceEvent::WantsDone {
+            status: response.status,
+            body: response.body.len(),
+        });
+        response.trace = self.trace.as_mut().map(std::mem::take);
+        if self.tls_info.is_none() {
+            self.tls_info = self.tls_info();
+        }
+        response.tls = self.tls_info.clone();
+
+        if self.draining.is_some() {
+            response.body.clear();
+            if let Some(sink) = self.parked_sink.take() {
+                self.body_sink = Some(sink);
+            }
+        }
+
+        self.reusable = self.keep_alive
+            && complete
+            && !self.peer_eof
+            && !self.peer_closed
+            && !self.we_closed
+            && keeps_alive(&response);
+
+        Ok(Wants::Done(response))
     }
 
     pub fn done_reading(&mut self, read: usize) {
+        self.record(TraceEvent::DoneReading(read));
+        self.stats.reads += 1;
+        self.stats.bytes_read += read as u64;
+        if read == 0 {
+            self.peer_eof = true;
+        }
         self.incoming_end += read;
     }
 
+    /// Call this when a write fails because the peer stopped reading,
+    /// e.g. it answered 413 or 401 before the whole body went out.
+    /// The rest of the request is dropped and the FSM switches to reading
+    /// the early response; the connection can't be reused after that.
+    ///
+    /// Returns `false` if the request wasn't handed to TLS yet,
+    /// then there's no response to wait for and the write error should be surfaced.
+    pub fn abort_write(&mut self) -> bool {
+        self.record(TraceEvent::AbortWrite);
+        if !self.sent_request {
+            return false;
+        }
+
+        self.outgoing_start = 0;
+        self.outgoing_end = 0;
+        // nobody is reading the rest of a streamed body
+        if let Some(body) = &mut self.body {
+            body.finished = true;
+        }
+        self.pending_body = false;
+        // close_notify can't be delivered anyway
+        self.we_closed = true;
+        self.write_aborted = true;
+        true
+    }
+
+    /// Call this when the rest of the response body isn't wanted. On a
+    /// kept-alive connection it's still read and thrown away if no more
+    /// than `ClientOptions::drain_budget` of it is left, so that the
+    /// connection can be reused, otherwise the next `wants` ends the
+    /// exchange right away and the connection has to be closed. Either way
+    /// `Wants::Done` comes with an empty body and any `BodySink` gets
+    /// nothing more.
+    pub fn abort_response(&mut self) {
+        self.record(TraceEvent::AbortResponse);
+        if self.draining.is_none() {
+            self.draining = Some(Drain {
+                received_before: self.reader.body_received(),
+                budget: self.keep_alive.then_some(self.drain_budget),
+            });
+            self.parked_sink = self.body_sink.take();
+        }
+    }
+
+    fn is_over_drain_budget(&self) -> bool {
+        self.draining
+            .is_some_and(|drain| drain.is_over(&self.reader))
+    }
+
+    pub fn is_write_aborted(&self) -> bool {
+        self.write_aborted
+    }
+
+    /// Whether any of the response to the current request has arrived.
+    #[cfg_attr(not(feature = "blocking"), allow(dead_code))]
+    pub(crate) fn has_received_response(&self) -> bool {
+        self.received_response
+    }
+
     pub fn done_writing(&mut self, written: usize) {
+        self.record(TraceEvent::DoneWriting(written));
+        self.stats.writes += 1;
+        self.stats.bytes_written += written as u64;
         self.outgoing_start += written;
         if self.outgoing_start == self.outgoing_end {
             self.outgoing_start = 0;
             self.outgoing_end = 0;
         }
     }
+
+    /// What was recorded of the current exchange so far, with
+    /// `ClientOptions::trace` on. Moves to `Response::trace` once it's done.
+    pub fn trace(&self) -> Option<&Trace> {
+        self.trace.as_ref()
+    }
+
+    /// The negotiated TLS version, cipher suite, ALPN protocol and the
+    /// server's certificates, once the handshake is done. Always `None`
+    /// for plain HTTP.
+    pub fn tls_info(&self) -> Option<Arc<TlsInfo>> {
+        if let Some(info) = &self.tls_info {
+            return Some(Arc::clone(info));
+        }
+        match &self.transport {
+            Transport::Tls(conn) => TlsInfo::of(conn).map(Arc::new),
+            Transport::Plain => None,
+        }
+    }
+
+    /// Streams response bodies into `sink` instead of collecting them in
+    /// `Response::body`, which then only holds what the sink had no room
+    /// for by the time the response was complete. Applies to the response
+    /// being read and the ones after it on a reused connection. Set before
+    /// the first `wants` (of a request), the request doesn't advertise
+    /// the `decompression` feature's codings: the sink would get the body
+    /// still compressed.
+    pub fn set_body_sink(&mut self, sink: impl BodySink) {
+        self.reader.set_streaming(true);
+        self.body_sink = Some(Box::new(sink));
+    }
+
+    /// The sink given to `set_body_sink`, if it's an `S`, e.g. to take
+    /// the buffers a `BufferRing` filled.
+    pub fn body_sink<S: BodySink>(&mut self) -> Option<&mut S> {
+        let sink: &mut dyn std::any::Any = self.body_sink.as_deref_mut()?;
+        sink.downcast_mut()
+    }
+
+    /// Removes the sink, bodies are collected in `Response::body` again.
+    pub fn take_body_sink(&mut self) -> Option<Box<dyn BodySink>> {
+        self.reader.set_streaming(false);
+        self.body_sink.take()
+    }
+
+    /// What the current exchange took so far.
+    #[cfg(all(test, feature = "poll"))]
+    pub(crate) fn stats(&self) -> Stats {
+        self.stats
+    }
+
+    /// The status of the response being read, once its head is in.
+    #[cfg_attr(not(feature = "blocking"), allow(dead_code))]
+    pub(crate) fn response_status(&self) -> Option<u16> {
+        self.reader.status()
+    }
+
+    fn record(&mut self, event: TraceEvent) {
+        if let Some(trace) = &mut self.trace {
+            trace.push(event);
+        }
+    }
+
+    /// The incoming and the outgoing TLS buffer, for drivers that register
+    /// them with the kernel. They move when they grow.
+    #[cfg(feature = "io-uring")]
+    pub(crate) fn tls_buffers(&mut self) -> [&mut [u8]; 2] {
+        [&mut self.incoming_tls, &mut self.outgoing_tls]
+    }
+}
+
+/// Fits a TLS record together with the chunk framing.
+const BODY_CHUNK: usize = 16 * 1024 - 16;
+
+fn initial_buffers(options: &ClientOptions) -> (Vec<u8>, Vec<u8>) {
+    (
+        vec![0; options.buffers.initial_incoming],
+        vec![0; options.buffers.initial_outgoing],
+    )
+}
+
+/// Body bytes are only held back while the sink has no room,
+/// `hold_back` bounds them.
+fn is_sink_full(
+    sink: &Option<Box<dyn BodySink>>,
+    reader: &ResponseReader,
+    hold_back: usize,
+) -> bool {
+    sink.is_some() && !reader.is_complete() && reader.held_back() >= hold_back
+}
+
+/// The peer closed the connection, which only ends a response
+/// whose body runs until close.
+fn check_closed(received_response: bool, reader: &ResponseReader) -> Result<()> {
+    if !received_response {
+        return Err(Error::Protocol(
+            "connection closed before a response was received".to_string(),
+        ));
+    }
+    if reader.is_truncated() {
+        return Err(Error::Protocol(
+            "connection closed before the response body was complete".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn keeps_alive(response: &Response) -> bool {
+    let mut tokens = response
+        .headers
+        .get_all("Connection")
+        .flat_map(|value| value.split(','))
+        .map(str::trim);
+
+    match response.version {
+        HttpVersion::Http11 => !tokens.any(|token| token.eq_ignore_ascii_case("close")),
+        HttpVersion::Http10 => tokens.any(|token| token.eq_ignore_ascii_case("keep-alive")),
+        HttpVersion::Http09 => false,
+    }
+}
+
+/// `Wants::SinkFull` in a driver, it has no way to make room in the sink.
+#[cfg_attr(
+    not(any(
+        feature = "blocking",
+        feature = "poll",
+        feature = "io-uring",
+        feature = "tokio",
+        feature = "futures",
+        feature = "mio",
+        feature = "epoll",
+        feature = "iocp"
+    )),
+    allow(dead_code)
+)]
+pub(crate) fn sink_full() -> Error {
+    Error::InvalidRequest("the body sink has no room and this driver can't wait for it".to_string())
+}
+
+/// Write errors after which an early response may still be waiting to be read.
+#[cfg_attr(
+    not(any(
+        feature = "blocking",
+        feature = "poll",
+        feature = "tokio",
+        feature = "futures",
+        feature = "mio",
+        feature = "epoll",
+        feature = "iocp"
+    )),
+    allow(dead_code)
+)]
+pub(crate) fn is_write_rejected(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::BrokenPipe
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+    )
 }
 
-const KB: usize = 1024;
-const INCOMING_TLS_BUFSIZE: usize = 16 * KB;
-const OUTGOING_TLS_INITIAL_BUFSIZE: usize = KB;
+fn not_reusable() -> Error {
+    Error::Protocol("the connection can't be reused".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{Peer, PlainPeer, Script, TlsPeer, drive};
+
+    fn tls_fsm(request: Request, options: &ClientOptions) -> FSM {
+        FSM::new(ServerName::try_from("localhost").unwrap(), request, options).unwrap()
+    }
+
+    fn keep_alive() -> ClientOptions {
+        ClientOptions {
+            keep_alive: true,
+            ..TlsPeer::options()
+        }
+    }
+
+    #[test]
+    fn keeps_a_tls_connection_alive_across_reads() {
+        let mut peer = TlsPeer::new(Script::new(&[
+            &[
+                b"HTTP/1.1 200 OK\r\nContent-Length: 11\r\n\r\nhel",
+                b"lo ",
+                b"worl",
+                b"d",
+            ],
+            &[
+                b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n",
+                b"3\r\nabc\r\n",
+                b"0\r\n\r\n",
+            ],
+        ]));
+        let mut fsm = tls_fsm(Request::get("/"), &keep_alive());
+
+        let response = drive(&mut fsm, &mut peer, &mut &[][..]).unwrap();
+        assert_eq!(response.body, b"hello world");
+        assert!(fsm.is_reusable());
+
+        fsm.reuse(Request::get("/next")).unwrap();
+        let response = drive(&mut fsm, &mut peer, &mut &[][..]).unwrap();
+        assert_eq!(response.body, b"abc");
+        assert!(fsm.is_reusable());
+        let received = String::from_utf8_lossy(peer.script.received());
+        assert_eq!(received.matches(" HTTP/1.1\r\n").count(), 2);
+    }
+
+    #[test]
+    fn reads_until_close_notify() {
+        let mut peer = TlsPeer::new(Script::new(&[&[
+            b"HTTP/1.1 200 OK\r\n\r\nhello",
+            b" world",
+        ]]));
+        peer.close = true;
+        let mut fsm = tls_fsm(Request::get("/"), &keep_alive());
+
+        let response = drive(&mut fsm, &mut peer, &mut &[][..]).unwrap();
+        assert_eq!(response.body, b"hello world");
+        assert!(!fsm.is_reusable());
+    }
+
+    #[test]
+    fn close_notify_before_the_body_is_complete_is_an_error() {
+        let mut peer = TlsPeer::new(Script::new(&[&[
+            b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\nhel",
+        ]]));
+        peer.close = true;
+        let mut fsm = tls_fsm(Request::get("/"), &keep_alive());
+
+        let err = drive(&mut fsm, &mut peer, &mut &[][..]).unwrap_err();
+        assert!(matches!(err, Error::Protocol(_)), "{err:?}");
+    }
+
+    #[test]
+    fn close_notify_before_a_response_is_an_error() {
+        let mut peer = TlsPeer::new(Script::new(&[]));
+        peer.close = true;
+        let mut fsm = tls_fsm(Request::get("/"), &TlsPeer::options());
+
+        let err = drive(&mut fsm, &mut peer, &mut &[][..]).unwrap_err();
+        assert!(matches!(err, Error::Protocol(_)), "{err:?}");
+    }
+
+    #[test]
+    fn eof_before_the_body_is_complete_is_an_error() {
+        let mut peer = PlainPeer::new(Script::new(&[&[
+            b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\nhel",
+        ]]));
+        let mut fsm = FSM::plain(Request::get("/"), &ClientOptions::default()).unwrap();
+
+        let err = drive(&mut fsm, &mut peer, &mut &[][..]).unwrap_err();
+        assert!(matches!(err, Error::Protocol(_)), "{err:?}");
+    }
+
+    #[cfg(feature = "decompression")]
+    #[test]
+    fn a_body_sink_keeps_the_request_from_asking_for_compression() {
+        for sink in [false, true] {
+            let mut peer = PlainPeer::new(Script::new(&[&[
+                b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok",
+            ]]));
+            let mut fsm = FSM::plain(Request::get("/"), &ClientOptions::default()).unwrap();
+            if sink {
+                fsm.set_body_sink(crate::BufferRing::new(vec![vec![0; 16]]));
+            }
+            drive(&mut fsm, &mut peer, &mut &[][..]).unwrap();
+            let received = String::from_utf8_lossy(peer.script.received());
+            assert_eq!(received.contains("accept-encoding"), !sink, "{received}");
+        }
+    }
+
+    #[test]
+    fn stops_reading_while_the_sink_is_full() {
+        let body = (0..200_000).map(|i| i as u8).collect::<Vec<_>>();
+        let head = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", body.len());
+        let pieces = std::iter::once(head.as_bytes())
+            .chain(body.chunks(4096))
+            .collect::<Vec<_>>();
+        let mut peer = TlsPeer::new(Script::new(&[&pieces]));
+        let mut fsm = tls_fsm(Request::get("/"), &TlsPeer::options());
+        fsm.set_body_sink(crate::BufferRing::new(vec![vec![0; 8192]; 2]));
+
+        let mut received = vec![];
+        let mut full = 0;
+        let response = loop {
+            match fsm.wants().unwrap() {
+                Wants::Read(buf) => {
+                    let read = peer.read(buf);
+                    fsm.done_reading(read);
+                }
+                Wants::Write(bytes) => {
+                    let written = peer.write(bytes).unwrap();
+                    fsm.done_writing(written);
+                }
+                Wants::SinkFull => {
+                    full += 1;
+                    assert!(fsm.reader.held_back() < 2 * fsm.incoming_tls.len());
+                    let ring = fsm.body_sink::<crate::BufferRing<Vec<u8>>>().unwrap();
+                    while let Some(index) = ring.pop_filled() {
+                        received.extend_from_slice(ring.get(index));
+                        ring.release(index);
+                    }
+                }
+                Wants::Body(_) => unreachable!(),
+                Wants::Done(response) => break response,
+            }
+        };
+        let ring = fsm.body_sink::<crate::BufferRing<Vec<u8>>>().unwrap();
+        ring.flush();
+        while let Some(index) = ring.pop_filled() {
+            received.extend_from_slice(ring.get(index));
+            ring.release(index);
+        }
+        received.extend_from_slice(&response.body);
+
+        assert!(full > 0);
+        assert_eq!(received, body);
+    }
+
+    fn upload(len: Option<u64>) -> Request {
+        let mut request = Request::put("/upload", vec![]);
+        request.stream_body(len);
+        request
+    }
+
+    #[cfg(feature = "digest")]
+    #[test]
+    fn sends_digests_of_a_streamed_body_as_trailers() {
+        let mut peer = PlainPeer::new(Script::new(&[&[
+            b"HTTP/1.1 201 Created\r\nContent-Length: 0\r\n\r\n",
+        ]]));
+        let mut request = upload(None);
+        request.digest(crate::DigestAlgorithm::Sha256);
+        let mut fsm = FSM::plain(request, &keep_alive()).unwrap();
+
+        let response = drive(&mut fsm, &mut peer, &mut &b"hello world"[..]).unwrap();
+        assert_eq!(response.status, 201);
+        let received = String::from_utf8_lossy(peer.script.received());
+        assert!(received.contains("trailer: Repr-Digest\r\n"), "{received}");
+        assert!(
+            received.ends_with(
+                "\r\n0\r\nrepr-digest: sha-256=:uU0nuZNNPgilLlLX2n2r+sSE7+N6U4DukIj3rOLvzek=:\r\n\r\n"
+            ),
+            "{received}"
+        );
+    }
+
+    #[cfg(feature = "digest")]
+    #[test]
+    fn trailer_digests_match_the_rfc_examples() {
+        let mut peer = PlainPeer::new(Script::new(&[&[
+            b"HTTP/1.1 201 Created\r\nContent-Length: 0\r\n\r\n",
+        ]]));
+        let mut request = upload(None);
+        request.digest(crate::DigestAlgorithm::Sha256);
+        request.digest(crate::DigestAlgorithm::Sha512);
+        request.digest(crate::DigestAlgorithm::Md5);
+        let mut fsm = FSM::plain(request, &keep_alive()).unwrap();
+
+        // RFC 9530, appendix B
+        drive(&mut fsm, &mut peer, &mut &br#"{"hello": "world"}"#[..]).unwrap();
+        let received = String::from_utf8_lossy(peer.script.received());
+        assert!(
+            received.ends_with(
+                "\r\n0\r\n\
+                 repr-digest: sha-256=:X48E9qOokqqrvdts8nOJRJN3OWDUoyWxBf7kbu9DBPE=:, \
+                 sha-512=:WZDPaVn/7XgHaAy8pmojAkGWoRx2UFChF41A2svX+TaPm+AbwAgBWnrIiYllu7BNNyealdVLvRwEmTHWXvJwew==:\r\n\
+                 content-md5: Sd/dVLAcvNLSq16eXua5uQ==\r\n\r\n"
+            ),
+            "{received}"
+        );
+    }
+
+    #[test]
+    fn reads_an_early_response_when_the_upload_is_rejected() {
+        let body = vec![b'x'; 64 * 1024];
+        let mut peer = PlainPeer::new(Script::new(&[&[
+            b"HTTP/1.1 413 Content Too Large\r\nContent-Length: 8\r\n\r\ntoo big!",
+        ]]));
+        peer.reject_after = Some(1000);
+        let mut fsm = FSM::plain(upload(Some(body.len() as u64)), &keep_alive()).unwrap();
+
+        let mut left = &body[..];
+        let response = drive(&mut fsm, &mut peer, &mut left).unwrap();
+        assert_eq!(response.status, 413);
+        assert_eq!(response.body, b"too big!");
+        assert!(fsm.is_write_aborted());
+        assert!(!fsm.is_reusable());
+        // the rest of the body wasn't asked for
+        assert!(!left.is_empty());
+        assert_eq!(peer.script.received().len(), 1000);
+    }
+
+    #[test]
+    fn reads_an_early_response_over_tls() {
+        let body = vec![b'x'; 64 * 1024];
+        let mut peer = TlsPeer::new(Script::new(&[&[
+            b"HTTP/1.1 401 Unauthorized\r\nContent-Length: 0\r\n\r\n",
+        ]]));
+        peer.reject_after = Some(1000);
+        let mut fsm = tls_fsm(upload(None), &keep_alive());
+
+        let response = drive(&mut fsm, &mut peer, &mut &body[..]).unwrap();
+        assert_eq!(response.status, 401);
+        assert!(fsm.is_write_aborted());
+        assert!(!fsm.is_reusable());
+    }
+
+    #[test]
+    fn abort_write_stops_asking_for_the_body() {
+        let mut peer = PlainPeer::new(Script::new(&[&[
+            b"HTTP/1.1 413 Content Too Large\r\nContent-Length: 0\r\n\r\n",
+        ]]));
+        let mut fsm = FSM::plain(upload(Some(10)), &keep_alive()).unwrap();
+
+        let Wants::Write(head) = fsm.wants().unwrap() else {
+            panic!("the head goes out first");
+        };
+        let written = peer.write(head).unwrap();
+        fsm.done_writing(written);
+        let Wants::Body(buf) = fsm.wants().unwrap() else {
+            panic!("then the body is asked for");
+        };
+        buf[..5].copy_from_slice(b"hello");
+        fsm.done_body(5).unwrap();
+        assert!(matches!(fsm.wants().unwrap(), Wants::Write(_)));
+
+        assert!(fsm.abort_write());
+        assert!(fsm.is_write_aborted());
+        let Wants::Read(buf) = fsm.wants().unwrap() else {
+            panic!("only the early response is left to read");
+        };
+        let read = peer.read(buf);
+        fsm.done_reading(read);
+        let Wants::Done(response) = fsm.wants().unwrap() else {
+            panic!("the response is complete");
+        };
+        assert_eq!(response.status, 413);
+        assert!(!fsm.is_reusable());
+    }
+
+    #[test]
+    fn abort_write_before_the_request_went_out_surfaces_the_error() {
+        let mut peer = TlsPeer::new(Script::new(&[&[b"HTTP/1.1 200 OK\r\n\r\n"]]));
+        let mut fsm = tls_fsm(upload(Some(10)), &keep_alive());
+
+        // the handshake isn't done, the request is still queued
+        let Wants::Write(hello) = fsm.wants().unwrap() else {
+            panic!("the handshake starts with a write");
+        };
+        assert!(peer.write(hello).is_ok());
+        assert!(!fsm.abort_write());
+    }
+
+    /// Serves the FSM until one read went through.
+    fn read_once(fsm: &mut FSM, peer: &mut impl Peer) {
+        loop {
+            match fsm.wants().unwrap() {
+                Wants::Write(bytes) => {
+                    let written = peer.write(bytes).unwrap();
+                    fsm.done_writing(written);
+                }
+                Wants::Read(buf) => {
+                    let read = peer.read(buf);
+                    fsm.done_reading(read);
+                    return;
+                }
+                _ => panic!("the response isn't complete yet"),
+            }
+        }
+    }
+
+    #[test]
+    fn drains_an_aborted_body_to_reuse_the_connection() {
+        let mut peer = TlsPeer::new(Script::new(&[
+            &[
+                b"HTTP/1.1 200 OK\r\nContent-Length: 30\r\n\r\nfirst ",
+                b"0123456789",
+                b"0123456789....",
+            ],
+            &[b"HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\nnext"],
+        ]));
+        let mut fsm = tls_fsm(Request::get("/"), &keep_alive());
+
+        while fsm.reader.body_received() == 0 {
+            read_once(&mut fsm, &mut peer);
+        }
+        fsm.abort_response();
+        let response = drive(&mut fsm, &mut peer, &mut &[][..]).unwrap();
+        assert_eq!(response.status, 200);
+        assert!(response.body.is_empty());
+        assert!(fsm.is_reusable());
+
+        fsm.reuse(Request::get("/next")).unwrap();
+        let response = drive(&mut fsm, &mut peer, &mut &[][..]).unwrap();
+        assert_eq!(response.body, b"next");
+    }
+
+    #[test]
+    fn reuse_with_applies_the_new_options() {
+        let mut peer = PlainPeer::new(Script::new(&[
+            &[b"HTTP/1.1 200 OK\r\nX-Case: 1\r\nContent-Length: 5\r\n\r\nfirst"],
+            &[b"HTTP/1.1 200 OK\r\nX-Case: 1\r\nContent-Length: 6\r\n\r\nsecond"],
+        ]));
+        let mut fsm = FSM::plain(Request::get("/"), &keep_alive()).unwrap();
+        let response = drive(&mut fsm, &mut peer, &mut &[][..]).unwrap();
+        assert!(response.trace.is_none());
+
+        let mut options = ClientOptions {
+            trace: true,
+            preserve_header_case: true,
+            ..keep_alive()
+        };
+        options.limits.max_body_size = Some(5);
+        fsm.reuse_with(Request::get("/next"), &options).unwrap();
+        let err = drive(&mut fsm, &mut peer, &mut &[][..]).unwrap_err();
+        assert!(
+            matches!(err, Error::LimitExceeded(LimitKind::BodySize)),
+            "{err:?}"
+        );
+        assert!(fsm.trace().is_some());
+
+        let mut fsm = FSM::plain(Request::get("/"), &keep_alive()).unwrap();
+        let mut peer = PlainPeer::new(Script::new(&[
+            &[b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n"],
+            &[b"HTTP/1.1 200 OK\r\nX-Case: 1\r\nContent-Length: 0\r\n\r\n"],
+        ]));
+        drive(&mut fsm, &mut peer, &mut &[][..]).unwrap();
+        fsm.reuse_with(Request::get("/next"), &options).unwrap();
+        let response = drive(&mut fsm, &mut peer, &mut &[][..]).unwrap();
+        assert!(response.trace.is_some());
+        assert_eq!(
+            response.headers.iter().next().map(|(name, _)| name),
+            Some("X-Case")
+        );
+    }
+
+    #[test]
+    fn drains_an_aborted_chunked_body() {
+        let mut peer = PlainPeer::new(Script::new(&[
+            &[
+                b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n5\r\nfirst\r\n",
+                b"a\r\n0123456789\r\n",
+                b"0\r\n\r\n",
+            ],
+            &[b"HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\nnext"],
+        ]));
+        let mut fsm = FSM::plain(Request::get("/"), &keep_alive()).unwrap();
+
+        read_once(&mut fsm, &mut peer);
+        fsm.abort_response();
+        let response = drive(&mut fsm, &mut peer, &mut &[][..]).unwrap();
+        assert!(response.body.is_empty());
+        assert!(fsm.is_reusable());
+
+        fsm.reuse(Request::get("/next")).unwrap();
+        let response = drive(&mut fsm, &mut peer, &mut &[][..]).unwrap();
+        assert_eq!(response.body, b"next");
+    }
+
+    #[test]
+    fn closes_when_the_aborted_body_is_over_the_drain_budget() {
+        let mut peer = PlainPeer::new(Script::new(&[&[
+            b"HTTP/1.1 200 OK\r\nContent-Length: 1000\r\n\r\nfirst",
+            b"never read",
+        ]]));
+        let options = ClientOptions {
+            drain_budget: 100,
+            ..keep_alive()
+        };
+        let mut fsm = FSM::plain(Request::get("/"), &options).unwrap();
+
+        read_once(&mut fsm, &mut peer);
+        fsm.abort_response();
+        let Wants::Done(response) = fsm.wants().unwrap() else {
+            panic!("the exchange ends without reading more");
+        };
+        assert_eq!(response.status, 200);
+        assert!(response.body.is_empty());
+        assert!(!fsm.is_reusable());
+    }
+
+    #[test]
+    fn aborting_without_keep_alive_closes() {
+        let mut peer = PlainPeer::new(Script::new(&[&[
+            b"HTTP/1.1 200 OK\r\nContent-Length: 10\r\n\r\nfirst",
+            b"never",
+        ]]));
+        let mut fsm = FSM::plain(Request::get("/"), &ClientOptions::default()).unwrap();
+
+        read_once(&mut fsm, &mut peer);
+        fsm.abort_response();
+        assert!(matches!(fsm.wants().unwrap(), Wants::Done(_)));
+        assert!(!fsm.is_reusable());
+    }
+}
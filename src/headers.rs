@@ -0,0 +1,210 @@
+//! The header map and parsers for the values of common fields.
+
+pub mod cache_control;
+pub mod content_type;
+pub mod date;
+pub mod link;
+
+use crate::redact::{REDACTED, is_sensitive_header};
+use smallvec::SmallVec;
+use std::fmt;
+
+/// Fields kept inline before `Headers` spills to the heap,
+/// enough for most requests and responses.
+const INLINE_HEADERS: usize = 16;
+
+/// Ordered header list that keeps repeated fields (e.g. `Set-Cookie`).
+/// Names are stored lowercased unless the map preserves case, lookups are
+/// case-insensitive either way. `Debug` masks credentials (`Authorization`,
+/// `Cookie`...), `get` and iterating give the actual values.
+#[derive(Clone, Default)]
+pub struct Headers {
+    entries: SmallVec<[(String, String); INLINE_HEADERS]>,
+    preserve_case: bool,
+}
+
+impl Headers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Keeps names as they're added, e.g. for signatures over the exact
+    /// header bytes. Responses come in such a map with
+    /// `ClientOptions::preserve_header_case`, a request's headers go out
+    /// as they're stored.
+    pub fn preserving_case() -> Self {
+        Self {
+            preserve_case: true,
+            ..Self::default()
+        }
+    }
+
+    pub(crate) fn with_capacity(capacity: usize, preserve_case: bool) -> Self {
+        Self {
+            entries: SmallVec::with_capacity(capacity),
+            preserve_case,
+        }
+    }
+
+    pub fn preserves_case(&self) -> bool {
+        self.preserve_case
+    }
+
+    /// First value of the field.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(existing, _)| existing.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a str> {
+        self.entries
+            .iter()
+            .filter(move |(existing, _)| existing.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.get(name).is_some()
+    }
+
+    /// Replaces every existing value of the field.
+    pub fn insert(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        let name = name.into();
+        self.remove(&name);
+        self.append(name, value);
+    }
+
+    pub fn append(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        let mut name = name.into();
+        if !self.preserve_case {
+            name.make_ascii_lowercase();
+        }
+        self.entries.push((name, value.into()));
+    }
+
+    /// Removes every value of the field, returning the first one.
+    pub fn remove(&mut self, name: &str) -> Option<String> {
+        let mut removed = None;
+        self.entries.retain_mut(|(existing, value)| {
+            if existing.eq_ignore_ascii_case(name) {
+                removed.get_or_insert_with(|| std::mem::take(value));
+                false
+            } else {
+                true
+            }
+        });
+        removed
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.into_iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Names are compared case-insensitively, so a map that preserves case
+/// equals its lowercased counterpart.
+impl PartialEq for Headers {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len()
+            && self
+                .iter()
+                .zip(other)
+                .all(|((name, value), (other_name, other_value))| {
+                    name.eq_ignore_ascii_case(other_name) && value == other_value
+                })
+    }
+}
+
+impl Eq for Headers {}
+
+impl fmt::Debug for Headers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.iter().map(|(name, value)| {
+                (
+                    name,
+                    if is_sensitive_header(name) {
+                        REDACTED
+                    } else {
+                        value
+                    },
+                )
+            }))
+            .finish()
+    }
+}
+
+impl<'a> IntoIterator for &'a Headers {
+    type Item = (&'a str, &'a str);
+    type IntoIter = std::iter::Map<
+        std::slice::Iter<'a, (String, String)>,
+        fn(&'a (String, String)) -> (&'a str, &'a str),
+    >;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+}
+
+/// Splits a list on `separator`s outside of quoted strings and `<>`,
+/// items are trimmed and empty ones skipped.
+pub(crate) fn split_list(value: &str, separator: char) -> impl Iterator<Item = &str> {
+    let mut items = vec![];
+    let (mut start, mut quoted, mut escaped, mut bracketed) = (0, false, false, false);
+    for (idx, c) in value.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' if !bracketed => quoted = !quoted,
+            '<' if !quoted => bracketed = true,
+            '>' if !quoted => bracketed = false,
+            _ if c == separator && !quoted && !bracketed => {
+                items.push(&value[start..idx]);
+                start = idx + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    items.push(&value[start..]);
+
+    items
+        .into_iter()
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+}
+
+/// `name`, `name=token` or `name="quoted string"`,
+/// returns the lowercased name and the unescaped value.
+pub(crate) fn parse_param(param: &str) -> (String, Option<String>) {
+    let Some((name, value)) = param.split_once('=') else {
+        return (param.trim().to_ascii_lowercase(), None);
+    };
+    let name = name.trim().to_ascii_lowercase();
+    let value = value.trim();
+
+    let Some(quoted) = value.strip_prefix('"') else {
+        return (name, Some(value.to_string()));
+    };
+    let mut out = String::with_capacity(quoted.len());
+    let mut chars = quoted.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => break,
+            '\\' => out.extend(chars.next()),
+            _ => out.push(c),
+        }
+    }
+    (name, Some(out))
+}
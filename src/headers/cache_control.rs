@@ -0,0 +1,128 @@
+//! `Cache-Control` directives (RFC 9111, section 5.2).
+
+use super::{parse_param, split_list};
+use crate::Headers;
+use std::time::Duration;
+
+/// The directives of a response's `Cache-Control`, the request-only ones
+/// (e.g. `max-stale`) end up in `extensions`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CacheControl {
+    pub max_age: Option<Duration>,
+    pub s_maxage: Option<Duration>,
+    pub stale_while_revalidate: Option<Duration>,
+    pub stale_if_error: Option<Duration>,
+    pub no_cache: bool,
+    pub no_store: bool,
+    pub no_transform: bool,
+    pub must_revalidate: bool,
+    pub proxy_revalidate: bool,
+    pub must_understand: bool,
+    pub public: bool,
+    pub private: bool,
+    pub immutable: bool,
+    /// Anything else, with the name lowercased.
+    pub extensions: Vec<(String, Option<String>)>,
+}
+
+impl CacheControl {
+    /// Directives with an invalid argument (e.g. `max-age=soon`) are skipped.
+    pub fn parse(value: &str) -> Self {
+        let mut out = Self::default();
+        for directive in split_list(value, ',') {
+            let (name, argument) = parse_param(directive);
+            let seconds = || {
+                let seconds = argument.as_deref()?.parse::<u64>().ok()?;
+                Some(Duration::from_secs(seconds))
+            };
+
+            match name.as_str() {
+                "max-age" => out.max_age = seconds().or(out.max_age),
+                "s-maxage" => out.s_maxage = seconds().or(out.s_maxage),
+                "stale-while-revalidate" => {
+                    out.stale_while_revalidate = seconds().or(out.stale_while_revalidate);
+                }
+                "stale-if-error" => out.stale_if_error = seconds().or(out.stale_if_error),
+                // `no-cache="Set-Cookie"` limits it to some fields, it's
+                // treated as unqualified here
+                "no-cache" => out.no_cache = true,
+                "no-store" => out.no_store = true,
+                "no-transform" => out.no_transform = true,
+                "must-revalidate" => out.must_revalidate = true,
+                "proxy-revalidate" => out.proxy_revalidate = true,
+                "must-understand" => out.must_understand = true,
+                "public" => out.public = true,
+                "private" => out.private = true,
+                "immutable" => out.immutable = true,
+                _ => out.extensions.push((name, argument)),
+            }
+        }
+        out
+    }
+
+    /// All `Cache-Control` fields of `headers` taken together.
+    pub fn from_headers(headers: &Headers) -> Self {
+        let value = headers
+            .get_all("Cache-Control")
+            .collect::<Vec<_>>()
+            .join(",");
+        Self::parse(&value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_directives() {
+        let parsed = CacheControl::parse(
+            "public, Max-Age=60, s-maxage=\"120\", stale-while-revalidate=30, \
+             no-cache=\"Set-Cookie, Vary\", immutable, community=\"UCI\"",
+        );
+        assert_eq!(
+            parsed,
+            CacheControl {
+                max_age: Some(Duration::from_secs(60)),
+                s_maxage: Some(Duration::from_secs(120)),
+                stale_while_revalidate: Some(Duration::from_secs(30)),
+                no_cache: true,
+                public: true,
+                immutable: true,
+                extensions: vec![("community".to_string(), Some("UCI".to_string()))],
+                ..CacheControl::default()
+            }
+        );
+        // spacing and quoting don't matter
+        assert_eq!(
+            CacheControl::parse(
+                "public,max-age=\"60\",s-maxage=120,stale-while-revalidate = 30,no-cache,immutable,community=UCI"
+            ),
+            parsed
+        );
+    }
+
+    #[test]
+    fn skips_invalid_arguments() {
+        let parsed = CacheControl::parse("max-age=10, max-age=soon, s-maxage=-1, stale-if-error=");
+        assert_eq!(parsed.max_age, Some(Duration::from_secs(10)));
+        assert_eq!(parsed.s_maxage, None);
+        assert_eq!(parsed.stale_if_error, None);
+        assert_eq!(CacheControl::parse(""), CacheControl::default());
+        assert_eq!(CacheControl::parse(",, ,"), CacheControl::default());
+        assert_eq!(
+            CacheControl::parse("max-age=99999999999999999999999").max_age,
+            None
+        );
+    }
+
+    #[test]
+    fn joins_repeated_fields() {
+        let mut headers = Headers::new();
+        headers.append("Cache-Control", "no-store");
+        headers.append("cache-control", "private, max-age=0");
+        let parsed = CacheControl::from_headers(&headers);
+        assert!(parsed.no_store && parsed.private);
+        assert_eq!(parsed.max_age, Some(Duration::ZERO));
+    }
+}
@@ -0,0 +1,101 @@
+//! Media types as used by `Content-Type` and `Accept` (RFC 9110, section 8.3.1).
+
+use super::{parse_param, split_list};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MediaType {
+    /// `type/subtype`, lowercased.
+    pub essence: String,
+    /// Names are lowercased, values are unquoted but otherwise as sent.
+    pub params: Vec<(String, String)>,
+}
+
+impl MediaType {
+    /// `None` if there's no `type/subtype` pair.
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut parts = split_list(value, ';');
+        let essence = parts.next()?.to_ascii_lowercase();
+        match essence.split_once('/') {
+            Some((kind, subtype)) if !kind.is_empty() && !subtype.is_empty() => {}
+            _ => return None,
+        }
+
+        let params = parts
+            .filter_map(|param| match parse_param(param) {
+                (name, Some(value)) => Some((name, value)),
+                (_, None) => None,
+            })
+            .collect();
+        Some(Self { essence, params })
+    }
+
+    pub fn kind(&self) -> &str {
+        self.essence.split_once('/').map_or("", |(kind, _)| kind)
+    }
+
+    pub fn subtype(&self) -> &str {
+        self.essence
+            .split_once('/')
+            .map_or("", |(_, subtype)| subtype)
+    }
+
+    /// First value of the parameter, the name is case-insensitive.
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(existing, _)| existing.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    pub fn charset(&self) -> Option<&str> {
+        self.param("charset")
+    }
+
+    /// `application/json` or any `+json` type (e.g. `application/problem+json`).
+    pub fn is_json(&self) -> bool {
+        self.essence == "application/json" || self.subtype().ends_with("+json")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_media_types() {
+        let parsed =
+            MediaType::parse("Text/HTML; Charset=\"utf-8\"; q=0.5; boundary=\"a;b\"").unwrap();
+        assert_eq!(parsed.essence, "text/html");
+        assert_eq!((parsed.kind(), parsed.subtype()), ("text", "html"));
+        assert_eq!(parsed.charset(), Some("utf-8"));
+        assert_eq!(parsed.param("BOUNDARY"), Some("a;b"));
+        assert_eq!(
+            MediaType::parse("text/html;charset=utf-8;q=0.5;boundary=\"a;b\"")
+                .unwrap()
+                .params,
+            parsed.params
+        );
+
+        assert!(
+            MediaType::parse("application/problem+json")
+                .unwrap()
+                .is_json()
+        );
+        assert!(
+            MediaType::parse("application/json; charset=utf-8")
+                .unwrap()
+                .is_json()
+        );
+        assert!(!MediaType::parse("application/jsonl").unwrap().is_json());
+    }
+
+    #[test]
+    fn rejects_malformed_media_types() {
+        for value in ["", ";charset=utf-8", "text", "text/", "/html", " / "] {
+            assert_eq!(MediaType::parse(value), None, "{value:?}");
+        }
+        // parameters without a value are dropped
+        let parsed = MediaType::parse("text/plain; flowed; charset").unwrap();
+        assert!(parsed.params.is_empty());
+    }
+}
@@ -0,0 +1,176 @@
+//! HTTP dates (RFC 9110, section 5.6.7) as used by `Date`, `Expires`,
+//! `Last-Modified` or `Retry-After`.
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Accepts the IMF-fixdate (`Sun, 06 Nov 1994 08:49:37 GMT`) and the two
+/// obsolete forms: RFC 850 (`Sunday, 06-Nov-94 08:49:37 GMT`) and asctime
+/// (`Sun Nov  6 08:49:37 1994`).
+pub fn parse(value: &str) -> Option<SystemTime> {
+    let mut parts = value.split_ascii_whitespace();
+    let weekday = parts.next()?;
+
+    let (day, month, year, time) = if weekday.ends_with(',') {
+        match parts.next()?.split('-').collect::<Vec<_>>()[..] {
+            [day, month, year] => {
+                // two digit years are within 50 years from now, close enough
+                let year = match year.parse::<u64>().ok()? {
+                    year @ 0..=69 => year + 2000,
+                    year @ 70..=99 => year + 1900,
+                    year => year,
+                };
+                (day, month, year, parts.next()?)
+            }
+            [day] => {
+                let month = parts.next()?;
+                let year = parts.next()?.parse().ok()?;
+                (day, month, year, parts.next()?)
+            }
+            _ => return None,
+        }
+    } else {
+        let month = parts.next()?;
+        let day = parts.next()?;
+        let time = parts.next()?;
+        let year = parts.next()?.parse().ok()?;
+        if parts.next().is_some() {
+            return None;
+        }
+        (day, month, year, time)
+    };
+    if weekday.ends_with(',') && (parts.next() != Some("GMT") || parts.next().is_some()) {
+        return None;
+    }
+
+    let day = day.parse::<u64>().ok()?;
+    let month = MONTHS.iter().position(|&name| name == month)? as u64 + 1;
+    let mut time = time.split(':').map(|part| part.parse::<u64>().ok());
+    let (hours, minutes, seconds) = (time.next()??, time.next()??, time.next()??);
+    if time.next().is_some()
+        || !(1..=31).contains(&day)
+        || !(1970..=9999).contains(&year)
+        || hours > 23
+        || minutes > 59
+        || seconds > 60
+    {
+        return None;
+    }
+
+    let secs = days_from_civil(year, month, day)?
+        .checked_mul(86400)?
+        .checked_add(hours * 3600 + minutes * 60 + seconds)?;
+    UNIX_EPOCH.checked_add(Duration::from_secs(secs))
+}
+
+/// The IMF-fixdate, the only form to send.
+pub fn format(time: SystemTime) -> String {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    let (days, secs) = (secs / 86400, secs % 86400);
+    let (year, month, day) = civil_from_days(days);
+    // 1970-01-01 was a Thursday
+    let weekday = WEEKDAYS[((days + 4) % 7) as usize];
+
+    format!(
+        "{weekday}, {day:02} {} {year:04} {:02}:{:02}:{:02} GMT",
+        MONTHS[month as usize - 1],
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+// http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+fn days_from_civil(year: u64, month: u64, day: u64) -> Option<u64> {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let yoe = year - era * 400;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era.checked_mul(146_097)?
+        .checked_add(doe)?
+        .checked_sub(719_468)
+}
+
+/// Days since 1970-01-01 to (year, month, day), see
+/// http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+pub(crate) fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z % 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secs(value: &str) -> Option<u64> {
+        parse(value).map(|time| time.duration_since(UNIX_EPOCH).unwrap().as_secs())
+    }
+
+    #[test]
+    fn parses_all_three_forms() {
+        for value in [
+            "Sun, 06 Nov 1994 08:49:37 GMT",
+            "Sunday, 06-Nov-94 08:49:37 GMT",
+            "Sun Nov  6 08:49:37 1994",
+        ] {
+            assert_eq!(secs(value), Some(784_111_777), "{value}");
+        }
+    }
+
+    #[test]
+    fn round_trips() {
+        for value in [
+            "Thu, 01 Jan 1970 00:00:00 GMT",
+            "Tue, 29 Feb 2000 12:00:00 GMT",
+            "Fri, 31 Dec 9999 23:59:59 GMT",
+        ] {
+            assert_eq!(format(parse(value).unwrap()), value);
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_dates() {
+        for value in [
+            "",
+            "Sun, 06 Nov 1994 08:49:37",
+            "Sun, 06 Nov 1994 08:49:37 UTC",
+            "Sun, 06 Foo 1994 08:49:37 GMT",
+            "Sun, 32 Nov 1994 08:49:37 GMT",
+            "Sun, 06 Nov 1994 24:00:00 GMT",
+            "Sun, 06 Nov 1994 08:49 GMT",
+            "Sun, 06 Nov 1969 08:49:37 GMT",
+            "Sun Nov  6 08:49:37 1994 extra",
+        ] {
+            assert_eq!(parse(value), None, "{value}");
+        }
+    }
+
+    #[test]
+    fn rejects_years_past_9999() {
+        for value in [
+            "Sat, 01 Jan 10000 00:00:00 GMT",
+            "Sun, 06 Nov 18446744073709551615 08:49:37 GMT",
+            "Sunday, 06-Nov-18446744073709551615 08:49:37 GMT",
+            "Sun Nov  6 08:49:37 99999999999999",
+        ] {
+            assert_eq!(parse(value), None, "{value}");
+        }
+    }
+}
@@ -0,0 +1,104 @@
+//! Web links from the `Link` header (RFC 8288), e.g. for pagination.
+
+use super::{parse_param, split_list};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Link {
+    /// The URI reference between `<>`, relative ones are left as is.
+    pub target: String,
+    /// Names are lowercased, values are unquoted.
+    pub params: Vec<(String, String)>,
+}
+
+impl Link {
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(existing, _)| existing.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// `rel` holds a space-separated list, e.g. `rel="next last"`.
+    pub fn has_rel(&self, rel: &str) -> bool {
+        self.param("rel").is_some_and(|rels| {
+            rels.split_ascii_whitespace()
+                .any(|existing| existing.eq_ignore_ascii_case(rel))
+        })
+    }
+}
+
+/// Malformed links are skipped.
+pub fn parse(value: &str) -> Vec<Link> {
+    split_list(value, ',')
+        .filter_map(|link| {
+            let mut parts = split_list(link, ';');
+            let target = parts.next()?.strip_prefix('<')?.strip_suffix('>')?;
+            let params = parts
+                .map(|param| {
+                    let (name, value) = parse_param(param);
+                    (name, value.unwrap_or_default())
+                })
+                .collect();
+            Some(Link {
+                target: target.trim().to_string(),
+                params,
+            })
+        })
+        .collect()
+}
+
+/// The target of the first link with `rel`, e.g. `find(value, "next")`.
+pub fn find(value: &str, rel: &str) -> Option<String> {
+    parse(value)
+        .into_iter()
+        .find(|link| link.has_rel(rel))
+        .map(|link| link.target)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGINATION: &str = "<https://api.example.com/items?page=2>; rel=\"next\", \
+         <https://api.example.com/items?page=9>; REL=\"last end\"; title=\"a, b\"";
+
+    #[test]
+    fn parses_links() {
+        let links = parse(PAGINATION);
+        assert_eq!(
+            links,
+            [
+                Link {
+                    target: "https://api.example.com/items?page=2".to_string(),
+                    params: vec![("rel".to_string(), "next".to_string())],
+                },
+                Link {
+                    target: "https://api.example.com/items?page=9".to_string(),
+                    params: vec![
+                        ("rel".to_string(), "last end".to_string()),
+                        ("title".to_string(), "a, b".to_string()),
+                    ],
+                },
+            ]
+        );
+        assert!(links[1].has_rel("END"));
+        assert!(!links[1].has_rel("las"));
+        assert_eq!(
+            find(PAGINATION, "last").as_deref(),
+            Some("https://api.example.com/items?page=9")
+        );
+        assert_eq!(find(PAGINATION, "prev"), None);
+        // commas and semicolons inside the brackets belong to the target
+        assert_eq!(parse("</a,b;c>;rel=x")[0].target, "/a,b;c");
+    }
+
+    #[test]
+    fn skips_malformed_links() {
+        assert_eq!(parse(""), []);
+        assert_eq!(parse("https://example.com; rel=next"), []);
+        assert_eq!(parse("<https://example.com; rel=next"), []);
+        let links = parse("nope, </ok>; rel=next, <unterminated");
+        assert_eq!(links.len(), 1);
+        assert_eq!(links[0].target, "/ok");
+    }
+}
@@ -0,0 +1,72 @@
+const IORING_OP_PROVIDE_BUFFERS: u8 = 31;
+const IORING_OP_SOCKET: u8 = 45;
+const IORING_OP_SEND_ZC: u8 = 47;
+
+/// The io_uring operations of interest the running kernel supports, see
+/// `IoUringConnection::set_capabilities`. Probe them once at startup,
+/// with `detect` or `from_probe`, and share the result between connections.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IoUringCapabilities {
+    /// `IORING_OP_SOCKET` (Linux 5.19). Without it the socket is created
+    /// with `socket(2)` and only connecting goes through the ring.
+    pub socket: bool,
+    /// `IORING_OP_SEND_ZC` (Linux 6.0).
+    pub send_zc: bool,
+    /// `IORING_RECV_MULTISHOT` (Linux 6.0). It's a flag rather than an
+    /// opcode and can't be probed, it's assumed along with `send_zc`
+    /// which came in the same release.
+    pub recv_multishot: bool,
+    /// `IORING_OP_PROVIDE_BUFFERS` (Linux 5.7).
+    pub provide_buffers: bool,
+}
+
+/// Everything, what `IoUringConnection` assumes until told otherwise.
+impl Default for IoUringCapabilities {
+    fn default() -> Self {
+        Self {
+            socket: true,
+            send_zc: true,
+            recv_multishot: true,
+            provide_buffers: true,
+        }
+    }
+}
+
+impl IoUringCapabilities {
+    /// None of them, e.g. for kernels before 5.6 that can't be probed.
+    pub const NONE: Self = Self {
+        socket: false,
+        send_zc: false,
+        recv_multishot: false,
+        provide_buffers: false,
+    };
+
+    /// From the result of `IORING_REGISTER_PROBE`, `supported` tells
+    /// whether an opcode is flagged `IO_URING_OP_SUPPORTED`.
+    pub fn from_probe(supported: impl Fn(u8) -> bool) -> Self {
+        Self {
+            socket: supported(IORING_OP_SOCKET),
+            send_zc: supported(IORING_OP_SEND_ZC),
+            recv_multishot: supported(IORING_OP_SEND_ZC),
+            provide_buffers: supported(IORING_OP_PROVIDE_BUFFERS),
+        }
+    }
+
+    /// Probes with a ring of its own on the first call, `None` if no ring
+    /// can be set up at all: before Linux 5.1, with
+    /// `kernel.io_uring_disabled` set or blocked by seccomp.
+    #[cfg(feature = "io-uring-with-dep")]
+    pub fn detect() -> Option<Self> {
+        static DETECTED: std::sync::LazyLock<Option<IoUringCapabilities>> =
+            std::sync::LazyLock::new(|| {
+                let ring = io_uring::IoUring::new(2).ok()?;
+                let mut probe = io_uring::Probe::new();
+                // every opcode above is newer than probing (5.6)
+                Some(match ring.submitter().register_probe(&mut probe) {
+                    Ok(()) => IoUringCapabilities::from_probe(|opcode| probe.is_supported(opcode)),
+                    Err(_) => IoUringCapabilities::NONE,
+                })
+            });
+        *DETECTED
+    }
+}
@@ -1,41 +1,142 @@
-use crate::{FSM, Request, Response, Wants};
-use anyhow::{Result, bail};
-use libc::{AF_INET, SOCK_STREAM, addrinfo, freeaddrinfo, gai_strerror, sockaddr, sockaddr_in};
-use rustls::pki_types::ServerName;
+use crate::{
+    ClientOptions, Error, FSM, IoUringCapabilities, NetObserver, Request, Response, Result, Scheme,
+    TimeoutKind, Timeouts, TlsInfo, Url, Wants,
+    deadline::{deadline, remaining},
+    fsm::sink_full,
+    net_event::{emit_connect, emit_resolve},
+    sock_addr::SockAddr,
+    url::host_header,
+};
+use libc::{
+    AF_INET, AF_INET6, AF_UNSPEC, EAGAIN, ECANCELED, ECONNABORTED, ECONNRESET, EINTR, EINVAL,
+    EPIPE, SOCK_CLOEXEC, SOCK_STREAM, addrinfo, freeaddrinfo, gai_strerror, sockaddr, sockaddr_in,
+    sockaddr_in6,
+};
 use std::{
-    collections::HashSet,
+    collections::{HashSet, VecDeque},
     ffi::{CStr, CString},
+    io,
     mem::MaybeUninit,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6},
+    os::fd::RawFd,
     ptr::null_mut,
+    sync::Arc,
+    time::{Duration, Instant},
 };
 
 #[derive(Default)]
 enum State {
     Initialized {
-        addr: sockaddr_in,
+        addr: SockAddr,
     },
     Connecting {
-        fd: i32,
-        addr: sockaddr_in,
+        fd: Fd,
+        addr: SockAddr,
     },
     Connected {
-        fd: i32,
+        fd: Fd,
     },
+    /// The response is in and `Sqe::Close` was handed out.
+    Closing,
+    Closed,
     #[default]
     None,
 }
 
+struct UserData {
+    /// Not used for sockets connected by the caller.
+    /// Also identifies `Sqe::Close`, the two never overlap.
+    socket: Option<u64>,
+    connect: Option<u64>,
+    read: u64,
+    write: u64,
+    timeout: u64,
+}
+
+/// Bits of a derived user_data that tell its operation apart,
+/// see `IoUringConnection::get_url_with_id`.
+const OP_BITS: u32 = 3;
+
+impl UserData {
+    fn from_id(id: u64) -> Result<Self> {
+        if id >> (u64::BITS - OP_BITS) != 0 {
+            return Err(Error::InvalidRequest(format!(
+                "user_data id {id} doesn't leave room for the operation bits"
+            )));
+        }
+        let op = |op: u64| (id << OP_BITS) | op;
+        Ok(Self {
+            socket: Some(op(0)),
+            connect: Some(op(1)),
+            read: op(2),
+            write: op(3),
+            timeout: op(4),
+        })
+    }
+}
+
 pub struct IoUringConnection {
     fsm: FSM,
     state: State,
-    socket_user_data: u64,
-    connect_user_data: u64,
-    read_user_data: u64,
-    write_user_data: u64,
+    /// Addresses to fall back to if connecting to the current one fails.
+    addrs: VecDeque<SocketAddr>,
+    /// The address being connected to and since when, for `NetObserver`.
+    attempt: Option<(SocketAddr, Instant)>,
+    observer: Option<NetObserver>,
+    user_data: UserData,
     pending: HashSet<u64>,
+    fixed_buffers: Option<FixedBuffers>,
+    capabilities: IoUringCapabilities,
+    direct: Direct,
+    /// The socket this connection created and hasn't closed yet,
+    /// unless it's a direct descriptor.
+    owned_fd: Option<RawFd>,
+
+    timeouts: Timeouts,
+    deadline: Option<Instant>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Direct {
+    /// Regular fds.
+    Off,
+    /// Direct descriptors, the kernel picks the slot for the first socket.
+    Alloc,
+    /// Direct descriptors, later attempts replace the socket in this slot.
+    Slot(u32),
+}
+
+/// Where the FSM's buffers were when they got registered, they move when
+/// they grow and then the plain `Read`/`Write` is used again.
+struct FixedBuffers {
+    read: Registered,
+    write: Registered,
+}
+
+struct Registered {
+    index: u16,
+    start: usize,
+    len: usize,
+}
+
+impl Registered {
+    fn new(index: u16, buf: &[u8]) -> Self {
+        Self {
+            index,
+            start: buf.as_ptr() as usize,
+            len: buf.len(),
+        }
+    }
+
+    /// The buffer index for an SQE over `buf`, if it's within this buffer.
+    fn index_for(&self, buf: &[u8]) -> Option<u16> {
+        let start = buf.as_ptr() as usize;
+        (start >= self.start && start + buf.len() <= self.start + self.len).then_some(self.index)
+    }
 }
 
 impl IoUringConnection {
+    #[allow(clippy::too_many_arguments)]
     pub fn get(
         hostname: &str,
         port: u16,
@@ -44,52 +145,371 @@ impl IoUringConnection {
         connect_user_data: u64,
         read_user_data: u64,
         write_user_data: u64,
+        timeout_user_data: u64,
+        options: &ClientOptions,
     ) -> Result<Self> {
-        let fsm = {
-            let server_name = ServerName::try_from(hostname)?.to_owned();
+        Self::with_request(
+            Scheme::Https,
+            hostname,
+            port,
+            resolve(hostname, port, options)?,
+            Request::get(path),
+            UserData {
+                socket: Some(socket_user_data),
+                connect: Some(connect_user_data),
+                read: read_user_data,
+                write: write_user_data,
+                timeout: timeout_user_data,
+            },
+            options,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn get_url(
+        url: &str,
+        socket_user_data: u64,
+        connect_user_data: u64,
+        read_user_data: u64,
+        write_user_data: u64,
+        timeout_user_data: u64,
+        options: &ClientOptions,
+    ) -> Result<Self> {
+        let url = Url::parse(url)?;
+        Self::with_request(
+            url.scheme,
+            &url.host,
+            url.port,
+            resolve(&url.host, url.port, options)?,
+            url.get_request(),
+            UserData {
+                socket: Some(socket_user_data),
+                connect: Some(connect_user_data),
+                read: read_user_data,
+                write: write_user_data,
+                timeout: timeout_user_data,
+            },
+            options,
+        )
+    }
+
+    /// Like `get_url`, but every user_data the connection uses is derived
+    /// from `id` (e.g. a slab key), with the operation in the low bits: one
+    /// id per connection is all the caller has to keep unique, and
+    /// `id_of` tells which connection a CQE belongs to. `id` must be
+    /// below 2^61.
+    pub fn get_url_with_id(url: &str, id: u64, options: &ClientOptions) -> Result<Self> {
+        let url = Url::parse(url)?;
+        Self::with_request(
+            url.scheme,
+            &url.host,
+            url.port,
+            resolve(&url.host, url.port, options)?,
+            url.get_request(),
+            UserData::from_id(id)?,
+            options,
+        )
+    }
+
+    /// Sends an arbitrary request, `Host` is added unless the request
+    /// already sets it. user_data is derived from `id` as in
+    /// `get_url_with_id`.
+    pub fn new(
+        scheme: Scheme,
+        hostname: &str,
+        port: u16,
+        request: Request,
+        id: u64,
+        options: &ClientOptions,
+    ) -> Result<Self> {
+        Self::with_request(
+            scheme,
+            hostname,
+            port,
+            resolve(hostname, port, options)?,
+            request,
+            UserData::from_id(id)?,
+            options,
+        )
+    }
+
+    /// The id a user_data of a connection made with `new` or
+    /// `get_url_with_id` was derived from.
+    pub fn id_of(user_data: u64) -> u64 {
+        user_data >> OP_BITS
+    }
+
+    /// Sends `request` to one of `addrs`, tried in the given order, without
+    /// resolving `hostname` (it's still used for SNI, certificate
+    /// verification and the `Host` header). `getaddrinfo` blocks, so
+    /// callers with an async resolver should prefer this over `get`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn send_to(
+        scheme: Scheme,
+        hostname: &str,
+        addrs: &[SocketAddr],
+        request: Request,
+        socket_user_data: u64,
+        connect_user_data: u64,
+        read_user_data: u64,
+        write_user_data: u64,
+        timeout_user_data: u64,
+        options: &ClientOptions,
+    ) -> Result<Self> {
+        Self::with_request(
+            scheme,
+            hostname,
+            addrs
+                .first()
+                .map_or(scheme.default_port(), SocketAddr::port),
+            addrs.to_vec(),
+            request,
+            UserData {
+                socket: Some(socket_user_data),
+                connect: Some(connect_user_data),
+                read: read_user_data,
+                write: write_user_data,
+                timeout: timeout_user_data,
+            },
+            options,
+        )
+    }
 
-            let mut request = Request::get(path);
-            request.add_header("Host", hostname);
-            request.add_header("Connection", "close");
+    /// Sends `request` over a socket the caller has already connected (e.g. a
+    /// unix socket or one bound to a specific interface), `hostname` and `port`
+    /// are only used for SNI, certificate verification and the `Host` header.
+    /// The caller keeps ownership of `fd`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn send_over(
+        fd: RawFd,
+        scheme: Scheme,
+        hostname: &str,
+        port: u16,
+        request: Request,
+        read_user_data: u64,
+        write_user_data: u64,
+        timeout_user_data: u64,
+        options: &ClientOptions,
+    ) -> Result<Self> {
+        let mut conn = Self::with_request(
+            scheme,
+            hostname,
+            port,
+            vec![],
+            request,
+            UserData {
+                socket: None,
+                connect: None,
+                read: read_user_data,
+                write: write_user_data,
+                timeout: timeout_user_data,
+            },
+            options,
+        )?;
+        conn.state = State::Connected { fd: Fd::Raw(fd) };
+        Ok(conn)
+    }
 
-            FSM::new(server_name, request)?
+    fn with_request(
+        scheme: Scheme,
+        hostname: &str,
+        port: u16,
+        addrs: Vec<SocketAddr>,
+        mut request: Request,
+        user_data: UserData,
+        options: &ClientOptions,
+    ) -> Result<Self> {
+        let fsm = {
+            request.add_default_header("Host", &host_header(scheme, hostname, port));
+
+            FSM::with_scheme(scheme, hostname, request, options)?
         };
 
-        let mut addr = getaddrinfo(hostname)?;
-        addr.sin_port = port.to_be();
+        let mut addrs = VecDeque::from(addrs);
+        let attempt = addrs.front().map(|addr| (*addr, Instant::now()));
+        let state = match addrs.pop_front() {
+            Some(addr) => State::Initialized {
+                addr: SockAddr::new(addr),
+            },
+            None if user_data.socket.is_none() => State::None,
+            None => {
+                return Err(Error::Dns(format!(
+                    "no addresses to connect to for {hostname}"
+                )));
+            }
+        };
 
         Ok(Self {
             fsm,
-            state: State::Initialized { addr },
-            socket_user_data,
-            connect_user_data,
-            read_user_data,
-            write_user_data,
+            state,
+            addrs,
+            attempt,
+            observer: options.net_observer.clone(),
+            user_data,
             pending: HashSet::new(),
+            fixed_buffers: None,
+            capabilities: IoUringCapabilities::default(),
+            direct: Direct::Off,
+            owned_fd: None,
+
+            timeouts: options.timeouts,
+            deadline: deadline(&options.timeouts),
+        })
+    }
+
+    /// See `FSM::tls_info`, available before the response is.
+    pub fn tls_info(&self) -> Option<Arc<TlsInfo>> {
+        self.fsm.tls_info()
+    }
+
+    /// The socket once it's connected, to be registered with the ring for
+    /// `use_fixed_file`. `None` after `use_fixed_file`.
+    pub fn fd(&self) -> Option<RawFd> {
+        match self.state {
+            State::Connected { fd: Fd::Raw(fd) } => Some(fd),
+            _ => None,
+        }
+    }
+
+    /// Switches reads and writes to the socket registered at `index` of the
+    /// ring's file table (`IOSQE_FIXED_FILE`), only valid once connected.
+    pub fn use_fixed_file(&mut self, index: u32) -> Result<()> {
+        match &mut self.state {
+            State::Connected { fd } => {
+                *fd = Fd::Fixed(index);
+                Ok(())
+            }
+            _ => Err(Error::Protocol(
+                "only a connected socket can be a fixed file".to_string(),
+            )),
+        }
+    }
+
+    /// Creates the socket as a direct descriptor (`Sqe::SocketDirect`), so
+    /// that it never enters the process's fd table: every SQE then refers to
+    /// it by its slot in the ring's registered files, which needs a file
+    /// table registered up front (e.g. `register_files_sparse`). Only valid
+    /// before the socket is created, not for `send_over`.
+    pub fn use_direct_descriptor(&mut self) -> Result<()> {
+        if !self.capabilities.socket {
+            return Err(Error::InvalidRequest(
+                "direct descriptors need IORING_OP_SOCKET".to_string(),
+            ));
+        }
+        match self.state {
+            State::Initialized { .. } if self.direct == Direct::Off => {
+                self.direct = Direct::Alloc;
+                Ok(())
+            }
+            _ => Err(Error::Protocol(
+                "the socket has already been created".to_string(),
+            )),
+        }
+    }
+
+    /// What the kernel supports, all of it by default. Operations it lacks
+    /// are replaced by plain ones: without `socket` the socket is created
+    /// with `socket(2)` when `next_sqe` is called. Reads and writes are
+    /// always `Read`/`Write` (or their fixed variants), the rest is for
+    /// callers building on the same ring.
+    pub fn set_capabilities(&mut self, capabilities: IoUringCapabilities) -> Result<()> {
+        if !capabilities.socket && self.direct != Direct::Off {
+            return Err(Error::InvalidRequest(
+                "direct descriptors need IORING_OP_SOCKET".to_string(),
+            ));
+        }
+        self.capabilities = capabilities;
+        Ok(())
+    }
+
+    pub fn capabilities(&self) -> IoUringCapabilities {
+        self.capabilities
+    }
+
+    /// The slot of the direct descriptor once it's allocated until it's
+    /// closed. Dropping the connection before the response arrives leaves
+    /// closing it to the caller (`IORING_OP_CLOSE` with a file index).
+    pub fn file_index(&self) -> Option<u32> {
+        match self.direct {
+            Direct::Slot(index) => Some(index),
+            Direct::Off | Direct::Alloc => None,
+        }
+    }
+
+    /// The FSM's incoming and outgoing TLS buffers, to be registered with
+    /// the ring (`io_uring_register_buffers`) at `read_index` and
+    /// `write_index`. Reads and writes then come as `ReadFixed` and
+    /// `WriteFixed`, until a buffer has to grow and moves; pick
+    /// `BufferConfig` sizes that don't need to grow to avoid it.
+    pub fn fixed_buffers(&mut self, read_index: u16, write_index: u16) -> [libc::iovec; 2] {
+        let [incoming, outgoing] = self.fsm.tls_buffers();
+        self.fixed_buffers = Some(FixedBuffers {
+            read: Registered::new(read_index, incoming),
+            write: Registered::new(write_index, outgoing),
+        });
+        [incoming, outgoing].map(|buf| libc::iovec {
+            iov_base: buf.as_mut_ptr().cast(),
+            iov_len: buf.len(),
         })
     }
 
+    /// Along with the response comes a `Sqe::Close` for the socket the
+    /// connection created (not the one passed to `send_over`), submit it too.
     pub fn next_sqe(&mut self) -> Result<(Option<Sqe>, Option<Response>)> {
+        if !self.capabilities.socket && matches!(self.state, State::Initialized { .. }) {
+            self.create_socket()?;
+        }
+
         let sqe;
 
         match &self.state {
-            State::Initialized { .. } => {
-                sqe = socket_sqe(self.socket_user_data);
+            State::Initialized { addr } => {
+                sqe = socket_sqe(
+                    addr.domain(),
+                    self.direct,
+                    self.user_data.socket.expect("socket user_data"),
+                );
             }
             State::Connecting { fd, addr, .. } => {
-                sqe = connect_sqe(*fd, addr, self.connect_user_data);
+                let timeout =
+                    link_timeout(self.timeouts.connect, self.deadline, self.user_data.timeout)?;
+                sqe = connect_sqe(
+                    *fd,
+                    addr,
+                    self.user_data.connect.expect("connect user_data"),
+                    timeout,
+                );
             }
             State::Connected { fd } => match self.fsm.wants()? {
                 Wants::Read(buf) => {
-                    sqe = read_sqe(*fd, buf, self.read_user_data);
+                    let timeout =
+                        link_timeout(self.timeouts.read, self.deadline, self.user_data.timeout)?;
+                    let index = self
+                        .fixed_buffers
+                        .as_ref()
+                        .and_then(|fixed| fixed.read.index_for(buf));
+                    sqe = read_sqe(*fd, buf, index, self.user_data.read, timeout);
                 }
                 Wants::Write(buf) => {
-                    sqe = write_sqe(*fd, buf, self.write_user_data);
+                    let timeout =
+                        link_timeout(self.timeouts.write, self.deadline, self.user_data.timeout)?;
+                    let index = self
+                        .fixed_buffers
+                        .as_ref()
+                        .and_then(|fixed| fixed.write.index_for(buf));
+                    sqe = write_sqe(*fd, buf, index, self.user_data.write, timeout);
                 }
+                Wants::Body(_) => {
+                    return Err(Error::InvalidRequest(
+                        "this driver can't stream request bodies".to_string(),
+                    ));
+                }
+                Wants::SinkFull => return Err(sink_full()),
                 Wants::Done(response) => {
-                    return Ok((None, Some(response)));
+                    return Ok((self.close_sqe(), Some(response)));
                 }
             },
+            State::Closing | State::Closed => return Ok((None, None)),
             State::None => unreachable!(),
         }
 
@@ -101,47 +521,145 @@ impl IoUringConnection {
         Ok((Some(sqe), None))
     }
 
+    /// Closes the socket this connection created, the caller must submit
+    /// the SQE even though the response is already out.
+    fn close_sqe(&mut self) -> Option<Sqe> {
+        let user_data = self.user_data.socket?;
+        let fd = match self.direct {
+            Direct::Slot(index) => {
+                self.direct = Direct::Off;
+                Fd::Fixed(index)
+            }
+            Direct::Off | Direct::Alloc => Fd::Raw(self.owned_fd.take()?),
+        };
+        self.state = State::Closing;
+        self.pending.insert(user_data);
+        Some(Sqe::Close { fd, user_data })
+    }
+
+    /// `socket(2)` in place of `IORING_OP_SOCKET`.
+    fn create_socket(&mut self) -> Result<()> {
+        loop {
+            let State::Initialized { addr } = self.take_state() else {
+                panic!("malformed state")
+            };
+            let fd = unsafe { libc::socket(addr.domain(), SOCK_STREAM | SOCK_CLOEXEC, 0) };
+            if fd >= 0 {
+                self.owned_fd = Some(fd);
+                self.state = State::Connecting {
+                    fd: Fd::Raw(fd),
+                    addr,
+                };
+                return Ok(());
+            }
+            let errno = io::Error::last_os_error().raw_os_error().unwrap_or(EINVAL);
+            self.try_next_addr(errno)?;
+        }
+    }
+
     fn take_state(&mut self) -> State {
         std::mem::take(&mut self.state)
     }
 
+    /// Moves on to the next resolved address after `errno`,
+    /// which is returned once there are none left.
+    fn try_next_addr(&mut self, errno: i32) -> Result<()> {
+        self.attempt_done(Err(io::Error::from_raw_os_error(errno)));
+        match self.addrs.pop_front() {
+            Some(addr) => {
+                self.attempt = Some((addr, Instant::now()));
+                self.state = State::Initialized {
+                    addr: SockAddr::new(addr),
+                };
+                Ok(())
+            }
+            None => Err(Error::Io(io::Error::from_raw_os_error(errno))),
+        }
+    }
+
+    fn attempt_done(&mut self, result: io::Result<()>) {
+        if let Some((addr, started)) = self.attempt.take() {
+            emit_connect(self.observer.as_ref(), addr, started, &result);
+        }
+    }
+
     pub fn process_cqe(&mut self, cqe: Cqe) -> Result<()> {
         self.pending.remove(&cqe.user_data);
 
         match cqe.user_data {
-            data if data == self.socket_user_data => {
-                let fd = cqe.result;
-                assert!(fd > 0);
-
+            // the socket is gone either way, nothing to do about errors
+            data if Some(data) == self.user_data.socket && matches!(self.state, State::Closing) => {
+                self.state = State::Closed;
+            }
+            data if Some(data) == self.user_data.socket => {
                 let State::Initialized { addr } = self.take_state() else {
                     panic!("malformed state")
                 };
 
+                // e.g. EAFNOSUPPORT for IPv6 on a host without it
+                if cqe.result < 0 {
+                    return self.try_next_addr(-cqe.result);
+                }
+                let fd = match self.direct {
+                    Direct::Off => {
+                        self.owned_fd = Some(cqe.result);
+                        Fd::Raw(cqe.result)
+                    }
+                    // the result is the allocated slot
+                    Direct::Alloc => {
+                        self.direct = Direct::Slot(cqe.result as u32);
+                        Fd::Fixed(cqe.result as u32)
+                    }
+                    Direct::Slot(index) => Fd::Fixed(index),
+                };
+
                 self.state = State::Connecting { fd, addr };
             }
-            data if data == self.connect_user_data => {
-                assert!(cqe.result >= 0);
+            data if Some(data) == self.user_data.connect => {
+                if cqe.result == -ECANCELED {
+                    self.attempt_done(Err(io::ErrorKind::TimedOut.into()));
+                    return Err(Error::Timeout(TimeoutKind::Connect));
+                }
 
                 let State::Connecting { fd, .. } = self.take_state() else {
                     panic!("malformed state")
                 };
 
-                self.state = State::Connected { fd };
-            }
-            data if data == self.read_user_data => {
-                let read = cqe.result;
-                assert!(read >= 0);
-                let read = read as usize;
-
-                self.fsm.done_reading(read);
-            }
-            data if data == self.write_user_data => {
-                let written = cqe.result;
-                assert!(written >= 0);
-                let written = written as usize;
+                if cqe.result < 0 {
+                    // a direct descriptor is replaced by the next attempt's
+                    if let Fd::Raw(fd) = fd {
+                        unsafe { libc::close(fd) };
+                        self.owned_fd = None;
+                    }
+                    return self.try_next_addr(-cqe.result);
+                }
 
-                self.fsm.done_writing(written);
+                self.attempt_done(Ok(()));
+                self.state = State::Connected { fd };
             }
+            data if data == self.user_data.read => match cqe.result {
+                result if result == -ECANCELED => {
+                    return Err(Error::Timeout(TimeoutKind::Read));
+                }
+                // resubmitted by the next `next_sqe`
+                result if is_retryable(result) => {}
+                result if result < 0 => return Err(errno(result)),
+                read => self.fsm.done_reading(read as usize),
+            },
+            data if data == self.user_data.write => match cqe.result {
+                result if result == -ECANCELED => {
+                    return Err(Error::Timeout(TimeoutKind::Write));
+                }
+                result if is_retryable(result) => {}
+                result
+                    if matches!(-result, EPIPE | ECONNRESET | ECONNABORTED)
+                        && self.fsm.abort_write() => {}
+                result if result < 0 => return Err(errno(result)),
+                0 => return Err(Error::Io(io::ErrorKind::WriteZero.into())),
+                // a short write leaves the rest in the FSM's outgoing
+                // buffer, the next `Write` picks it up from there
+                written => self.fsm.done_writing(written as usize),
+            },
 
             _ => {}
         }
@@ -150,32 +668,100 @@ impl IoUringConnection {
     }
 }
 
-fn getaddrinfo(hostname: &str) -> Result<sockaddr_in> {
-    let node = CString::new(hostname)?;
+/// Closes the socket if it's dropped before the response arrived.
+impl Drop for IoUringConnection {
+    fn drop(&mut self) {
+        if let Some(fd) = self.owned_fd.take() {
+            unsafe { libc::close(fd) };
+        }
+    }
+}
+
+fn is_retryable(result: i32) -> bool {
+    matches!(-result, EINTR | EAGAIN)
+}
+
+/// A negative CQE result.
+fn errno(result: i32) -> Error {
+    Error::Io(io::Error::from_raw_os_error(-result))
+}
+
+fn link_timeout(
+    timeout: Option<Duration>,
+    deadline: Option<Instant>,
+    user_data: u64,
+) -> Result<Option<LinkTimeout>> {
+    Ok(remaining(timeout, deadline)?.map(|duration| LinkTimeout {
+        duration,
+        user_data,
+    }))
+}
+
+fn resolve(hostname: &str, port: u16, options: &ClientOptions) -> Result<Vec<SocketAddr>> {
+    let host = options.connect_host(hostname);
+    let started = Instant::now();
+    let addrs = getaddrinfo(host, port).and_then(|addrs| {
+        if addrs.is_empty() {
+            return Err(Error::Dns(format!(
+                "failed to resolve DNS name: {hostname}"
+            )));
+        }
+        Ok(options.ip_preference.order(addrs))
+    });
+    emit_resolve(options.net_observer.as_ref(), host, port, started, &addrs);
+    addrs
+}
+
+fn getaddrinfo(hostname: &str, port: u16) -> Result<Vec<SocketAddr>> {
+    let node = CString::new(hostname).map_err(|err| Error::Dns(err.to_string()))?;
     let mut hints = unsafe { MaybeUninit::<addrinfo>::zeroed().assume_init() };
-    hints.ai_family = AF_INET;
+    hints.ai_family = AF_UNSPEC;
     hints.ai_socktype = SOCK_STREAM;
 
     let mut result = null_mut();
 
     let res = unsafe { libc::getaddrinfo(node.as_ptr(), null_mut(), &hints, &mut result) };
     if res != 0 {
-        bail!("{}", unsafe { CStr::from_ptr(gai_strerror(res)) }.to_str()?)
+        let message = unsafe { CStr::from_ptr(gai_strerror(res)) };
+        return Err(Error::Dns(message.to_string_lossy().into_owned()));
     }
 
+    let mut addrs = vec![];
     let mut rp = result;
     while !rp.is_null() {
-        if unsafe { *rp }.ai_family == AF_INET {
-            let ip = unsafe { *(*rp).ai_addr.cast::<sockaddr_in>() };
-            unsafe { freeaddrinfo(rp) }
-            return Ok(ip);
+        let info = unsafe { *rp };
+        match info.ai_family {
+            AF_INET => {
+                let addr = unsafe { *info.ai_addr.cast::<sockaddr_in>() };
+                let ip = Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr));
+                addrs.push(SocketAddr::V4(SocketAddrV4::new(ip, port)));
+            }
+            AF_INET6 => {
+                let addr = unsafe { *info.ai_addr.cast::<sockaddr_in6>() };
+                let ip = Ipv6Addr::from(addr.sin6_addr.s6_addr);
+                addrs.push(SocketAddr::V6(SocketAddrV6::new(
+                    ip,
+                    port,
+                    addr.sin6_flowinfo,
+                    addr.sin6_scope_id,
+                )));
+            }
+            _ => {}
         }
 
-        rp = (unsafe { *rp }).ai_next;
+        rp = info.ai_next;
     }
-    unsafe { freeaddrinfo(rp) }
+    unsafe { freeaddrinfo(result) }
 
-    bail!("failed to resolve DNS name: {hostname}")
+    Ok(addrs)
+}
+
+/// The file an SQE operates on.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum Fd {
+    Raw(RawFd),
+    /// An index into the ring's registered files, submit with `IOSQE_FIXED_FILE`.
+    Fixed(u32),
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
@@ -187,25 +773,63 @@ pub enum Sqe {
         user_data: u64,
     },
 
+    /// A `Socket` created as a direct descriptor, see
+    /// `IoUringConnection::use_direct_descriptor`. Submit it with
+    /// `file_index` set to `IORING_FILE_INDEX_ALLOC` if it's `None`
+    /// or to the slot plus one otherwise.
+    SocketDirect {
+        domain: i32,
+        socket_type: i32,
+        protocol: i32,
+        file_index: Option<u32>,
+        user_data: u64,
+    },
+
     Connect {
-        fd: i32,
+        fd: Fd,
         addr: *const sockaddr,
         addrlen: u32,
         user_data: u64,
+        timeout: Option<LinkTimeout>,
     },
 
     Write {
-        fd: i32,
+        fd: Fd,
         buf: *const u8,
         len: u32,
         user_data: u64,
+        timeout: Option<LinkTimeout>,
     },
 
     Read {
-        fd: i32,
+        fd: Fd,
         buf: *mut u8,
         len: u32,
         user_data: u64,
+        timeout: Option<LinkTimeout>,
+    },
+
+    /// Closes the socket once the response is in, see `IoUringConnection::next_sqe`.
+    Close { fd: Fd, user_data: u64 },
+
+    /// A `Write` from the registered buffer `buf_index`, see `fixed_buffers`.
+    WriteFixed {
+        fd: Fd,
+        buf: *const u8,
+        len: u32,
+        buf_index: u16,
+        user_data: u64,
+        timeout: Option<LinkTimeout>,
+    },
+
+    /// A `Read` into the registered buffer `buf_index`, see `fixed_buffers`.
+    ReadFixed {
+        fd: Fd,
+        buf: *mut u8,
+        len: u32,
+        buf_index: u16,
+        user_data: u64,
+        timeout: Option<LinkTimeout>,
     },
 }
 
@@ -213,46 +837,119 @@ impl Sqe {
     fn user_data(self) -> u64 {
         match self {
             Self::Socket { user_data, .. }
+            | Self::SocketDirect { user_data, .. }
+            | Self::Close { user_data, .. }
             | Self::Connect { user_data, .. }
             | Self::Write { user_data, .. }
-            | Self::Read { user_data, .. } => user_data,
+            | Self::Read { user_data, .. }
+            | Self::WriteFixed { user_data, .. }
+            | Self::ReadFixed { user_data, .. } => user_data,
+        }
+    }
+
+    /// Timeout that must be submitted as an `IORING_OP_LINK_TIMEOUT`
+    /// right after this SQE, with `IOSQE_IO_LINK` set on the SQE itself.
+    pub fn link_timeout(self) -> Option<LinkTimeout> {
+        match self {
+            Self::Socket { .. } | Self::SocketDirect { .. } | Self::Close { .. } => None,
+            Self::Connect { timeout, .. }
+            | Self::Write { timeout, .. }
+            | Self::Read { timeout, .. }
+            | Self::WriteFixed { timeout, .. }
+            | Self::ReadFixed { timeout, .. } => timeout,
         }
     }
 }
 
-fn socket_sqe(user_data: u64) -> Sqe {
-    Sqe::Socket {
-        domain: AF_INET,
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub struct LinkTimeout {
+    pub duration: Duration,
+    pub user_data: u64,
+}
+
+fn socket_sqe(domain: i32, direct: Direct, user_data: u64) -> Sqe {
+    let file_index = match direct {
+        Direct::Off => {
+            return Sqe::Socket {
+                domain,
+                socket_type: SOCK_STREAM,
+                protocol: 0,
+                user_data,
+            };
+        }
+        Direct::Alloc => None,
+        Direct::Slot(index) => Some(index),
+    };
+    Sqe::SocketDirect {
+        domain,
         socket_type: SOCK_STREAM,
         protocol: 0,
+        file_index,
         user_data,
     }
 }
 
-fn connect_sqe(fd: i32, addr: *const sockaddr_in, user_data: u64) -> Sqe {
+fn connect_sqe(fd: Fd, addr: &SockAddr, user_data: u64, timeout: Option<LinkTimeout>) -> Sqe {
     Sqe::Connect {
         fd,
-        addr: addr.cast::<sockaddr>(),
-        addrlen: std::mem::size_of::<sockaddr_in>() as u32,
+        addr: addr.as_ptr(),
+        addrlen: addr.len(),
         user_data,
+        timeout,
     }
 }
 
-fn write_sqe(fd: i32, buf: &[u8], user_data: u64) -> Sqe {
-    Sqe::Write {
-        fd,
-        buf: buf.as_ptr(),
-        len: buf.len() as u32,
-        user_data,
+fn write_sqe(
+    fd: Fd,
+    buf: &[u8],
+    buf_index: Option<u16>,
+    user_data: u64,
+    timeout: Option<LinkTimeout>,
+) -> Sqe {
+    let (buf, len) = (buf.as_ptr(), buf.len() as u32);
+    match buf_index {
+        Some(buf_index) => Sqe::WriteFixed {
+            fd,
+            buf,
+            len,
+            buf_index,
+            user_data,
+            timeout,
+        },
+        None => Sqe::Write {
+            fd,
+            buf,
+            len,
+            user_data,
+            timeout,
+        },
     }
 }
 
-fn read_sqe(fd: i32, buf: &mut [u8], user_data: u64) -> Sqe {
-    Sqe::Read {
-        fd,
-        buf: buf.as_mut_ptr(),
-        len: buf.len() as u32,
-        user_data,
+fn read_sqe(
+    fd: Fd,
+    buf: &mut [u8],
+    buf_index: Option<u16>,
+    user_data: u64,
+    timeout: Option<LinkTimeout>,
+) -> Sqe {
+    let (buf, len) = (buf.as_mut_ptr(), buf.len() as u32);
+    match buf_index {
+        Some(buf_index) => Sqe::ReadFixed {
+            fd,
+            buf,
+            len,
+            buf_index,
+            user_data,
+            timeout,
+        },
+        None => Sqe::Read {
+            fd,
+            buf,
+            len,
+            user_data,
+            timeout,
+        },
     }
 }
 
@@ -0,0 +1,165 @@
+use crate::{
+    ClientOptions, Error, FSM, Request, Response, Result, Scheme, TimeoutKind, Timeouts, TlsInfo,
+    Wants,
+    deadline::deadline,
+    fsm::{is_write_rejected, sink_full},
+    url::host_header,
+};
+use std::{
+    io,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// A completion-based connection in the style of `IoUringConnection`, meant
+/// for Windows IOCP: start every `IocpOp` as an overlapped `WSARecv` or
+/// `WSASend` on a socket associated with the completion port and pass its
+/// result to `complete`. Nothing in it is Windows-specific, any other
+/// completion API works the same way.
+///
+/// Connecting (e.g. with `ConnectEx`) is up to the caller, the connection
+/// only needs the hostname for SNI, certificate verification and the
+/// `Host` header.
+pub struct IocpConnection {
+    fsm: FSM,
+    /// The started operation waiting for its completion.
+    in_flight: Option<IocpOp>,
+
+    timeouts: Timeouts,
+    deadline: Option<Instant>,
+    io_deadline: Option<(Instant, TimeoutKind)>,
+}
+
+/// An operation to start. The buffer belongs to the connection and stays
+/// put until the operation's completion is passed to `IocpConnection::complete`,
+/// the connection must not be dropped while one is in flight.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
+pub enum IocpOp {
+    Recv { buf: *mut u8, len: usize },
+    Send { buf: *const u8, len: usize },
+}
+
+impl IocpConnection {
+    pub fn new(
+        scheme: Scheme,
+        hostname: &str,
+        port: u16,
+        mut request: Request,
+        options: &ClientOptions,
+    ) -> Result<Self> {
+        request.add_default_header("Host", &host_header(scheme, hostname, port));
+        let fsm = FSM::with_scheme(scheme, hostname, request, options)?;
+
+        Ok(Self {
+            fsm,
+            in_flight: None,
+
+            timeouts: options.timeouts,
+            deadline: deadline(&options.timeouts),
+            io_deadline: None,
+        })
+    }
+
+    /// The operation to start next, or the response once it's complete.
+    /// Returns neither while an operation is in flight.
+    pub fn next_op(&mut self) -> Result<(Option<IocpOp>, Option<Response>)> {
+        if self.in_flight.is_some() {
+            return Ok((None, None));
+        }
+        self.check_timeouts()?;
+
+        let (op, timeout, kind) = match self.fsm.wants()? {
+            Wants::Read(buf) => (
+                IocpOp::Recv {
+                    buf: buf.as_mut_ptr(),
+                    len: buf.len(),
+                },
+                self.timeouts.read,
+                TimeoutKind::Read,
+            ),
+            Wants::Write(buf) => (
+                IocpOp::Send {
+                    buf: buf.as_ptr(),
+                    len: buf.len(),
+                },
+                self.timeouts.write,
+                TimeoutKind::Write,
+            ),
+            Wants::Body(_) => {
+                return Err(Error::InvalidRequest(
+                    "this driver can't stream request bodies".to_string(),
+                ));
+            }
+            Wants::SinkFull => return Err(sink_full()),
+            Wants::Done(response) => return Ok((None, Some(response))),
+        };
+
+        self.io_deadline = timeout.map(|timeout| (Instant::now() + timeout, kind));
+        self.in_flight = Some(op);
+        Ok((Some(op), None))
+    }
+
+    /// Feeds back the outcome of the operation in flight: the number of
+    /// bytes transferred or the error it failed with. An operation cancelled
+    /// after `check_timeouts` failed can be completed with any error.
+    pub fn complete(&mut self, result: io::Result<usize>) -> Result<()> {
+        let Some(op) = self.in_flight.take() else {
+            return Err(Error::Protocol(
+                "completion without an operation in flight".to_string(),
+            ));
+        };
+        self.io_deadline = None;
+
+        match (op, result) {
+            (IocpOp::Recv { len, .. }, Ok(read)) if read <= len => self.fsm.done_reading(read),
+            (IocpOp::Send { len, .. }, Ok(written)) if written <= len => {
+                self.fsm.done_writing(written)
+            }
+            (_, Ok(transferred)) => {
+                return Err(Error::Protocol(format!(
+                    "completion reports {transferred} bytes, more than the buffer holds"
+                )));
+            }
+            (IocpOp::Send { .. }, Err(err))
+                if is_write_rejected(&err) && self.fsm.abort_write() => {}
+            (_, Err(err)) => return Err(Error::Io(err)),
+        }
+        Ok(())
+    }
+
+    /// See `FSM::tls_info`, available before the response is.
+    pub fn tls_info(&self) -> Option<Arc<TlsInfo>> {
+        self.fsm.tls_info()
+    }
+
+    /// Time left until the nearest configured deadline, suitable as the
+    /// timeout of `GetQueuedCompletionStatusEx`.
+    pub fn timeout(&self) -> Option<Duration> {
+        [
+            self.deadline,
+            self.io_deadline.map(|(deadline, _)| deadline),
+        ]
+        .into_iter()
+        .flatten()
+        .min()
+        .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+    }
+
+    /// Fails with `Error::Timeout` once a deadline has passed, the caller
+    /// should then cancel the operation in flight (`CancelIoEx`) and wait
+    /// for its completion before dropping the connection.
+    pub fn check_timeouts(&self) -> Result<()> {
+        let now = Instant::now();
+
+        if self.deadline.is_some_and(|deadline| now >= deadline) {
+            return Err(Error::Timeout(TimeoutKind::Total));
+        }
+        if let Some((deadline, kind)) = self.io_deadline
+            && now >= deadline
+        {
+            return Err(Error::Timeout(kind));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,46 @@
+use std::net::SocketAddr;
+
+/// Order in which resolved addresses are tried, the next one is dialed
+/// only after the previous attempt failed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum IpPreference {
+    /// Alternate families starting with IPv6, as RFC 8305 recommends,
+    /// so a broken IPv6 route costs one attempt instead of all of them.
+    #[default]
+    PreferV6,
+    /// Alternate families starting with IPv4.
+    PreferV4,
+    /// Keep the resolver's order.
+    Resolver,
+}
+
+impl IpPreference {
+    #[cfg_attr(
+        not(any(
+            feature = "blocking",
+            feature = "poll",
+            feature = "io-uring",
+            feature = "tokio",
+            feature = "mio",
+            feature = "epoll"
+        )),
+        allow(dead_code)
+    )]
+    pub(crate) fn order(self, addrs: impl IntoIterator<Item = SocketAddr>) -> Vec<SocketAddr> {
+        let addrs = addrs.into_iter();
+        let (v6, v4): (Vec<_>, Vec<_>) = match self {
+            Self::Resolver => return addrs.collect(),
+            Self::PreferV6 => addrs.partition(SocketAddr::is_ipv6),
+            Self::PreferV4 => addrs.partition(SocketAddr::is_ipv4),
+        };
+
+        let mut out = Vec::with_capacity(v6.len() + v4.len());
+        let (mut first, mut second) = (v6.into_iter(), v4.into_iter());
+        loop {
+            match (first.next(), second.next()) {
+                (None, None) => return out,
+                (a, b) => out.extend(a.into_iter().chain(b)),
+            }
+        }
+    }
+}
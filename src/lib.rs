@@ -1,25 +1,175 @@
+pub mod acme;
+mod adaptive_buffers;
+mod auth;
+mod base64;
+mod body_sink;
+mod buffer_config;
 mod client_config;
+mod client_identity;
+mod client_options;
+mod clock_skew;
+#[cfg(feature = "decompression")]
+mod decompression;
+#[cfg(feature = "digest")]
+mod digest;
+mod error;
 mod fsm;
+pub mod headers;
+#[cfg(feature = "insecure")]
+mod insecure;
+mod ip_preference;
+mod limits;
+pub mod multipart;
+mod net_event;
+#[cfg(feature = "ocsp")]
+pub mod ocsp;
+mod percent;
+mod pinning;
+pub mod prometheus;
+#[cfg(feature = "public-suffix")]
+mod public_suffix;
+mod redact;
+mod redirect;
 mod request;
 mod response;
+mod response_reader;
+pub mod resumable;
+mod retry;
+mod root_certs;
+#[cfg(feature = "s3")]
+pub mod s3;
+mod scheme;
+mod stats;
+#[cfg(test)]
+mod testing;
+mod timeouts;
+mod tls_info;
+mod trace;
+mod url;
+mod validation;
+mod version;
+pub mod webdav;
 
 pub use crate::{
+    adaptive_buffers::AdaptiveBuffers,
+    auth::{AuthCache, AuthScheme, Challenge, Credentials, Party},
+    body_sink::{BodySink, BufferRing},
+    buffer_config::{Buffer, BufferConfig, FixedBuffer, Growth},
+    client_identity::ClientIdentity,
+    client_options::ClientOptions,
+    clock_skew::ClockSkew,
+    error::{Error, LimitKind, Result, TimeoutKind},
     fsm::{FSM, Wants},
-    request::Request,
+    headers::Headers,
+    ip_preference::IpPreference,
+    limits::Limits,
+    net_event::{NetEvent, NetObserver},
+    pinning::{NoPinMatched, Pin},
+    redirect::{Redirect, SchemePolicy},
+    request::{Method, Request},
     response::Response,
+    retry::RetryPolicy,
+    root_certs::RootCerts,
+    scheme::Scheme,
+    stats::Stats,
+    timeouts::Timeouts,
+    tls_info::TlsInfo,
+    trace::{Trace, TraceEvent},
+    url::Url,
+    validation::{Location, ValidationError},
+    version::HttpVersion,
 };
 
+#[cfg(feature = "digest")]
+pub use digest::DigestAlgorithm;
+#[cfg(feature = "public-suffix")]
+pub use public_suffix::PublicSuffixList;
+
+#[cfg(all(feature = "poll", not(unix)))]
+compile_error!("the poll driver needs a unix target");
+#[cfg(all(feature = "io-uring", not(target_os = "linux")))]
+compile_error!("the io-uring driver needs Linux");
+#[cfg(all(feature = "epoll", not(target_os = "linux")))]
+compile_error!("the epoll driver needs Linux");
+
+#[cfg(any(
+    feature = "blocking",
+    feature = "poll",
+    feature = "io-uring",
+    feature = "tokio",
+    feature = "mio",
+    feature = "epoll",
+    feature = "iocp"
+))]
+mod deadline;
+#[cfg(any(
+    feature = "blocking",
+    feature = "poll",
+    feature = "mio",
+    feature = "epoll"
+))]
+mod net;
+#[cfg(any(feature = "poll", feature = "io-uring"))]
+mod sock_addr;
+#[cfg(any(feature = "poll", feature = "epoll"))]
+mod socket;
+
+#[cfg(feature = "blocking")]
+mod backend;
+#[cfg(feature = "blocking")]
+pub use backend::Backend;
 #[cfg(feature = "blocking")]
 mod blocking_connection;
 #[cfg(feature = "blocking")]
-pub use blocking_connection::BlockingConnection;
+pub use blocking_connection::{BlockingConnection, Fetched, LargeBody, Stream};
+#[cfg(feature = "blocking")]
+mod client;
+#[cfg(feature = "blocking")]
+pub use client::{Client, Proxy};
+#[cfg(feature = "blocking")]
+mod connection_pool;
+#[cfg(feature = "blocking")]
+pub use connection_pool::{ConnectionPool, HostStats, IdleStats};
+#[cfg(feature = "blocking")]
+mod download;
+#[cfg(feature = "blocking")]
+pub use download::{Download, DownloadSettings, Progress};
 
 #[cfg(feature = "poll")]
 mod poll_connection;
 #[cfg(feature = "poll")]
 pub use poll_connection::{EventsOrResponse, PollConnection};
 
+#[cfg(feature = "mio")]
+mod mio_connection;
+#[cfg(feature = "mio")]
+pub use mio_connection::MioConnection;
+
+#[cfg(feature = "epoll")]
+mod epoll_connection;
+#[cfg(feature = "epoll")]
+pub use epoll_connection::EpollConnection;
+
+#[cfg(feature = "iocp")]
+mod iocp_connection;
+#[cfg(feature = "iocp")]
+pub use iocp_connection::{IocpConnection, IocpOp};
+
+#[cfg(feature = "io-uring")]
+mod io_uring_capabilities;
+#[cfg(feature = "io-uring")]
+pub use io_uring_capabilities::IoUringCapabilities;
 #[cfg(feature = "io-uring")]
 mod io_uring_connection;
 #[cfg(feature = "io-uring")]
-pub use io_uring_connection::{Cqe, IoUringConnection, Sqe};
+pub use io_uring_connection::{Cqe, Fd, IoUringConnection, LinkTimeout, Sqe};
+
+#[cfg(feature = "tokio")]
+mod tokio_connection;
+#[cfg(feature = "tokio")]
+pub use tokio_connection::{AsyncConnection, AsyncExchange};
+
+#[cfg(feature = "futures")]
+mod async_connection;
+#[cfg(feature = "futures")]
+pub use async_connection::{FuturesConnection, FuturesExchange};
@@ -0,0 +1,31 @@
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    /// Status line plus all headers, in bytes. Also bounds the trailers
+    /// of a chunked body.
+    pub max_head_size: usize,
+    pub max_status_line_length: usize,
+    /// A single header line, name and value included. Also bounds each
+    /// line of chunked framing: chunk sizes with their extensions, and
+    /// trailers.
+    pub max_header_size: usize,
+    pub max_header_count: usize,
+    /// The body, unlimited by default. A declared `Content-Length` over it
+    /// fails right away, other bodies once they grow past it. With the
+    /// `decompression` feature it also bounds the decompressed body, so
+    /// that a small compressed one can't expand into all the memory.
+    /// Going over it is `Error::LimitExceeded(LimitKind::BodySize)`, and
+    /// nothing more is read for the response.
+    pub max_body_size: Option<usize>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_head_size: 256 * 1024,
+            max_status_line_length: 8 * 1024,
+            max_header_size: 64 * 1024,
+            max_header_count: 100,
+            max_body_size: None,
+        }
+    }
+}
@@ -0,0 +1,227 @@
+use crate::{
+    ClientOptions, Error, FSM, Request, Response, Result, Scheme, TimeoutKind, Timeouts, TlsInfo,
+    Url, Wants,
+    deadline::deadline,
+    fsm::{is_write_rejected, sink_full},
+    net,
+    url::host_header,
+};
+use mio::{Interest, Registry, Token, event::Event, net::TcpStream};
+use std::{
+    io::{ErrorKind, Read, Write},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// A connection driven by `mio` readiness events. mio is edge-triggered, so
+/// every event is handled by doing IO until the socket would block, after
+/// which the connection re-registers for whatever the FSM waits for next.
+pub struct MioConnection {
+    fsm: FSM,
+    sock: TcpStream,
+    token: Option<Token>,
+    interest: Option<Interest>,
+
+    timeouts: Timeouts,
+    deadline: Option<Instant>,
+    io_deadline: Option<(Instant, TimeoutKind)>,
+}
+
+impl MioConnection {
+    pub fn get(hostname: &str, port: u16, path: &str, options: &ClientOptions) -> Result<Self> {
+        Self::send(Scheme::Https, hostname, port, Request::get(path), options)
+    }
+
+    pub fn get_url(url: &str, options: &ClientOptions) -> Result<Self> {
+        let url = Url::parse(url)?;
+        Self::send(url.scheme, &url.host, url.port, url.get_request(), options)
+    }
+
+    /// Connects (blocking, bounded by the connect timeout) and prepares
+    /// `request`, register the connection to start sending it.
+    pub fn send(
+        scheme: Scheme,
+        hostname: &str,
+        port: u16,
+        request: Request,
+        options: &ClientOptions,
+    ) -> Result<Self> {
+        let fsm = Self::fsm(scheme, hostname, port, request, options)?;
+        let deadline = deadline(&options.timeouts);
+
+        let sock = net::connect(
+            options.connect_host(hostname),
+            port,
+            &options.timeouts,
+            deadline,
+            options.ip_preference,
+            options.net_observer.as_ref(),
+        )?;
+
+        Self::with_socket(fsm, sock, options, deadline)
+    }
+
+    /// Sends `request` over a socket the caller has connected, `hostname` and
+    /// `port` are only used for SNI, certificate verification and the `Host`
+    /// header.
+    pub fn from_stream(
+        stream: std::net::TcpStream,
+        scheme: Scheme,
+        hostname: &str,
+        port: u16,
+        request: Request,
+        options: &ClientOptions,
+    ) -> Result<Self> {
+        let fsm = Self::fsm(scheme, hostname, port, request, options)?;
+        Self::with_socket(fsm, stream, options, deadline(&options.timeouts))
+    }
+
+    fn fsm(
+        scheme: Scheme,
+        hostname: &str,
+        port: u16,
+        mut request: Request,
+        options: &ClientOptions,
+    ) -> Result<FSM> {
+        request.add_default_header("Host", &host_header(scheme, hostname, port));
+
+        FSM::with_scheme(scheme, hostname, request, options)
+    }
+
+    fn with_socket(
+        fsm: FSM,
+        sock: std::net::TcpStream,
+        options: &ClientOptions,
+        deadline: Option<Instant>,
+    ) -> Result<Self> {
+        sock.set_nonblocking(true)?;
+
+        Ok(Self {
+            fsm,
+            sock: TcpStream::from_std(sock),
+            token: None,
+            interest: None,
+
+            timeouts: options.timeouts,
+            deadline,
+            io_deadline: None,
+        })
+    }
+
+    /// Registers the socket, events for it then carry `token`. Interest
+    /// is narrowed to what the FSM waits for as the exchange goes.
+    pub fn register(&mut self, registry: &Registry, token: Token) -> Result<()> {
+        let interest = Interest::READABLE | Interest::WRITABLE;
+        registry.register(&mut self.sock, token, interest)?;
+        self.token = Some(token);
+        self.interest = Some(interest);
+        Ok(())
+    }
+
+    pub fn deregister(&mut self, registry: &Registry) -> Result<()> {
+        if self.token.take().is_some() {
+            registry.deregister(&mut self.sock)?;
+        }
+        self.interest = None;
+        Ok(())
+    }
+
+    /// Handles an event for this connection's token, `Some` once the
+    /// response is complete; the connection can be deregistered then.
+    pub fn handle_event(&mut self, registry: &Registry, event: &Event) -> Result<Option<Response>> {
+        debug_assert_eq!(Some(event.token()), self.token);
+        self.check_timeouts()?;
+
+        loop {
+            match self.fsm.wants()? {
+                Wants::Read(buf) => match self.sock.read(buf) {
+                    Ok(read) => {
+                        self.io_deadline = None;
+                        self.fsm.done_reading(read);
+                    }
+                    Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                        self.arm_io_deadline(self.timeouts.read, TimeoutKind::Read);
+                        return self.reregister(registry, Interest::READABLE);
+                    }
+                    Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                    Err(err) => return Err(Error::Io(err)),
+                },
+                Wants::Write(buf) => match self.sock.write(buf) {
+                    Ok(written) => {
+                        self.io_deadline = None;
+                        self.fsm.done_writing(written);
+                    }
+                    Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                        self.arm_io_deadline(self.timeouts.write, TimeoutKind::Write);
+                        return self.reregister(registry, Interest::WRITABLE);
+                    }
+                    Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                    Err(err) if is_write_rejected(&err) && self.fsm.abort_write() => {
+                        self.io_deadline = None;
+                    }
+                    Err(err) => return Err(Error::Io(err)),
+                },
+                Wants::Body(_) => {
+                    return Err(Error::InvalidRequest(
+                        "this driver can't stream request bodies".to_string(),
+                    ));
+                }
+                Wants::SinkFull => return Err(sink_full()),
+                Wants::Done(response) => return Ok(Some(response)),
+            }
+        }
+    }
+
+    /// See `FSM::tls_info`, available before the response is.
+    pub fn tls_info(&self) -> Option<Arc<TlsInfo>> {
+        self.fsm.tls_info()
+    }
+
+    /// Time left until the nearest configured deadline,
+    /// suitable as the timeout of `mio::Poll::poll`.
+    pub fn timeout(&self) -> Option<Duration> {
+        [
+            self.deadline,
+            self.io_deadline.map(|(deadline, _)| deadline),
+        ]
+        .into_iter()
+        .flatten()
+        .min()
+        .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+    }
+
+    /// Fails with `Error::Timeout` once a deadline has passed, call it
+    /// when `mio::Poll::poll` returns without events for this connection.
+    pub fn check_timeouts(&self) -> Result<()> {
+        let now = Instant::now();
+
+        if self.deadline.is_some_and(|deadline| now >= deadline) {
+            return Err(Error::Timeout(TimeoutKind::Total));
+        }
+        if let Some((deadline, kind)) = self.io_deadline
+            && now >= deadline
+        {
+            return Err(Error::Timeout(kind));
+        }
+
+        Ok(())
+    }
+
+    fn reregister(&mut self, registry: &Registry, interest: Interest) -> Result<Option<Response>> {
+        if self.interest != Some(interest)
+            && let Some(token) = self.token
+        {
+            registry.reregister(&mut self.sock, token, interest)?;
+            self.interest = Some(interest);
+        }
+        Ok(None)
+    }
+
+    fn arm_io_deadline(&mut self, timeout: Option<Duration>, kind: TimeoutKind) {
+        if self.io_deadline.is_none()
+            && let Some(timeout) = timeout
+        {
+            self.io_deadline = Some((Instant::now() + timeout, kind));
+        }
+    }
+}
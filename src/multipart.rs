@@ -0,0 +1,355 @@
+//! `multipart/form-data` (RFC 7578) request bodies.
+
+use crate::{Error, Headers, Method, Request, Result};
+use std::{
+    collections::{VecDeque, hash_map::RandomState},
+    hash::{BuildHasher as _, Hasher as _},
+    io::Read,
+};
+
+pub struct Part {
+    name: String,
+    filename: Option<String>,
+    content_type: Option<String>,
+    headers: Headers,
+    body: PartBody,
+}
+
+enum PartBody {
+    Bytes(Vec<u8>),
+    /// Read while the request is sent, with its length if it's known.
+    Reader(Box<dyn Read + Send>, Option<u64>),
+}
+
+impl Part {
+    pub fn new(name: impl Into<String>, data: impl Into<Vec<u8>>) -> Self {
+        Self::with_body(name.into(), PartBody::Bytes(data.into()))
+    }
+
+    /// A part read from `reader` during the upload, e.g. a large file.
+    /// If `len` is given the part is cut off there and a shorter reader
+    /// fails the request.
+    pub fn reader(
+        name: impl Into<String>,
+        reader: impl Read + Send + 'static,
+        len: Option<u64>,
+    ) -> Self {
+        Self::with_body(name.into(), PartBody::Reader(Box::new(reader), len))
+    }
+
+    fn with_body(name: String, body: PartBody) -> Self {
+        Self {
+            name,
+            filename: None,
+            content_type: None,
+            headers: Headers::new(),
+            body,
+        }
+    }
+
+    pub fn set_filename(&mut self, filename: impl Into<String>) {
+        self.filename = Some(filename.into());
+    }
+
+    /// `text/plain` is implied without it.
+    pub fn set_content_type(&mut self, content_type: impl Into<String>) {
+        self.content_type = Some(content_type.into());
+    }
+
+    /// Any other header of the part, e.g. `Content-Transfer-Encoding`.
+    pub fn add_header(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        self.headers.insert(name, value);
+    }
+
+    fn len(&self) -> Option<u64> {
+        match &self.body {
+            PartBody::Bytes(data) => Some(data.len() as u64),
+            PartBody::Reader(_, len) => *len,
+        }
+    }
+
+    fn head(&self, boundary: &str) -> Vec<u8> {
+        let mut head = format!(
+            "--{boundary}\r\nContent-Disposition: form-data; name=\"{}\"",
+            escape(&self.name)
+        );
+        if let Some(filename) = &self.filename {
+            head.push_str(&format!("; filename=\"{}\"", escape(filename)));
+        }
+        head.push_str("\r\n");
+        if let Some(content_type) = &self.content_type {
+            head.push_str(&format!("Content-Type: {content_type}\r\n"));
+        }
+        for (name, value) in &self.headers {
+            head.push_str(&format!("{name}: {value}\r\n"));
+        }
+        head.push_str("\r\n");
+        head.into_bytes()
+    }
+}
+
+/// Collects the fields of a form and turns them into a request, either
+/// with the whole body upfront or streamed when some parts are readers.
+pub struct Multipart {
+    boundary: String,
+    parts: Vec<Part>,
+}
+
+impl Default for Multipart {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Multipart {
+    /// Uses a random boundary, the chance of it showing up in the data is negligible.
+    pub fn new() -> Self {
+        let mut boundary = String::from("----https-sans-io-");
+        for _ in 0..2 {
+            let random = RandomState::new().build_hasher().finish();
+            boundary.push_str(&format!("{random:016x}"));
+        }
+        Self::with_boundary(boundary)
+    }
+
+    /// The caller makes sure that `boundary` doesn't occur in any part.
+    pub fn with_boundary(boundary: impl Into<String>) -> Self {
+        Self {
+            boundary: boundary.into(),
+            parts: vec![],
+        }
+    }
+
+    pub fn boundary(&self) -> &str {
+        &self.boundary
+    }
+
+    pub fn content_type(&self) -> String {
+        format!("multipart/form-data; boundary={}", self.boundary)
+    }
+
+    pub fn add_text(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        self.parts.push(Part::new(name, value.into()));
+    }
+
+    pub fn add_file(
+        &mut self,
+        name: impl Into<String>,
+        filename: impl Into<String>,
+        content_type: impl Into<String>,
+        data: impl Into<Vec<u8>>,
+    ) {
+        let mut part = Part::new(name, data);
+        part.set_filename(filename);
+        part.set_content_type(content_type);
+        self.parts.push(part);
+    }
+
+    pub fn add_part(&mut self, part: Part) {
+        self.parts.push(part);
+    }
+
+    /// The whole form as the body of a request, parts added with
+    /// `Part::reader` need `into_upload` instead.
+    pub fn into_request(self, method: Method, path: impl Into<String>) -> Result<Request> {
+        let mut request = Request::new(method, path);
+        request.add_header("Content-Type", self.content_type());
+
+        let mut body = vec![];
+        for part in &self.parts {
+            let PartBody::Bytes(data) = &part.body else {
+                return Err(Error::InvalidRequest(
+                    "the form has streamed parts, send it with into_upload".to_string(),
+                ));
+            };
+            body.extend_from_slice(&part.head(&self.boundary));
+            body.extend_from_slice(data);
+            body.extend_from_slice(b"\r\n");
+        }
+        body.extend_from_slice(self.closing().as_bytes());
+
+        request.set_body(body);
+        Ok(request)
+    }
+
+    /// A request with a streamed body (see `Request::stream_body`) and the
+    /// body itself to send with e.g. `BlockingConnection::upload`. It has a
+    /// `Content-Length` if the length of every part is known.
+    pub fn into_upload(self, method: Method, path: impl Into<String>) -> (Request, MultipartBody) {
+        let mut request = Request::new(method, path);
+        request.add_header("Content-Type", self.content_type());
+
+        let closing = self.closing();
+        let mut len = Some(closing.len() as u64);
+        let mut segments = VecDeque::new();
+        for part in self.parts {
+            let head = part.head(&self.boundary);
+            len = len
+                .zip(part.len())
+                .map(|(len, part_len)| len + head.len() as u64 + part_len + 2);
+
+            segments.push_back(Segment::Bytes(head));
+            match part.body {
+                PartBody::Bytes(data) => segments.push_back(Segment::Bytes(data)),
+                PartBody::Reader(reader, Some(limit)) => {
+                    segments.push_back(Segment::Reader(Box::new(reader.take(limit))))
+                }
+                PartBody::Reader(reader, None) => segments.push_back(Segment::Reader(reader)),
+            }
+            segments.push_back(Segment::Bytes(b"\r\n".to_vec()));
+        }
+        segments.push_back(Segment::Bytes(closing.into_bytes()));
+
+        request.stream_body(len);
+        let body = MultipartBody {
+            segments,
+            offset: 0,
+        };
+        (request, body)
+    }
+
+    fn closing(&self) -> String {
+        format!("--{}--\r\n", self.boundary)
+    }
+}
+
+/// The encoded form, read it into the buffers of `Wants::Body`.
+pub struct MultipartBody {
+    segments: VecDeque<Segment>,
+    /// Position in the front `Segment::Bytes`.
+    offset: usize,
+}
+
+enum Segment {
+    Bytes(Vec<u8>),
+    Reader(Box<dyn Read + Send>),
+}
+
+impl Read for MultipartBody {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let mut filled = 0;
+        while filled < buf.len()
+            && let Some(segment) = self.segments.front_mut()
+        {
+            let out = &mut buf[filled..];
+            match segment {
+                Segment::Bytes(data) => {
+                    let rest = &data[self.offset..];
+                    let read = rest.len().min(out.len());
+                    out[..read].copy_from_slice(&rest[..read]);
+                    self.offset += read;
+                    filled += read;
+                    if self.offset < data.len() {
+                        continue;
+                    }
+                }
+                Segment::Reader(reader) => {
+                    let read = reader.read(out)?;
+                    if read > 0 {
+                        // another read could block, hand over what's there
+                        return Ok(filled + read);
+                    }
+                }
+            }
+            self.segments.pop_front();
+            self.offset = 0;
+        }
+        Ok(filled)
+    }
+}
+
+/// Quotes and line breaks would end the parameter, they're
+/// percent-encoded like browsers do.
+fn escape(value: &str) -> String {
+    value
+        .replace('"', "%22")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request::Streaming;
+
+    fn form() -> Multipart {
+        let mut form = Multipart::with_boundary("XyZ");
+        form.add_text("title", "a cat");
+        form.add_file("photo", "cat \"1\".jpg", "image/jpeg", *b"\xff\xd8");
+        form
+    }
+
+    const ENCODED: &[u8] = b"--XyZ\r\n\
+        Content-Disposition: form-data; name=\"title\"\r\n\r\n\
+        a cat\r\n\
+        --XyZ\r\n\
+        Content-Disposition: form-data; name=\"photo\"; filename=\"cat %221%22.jpg\"\r\n\
+        Content-Type: image/jpeg\r\n\r\n\
+        \xff\xd8\r\n\
+        --XyZ--\r\n";
+
+    /// Reads `body` a few bytes at a time, like small `Wants::Body` buffers.
+    fn read_all(mut body: impl Read) -> Vec<u8> {
+        let mut out = vec![];
+        let mut buf = [0; 7];
+        loop {
+            match body.read(&mut buf).unwrap() {
+                0 => return out,
+                read => out.extend_from_slice(&buf[..read]),
+            }
+        }
+    }
+
+    #[test]
+    fn encodes_a_form() {
+        let request = form().into_request(Method::Post, "/upload").unwrap();
+        assert_eq!(
+            request.headers().get("Content-Type"),
+            Some("multipart/form-data; boundary=XyZ")
+        );
+        assert_eq!(request.body(), ENCODED);
+    }
+
+    #[test]
+    fn streams_the_same_bytes() {
+        let (request, body) = form().into_upload(Method::Post, "/upload");
+        assert!(
+            matches!(request.streaming(), Some(Streaming::Sized(len)) if len == ENCODED.len() as u64)
+        );
+        assert_eq!(read_all(body), ENCODED);
+    }
+
+    #[test]
+    fn streams_readers() {
+        let mut form = Multipart::with_boundary("b");
+        form.add_part(Part::reader("cut", &b"0123456789"[..], Some(4)));
+        let mut unsized_part = Part::reader("whole", &b"abc"[..], None);
+        unsized_part.add_header("Content-Transfer-Encoding", "binary");
+        form.add_part(unsized_part);
+
+        let (request, body) = form.into_upload(Method::Put, "/");
+        assert!(matches!(request.streaming(), Some(Streaming::Chunked)));
+        assert_eq!(
+            read_all(body),
+            b"--b\r\nContent-Disposition: form-data; name=\"cut\"\r\n\r\n0123\r\n\
+              --b\r\nContent-Disposition: form-data; name=\"whole\"\r\n\
+              content-transfer-encoding: binary\r\n\r\nabc\r\n--b--\r\n"
+        );
+    }
+
+    #[test]
+    fn readers_need_an_upload() {
+        let mut form = Multipart::new();
+        form.add_part(Part::reader("file", std::io::empty(), None));
+        let err = form.into_request(Method::Post, "/").unwrap_err();
+        assert!(matches!(err, Error::InvalidRequest(_)), "{err:?}");
+    }
+
+    #[test]
+    fn escapes_what_would_end_a_parameter() {
+        assert_eq!(escape("a\"b\r\nc"), "a%22b%0D%0Ac");
+        let boundary = Multipart::new().boundary().to_string();
+        assert_ne!(boundary, Multipart::new().boundary());
+        assert!(boundary.len() <= 70, "RFC 2046 caps boundaries at 70");
+    }
+}
@@ -0,0 +1,64 @@
+use crate::{
+    Error, IpPreference, NetObserver, Result, TimeoutKind, Timeouts,
+    deadline::remaining,
+    net_event::{emit_connect, emit_resolve},
+};
+use std::{
+    io::ErrorKind,
+    net::{SocketAddr, TcpStream, ToSocketAddrs as _},
+    time::Instant,
+};
+
+/// Blocks in `getaddrinfo`, addresses come in the order they should be tried.
+pub(crate) fn resolve(
+    hostname: &str,
+    port: u16,
+    preference: IpPreference,
+    observer: Option<&NetObserver>,
+) -> Result<Vec<SocketAddr>> {
+    let started = Instant::now();
+    let addrs = (hostname, port)
+        .to_socket_addrs()
+        .map(|addrs| preference.order(addrs))
+        .map_err(|err| Error::Dns(format!("failed to resolve {hostname}: {err}")));
+    emit_resolve(observer, hostname, port, started, &addrs);
+    addrs
+}
+
+#[cfg_attr(
+    not(any(feature = "blocking", feature = "mio", feature = "epoll")),
+    allow(dead_code)
+)]
+pub(crate) fn connect(
+    hostname: &str,
+    port: u16,
+    timeouts: &Timeouts,
+    deadline: Option<Instant>,
+    preference: IpPreference,
+    observer: Option<&NetObserver>,
+) -> Result<TcpStream> {
+    let timeout = remaining(timeouts.connect, deadline)?;
+
+    let mut last_err = None;
+    for addr in resolve(hostname, port, preference, observer)? {
+        let started = Instant::now();
+        let sock = match timeout {
+            Some(timeout) => TcpStream::connect_timeout(&addr, timeout),
+            None => TcpStream::connect(addr),
+        };
+        emit_connect(observer, addr, started, &sock);
+
+        match sock {
+            Ok(sock) => return Ok(sock),
+            Err(err) if err.kind() == ErrorKind::TimedOut => {
+                return Err(Error::Timeout(TimeoutKind::Connect));
+            }
+            Err(err) => last_err = Some(err),
+        }
+    }
+
+    Err(last_err.map_or_else(
+        || Error::Dns(format!("failed to resolve DNS name: {hostname}")),
+        Error::Io,
+    ))
+}
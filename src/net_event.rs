@@ -0,0 +1,125 @@
+use crate::{Error, Result};
+use std::{
+    fmt, io,
+    net::SocketAddr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// What the drivers that connect themselves did to reach a server,
+/// reported to `ClientOptions::net_observer`.
+#[derive(Debug)]
+pub enum NetEvent<'a> {
+    Resolved {
+        host: &'a str,
+        port: u16,
+        /// In the order they're going to be tried, see `IpPreference`.
+        addrs: &'a [SocketAddr],
+        duration: Duration,
+    },
+    ResolveFailed {
+        host: &'a str,
+        port: u16,
+        error: &'a Error,
+        duration: Duration,
+    },
+    Connected {
+        addr: SocketAddr,
+        duration: Duration,
+    },
+    /// The next address is tried after it, if there's one left.
+    /// Attempts cut off by the connect timeout fail with `TimedOut`.
+    ConnectFailed {
+        addr: SocketAddr,
+        error: &'a io::Error,
+        duration: Duration,
+    },
+}
+
+/// A callback for `NetEvent`s, called synchronously from the driver
+/// so it should only record the event and return.
+#[derive(Clone)]
+pub struct NetObserver(Arc<dyn Fn(&NetEvent) + Send + Sync>);
+
+impl NetObserver {
+    pub fn new(observer: impl Fn(&NetEvent) + Send + Sync + 'static) -> Self {
+        Self(Arc::new(observer))
+    }
+}
+
+impl fmt::Debug for NetObserver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("NetObserver")
+    }
+}
+
+/// Reports the outcome of connecting to `addr`, started at `started`.
+#[cfg_attr(
+    not(any(
+        feature = "blocking",
+        feature = "poll",
+        feature = "io-uring",
+        feature = "tokio",
+        feature = "mio",
+        feature = "epoll"
+    )),
+    allow(dead_code)
+)]
+pub(crate) fn emit_connect<T>(
+    observer: Option<&NetObserver>,
+    addr: SocketAddr,
+    started: Instant,
+    result: &io::Result<T>,
+) {
+    let Some(observer) = observer else {
+        return;
+    };
+    let duration = started.elapsed();
+    (observer.0)(&match result {
+        Ok(_) => NetEvent::Connected { addr, duration },
+        Err(error) => NetEvent::ConnectFailed {
+            addr,
+            error,
+            duration,
+        },
+    });
+}
+
+/// Reports the outcome of resolving `host`, started at `started`.
+#[cfg_attr(
+    not(any(
+        feature = "blocking",
+        feature = "poll",
+        feature = "io-uring",
+        feature = "tokio",
+        feature = "mio",
+        feature = "epoll"
+    )),
+    allow(dead_code)
+)]
+pub(crate) fn emit_resolve(
+    observer: Option<&NetObserver>,
+    host: &str,
+    port: u16,
+    started: Instant,
+    result: &Result<Vec<SocketAddr>>,
+) {
+    let Some(observer) = observer else {
+        return;
+    };
+    let duration = started.elapsed();
+    (observer.0)(&match result {
+        Ok(addrs) => NetEvent::Resolved {
+            host,
+            port,
+            addrs,
+            duration,
+        },
+        Err(error) => NetEvent::ResolveFailed {
+            host,
+            port,
+            error,
+            duration,
+        },
+    });
+}
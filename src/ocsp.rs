@@ -0,0 +1,359 @@
+//! OCSP (RFC 6960) requests for a certificate, sent with the crate itself.
+//!
+//! Only the transport is covered: the returned response is the raw DER,
+//! its signature and the certificate status inside are not checked.
+
+use crate::{Error, Method, Request, Response, Result};
+use sha1::{Digest as _, Sha1};
+
+#[derive(Debug, Clone)]
+pub struct OcspRequest {
+    url: Option<String>,
+    der: Vec<u8>,
+}
+
+impl OcspRequest {
+    /// Builds a request for the status of `cert`, both certificates are DER.
+    pub fn new(cert: &[u8], issuer: &[u8]) -> Result<Self> {
+        let cert = Certificate::parse(cert)?;
+        let issuer = Certificate::parse(issuer)?;
+
+        let mut spki = Der::new(Der::new(issuer.spki).expect(SEQUENCE)?.0);
+        spki.expect(SEQUENCE)?; // algorithm
+        let public_key = spki.expect(BIT_STRING)?.0;
+        // the first byte is the number of unused bits
+        let public_key = public_key.get(1..).ok_or_else(malformed)?;
+
+        let cert_id = [
+            SHA1_ALGORITHM.to_vec(),
+            encode(OCTET_STRING, &Sha1::digest(cert.issuer)),
+            encode(OCTET_STRING, &Sha1::digest(public_key)),
+            encode(INTEGER, cert.serial),
+        ]
+        .concat();
+        let request = encode(SEQUENCE, &encode(SEQUENCE, &cert_id));
+        let tbs_request = encode(SEQUENCE, &encode(SEQUENCE, &request));
+
+        Ok(Self {
+            url: cert.ocsp_url,
+            der: encode(SEQUENCE, &tbs_request),
+        })
+    }
+
+    /// The responder listed in the certificate's Authority Information Access.
+    pub fn responder_url(&self) -> Option<&str> {
+        self.url.as_deref()
+    }
+
+    pub fn as_der(&self) -> &[u8] {
+        &self.der
+    }
+
+    /// `POST` of the request to `path` on the responder.
+    pub fn http_request(&self, path: impl Into<String>) -> Request {
+        let mut request = Request::new(Method::Post, path);
+        request.add_header("Content-Type", "application/ocsp-request");
+        request.add_header("Accept", "application/ocsp-response");
+        request.set_body(self.der.clone());
+        request
+    }
+}
+
+/// Returns the DER of a `successful` OCSP response, other response
+/// statuses (e.g. `tryLater`) are errors.
+pub fn parse_response(response: Response) -> Result<Vec<u8>> {
+    let response = response.error_for_status()?;
+
+    let mut der = Der::new(&response.body);
+    let mut outer = Der::new(der.expect(SEQUENCE)?.0);
+    let status = match outer.expect(ENUMERATED)?.0 {
+        [status] => *status,
+        _ => return Err(malformed()),
+    };
+    if status != 0 {
+        let status = match status {
+            1 => "malformedRequest",
+            2 => "internalError",
+            3 => "tryLater",
+            5 => "sigRequired",
+            6 => "unauthorized",
+            _ => "unknown",
+        };
+        return Err(Error::Protocol(format!("OCSP responder answered {status}")));
+    }
+
+    Ok(response.body)
+}
+
+/// Asks the responder from the certificate's Authority Information Access.
+#[cfg(feature = "blocking")]
+pub fn fetch(cert: &[u8], issuer: &[u8], options: &crate::ClientOptions) -> Result<Vec<u8>> {
+    let request = OcspRequest::new(cert, issuer)?;
+    let url = request.responder_url().ok_or_else(|| {
+        Error::InvalidRequest("the certificate doesn't name an OCSP responder".to_string())
+    })?;
+    let url = crate::Url::parse(url)?;
+
+    let response = crate::BlockingConnection::perform(
+        url.scheme,
+        &url.host,
+        url.port,
+        request.http_request(url.path.clone()),
+        options,
+    )?;
+    parse_response(response)
+}
+
+struct Certificate<'a> {
+    serial: &'a [u8],
+    /// Whole DER of the issuer Name.
+    issuer: &'a [u8],
+    /// Whole DER of the SubjectPublicKeyInfo.
+    spki: &'a [u8],
+    ocsp_url: Option<String>,
+}
+
+impl<'a> Certificate<'a> {
+    fn parse(der: &'a [u8]) -> Result<Self> {
+        let mut cert = Der::new(der);
+        let mut cert = Der::new(cert.expect(SEQUENCE)?.0);
+        let mut tbs = Der::new(cert.expect(SEQUENCE)?.0);
+
+        if tbs.peek() == Some(VERSION) {
+            tbs.next()?;
+        }
+        let serial = tbs.expect(INTEGER)?.0;
+        tbs.expect(SEQUENCE)?; // signature algorithm
+        let issuer = tbs.expect(SEQUENCE)?.1;
+        tbs.expect(SEQUENCE)?; // validity
+        tbs.expect(SEQUENCE)?; // subject
+        let spki = tbs.expect(SEQUENCE)?.1;
+
+        let mut ocsp_url = None;
+        while let Some(tag) = tbs.peek() {
+            let (content, _) = tbs.next()?;
+            if tag == EXTENSIONS {
+                ocsp_url = find_ocsp_url(content)?;
+            }
+        }
+
+        Ok(Self {
+            serial,
+            issuer,
+            spki,
+            ocsp_url,
+        })
+    }
+}
+
+fn find_ocsp_url(extensions: &[u8]) -> Result<Option<String>> {
+    let mut extensions = Der::new(extensions);
+    let mut extensions = Der::new(extensions.expect(SEQUENCE)?.0);
+
+    while extensions.peek().is_some() {
+        let mut extension = Der::new(extensions.expect(SEQUENCE)?.0);
+        if extension.expect(OID)?.0 != AUTHORITY_INFO_ACCESS {
+            continue;
+        }
+        if extension.peek() == Some(BOOLEAN) {
+            extension.next()?;
+        }
+
+        let mut value = Der::new(extension.expect(OCTET_STRING)?.0);
+        let mut descriptions = Der::new(value.expect(SEQUENCE)?.0);
+        while descriptions.peek().is_some() {
+            let mut description = Der::new(descriptions.expect(SEQUENCE)?.0);
+            let method = description.expect(OID)?.0;
+            let tag = description.peek();
+            let location = description.next()?.0;
+            if method == ID_AD_OCSP && tag == Some(URI) {
+                let url = std::str::from_utf8(location).map_err(|_| malformed())?;
+                return Ok(Some(url.to_string()));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// Just enough of a DER reader to walk a certificate.
+struct Der<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Der<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.data.first().copied()
+    }
+
+    /// Content and whole encoding of the next element.
+    fn next(&mut self) -> Result<(&'a [u8], &'a [u8])> {
+        let [_, first, rest @ ..] = self.data else {
+            return Err(malformed());
+        };
+        let first = *first;
+
+        let (len, rest) = if first < 0x80 {
+            (usize::from(first), rest)
+        } else {
+            let count = usize::from(first & 0x7f);
+            if count == 0 || count > 4 || rest.len() < count {
+                return Err(malformed());
+            }
+            let len = rest[..count]
+                .iter()
+                .fold(0, |len, &byte| (len << 8) | usize::from(byte));
+            (len, &rest[count..])
+        };
+
+        let header = self.data.len() - rest.len();
+        if rest.len() < len {
+            return Err(malformed());
+        }
+        let whole = &self.data[..header + len];
+        self.data = &self.data[header + len..];
+        Ok((&rest[..len], whole))
+    }
+
+    fn expect(&mut self, tag: u8) -> Result<(&'a [u8], &'a [u8])> {
+        if self.peek() != Some(tag) {
+            return Err(malformed());
+        }
+        self.next()
+    }
+}
+
+fn encode(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    let len = content.len();
+    if len < 0x80 {
+        out.push(len as u8);
+    } else {
+        let bytes = len.to_be_bytes();
+        let skip = bytes.iter().take_while(|&&byte| byte == 0).count();
+        out.push(0x80 | (bytes.len() - skip) as u8);
+        out.extend_from_slice(&bytes[skip..]);
+    }
+    out.extend_from_slice(content);
+    out
+}
+
+fn malformed() -> Error {
+    Error::Parse("malformed DER".to_string())
+}
+
+const BOOLEAN: u8 = 0x01;
+const INTEGER: u8 = 0x02;
+const BIT_STRING: u8 = 0x03;
+const OCTET_STRING: u8 = 0x04;
+const OID: u8 = 0x06;
+const ENUMERATED: u8 = 0x0a;
+const SEQUENCE: u8 = 0x30;
+const URI: u8 = 0x86;
+const VERSION: u8 = 0xa0;
+const EXTENSIONS: u8 = 0xa3;
+
+// 1.3.6.1.5.5.7.1.1
+const AUTHORITY_INFO_ACCESS: &[u8] = &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x01, 0x01];
+// 1.3.6.1.5.5.7.48.1
+const ID_AD_OCSP: &[u8] = &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x07, 0x30, 0x01];
+// AlgorithmIdentifier { 1.3.14.3.2.26, NULL }
+const SHA1_ALGORITHM: &[u8] = &[
+    0x30, 0x09, 0x06, 0x05, 0x2b, 0x0e, 0x03, 0x02, 0x1a, 0x05, 0x00,
+];
+
+#[cfg(all(test, feature = "ocsp"))]
+mod tests {
+    use super::*;
+    use rustls::pki_types::{CertificateDer, pem::PemObject as _};
+
+    fn der(pem: &[u8]) -> Vec<u8> {
+        CertificateDer::from_pem_slice(pem).unwrap().to_vec()
+    }
+
+    fn response(status: &str, body: &[u8]) -> Response {
+        let head = format!(
+            "HTTP/1.1 {status}\r\nContent-Length: {}\r\n\r\n",
+            body.len()
+        );
+        let mut response = Response::parse(head.as_bytes(), false).unwrap();
+        response.body = body.to_vec();
+        response
+    }
+
+    #[test]
+    fn encodes_a_request_like_openssl() {
+        let leaf = der(include_bytes!("../tests/data/localhost.pem"));
+        let ca = der(include_bytes!("../tests/data/ca.pem"));
+        let request = OcspRequest::new(&leaf, &ca).unwrap();
+
+        // `openssl ocsp -issuer ca.pem -cert localhost.pem -no_nonce -reqout`
+        let expected = "305530533051304f304d300906052b0e03021a050004146888b1e48de69365af4591b5\
+                        cf22f10680291a1e0414a8546ea5a439af56026c5985b6840bff79edea5202141e797e\
+                        1c24a4f085bfbf1f045792e5dbaa4c2c54";
+        let hex = request
+            .as_der()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect::<String>();
+        assert_eq!(hex, expected);
+        // the test certificate has no Authority Information Access
+        assert_eq!(request.responder_url(), None);
+
+        let http = request.http_request("/");
+        assert_eq!(
+            http.headers().get("Content-Type"),
+            Some("application/ocsp-request")
+        );
+        assert_eq!(http.body(), request.as_der());
+    }
+
+    #[test]
+    fn rejects_malformed_certificates() {
+        let ca = der(include_bytes!("../tests/data/ca.pem"));
+        for cert in [
+            &b""[..],
+            b"\x30",
+            b"\x30\x05\x02\x01",
+            b"\x30\x84\xff\xff\xff\xff",
+        ] {
+            let err = OcspRequest::new(cert, &ca).unwrap_err();
+            assert!(matches!(err, Error::Parse(_)), "{cert:?}: {err:?}");
+        }
+        let leaf = der(include_bytes!("../tests/data/localhost.pem"));
+        assert!(OcspRequest::new(&leaf, &leaf[..leaf.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn accepts_successful_responses() {
+        // responseStatus successful, responseBytes elided
+        let body = b"\x30\x03\x0a\x01\x00";
+        assert_eq!(parse_response(response("200 OK", body)).unwrap(), body);
+    }
+
+    #[test]
+    fn rejects_malformed_responses() {
+        for body in [
+            &b""[..],
+            b"\x04\x03\x0a\x01\x00",
+            b"\x30\x03\x0a\x02\x00",
+            b"\x30\x05\x0a\x01",
+            b"\x30\x04\x0a\x02\x00\x00",
+        ] {
+            let err = parse_response(response("200 OK", body)).unwrap_err();
+            assert!(matches!(err, Error::Parse(_)), "{body:?}: {err:?}");
+        }
+
+        let err = parse_response(response("200 OK", b"\x30\x03\x0a\x01\x03")).unwrap_err();
+        assert!(
+            matches!(&err, Error::Protocol(message) if message.contains("tryLater")),
+            "{err:?}"
+        );
+        let err = parse_response(response("500 Oops", b"")).unwrap_err();
+        assert!(matches!(err, Error::Status(_)), "{err:?}");
+    }
+}
@@ -0,0 +1,334 @@
+use crate::{Error, Result, base64};
+use rustls::{
+    CertificateError, DigitallySignedStruct, OtherError, RootCertStore, SignatureScheme,
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    crypto::{CryptoProvider, hash::Hash, hash::HashAlgorithm},
+    pki_types::{CertificateDer, ServerName, TrustAnchor, UnixTime},
+    server::ParsedCertificate,
+};
+use std::{fmt, str::FromStr, sync::Arc};
+use webpki::{EndEntityCert, KeyUsage, VerifiedPath};
+
+/// A SHA-256 fingerprint the server's chain has to contain,
+/// see `ClientOptions::pins`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Pin {
+    /// Of a certificate's DER, as printed by
+    /// `openssl x509 -noout -fingerprint -sha256`.
+    Certificate([u8; 32]),
+    /// Of a SubjectPublicKeyInfo's DER, stays valid when a certificate is
+    /// re-issued for the same key.
+    Spki([u8; 32]),
+}
+
+/// `sha256/` and base64 for an SPKI pin, the format of HPKP and curl's
+/// `--pinnedpubkey`, or hex (colons allowed) for a certificate pin.
+impl FromStr for Pin {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::InvalidRequest(format!("invalid pin {s:?}"));
+
+        if let Some(encoded) = s.strip_prefix("sha256/") {
+            let hash = base64::decode(encoded).ok_or_else(invalid)?;
+            return Ok(Self::Spki(hash.try_into().map_err(|_| invalid())?));
+        }
+
+        let digits = s.replace(':', "");
+        if digits.len() != 64 || !digits.is_ascii() {
+            return Err(invalid());
+        }
+        let mut hash = [0; 32];
+        for (byte, pair) in hash.iter_mut().zip(digits.as_bytes().chunks(2)) {
+            let pair = std::str::from_utf8(pair).map_err(|_| invalid())?;
+            *byte = u8::from_str_radix(pair, 16).map_err(|_| invalid())?;
+        }
+        Ok(Self::Certificate(hash))
+    }
+}
+
+/// Verifies the chain with `inner`, then requires one of the certificates
+/// of a path from the end entity to one of `roots` to match one of the pins.
+/// Certificates the server sent but that aren't on such a path don't count,
+/// anyone can append a copy of a pinned CA to their chain. Without `roots`
+/// only the end entity does.
+#[derive(Debug)]
+pub(crate) struct PinningVerifier {
+    inner: Arc<dyn ServerCertVerifier>,
+    provider: Arc<CryptoProvider>,
+    /// What `inner` verifies against, `None` when it accepts any chain.
+    roots: Option<Arc<RootCertStore>>,
+    pins: Vec<Pin>,
+}
+
+impl PinningVerifier {
+    pub(crate) fn new(
+        inner: Arc<dyn ServerCertVerifier>,
+        provider: Arc<CryptoProvider>,
+        roots: Option<Arc<RootCertStore>>,
+        pins: Vec<Pin>,
+    ) -> Self {
+        Self {
+            inner,
+            provider,
+            roots,
+            pins,
+        }
+    }
+
+    fn sha256(&self) -> Result<&'static dyn Hash, rustls::Error> {
+        self.provider
+            .cipher_suites
+            .iter()
+            .filter_map(|suite| suite.tls13())
+            .map(|suite| suite.common.hash_provider)
+            .find(|hash| hash.algorithm() == HashAlgorithm::SHA256)
+            .ok_or_else(|| rustls::Error::General("no SHA-256 to check pins with".to_string()))
+    }
+
+    /// `cert` is the DER of the certificate when there's one to hash.
+    fn matches(&self, sha256: &dyn Hash, cert: Option<&[u8]>, spki: &[u8]) -> bool {
+        let cert_hash = cert.map(|cert| sha256.hash(cert));
+        let spki_hash = sha256.hash(spki);
+
+        self.pins.iter().any(|pin| match pin {
+            Pin::Certificate(pinned) => cert_hash
+                .as_ref()
+                .is_some_and(|cert_hash| cert_hash.as_ref() == pinned),
+            Pin::Spki(pinned) => spki_hash.as_ref() == pinned,
+        })
+    }
+
+    fn matches_cert(
+        &self,
+        sha256: &dyn Hash,
+        cert: &CertificateDer<'_>,
+    ) -> Result<bool, rustls::Error> {
+        let spki = ParsedCertificate::try_from(cert)?.subject_public_key_info();
+        Ok(self.matches(sha256, Some(cert.as_ref()), spki.as_ref()))
+    }
+
+    /// Whether some path from `end_entity` to a root has a pinned
+    /// certificate, webpki tries the other paths while one doesn't.
+    fn path_matches(
+        &self,
+        sha256: &dyn Hash,
+        roots: &RootCertStore,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        now: UnixTime,
+    ) -> Result<bool, rustls::Error> {
+        let cert = EndEntityCert::try_from(end_entity)
+            .map_err(|_| rustls::Error::InvalidCertificate(CertificateError::BadEncoding))?;
+        let pinned = |path: &VerifiedPath<'_>| {
+            let certs = std::iter::once(path.end_entity().der())
+                .chain(path.intermediate_certificates().map(|cert| cert.der()));
+            for cert in certs {
+                if self.matches_cert(sha256, &cert).unwrap_or(false) {
+                    return Ok(());
+                }
+            }
+            if self.anchor_matches(sha256, path.anchor(), intermediates) {
+                return Ok(());
+            }
+            Err(webpki::Error::UnknownIssuer)
+        };
+
+        let path = cert.verify_for_usage(
+            self.provider.signature_verification_algorithms.all,
+            &roots.roots,
+            intermediates,
+            now,
+            KeyUsage::server_auth(),
+            None,
+            Some(&pinned),
+        );
+        // `inner` found a path already, so there's none with a pin
+        Ok(path.is_ok())
+    }
+
+    /// Roots are only known by their subject and key, a certificate pin
+    /// matches one if the server sent the root's certificate along.
+    fn anchor_matches(
+        &self,
+        sha256: &dyn Hash,
+        anchor: &TrustAnchor<'_>,
+        presented: &[CertificateDer<'_>],
+    ) -> bool {
+        let spki = der_sequence(anchor.subject_public_key_info.as_ref());
+        if self.matches(sha256, None, &spki) {
+            return true;
+        }
+        presented.iter().any(|cert| {
+            webpki::anchor_from_trusted_cert(cert).is_ok_and(|presented| {
+                presented.subject == anchor.subject
+                    && presented.subject_public_key_info == anchor.subject_public_key_info
+                    && self.matches(sha256, Some(cert.as_ref()), &spki)
+            })
+        })
+    }
+}
+
+/// Wraps the contents of a SubjectPublicKeyInfo (how roots keep it) back
+/// into the SEQUENCE that SPKI pins are hashes of.
+fn der_sequence(contents: &[u8]) -> Vec<u8> {
+    let mut der = vec![0x30];
+    let len = contents.len();
+    if len < 0x80 {
+        der.push(len as u8);
+    } else {
+        let bytes = len.to_be_bytes();
+        let skip = bytes.iter().take_while(|byte| **byte == 0).count();
+        der.push(0x80 | (bytes.len() - skip) as u8);
+        der.extend_from_slice(&bytes[skip..]);
+    }
+    der.extend_from_slice(contents);
+    der
+}
+
+impl ServerCertVerifier for PinningVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        intermediates: &[CertificateDer<'_>],
+        server_name: &ServerName<'_>,
+        ocsp_response: &[u8],
+        now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let verified = self.inner.verify_server_cert(
+            end_entity,
+            intermediates,
+            server_name,
+            ocsp_response,
+            now,
+        )?;
+
+        let sha256 = self.sha256()?;
+        let matched = match &self.roots {
+            Some(roots) => self.path_matches(sha256, roots, end_entity, intermediates, now)?,
+            // with no path to speak of, only the certificate the handshake
+            // was signed with is the server's own
+            None => self.matches_cert(sha256, end_entity)?,
+        };
+        if matched {
+            return Ok(verified);
+        }
+        Err(rustls::Error::InvalidCertificate(CertificateError::Other(
+            OtherError(Arc::new(NoPinMatched)),
+        )))
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls12_signature(message, cert, dss)
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        self.inner.verify_tls13_signature(message, cert, dss)
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.inner.supported_verify_schemes()
+    }
+}
+
+/// The chain verified but none of the certificates on its path matches a pin.
+#[derive(Debug)]
+pub struct NoPinMatched;
+
+impl fmt::Display for NoPinMatched {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("no certificate on the verified path matches a pin")
+    }
+}
+
+impl std::error::Error for NoPinMatched {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ClientOptions, FSM, Request,
+        testing::{Script, TlsPeer, UNRELATED_CA, drive},
+    };
+
+    const CA_CERT: &str = "A4:33:2E:DD:C9:D8:73:AB:57:F6:05:DA:29:3F:06:8E:72:3E:88:60:96:F9:9A:2D:76:DE:80:FF:0D:89:61:32";
+    const CA_SPKI: &str = "sha256/cadHtrcYnHg5HGF2T98cciJmRXS/OEcYJBoBC+woTtI=";
+    const LEAF_SPKI: &str = "sha256/3jny6e2tZRkWZmRsTbgpOhkkAv2E2optdRIXRBZliFY=";
+    const UNRELATED_CERT: &str = "DB:6C:FE:6D:D8:2D:9E:A8:7D:35:41:9B:94:42:D8:C6:B7:88:7F:9E:5C:9B:02:FB:30:40:FA:51:80:88:59:23";
+    const UNRELATED_SPKI: &str = "sha256/s+QAEIK9zKVrThR3jL/9FC93l8ZzCH+6rOuwaanonOQ=";
+
+    fn connect(pin: &str, mut peer: TlsPeer) -> crate::Result<()> {
+        let options = ClientOptions {
+            pins: vec![("localhost".to_string(), pin.parse().unwrap())],
+            ..TlsPeer::options()
+        };
+        let server_name = ServerName::try_from("localhost").unwrap();
+        let mut fsm = FSM::new(server_name, Request::get("/"), &options)?;
+        drive(&mut fsm, &mut peer, &mut &[][..]).map(|_| ())
+    }
+
+    fn ok() -> Script {
+        Script::new(&[&[b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n"]])
+    }
+
+    #[test]
+    fn matches_the_certificates_of_the_path() {
+        connect(LEAF_SPKI, TlsPeer::new(ok())).unwrap();
+        // the root isn't sent, its key is known from the trust store
+        connect(CA_SPKI, TlsPeer::new(ok())).unwrap();
+        // its certificate only when the server sends it along
+        connect(CA_CERT, TlsPeer::new(ok())).unwrap_err();
+        let ca = include_bytes!("../tests/data/ca.pem");
+        connect(CA_CERT, TlsPeer::appending(ok(), ca)).unwrap();
+    }
+
+    #[test]
+    fn ignores_sent_certificates_off_the_path() {
+        for pin in [UNRELATED_CERT, UNRELATED_SPKI] {
+            let err = connect(pin, TlsPeer::appending(ok(), UNRELATED_CA)).unwrap_err();
+            assert!(
+                matches!(&err, crate::Error::Certificate(CertificateError::Other(other)) if other.0.is::<NoPinMatched>()),
+                "{err:?}"
+            );
+        }
+    }
+
+    #[cfg(feature = "insecure")]
+    #[test]
+    fn only_the_end_entity_counts_when_any_chain_is_accepted() {
+        let connect = |pin: &str, mut peer: TlsPeer| {
+            let options = ClientOptions {
+                pins: vec![("localhost".to_string(), pin.parse().unwrap())],
+                roots: Some(crate::RootCerts::empty()),
+                danger_accept_invalid_certs: true,
+                ..ClientOptions::default()
+            };
+            let server_name = ServerName::try_from("localhost").unwrap();
+            let mut fsm = FSM::new(server_name, Request::get("/"), &options).unwrap();
+            drive(&mut fsm, &mut peer, &mut &[][..]).map(|_| ())
+        };
+
+        connect(LEAF_SPKI, TlsPeer::new(ok())).unwrap();
+        connect(UNRELATED_SPKI, TlsPeer::appending(ok(), UNRELATED_CA)).unwrap_err();
+        let ca = include_bytes!("../tests/data/ca.pem");
+        connect(CA_SPKI, TlsPeer::appending(ok(), ca)).unwrap_err();
+    }
+
+    #[test]
+    fn der_sequence_lengths() {
+        assert_eq!(der_sequence(&[1, 2]), [0x30, 2, 1, 2]);
+        let long = der_sequence(&[0; 300]);
+        assert_eq!(long[..4], [0x30, 0x82, 0x01, 0x2c]);
+        assert_eq!(long.len(), 304);
+    }
+}
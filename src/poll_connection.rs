@@ -1,112 +1,447 @@
-use crate::{FSM, Request, Response, Wants};
-use anyhow::Result;
+use crate::{
+    ClientOptions, Error, FSM, NetObserver, Request, Response, Result, Scheme, TimeoutKind,
+    Timeouts, TlsInfo, Url, Wants,
+    deadline::{deadline, remaining},
+    fsm::{is_write_rejected, sink_full},
+    net,
+    net_event::emit_connect,
+    socket::Socket,
+    url::host_header,
+};
 use libc::{POLLIN, POLLOUT};
-use rustls::pki_types::ServerName;
 use std::{
-    io::{ErrorKind, Read, Write},
-    net::TcpStream,
-    os::fd::AsRawFd,
+    collections::VecDeque,
+    io::{self, ErrorKind, Read, Write},
+    net::SocketAddr,
+    num::NonZeroUsize,
+    os::fd::{AsRawFd, OwnedFd},
+    sync::Arc,
+    time::{Duration, Instant},
 };
 
 pub struct PollConnection {
     fsm: FSM,
-    sock: TcpStream,
+    sock: Socket,
+    /// Set until the non-blocking connect has finished.
+    connecting: Option<Connecting>,
+    observer: Option<NetObserver>,
     response: Option<Response>,
     done: bool,
+
+    timeouts: Timeouts,
+    deadline: Option<Instant>,
+    io_deadline: Option<(Instant, TimeoutKind)>,
+
+    budget: Option<NonZeroUsize>,
+}
+
+struct Connecting {
+    addr: SocketAddr,
+    started: Instant,
+    /// Addresses to fall back to if this attempt fails.
+    rest: VecDeque<SocketAddr>,
+    /// For each attempt, like `TcpStream::connect_timeout`.
+    timeout: Option<Duration>,
 }
 
+// boxing the response would bring back the allocation its inline headers save
+#[allow(clippy::large_enum_variant)]
 pub enum EventsOrResponse {
     Events(i16),
     Response(Response),
 }
 
 impl PollConnection {
-    pub fn get(hostname: &str, port: u16, path: &str) -> Result<Self> {
-        let fsm = {
-            let server_name = ServerName::try_from(hostname)?.to_owned();
+    pub fn get(hostname: &str, port: u16, path: &str, options: &ClientOptions) -> Result<Self> {
+        Self::new(Scheme::Https, hostname, port, Request::get(path), options)
+    }
 
-            let mut request = Request::get(path);
-            request.add_header("Host", hostname);
-            request.add_header("Connection", "close");
+    pub fn get_url(url: &str, options: &ClientOptions) -> Result<Self> {
+        let url = Url::parse(url)?;
+        Self::new(url.scheme, &url.host, url.port, url.get_request(), options)
+    }
 
-            FSM::new(server_name, request)?
-        };
+    /// Sends `request` over a socket the caller has connected (TCP, a unix
+    /// socket, one bound to a specific interface...), `hostname` and `port` are
+    /// only used for SNI, certificate verification and the `Host` header. The
+    /// socket is switched to non-blocking mode and closed when the connection
+    /// is dropped.
+    pub fn from_stream(
+        stream: impl Into<OwnedFd>,
+        scheme: Scheme,
+        hostname: &str,
+        port: u16,
+        request: Request,
+        options: &ClientOptions,
+    ) -> Result<Self> {
+        let fsm = Self::fsm(scheme, hostname, port, request, options)?;
+        let sock = Socket(stream.into());
+        sock.set_nonblocking()?;
+        Ok(Self::with_socket(
+            fsm,
+            sock,
+            None,
+            options,
+            deadline(&options.timeouts),
+        ))
+    }
+
+    /// Sends an arbitrary request, `Host` is added unless the request
+    /// already sets it. Resolving blocks, connecting doesn't: the socket
+    /// reports `POLLOUT` once it's connected (or failed, then the next
+    /// address is tried).
+    pub fn new(
+        scheme: Scheme,
+        hostname: &str,
+        port: u16,
+        request: Request,
+        options: &ClientOptions,
+    ) -> Result<Self> {
+        let fsm = Self::fsm(scheme, hostname, port, request, options)?;
+        let deadline = deadline(&options.timeouts);
+        let timeout = remaining(options.timeouts.connect, deadline)?;
+        let observer = options.net_observer.as_ref();
+
+        let host = options.connect_host(hostname);
+        let mut addrs = VecDeque::from(net::resolve(host, port, options.ip_preference, observer)?);
+        let (sock, addr) = start_connect(&mut addrs, observer).map_err(|last_err| {
+            last_err.map_or_else(
+                || Error::Dns(format!("failed to resolve DNS name: {host}")),
+                Error::Io,
+            )
+        })?;
+
+        let mut conn = Self::with_socket(fsm, sock, None, options, deadline);
+        conn.set_connecting(addr, addrs, timeout);
+        Ok(conn)
+    }
 
-        let sock = TcpStream::connect(format!("{hostname}:{port}"))?;
-        sock.set_nonblocking(true)?;
+    fn fsm(
+        scheme: Scheme,
+        hostname: &str,
+        port: u16,
+        mut request: Request,
+        options: &ClientOptions,
+    ) -> Result<FSM> {
+        request.add_default_header("Host", &host_header(scheme, hostname, port));
 
-        Ok(Self {
+        FSM::with_scheme(scheme, hostname, request, options)
+    }
+
+    fn with_socket(
+        fsm: FSM,
+        sock: Socket,
+        connecting: Option<Connecting>,
+        options: &ClientOptions,
+        deadline: Option<Instant>,
+    ) -> Self {
+        let timeouts = options.timeouts;
+
+        Self {
             fsm,
             sock,
+            connecting,
+            observer: options.net_observer.clone(),
             response: None,
             done: false,
-        })
+
+            timeouts,
+            deadline,
+            io_deadline: None,
+
+            budget: None,
+        }
+    }
+
+    fn set_connecting(
+        &mut self,
+        addr: SocketAddr,
+        rest: VecDeque<SocketAddr>,
+        timeout: Option<Duration>,
+    ) {
+        self.io_deadline = timeout.map(|timeout| (Instant::now() + timeout, TimeoutKind::Connect));
+        self.connecting = Some(Connecting {
+            addr,
+            started: Instant::now(),
+            rest,
+            timeout,
+        });
+    }
+
+    /// Called once the socket is writable while connecting, moves on to the
+    /// next address if the attempt failed, over a new socket.
+    fn finish_connect(&mut self) -> Result<()> {
+        let Some(Connecting {
+            addr,
+            started,
+            mut rest,
+            timeout,
+        }) = self.connecting.take()
+        else {
+            return Ok(());
+        };
+        self.io_deadline = None;
+
+        let result = match self.sock.take_error()? {
+            None => Ok(()),
+            Some(err) => Err(err),
+        };
+        emit_connect(self.observer.as_ref(), addr, started, &result);
+        let Err(err) = result else {
+            return Ok(());
+        };
+
+        let (sock, addr) = start_connect(&mut rest, self.observer.as_ref())
+            .map_err(|last_err| Error::Io(last_err.unwrap_or(err)))?;
+        self.sock = sock;
+        self.set_connecting(addr, rest, timeout);
+        Ok(())
     }
 
+    /// While connecting it asks for `POLLOUT`. A failed attempt is retried
+    /// with the next address over a new socket, so take `as_raw_fd` anew
+    /// after every `poll()`.
     pub fn events(&mut self) -> Result<EventsOrResponse> {
+        self.check_deadlines()?;
+        if self.connecting.is_some() {
+            return Ok(EventsOrResponse::Events(POLLOUT));
+        }
+
         match self.fsm.wants()? {
-            Wants::Read(_) => Ok(EventsOrResponse::Events(POLLIN)),
-            Wants::Write(_) => Ok(EventsOrResponse::Events(POLLOUT)),
+            Wants::Read(_) => {
+                self.arm_io_deadline(self.timeouts.read, TimeoutKind::Read);
+                Ok(EventsOrResponse::Events(POLLIN))
+            }
+            Wants::Write(_) => {
+                self.arm_io_deadline(self.timeouts.write, TimeoutKind::Write);
+                Ok(EventsOrResponse::Events(POLLOUT))
+            }
+            Wants::Body(_) => Err(Error::InvalidRequest(
+                "this driver can't stream request bodies".to_string(),
+            )),
+            Wants::SinkFull => Err(sink_full()),
             Wants::Done(response) => Ok(EventsOrResponse::Response(response)),
         }
     }
 
+    /// See `FSM::tls_info`, available before the response is.
+    pub fn tls_info(&self) -> Option<Arc<TlsInfo>> {
+        self.fsm.tls_info()
+    }
+
+    /// Time left until the nearest configured deadline,
+    /// suitable as a timeout for the caller's `poll(2)`.
+    pub fn timeout(&self) -> Option<Duration> {
+        [
+            self.deadline,
+            self.io_deadline.map(|(deadline, _)| deadline),
+        ]
+        .into_iter()
+        .flatten()
+        .min()
+        .map(|deadline| deadline.saturating_duration_since(Instant::now()))
+    }
+
+    /// Caps how many bytes a single `poll()` call moves, so that a fast peer
+    /// can't starve other connections in the same event loop. Once the budget
+    /// is spent `poll()` returns `Ok(None)`; with level-triggered `poll(2)`
+    /// the socket is reported ready again right away. Reads and writes are
+    /// cut down to what's left of it, so a call never goes over.
+    pub fn set_budget(&mut self, max_bytes: Option<NonZeroUsize>) {
+        self.budget = max_bytes;
+    }
+
+    fn arm_io_deadline(&mut self, timeout: Option<Duration>, kind: TimeoutKind) {
+        if self.io_deadline.is_none()
+            && let Some(timeout) = timeout
+        {
+            self.io_deadline = Some((Instant::now() + timeout, kind));
+        }
+    }
+
+    /// An attempt to connect that runs out of time is reported as timed out.
+    fn check_deadlines(&mut self) -> Result<()> {
+        let now = Instant::now();
+
+        let kind = if self.deadline.is_some_and(|deadline| now >= deadline) {
+            TimeoutKind::Total
+        } else if let Some((deadline, kind)) = self.io_deadline
+            && now >= deadline
+        {
+            kind
+        } else {
+            return Ok(());
+        };
+
+        if let Some(connecting) = self.connecting.take() {
+            let result: io::Result<()> = Err(ErrorKind::TimedOut.into());
+            emit_connect(
+                self.observer.as_ref(),
+                connecting.addr,
+                connecting.started,
+                &result,
+            );
+        }
+        Err(Error::Timeout(kind))
+    }
+
+    /// Moves data in whichever directions `poll(2)` reported ready, both at
+    /// once are fine: it writes while the FSM has something to send and then
+    /// reads, or the other way around, until the FSM waits for a direction
+    /// that wasn't reported or the socket would block. Neither being set
+    /// (a spurious wakeup or a timeout) only checks the deadlines.
+    ///
+    /// Report `POLLERR` and `POLLHUP` as both readable and writable,
+    /// the error then surfaces from the socket.
     pub fn poll(&mut self, readable: bool, writable: bool) -> Result<Option<Response>> {
         if self.done {
             return Ok(self.response.take());
         }
 
-        assert!(
-            !(readable && writable),
-            "exactly one of readable/writable must be set, got both: {readable}/{writable}"
-        );
+        self.check_deadlines()?;
 
-        if readable {
-            self.poll_read()
-        } else if writable {
-            self.poll_write()
-        } else {
-            panic!("at least one of readable/writable must be set: {readable}/{writable}");
+        if self.connecting.is_some() {
+            if !writable {
+                return Ok(None);
+            }
+            self.finish_connect()?;
+            if self.connecting.is_some() {
+                return Ok(None);
+            }
         }
-    }
 
-    fn poll_read(&mut self) -> Result<Option<Response>> {
+        let mut budget = self.budget.map_or(usize::MAX, NonZeroUsize::get);
         loop {
             match self.fsm.wants()? {
-                Wants::Read(buf) => match self.sock.read(buf) {
-                    Ok(read) => self.fsm.done_reading(read),
-                    Err(err) if err.kind() == ErrorKind::WouldBlock => return Ok(None),
-                    Err(err) => return Err(err.into()),
-                },
+                Wants::Read(_) | Wants::Write(_) if budget == 0 => return Ok(None),
+                Wants::Read(buf) if readable => {
+                    match self.sock.read(buf.split_at_mut(budget.min(buf.len())).0) {
+                        Ok(read) => {
+                            self.io_deadline = None;
+                            budget = budget.saturating_sub(read);
+                            self.fsm.done_reading(read)
+                        }
+                        Err(err) if err.kind() == ErrorKind::WouldBlock => return Ok(None),
+                        Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                        Err(err) => return Err(Error::Io(err)),
+                    }
+                }
+                Wants::Write(buf) if writable => {
+                    match self.sock.write(&buf[..budget.min(buf.len())]) {
+                        Ok(written) => {
+                            self.io_deadline = None;
+                            budget = budget.saturating_sub(written);
+                            self.fsm.done_writing(written)
+                        }
+                        Err(err) if err.kind() == ErrorKind::WouldBlock => return Ok(None),
+                        Err(err) if err.kind() == ErrorKind::Interrupted => {}
+                        Err(err) if is_write_rejected(&err) && self.fsm.abort_write() => {
+                            self.io_deadline = None;
+                        }
+                        Err(err) => return Err(Error::Io(err)),
+                    }
+                }
+                Wants::Read(_) | Wants::Write(_) => return Ok(None),
+                Wants::SinkFull => return Err(sink_full()),
                 Wants::Done(response) => {
                     self.done = true;
                     return Ok(Some(response));
                 }
-                Wants::Write(_) => return Ok(None),
+                Wants::Body(_) => {
+                    return Err(Error::InvalidRequest(
+                        "this driver can't stream request bodies".to_string(),
+                    ));
+                }
             }
         }
     }
+}
 
-    fn poll_write(&mut self) -> Result<Option<Response>> {
-        loop {
-            match self.fsm.wants()? {
-                Wants::Write(buf) => match self.sock.write(buf) {
-                    Ok(written) => self.fsm.done_writing(written),
-                    Err(err) if err.kind() == ErrorKind::WouldBlock => return Ok(None),
-                    Err(err) => return Err(err.into()),
-                },
-                Wants::Done(response) => {
-                    self.done = true;
-                    return Ok(Some(response));
-                }
-                Wants::Read(_) => return Ok(None),
+/// Starts connecting to the first address that doesn't fail right away,
+/// `Err` carries the last error, `None` if there were no addresses.
+fn start_connect(
+    addrs: &mut VecDeque<SocketAddr>,
+    observer: Option<&NetObserver>,
+) -> std::result::Result<(Socket, SocketAddr), Option<io::Error>> {
+    let mut last_err = None;
+    while let Some(addr) = addrs.pop_front() {
+        let started = Instant::now();
+        match Socket::connect_nonblocking(addr) {
+            Ok(sock) => return Ok((sock, addr)),
+            Err(err) => {
+                let result = Err(err);
+                emit_connect::<()>(observer, addr, started, &result);
+                last_err = result.err();
             }
         }
     }
+    Err(last_err)
 }
 
 impl AsRawFd for PollConnection {
     fn as_raw_fd(&self) -> std::os::unix::prelude::RawFd {
-        self.sock.as_raw_fd()
+        self.sock.0.as_raw_fd()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{net::TcpListener, thread};
+
+    #[test]
+    fn a_budget_spreads_the_response_over_several_polls() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let body = vec![b'x'; 10_000];
+        let server = thread::spawn({
+            let body = body.clone();
+            move || {
+                let (mut conn, _) = listener.accept().unwrap();
+                crate::testing::read_request(&mut conn);
+                conn.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 10000\r\n\r\n")
+                    .unwrap();
+                conn.write_all(&body).unwrap();
+                // until the client is done
+                conn.read_exact(&mut [0]).ok();
+            }
+        });
+
+        let options = ClientOptions::default();
+        let mut conn =
+            PollConnection::new(Scheme::Http, "127.0.0.1", port, Request::get("/"), &options)
+                .unwrap();
+        conn.set_budget(NonZeroUsize::new(1000));
+
+        let mut polls = 0;
+        let response = loop {
+            let fd = libc::pollfd {
+                fd: conn.as_raw_fd(),
+                events: match conn.events().unwrap() {
+                    EventsOrResponse::Events(events) => events,
+                    EventsOrResponse::Response(response) => break response,
+                },
+                revents: 0,
+            };
+            let mut fds = [fd];
+            assert!(unsafe { libc::poll(fds.as_mut_ptr(), 1, 5000) } > 0);
+
+            let stats = conn.fsm.stats();
+            let before = stats.bytes_read + stats.bytes_written;
+            let readable = fds[0].revents & (POLLIN | libc::POLLHUP | libc::POLLERR) != 0;
+            let writable = fds[0].revents & (POLLOUT | libc::POLLHUP | libc::POLLERR) != 0;
+            let polled = conn.poll(readable, writable).unwrap();
+            let stats = conn.fsm.stats();
+            assert!(stats.bytes_read + stats.bytes_written - before <= 1000);
+            polls += 1;
+            if let Some(response) = polled {
+                break response;
+            }
+        };
+        drop(conn);
+        server.join().unwrap();
+
+        assert_eq!(response.body, body);
+        assert!(polls > 10, "{polls}");
     }
 }
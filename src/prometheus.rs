@@ -0,0 +1,341 @@
+//! Scraping Prometheus/OpenMetrics endpoints: a scheduler that builds
+//! conditional requests and a lazy parser for the text exposition format.
+
+use crate::{Error, Request, Response, Result};
+use std::{
+    str::Lines,
+    time::{Duration, Instant},
+};
+
+/// Keeps the scrape schedule and the validators of the last response,
+/// so that unchanged metrics cost a 304 instead of a full body.
+#[derive(Debug, Clone)]
+pub struct Scraper {
+    path: String,
+    interval: Duration,
+    next: Option<Instant>,
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+impl Scraper {
+    pub fn new(path: impl Into<String>, interval: Duration) -> Self {
+        Self {
+            path: path.into(),
+            interval,
+            next: None,
+            etag: None,
+            last_modified: None,
+        }
+    }
+
+    /// How long to wait before the next scrape, zero if it's due.
+    pub fn wait(&self, now: Instant) -> Duration {
+        self.next
+            .map_or(Duration::ZERO, |next| next.saturating_duration_since(now))
+    }
+
+    /// The request for the scrape that's due, schedules the next one.
+    pub fn request(&mut self, now: Instant) -> Request {
+        self.next = Some(now + self.interval);
+
+        let mut request = Request::get(self.path.clone());
+        request.add_header("Accept", ACCEPT);
+        if let Some(etag) = &self.etag {
+            request.add_header("If-None-Match", etag.as_str());
+        }
+        if let Some(last_modified) = &self.last_modified {
+            request.add_header("If-Modified-Since", last_modified.as_str());
+        }
+        #[cfg(feature = "decompression")]
+        request.accept_compressed();
+        request
+    }
+
+    /// Remembers the validators of a fresh response and returns it,
+    /// `None` means the metrics haven't changed since the last scrape.
+    pub fn update(&mut self, response: Response) -> Result<Option<Response>> {
+        if response.status == 304 {
+            return Ok(None);
+        }
+        let response = response.error_for_status()?;
+        self.etag = response.etag().map(str::to_string);
+        self.last_modified = response.headers.get("Last-Modified").map(str::to_string);
+        Ok(Some(response))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetricType {
+    Counter,
+    Gauge,
+    Histogram,
+    GaugeHistogram,
+    Summary,
+    StateSet,
+    Info,
+    /// `untyped` in the Prometheus format, `unknown` in OpenMetrics,
+    /// also used for samples without a `# TYPE` line.
+    Unknown,
+}
+
+impl MetricType {
+    fn parse(kind: &str) -> Self {
+        match kind {
+            "counter" => Self::Counter,
+            "gauge" => Self::Gauge,
+            "histogram" => Self::Histogram,
+            "gaugehistogram" => Self::GaugeHistogram,
+            "summary" => Self::Summary,
+            "stateset" => Self::StateSet,
+            "info" => Self::Info,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample<'a> {
+    /// Name of the metric family, e.g. `http_requests` for `http_requests_total`.
+    pub family: &'a str,
+    pub kind: MetricType,
+    pub name: &'a str,
+    pub labels: Vec<(&'a str, String)>,
+    pub value: f64,
+    /// As written: milliseconds in the Prometheus format, seconds in OpenMetrics.
+    pub timestamp: Option<f64>,
+}
+
+/// Parses samples one line at a time, comments other than `# TYPE` are skipped.
+pub fn samples(text: &str) -> Samples<'_> {
+    Samples {
+        lines: text.lines(),
+        family: "",
+        kind: MetricType::Unknown,
+    }
+}
+
+pub struct Samples<'a> {
+    lines: Lines<'a>,
+    family: &'a str,
+    kind: MetricType,
+}
+
+impl<'a> Iterator for Samples<'a> {
+    type Item = Result<Sample<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let line = self.lines.next()?.trim();
+
+            if let Some(comment) = line.strip_prefix('#') {
+                let mut words = comment.split_whitespace();
+                if let (Some("TYPE"), Some(family), Some(kind)) =
+                    (words.next(), words.next(), words.next())
+                {
+                    self.family = family;
+                    self.kind = MetricType::parse(kind);
+                }
+                continue;
+            }
+            if line.is_empty() {
+                continue;
+            }
+
+            return Some(self.sample(line));
+        }
+    }
+}
+
+impl<'a> Samples<'a> {
+    fn sample(&self, line: &'a str) -> Result<Sample<'a>> {
+        let malformed = || Error::Parse(format!("malformed metrics line: {line}"));
+
+        let name_end = line
+            .find(|c: char| c == '{' || c.is_ascii_whitespace())
+            .ok_or_else(malformed)?;
+        let name = &line[..name_end];
+        let mut rest = &line[name_end..];
+
+        let mut labels = vec![];
+        if let Some(inner) = rest.strip_prefix('{') {
+            rest = parse_labels(inner, &mut labels).ok_or_else(malformed)?;
+        }
+
+        // OpenMetrics exemplars follow a ` # `
+        let rest = rest.split(" # ").next().unwrap_or_default();
+        let mut fields = rest.split_whitespace();
+        let value = fields
+            .next()
+            .and_then(|value| value.parse().ok())
+            .ok_or_else(malformed)?;
+        let timestamp = match fields.next() {
+            Some(timestamp) => Some(timestamp.parse().map_err(|_| malformed())?),
+            None => None,
+        };
+
+        let (family, kind) = if belongs_to(name, self.family) {
+            (self.family, self.kind)
+        } else {
+            (name, MetricType::Unknown)
+        };
+
+        Ok(Sample {
+            family,
+            kind,
+            name,
+            labels,
+            value,
+            timestamp,
+        })
+    }
+}
+
+fn belongs_to(name: &str, family: &str) -> bool {
+    match name.strip_prefix(family) {
+        Some("") => true,
+        Some(suffix) => SUFFIXES.contains(&suffix),
+        None => false,
+    }
+}
+
+/// Parses `name="value",...}` and returns what follows the closing brace.
+fn parse_labels<'a>(mut input: &'a str, labels: &mut Vec<(&'a str, String)>) -> Option<&'a str> {
+    loop {
+        input = input.trim_start();
+        if let Some(rest) = input.strip_prefix('}') {
+            return Some(rest);
+        }
+
+        let (name, rest) = input.split_once('=')?;
+        let quoted = rest.trim_start().strip_prefix('"')?;
+        let mut chars = quoted.char_indices();
+
+        let mut value = String::new();
+        let end = loop {
+            match chars.next()? {
+                (idx, '"') => break idx,
+                (_, '\\') => match chars.next()?.1 {
+                    'n' => value.push('\n'),
+                    other => value.push(other),
+                },
+                (_, other) => value.push(other),
+            }
+        };
+        labels.push((name.trim(), value));
+
+        input = quoted[end + 1..].trim_start();
+        input = input.strip_prefix(',').unwrap_or(input);
+    }
+}
+
+const ACCEPT: &str = "application/openmetrics-text;version=1.0.0,text/plain;version=0.0.4;q=0.5";
+
+const SUFFIXES: &[&str] = &[
+    "_total", "_created", "_count", "_sum", "_bucket", "_gcount", "_gsum", "_info",
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = r#"# HELP http_requests Requests served.
+# TYPE http_requests counter
+http_requests_total{method="get",path="/a \"b\"\\c\nd"} 1027 1395066363000
+http_requests_created{method="get"} 1395066363
+# TYPE latency histogram
+latency_bucket{le="0.5"} 12 # {trace_id="abc"} 0.3
+latency_bucket{ le = "+Inf" , } 15
+latency_count 15
+
+orphan NaN
+"#;
+
+    #[test]
+    fn parses_samples() {
+        let samples = samples(TEXT).collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(samples.len(), 6);
+
+        assert_eq!(
+            samples[0],
+            Sample {
+                family: "http_requests",
+                kind: MetricType::Counter,
+                name: "http_requests_total",
+                labels: vec![
+                    ("method", "get".to_string()),
+                    ("path", "/a \"b\"\\c\nd".to_string())
+                ],
+                value: 1027.0,
+                timestamp: Some(1395066363000.0),
+            }
+        );
+        assert_eq!(samples[1].family, "http_requests");
+
+        assert_eq!(samples[2].kind, MetricType::Histogram);
+        assert_eq!(samples[2].labels, vec![("le", "0.5".to_string())]);
+        assert_eq!(samples[2].value, 12.0);
+        assert_eq!(samples[2].timestamp, None);
+        assert_eq!(samples[3].labels, vec![("le", "+Inf".to_string())]);
+        assert_eq!(samples[4].family, "latency");
+
+        assert_eq!(samples[5].family, "orphan");
+        assert_eq!(samples[5].kind, MetricType::Unknown);
+        assert!(samples[5].value.is_nan());
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        for line in [
+            "no_value",
+            "no_value ",
+            "bad_value abc",
+            "bad_timestamp 1 soon",
+            "unterminated{a=\"b} 1",
+            "unquoted{a=b} 1",
+            "no_equals{a} 1",
+            "dangling_escape{a=\"\\",
+        ] {
+            let err = samples(line).next().unwrap().unwrap_err();
+            assert!(matches!(err, Error::Parse(_)), "{line}: {err:?}");
+        }
+    }
+
+    #[test]
+    fn a_bad_line_does_not_stop_the_rest() {
+        let parsed = samples("a 1\nb x\nc 3").collect::<Vec<_>>();
+        assert_eq!(parsed.len(), 3);
+        assert!(parsed[1].is_err());
+        assert_eq!(parsed[2].as_ref().unwrap().value, 3.0);
+    }
+
+    #[test]
+    fn scrapes_conditionally() {
+        let now = Instant::now();
+        let mut scraper = Scraper::new("/metrics", Duration::from_secs(15));
+        assert_eq!(scraper.wait(now), Duration::ZERO);
+
+        let request = scraper.request(now);
+        assert_eq!(request.headers().get("If-None-Match"), None);
+        assert_eq!(scraper.wait(now), Duration::from_secs(15));
+
+        let fresh = Response::parse(
+            b"HTTP/1.1 200 OK\r\nETag: \"v1\"\r\nLast-Modified: Wed, 21 Oct 2015 07:28:00 GMT\r\n\r\n",
+            false,
+        )
+        .unwrap();
+        assert!(scraper.update(fresh).unwrap().is_some());
+
+        let request = scraper.request(now + Duration::from_secs(15));
+        assert_eq!(request.headers().get("If-None-Match"), Some("\"v1\""));
+        assert_eq!(
+            request.headers().get("If-Modified-Since"),
+            Some("Wed, 21 Oct 2015 07:28:00 GMT")
+        );
+
+        let unchanged = Response::parse(b"HTTP/1.1 304 Not Modified\r\n\r\n", false).unwrap();
+        assert!(scraper.update(unchanged).unwrap().is_none());
+        let failed = Response::parse(b"HTTP/1.1 500 Oops\r\n\r\n", false).unwrap();
+        assert!(scraper.update(failed).is_err());
+    }
+}
@@ -0,0 +1,61 @@
+use crate::{Error, Result};
+use publicsuffix::{List, Psl as _};
+use std::net::IpAddr;
+
+/// Domain matching that refuses to treat public suffixes (`com`, `co.uk`,
+/// `github.io`) as a shared parent domain, so a cookie or an HSTS policy
+/// can't be scoped to every site under them.
+///
+/// The list isn't bundled, load the current `public_suffix_list.dat`
+/// from <https://publicsuffix.org/list/>.
+pub struct PublicSuffixList {
+    list: List,
+}
+
+impl PublicSuffixList {
+    pub fn parse(list: &str) -> Result<Self> {
+        let list = list
+            .parse::<List>()
+            .map_err(|err| Error::Parse(format!("invalid public suffix list: {err}")))?;
+        Ok(Self { list })
+    }
+
+    /// Domains not covered by the list fall under the implicit `*` rule,
+    /// so any single-label domain is a public suffix.
+    pub fn is_public_suffix(&self, domain: &str) -> bool {
+        let domain = normalize(domain);
+        self.list
+            .suffix(domain.as_bytes())
+            .is_some_and(|suffix| suffix.as_bytes() == domain.as_bytes())
+    }
+
+    /// The public suffix plus one label, e.g. `example.co.uk` for `www.example.co.uk`.
+    pub fn registrable_domain(&self, domain: &str) -> Option<String> {
+        let domain = normalize(domain);
+        let registrable = self.list.domain(domain.as_bytes())?;
+        std::str::from_utf8(registrable.as_bytes())
+            .ok()
+            .map(str::to_string)
+    }
+
+    /// RFC 6265 domain-match of `host` against a `Domain` attribute,
+    /// with a public suffix only matching itself.
+    pub fn domain_matches(&self, host: &str, domain: &str) -> bool {
+        let host = normalize(host);
+        let domain = normalize(domain.strip_prefix('.').unwrap_or(domain));
+
+        if host == domain {
+            return true;
+        }
+        if host.parse::<IpAddr>().is_ok() || self.is_public_suffix(&domain) {
+            return false;
+        }
+
+        host.strip_suffix(domain.as_str())
+            .is_some_and(|prefix| prefix.ends_with('.'))
+    }
+}
+
+fn normalize(domain: &str) -> String {
+    domain.trim_end_matches('.').to_ascii_lowercase()
+}
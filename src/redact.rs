@@ -0,0 +1,69 @@
+//! Masks credentials in what the crate formats (`Debug` of headers,
+//! requests and URLs, error messages), so that logging them is safe.
+
+use std::borrow::Cow;
+
+pub(crate) const REDACTED: &str = "[redacted]";
+
+/// Fields whose values are credentials.
+const SENSITIVE_HEADERS: &[&str] = &[
+    "authorization",
+    "proxy-authorization",
+    "cookie",
+    "set-cookie",
+];
+
+pub(crate) fn is_sensitive_header(name: &str) -> bool {
+    SENSITIVE_HEADERS
+        .iter()
+        .any(|sensitive| sensitive.eq_ignore_ascii_case(name))
+}
+
+/// Masks userinfo and the values of query (and fragment) parameters
+/// of a URL or a request target, names are kept.
+pub(crate) fn redact_url(url: &str) -> Cow<'_, str> {
+    let (before_fragment, fragment) = match url.split_once('#') {
+        Some((url, fragment)) => (url, Some(fragment)),
+        None => (url, None),
+    };
+    let (before_query, query) = match before_fragment.split_once('?') {
+        Some((url, query)) => (url, Some(query)),
+        None => (before_fragment, None),
+    };
+
+    let userinfo = before_query.split_once("://").and_then(|(_, rest)| {
+        let authority = rest.split('/').next().unwrap_or_default();
+        authority.rsplit_once('@').map(|(userinfo, _)| userinfo)
+    });
+    let has_values = |params: Option<&str>| params.is_some_and(|params| params.contains('='));
+    if userinfo.is_none() && !has_values(query) && !has_values(fragment) {
+        return Cow::Borrowed(url);
+    }
+
+    let mut out = match userinfo {
+        Some(userinfo) => {
+            before_query.replacen(&format!("{userinfo}@"), &format!("{REDACTED}@"), 1)
+        }
+        None => before_query.to_string(),
+    };
+    for (separator, params) in [('?', query), ('#', fragment)] {
+        let Some(params) = params else {
+            continue;
+        };
+        out.push(separator);
+        for (idx, param) in params.split('&').enumerate() {
+            if idx > 0 {
+                out.push('&');
+            }
+            match param.split_once('=') {
+                Some((name, _)) => {
+                    out.push_str(name);
+                    out.push('=');
+                    out.push_str(REDACTED);
+                }
+                None => out.push_str(param),
+            }
+        }
+    }
+    Cow::Owned(out)
+}
@@ -0,0 +1,174 @@
+use crate::{Error, Method, Response, Result, Scheme, Url};
+
+/// Which schemes a redirect may switch to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SchemePolicy {
+    /// https to http is refused, plain http may still upgrade. Following a
+    /// downgrade would send the request's headers and cookies in the clear.
+    #[default]
+    NoDowngrade,
+    /// Only https targets, even when the original request was plain http.
+    HttpsOnly,
+    /// Any target, e.g. for local test setups.
+    Any,
+}
+
+impl SchemePolicy {
+    fn allows(self, from: Scheme, to: Scheme) -> bool {
+        match self {
+            Self::NoDowngrade => !(from == Scheme::Https && to == Scheme::Http),
+            Self::HttpsOnly => to == Scheme::Https,
+            Self::Any => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Redirect {
+    /// Repeat the request against `location` using `method`,
+    /// re-sending the original body only if `keep_body` is set.
+    Follow {
+        location: String,
+        method: Method,
+        keep_body: bool,
+    },
+
+    /// 300, the server lists alternative representations in the body and
+    /// may point at its preferred one in `Location`. Picking one is up to
+    /// the caller.
+    MultipleChoices { location: Option<String> },
+
+    /// Not a redirect, a 3xx without a `Location`, or a status that can't
+    /// be followed automatically (304, 305, 306 and unknown 3xx codes).
+    Terminal,
+}
+
+impl Redirect {
+    pub fn classify(method: &Method, response: &Response) -> Self {
+        let location = response.headers.get("Location").map(str::to_string);
+
+        if response.status == 300 {
+            return Self::MultipleChoices { location };
+        }
+
+        let (method, keep_body) = match response.status {
+            // historically clients turn POST into GET here, other methods are kept
+            301 | 302 if *method == Method::Post => (Method::Get, false),
+            301 | 302 => (method.clone(), true),
+            // "see other" means "GET it", HEAD is the only method that stays
+            303 if *method == Method::Head => (Method::Head, false),
+            303 => (Method::Get, false),
+            307 | 308 => (method.clone(), true),
+            _ => return Self::Terminal,
+        };
+
+        match location {
+            Some(location) => Self::Follow {
+                location,
+                method,
+                keep_body,
+            },
+            None => Self::Terminal,
+        }
+    }
+
+    /// The URL to follow to, `location` resolved against `from` (the URL
+    /// the redirected request went to); `None` if it isn't `Follow`.
+    /// Fails with `Error::DowngradeBlocked` if `policy` doesn't allow
+    /// switching to the target's scheme.
+    pub fn target(&self, from: &Url, policy: SchemePolicy) -> Result<Option<Url>> {
+        let Self::Follow { location, .. } = self else {
+            return Ok(None);
+        };
+
+        let to = from.join(location)?;
+        if !policy.allows(from.scheme, to.scheme) {
+            return Err(Error::DowngradeBlocked(Box::new(to)));
+        }
+        Ok(Some(to))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn redirect(status: u16, method: Method) -> Redirect {
+        let head = format!("HTTP/1.1 {status} Redirect\r\nLocation: /next\r\n\r\n");
+        let response = Response::parse(head.as_bytes(), false).unwrap();
+        Redirect::classify(&method, &response)
+    }
+
+    fn follow(method: Method, keep_body: bool) -> Redirect {
+        Redirect::Follow {
+            location: "/next".to_string(),
+            method,
+            keep_body,
+        }
+    }
+
+    #[test]
+    fn moved_and_found_turn_post_into_get() {
+        for status in [301, 302] {
+            assert_eq!(redirect(status, Method::Get), follow(Method::Get, true));
+            assert_eq!(redirect(status, Method::Post), follow(Method::Get, false));
+            assert_eq!(redirect(status, Method::Head), follow(Method::Head, true));
+            assert_eq!(redirect(status, Method::Put), follow(Method::Put, true));
+        }
+    }
+
+    #[test]
+    fn see_other_gets_without_a_body() {
+        assert_eq!(redirect(303, Method::Get), follow(Method::Get, false));
+        assert_eq!(redirect(303, Method::Post), follow(Method::Get, false));
+        assert_eq!(redirect(303, Method::Head), follow(Method::Head, false));
+        assert_eq!(redirect(303, Method::Put), follow(Method::Get, false));
+    }
+
+    #[test]
+    fn temporary_and_permanent_keep_method_and_body() {
+        for status in [307, 308] {
+            for method in [Method::Get, Method::Post, Method::Head] {
+                assert_eq!(redirect(status, method.clone()), follow(method, true));
+            }
+        }
+    }
+
+    #[test]
+    fn not_followed() {
+        for status in [200, 304, 305, 306, 399] {
+            assert_eq!(redirect(status, Method::Get), Redirect::Terminal);
+        }
+        let response = Response::parse(b"HTTP/1.1 302 Found\r\n\r\n", false).unwrap();
+        assert_eq!(
+            Redirect::classify(&Method::Get, &response),
+            Redirect::Terminal
+        );
+        assert_eq!(
+            redirect(300, Method::Get),
+            Redirect::MultipleChoices {
+                location: Some("/next".to_string())
+            }
+        );
+    }
+
+    #[test]
+    fn downgrades_follow_the_policy() {
+        let from = Url::parse("https://example.com/").unwrap();
+        let to_http = Redirect::Follow {
+            location: "http://example.com/".to_string(),
+            method: Method::Get,
+            keep_body: false,
+        };
+        assert!(matches!(
+            to_http.target(&from, SchemePolicy::NoDowngrade),
+            Err(Error::DowngradeBlocked(_))
+        ));
+        assert!(to_http.target(&from, SchemePolicy::Any).unwrap().is_some());
+        let target = follow(Method::Get, false)
+            .target(&from, SchemePolicy::HttpsOnly)
+            .unwrap()
+            .unwrap();
+        assert_eq!(target.to_string(), "https://example.com/next");
+    }
+}
@@ -1,39 +1,588 @@
-use std::collections::HashMap;
+#[cfg(feature = "digest")]
+use crate::{
+    DigestAlgorithm,
+    digest::{BodyDigests, field_names},
+};
+use crate::{Headers, Location, Result, base64, percent, redact::redact_url, validation::Problems};
+use std::fmt;
 
-#[derive(Debug)]
-pub enum Request {
-    Get {
-        path: String,
-        headers: HashMap<String, String>,
-    },
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Method {
+    Get,
+    Head,
+    Post,
+    Put,
+    Patch,
+    Delete,
+    Options,
+    /// Only sent if `ClientOptions::allow_trace` is set.
+    Trace,
+    /// Any other token, e.g. `PURGE` or WebDAV's `MKCOL`,
+    /// build it with `Method::custom` to get it validated.
+    Custom(String),
+}
+
+impl Method {
+    /// Standard names map to their own variants, so `custom("GET")` is `Method::Get`.
+    pub fn custom(name: &str) -> Result<Self> {
+        let method = match name {
+            "GET" => Method::Get,
+            "HEAD" => Method::Head,
+            "POST" => Method::Post,
+            "PUT" => Method::Put,
+            "PATCH" => Method::Patch,
+            "DELETE" => Method::Delete,
+            "OPTIONS" => Method::Options,
+            "TRACE" => Method::Trace,
+            _ => Method::Custom(name.to_string()),
+        };
+        let mut problems = Problems::default();
+        method.check(&mut problems);
+        problems.into_result()?;
+        Ok(method)
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            Method::Get => "GET",
+            Method::Head => "HEAD",
+            Method::Post => "POST",
+            Method::Put => "PUT",
+            Method::Patch => "PATCH",
+            Method::Delete => "DELETE",
+            Method::Options => "OPTIONS",
+            Method::Trace => "TRACE",
+            Method::Custom(name) => name,
+        }
+    }
+
+    /// Repeating the request has the same effect as sending it once (RFC 9110, section 9.2.2).
+    pub fn is_idempotent(&self) -> bool {
+        matches!(
+            self,
+            Method::Get
+                | Method::Head
+                | Method::Put
+                | Method::Delete
+                | Method::Options
+                | Method::Trace
+        )
+    }
+
+    fn expects_body(&self) -> bool {
+        matches!(self, Method::Post | Method::Put | Method::Patch)
+    }
+
+    fn check(&self, problems: &mut Problems) {
+        check_token(self.as_str(), Location::Method, "method", problems);
+    }
+}
+
+// RFC 9110, section 5.6.2
+fn is_tchar(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&byte)
+}
+
+fn check_token(token: &str, location: Location, what: &str, problems: &mut Problems) {
+    if token.is_empty() {
+        problems.push(location, format!("{what} is empty"));
+    } else if let Some(idx) = token.bytes().position(|byte| !is_tchar(byte)) {
+        problems.push_span(
+            location,
+            idx..idx + 1,
+            format!("{what} {token:?} is not a valid token"),
+        );
+    }
+}
+
+/// Bytes that would end the line or the value early, RFC 9110 section 5.5.
+fn check_field_value(value: &str, location: Location, problems: &mut Problems) {
+    if let Some(idx) = value
+        .bytes()
+        .position(|byte| matches!(byte, b'\r' | b'\n' | 0))
+    {
+        problems.push_span(
+            location,
+            idx..idx + 1,
+            "header values can't contain CR, LF or NUL",
+        );
+    }
+}
+
+/// `Debug` masks credentials and query parameter values,
+/// and only gives the size of the body.
+#[derive(Clone)]
+pub struct Request {
+    method: Method,
+    path: String,
+    headers: Headers,
+    body: Vec<u8>,
+    streaming: Option<Streaming>,
+    #[cfg(feature = "digest")]
+    digests: Vec<DigestAlgorithm>,
+}
+
+impl fmt::Debug for Request {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Request")
+            .field("method", &self.method)
+            .field("path", &redact_url(&self.path))
+            .field("headers", &self.headers)
+            .field("body", &format_args!("{} bytes", self.body.len()))
+            .field("streaming", &self.streaming)
+            .finish()
+    }
+}
+
+/// How a body supplied through `Wants::Body` is framed.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Streaming {
+    Sized(u64),
+    Chunked,
 }
 
 impl Request {
-    pub fn get(path: impl Into<String>) -> Self {
-        Self::Get {
+    pub fn new(method: Method, path: impl Into<String>) -> Self {
+        Self {
+            method,
             path: path.into(),
-            headers: HashMap::new(),
+            headers: Headers::new(),
+            body: vec![],
+            streaming: None,
+            #[cfg(feature = "digest")]
+            digests: vec![],
+        }
+    }
+
+    pub fn get(path: impl Into<String>) -> Self {
+        Self::new(Method::Get, path)
+    }
+
+    pub fn head(path: impl Into<String>) -> Self {
+        Self::new(Method::Head, path)
+    }
+
+    /// `Request::options("*")` asks about the server as a whole
+    /// rather than a specific resource.
+    pub fn options(path: impl Into<String>) -> Self {
+        Self::new(Method::Options, path)
+    }
+
+    pub fn trace(path: impl Into<String>) -> Self {
+        Self::new(Method::Trace, path)
+    }
+
+    pub fn put(path: impl Into<String>, body: impl Into<Vec<u8>>) -> Self {
+        let mut request = Self::new(Method::Put, path);
+        request.set_body(body);
+        request
+    }
+
+    /// `POST` of the pairs as an `application/x-www-form-urlencoded` body.
+    pub fn post_form(path: impl Into<String>, pairs: &[(&str, &str)]) -> Self {
+        let mut body = String::new();
+        for (idx, (key, value)) in pairs.iter().enumerate() {
+            if idx > 0 {
+                body.push('&');
+            }
+            body.push_str(&percent::encode_form(key));
+            body.push('=');
+            body.push_str(&percent::encode_form(value));
         }
+
+        let mut request = Self::new(Method::Post, path);
+        request.add_header("Content-Type", "application/x-www-form-urlencoded");
+        request.set_body(body);
+        request
+    }
+
+    /// `POST` of `value` serialized as JSON.
+    #[cfg(feature = "json")]
+    pub fn post_json<T: serde::Serialize + ?Sized>(
+        path: impl Into<String>,
+        value: &T,
+    ) -> Result<Self> {
+        let body = serde_json::to_vec(value).map_err(|err| {
+            crate::Error::InvalidRequest(format!("failed to serialize JSON: {err}"))
+        })?;
+
+        let mut request = Self::new(Method::Post, path);
+        request.add_header("Content-Type", "application/json");
+        request.add_header("Accept", "application/json");
+        request.set_body(body);
+        Ok(request)
+    }
+
+    /// Appends a percent-encoded `key=value` pair to the query string.
+    pub fn query(mut self, key: &str, value: &str) -> Self {
+        let separator = if self.path.contains('?') { '&' } else { '?' };
+        self.path.push(separator);
+        self.path.push_str(&percent::encode(key));
+        self.path.push('=');
+        self.path.push_str(&percent::encode(value));
+        self
+    }
+
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn body(&self) -> &[u8] {
+        &self.body
+    }
+
+    pub fn set_body(&mut self, body: impl Into<Vec<u8>>) {
+        self.body = body.into();
+    }
+
+    /// The body is fed through `Wants::Body` while the request is sent
+    /// instead of being set upfront, with `Content-Length: len` if the size
+    /// is known and chunked otherwise.
+    pub fn stream_body(&mut self, len: Option<u64>) {
+        self.streaming = Some(match len {
+            Some(len) => Streaming::Sized(len),
+            None => Streaming::Chunked,
+        });
+    }
+
+    pub(crate) fn streaming(&self) -> Option<Streaming> {
+        self.streaming
+    }
+
+    /// What a streamed body is hashed with, see `digest`.
+    #[cfg(feature = "digest")]
+    pub(crate) fn digests(&self) -> &[DigestAlgorithm] {
+        &self.digests
+    }
+
+    pub fn headers(&self) -> &Headers {
+        &self.headers
+    }
+
+    pub fn headers_mut(&mut self) -> &mut Headers {
+        &mut self.headers
     }
 
+    /// Sets the header, replacing any previous value.
     pub fn add_header(&mut self, name: impl Into<String>, value: impl Into<String>) {
-        match self {
-            Request::Get { headers, .. } => {
-                headers.insert(name.into(), value.into());
+        self.headers.insert(name, value);
+    }
+
+    /// Adds one more value for the header, keeping previous ones.
+    pub fn append_header(&mut self, name: impl Into<String>, value: impl Into<String>) {
+        self.headers.append(name, value);
+    }
+
+    pub(crate) fn add_default_header(&mut self, name: &str, value: &str) {
+        if !self.headers.contains(name) {
+            self.add_header(name, value);
+        }
+    }
+
+    /// Advertises every Content-Encoding the `decompression` feature can undo,
+    /// unless the caller has already picked an `Accept-Encoding` themselves.
+    #[cfg(feature = "decompression")]
+    pub fn accept_compressed(&mut self) {
+        self.add_default_header("Accept-Encoding", crate::decompression::ACCEPT_ENCODING);
+    }
+
+    /// Makes the request conditional on the current representation having
+    /// the given entity tag, the server answers 412 if it doesn't.
+    /// Unquoted tags are quoted, `*` and weak tags are sent as is.
+    pub fn if_match(&mut self, etag: &str) {
+        let etag = if etag == "*" || etag.starts_with('"') || etag.starts_with("W/") {
+            etag.to_string()
+        } else {
+            format!("\"{etag}\"")
+        };
+        self.add_header("If-Match", etag);
+    }
+
+    /// Sets `Authorization: Basic` with base64-encoded `user:password`.
+    pub fn basic_auth(&mut self, user: &str, password: &str) {
+        let credentials = base64::encode(format!("{user}:{password}").as_bytes());
+        self.add_header("Authorization", format!("Basic {credentials}"));
+    }
+
+    pub fn bearer_auth(&mut self, token: &str) {
+        self.add_header("Authorization", format!("Bearer {token}"));
+    }
+
+    /// Limits how many proxies may forward a TRACE or OPTIONS request,
+    /// the one that gets it with 0 answers instead of passing it on.
+    pub fn max_forwards(&mut self, hops: u32) {
+        self.add_header("Max-Forwards", hops.to_string());
+    }
+
+    /// Attaches a digest of the body (`Repr-Digest` or `Content-MD5`),
+    /// computed when the request is serialized. A chunked body set up with
+    /// `stream_body(None)` is hashed as it's fed through `Wants::Body` and
+    /// its digests go out as trailers, announced by a `Trailer` header.
+    /// One with a known length has nothing to carry them after the body,
+    /// such a request fails validation: compute the digest upfront and add
+    /// the header instead.
+    #[cfg(feature = "digest")]
+    pub fn digest(&mut self, algorithm: DigestAlgorithm) {
+        if !self.digests.contains(&algorithm) {
+            self.digests.push(algorithm);
+        }
+    }
+
+    /// Reports every problem found in `Error::Validation`, not just the first.
+    pub fn validate(&self) -> Result<()> {
+        let mut problems = Problems::default();
+        self.check(&mut problems);
+        problems.into_result()
+    }
+
+    pub(crate) fn check(&self, problems: &mut Problems) {
+        self.method.check(problems);
+
+        if self.path.is_empty() {
+            problems.push(Location::Target, "the target is empty");
+        } else if let Some(idx) = self
+            .path
+            .bytes()
+            .position(|byte| byte.is_ascii_whitespace() || byte.is_ascii_control())
+        {
+            problems.push_span(
+                Location::Target,
+                idx..idx + 1,
+                "the target can't contain whitespace or control characters",
+            );
+        }
+        if self.path == "*" && self.method != Method::Options {
+            problems.push(
+                Location::Target,
+                format!(
+                    "the asterisk-form target `*` is only valid for OPTIONS, not {}",
+                    self.method.as_str()
+                ),
+            );
+        }
+
+        for (idx, (name, value)) in self.headers.iter().enumerate() {
+            check_token(name, Location::HeaderName(idx), "header name", problems);
+            check_field_value(value, Location::HeaderValue(idx), problems);
+            // a second framing header next to the one `write_into` adds
+            // would let the server and a proxy disagree on where the body ends
+            if name.eq_ignore_ascii_case("Content-Length")
+                || name.eq_ignore_ascii_case("Transfer-Encoding")
+            {
+                problems.push(
+                    Location::HeaderName(idx),
+                    format!("{name} is set from the body, see Request::stream_body"),
+                );
             }
         }
+
+        if self.method == Method::Trace && (!self.body.is_empty() || self.streaming.is_some()) {
+            problems.push(Location::Body, "TRACE requests can't have a body");
+        }
+        if self.streaming.is_some() && !self.body.is_empty() {
+            problems.push(
+                Location::Body,
+                "a streamed request can't also have a body set upfront",
+            );
+        }
+        #[cfg(feature = "digest")]
+        if matches!(self.streaming, Some(Streaming::Sized(_))) && !self.digests.is_empty() {
+            problems.push(
+                Location::Body,
+                "digests of a streamed body are sent as trailers, which needs a chunked one",
+            );
+        }
     }
 
     pub fn into_bytes(self) -> Vec<u8> {
-        match self {
-            Request::Get { path, headers } => {
-                let headers = headers
-                    .into_iter()
-                    .map(|(name, value)| format!("{name}: {value}"))
-                    .collect::<Vec<_>>()
-                    .join("\r\n");
-                format!("GET {path} HTTP/1.1\r\n{headers}\r\n\r\n").into_bytes()
+        let mut out = vec![];
+        self.write_into(&mut out);
+        out
+    }
+
+    /// Appends the serialized request to `out`, reserving the exact size
+    /// once, so a reused buffer doesn't allocate at all.
+    pub fn write_into(self, out: &mut Vec<u8>) {
+        let Self {
+            method,
+            path,
+            headers,
+            body,
+            streaming,
+            #[cfg(feature = "digest")]
+            digests,
+        } = self;
+
+        #[cfg(feature = "digest")]
+        let headers = {
+            let mut headers = headers;
+            if streaming.is_some() {
+                if !digests.is_empty() {
+                    headers.insert("Trailer", field_names(&digests).join(", "));
+                }
+            } else {
+                let mut hasher = BodyDigests::new(&digests);
+                hasher.update(&body);
+                for (name, value) in hasher.finish() {
+                    headers.insert(name, value);
+                }
             }
+            headers
+        };
+
+        let content_length = match streaming {
+            Some(Streaming::Sized(len)) => Some(len),
+            Some(Streaming::Chunked) => None,
+            None if !body.is_empty() || method.expects_body() => Some(body.len() as u64),
+            None => None,
+        };
+
+        let method = method.as_str();
+        let mut len = method.len() + 1 + path.len() + b" HTTP/1.1\r\n".len();
+        for (name, value) in &headers {
+            len += name.len() + 2 + value.len() + 2;
+        }
+        // the longest of the framing headers, with a 20 digit u64
+        len += "Content-Length: \r\n".len() + 20 + 2 + body.len();
+        out.reserve(len);
+
+        out.extend_from_slice(method.as_bytes());
+        out.push(b' ');
+        out.extend_from_slice(path.as_bytes());
+        out.extend_from_slice(b" HTTP/1.1\r\n");
+        for (name, value) in &headers {
+            out.extend_from_slice(name.as_bytes());
+            out.extend_from_slice(b": ");
+            out.extend_from_slice(value.as_bytes());
+            out.extend_from_slice(b"\r\n");
+        }
+        match (content_length, streaming) {
+            (Some(len), _) => {
+                out.extend_from_slice(b"Content-Length: ");
+                push_decimal(out, len);
+                out.extend_from_slice(b"\r\n");
+            }
+            (None, Some(Streaming::Chunked)) => {
+                out.extend_from_slice(b"Transfer-Encoding: chunked\r\n");
+            }
+            (None, _) => {}
+        }
+        out.extend_from_slice(b"\r\n");
+        out.extend_from_slice(&body);
+    }
+}
+
+fn push_decimal(out: &mut Vec<u8>, mut n: u64) {
+    let mut digits = [0; 20];
+    let mut start = digits.len();
+    loop {
+        start -= 1;
+        digits[start] = b'0' + (n % 10) as u8;
+        n /= 10;
+        if n == 0 {
+            break;
+        }
+    }
+    out.extend_from_slice(&digits[start..]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Error;
+
+    fn locations(request: &Request) -> Vec<Location> {
+        match request.validate() {
+            Ok(()) => vec![],
+            Err(Error::Validation(errors)) => errors.into_iter().map(|err| err.location).collect(),
+            Err(err) => panic!("{err:?}"),
         }
     }
+
+    #[test]
+    fn framing_headers_are_rejected() {
+        for name in ["Content-Length", "transfer-encoding"] {
+            let mut request = Request::put("/", "body");
+            request.add_header("Accept", "*/*");
+            request.add_header(name, "4");
+            assert_eq!(locations(&request), [Location::HeaderName(1)]);
+        }
+    }
+
+    #[test]
+    fn one_content_length_on_the_wire() {
+        let request = Request::put("/", "body");
+        assert!(request.validate().is_ok());
+        let bytes = String::from_utf8(request.into_bytes()).unwrap();
+        assert_eq!(bytes.matches("Content-Length").count(), 1);
+        assert!(bytes.ends_with("Content-Length: 4\r\n\r\nbody"));
+    }
+
+    #[test]
+    fn chunked_streamed_body() {
+        let mut request = Request::put("/", vec![]);
+        request.stream_body(None);
+        let bytes = String::from_utf8(request.into_bytes()).unwrap();
+        assert!(bytes.ends_with("Transfer-Encoding: chunked\r\n\r\n"));
+        assert!(!bytes.contains("Content-Length"));
+    }
+
+    #[cfg(feature = "digest")]
+    #[test]
+    fn digest_headers_in_the_order_asked_for() {
+        let mut request = Request::put("/", "body");
+        request.digest(DigestAlgorithm::Sha256);
+        request.digest(DigestAlgorithm::Md5);
+        request.digest(DigestAlgorithm::Sha512);
+        let bytes = String::from_utf8(request.into_bytes()).unwrap();
+
+        let repr = bytes.find("repr-digest: sha-256=:").unwrap();
+        let md5 = bytes.find("content-md5: ").unwrap();
+        assert!(repr < md5);
+        assert_eq!(bytes.matches("repr-digest").count(), 1);
+        assert!(bytes.contains(":, sha-512=:"));
+    }
+
+    #[cfg(feature = "digest")]
+    #[test]
+    fn digest_headers_match_the_rfc_examples() {
+        // RFC 9530, appendix B
+        let mut request = Request::put("/", r#"{"hello": "world"}"#);
+        request.digest(DigestAlgorithm::Sha256);
+        request.digest(DigestAlgorithm::Md5);
+        let bytes = String::from_utf8(request.into_bytes()).unwrap();
+        assert!(
+            bytes.contains(
+                "repr-digest: sha-256=:X48E9qOokqqrvdts8nOJRJN3OWDUoyWxBf7kbu9DBPE=:\r\n"
+            )
+        );
+        assert!(bytes.contains("content-md5: Sd/dVLAcvNLSq16eXua5uQ==\r\n"));
+    }
+
+    #[cfg(feature = "digest")]
+    #[test]
+    fn digests_of_sized_streamed_bodies_are_rejected() {
+        let mut request = Request::put("/", vec![]);
+        request.stream_body(Some(4));
+        request.digest(DigestAlgorithm::Sha256);
+        assert_eq!(locations(&request), [Location::Body]);
+    }
+
+    #[cfg(feature = "digest")]
+    #[test]
+    fn digests_of_chunked_streamed_bodies_are_announced_as_trailers() {
+        let mut request = Request::put("/", vec![]);
+        request.stream_body(None);
+        request.digest(DigestAlgorithm::Sha256);
+        request.digest(DigestAlgorithm::Md5);
+        request.digest(DigestAlgorithm::Sha512);
+        assert!(request.validate().is_ok());
+        let bytes = String::from_utf8(request.into_bytes()).unwrap();
+        assert!(bytes.contains("trailer: Repr-Digest, Content-MD5\r\n"));
+        assert!(!bytes.contains("repr-digest"));
+    }
 }
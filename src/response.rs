@@ -1,43 +1,486 @@
-use anyhow::{Context as _, Result};
-use std::collections::HashMap;
+use crate::{
+    Error, Headers, HttpVersion, LimitKind, Limits, Result, Stats, TlsInfo, Trace,
+    redact::{REDACTED, is_sensitive_header},
+};
+use std::{borrow::Cow, fmt, str::Utf8Error, sync::Arc};
+
+/// How much of the body `Display` shows, `{:#}` shows all of it.
+const BODY_PREVIEW_LEN: usize = 1024;
 
 #[derive(Debug)]
 pub struct Response {
+    pub version: HttpVersion,
     pub status: u16,
-    pub headers: HashMap<String, String>,
-    pub body: String,
+    /// As sent by the server, see `status_text` for the canonical one.
+    pub reason: String,
+    pub headers: Headers,
+    pub body: Vec<u8>,
+    /// IO operations this response took, filled in by the `FSM`.
+    pub stats: Stats,
+    /// The exchange step by step, with `ClientOptions::trace` on.
+    pub trace: Option<Trace>,
+    /// How the connection was secured, `None` for plain HTTP.
+    pub tls: Option<Arc<TlsInfo>>,
 }
 
 impl Response {
-    pub(crate) fn parse(data: Vec<u8>) -> Result<Self> {
-        let data = String::from_utf8(data)?;
-        let (pre, body) = data
-            .split_once("\r\n\r\n")
-            .context("no separator between headers and body")?;
-        let (status, headers) = pre
-            .split_once("\r\n")
-            .context("no separator between status line and headers")?;
-
-        let status = status
-            .split(" ")
-            .nth(1)
-            .context("malformed status line")?
+    pub fn text(&self) -> Result<&str, Utf8Error> {
+        std::str::from_utf8(&self.body)
+    }
+
+    pub fn text_lossy(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(&self.body)
+    }
+
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+
+    pub fn is_redirect(&self) -> bool {
+        (300..400).contains(&self.status)
+    }
+
+    pub fn is_client_error(&self) -> bool {
+        (400..500).contains(&self.status)
+    }
+
+    pub fn is_server_error(&self) -> bool {
+        (500..600).contains(&self.status)
+    }
+
+    /// Canonical reason phrase for the status code, not necessarily
+    /// the one the server sent.
+    pub fn status_text(&self) -> Option<&'static str> {
+        status_text(self.status)
+    }
+
+    /// Turns 4xx and 5xx responses into `Error::Status`.
+    pub fn error_for_status(self) -> Result<Self> {
+        if self.is_client_error() || self.is_server_error() {
+            Err(Error::Status(Box::new(self)))
+        } else {
+            Ok(self)
+        }
+    }
+
+    pub fn content_length(&self) -> Option<u64> {
+        self.headers.get("Content-Length")?.parse().ok()
+    }
+
+    pub fn etag(&self) -> Option<&str> {
+        self.headers.get("ETag")
+    }
+
+    /// Turns a 412 answer to a conditional request (e.g. `If-Match`)
+    /// into an error, any other response is returned back as is.
+    pub fn check_precondition(self) -> Result<Self> {
+        if self.status == 412 {
+            Err(Error::PreconditionFailed(Box::new(self)))
+        } else {
+            Ok(self)
+        }
+    }
+
+    /// Deserializes a JSON body, a `Content-Type` other than
+    /// `application/json` (or a `+json` type) is an error.
+    #[cfg(feature = "json")]
+    pub fn json<T: serde::de::DeserializeOwned>(&self) -> Result<T> {
+        use crate::headers::content_type::MediaType;
+
+        if let Some(content_type) = self.headers.get("Content-Type") {
+            let is_json = MediaType::parse(content_type).is_some_and(|mime| mime.is_json());
+            if !is_json {
+                return Err(Error::Parse(format!(
+                    "expected a JSON body, got {content_type}"
+                )));
+            }
+        }
+
+        serde_json::from_slice(&self.body)
+            .map_err(|err| Error::Parse(format!("invalid JSON body: {err}")))
+    }
+
+    /// Serializes the response back into HTTP/1 wire format, headers
+    /// unredacted and in the order they were received. The body is the
+    /// decoded one, `chunked` (and codings undone with the `decompression`
+    /// feature) are already gone from the headers, so a `Content-Length`
+    /// is added where nothing else frames it. HTTP/0.9 responses have no
+    /// head and come out as the body alone.
+    pub fn to_http1_bytes(&self) -> Vec<u8> {
+        if self.version == HttpVersion::Http09 {
+            return self.body.clone();
+        }
+
+        let mut out = Vec::with_capacity(self.body.len() + 256);
+        out.extend_from_slice(
+            format!("{} {} {}\r\n", self.version, self.status, self.reason).as_bytes(),
+        );
+        for (name, value) in &self.headers {
+            out.extend_from_slice(format!("{name}: {value}\r\n").as_bytes());
+        }
+        let framed = self.headers.get("Content-Length").is_some()
+            || self.headers.get("Transfer-Encoding").is_some();
+        if !framed && !is_bodiless(self.status) {
+            out.extend_from_slice(format!("Content-Length: {}\r\n", self.body.len()).as_bytes());
+        }
+        out.extend_from_slice(b"\r\n");
+        out.extend_from_slice(&self.body);
+        out
+    }
+
+    /// For a response whose head never ended: either HTTP/0.9, where the whole
+    /// stream is the body, or a server that closed right after the head.
+    pub(crate) fn parse(data: &[u8], preserve_header_case: bool) -> Result<Self> {
+        if !data.starts_with(b"HTTP/") {
+            // HTTP/0.9, no status line and no headers, the whole stream is the body
+            return Ok(Self {
+                version: HttpVersion::Http09,
+                status: 200,
+                reason: String::new(),
+                headers: Headers::new(),
+                body: data.to_vec(),
+                stats: Stats::default(),
+                trace: None,
+                tls: None,
+            });
+        }
+
+        // no body to limit
+        Self::from_parts(Head::parse(data, preserve_header_case)?, vec![], None)
+    }
+
+    /// `max_body_size` bounds the decompressed body, see `Limits`.
+    #[cfg_attr(not(feature = "decompression"), allow(unused_variables))]
+    pub(crate) fn from_parts(
+        head: Head,
+        body: Vec<u8>,
+        max_body_size: Option<usize>,
+    ) -> Result<Self> {
+        #[cfg(feature = "decompression")]
+        let (head, body) = {
+            let (headers, body) = decompress_body(head.headers, body, max_body_size)?;
+            (Head { headers, ..head }, body)
+        };
+
+        Ok(Self::from_raw_parts(head, body))
+    }
+
+    /// Like `from_parts` but with the body as it was sent.
+    pub(crate) fn from_raw_parts(head: Head, body: Vec<u8>) -> Self {
+        let Head {
+            version,
+            status,
+            reason,
+            headers,
+        } = head;
+
+        Self {
+            version,
+            status,
+            reason,
+            headers,
+            body,
+            stats: Stats::default(),
+            trace: None,
+            tls: None,
+        }
+    }
+}
+
+/// The status line, the headers sorted by name with credentials redacted
+/// and a preview of the body: text up to `BODY_PREVIEW_LEN` bytes, or just
+/// the size of a binary one. `{:#}` doesn't truncate the text.
+impl fmt::Display for Response {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.version, self.status)?;
+        if !self.reason.is_empty() {
+            write!(f, " {}", self.reason)?;
+        }
+        writeln!(f)?;
+
+        let mut headers = self.headers.iter().collect::<Vec<_>>();
+        headers.sort_by_cached_key(|(name, _)| name.to_ascii_lowercase());
+        for (name, value) in headers {
+            let value = if is_sensitive_header(name) {
+                REDACTED
+            } else {
+                value
+            };
+            writeln!(f, "{name}: {value}")?;
+        }
+
+        if self.body.is_empty() {
+            return Ok(());
+        }
+        writeln!(f)?;
+
+        let len = if f.alternate() {
+            self.body.len()
+        } else {
+            self.body.len().min(BODY_PREVIEW_LEN)
+        };
+        let Some(text) = text_prefix(&self.body[..len]) else {
+            return write!(f, "[{} bytes of binary data]", self.body.len());
+        };
+        f.write_str(text)?;
+        if text.len() < self.body.len() {
+            write!(
+                f,
+                "\n[... {} more bytes, {} total]",
+                self.body.len() - text.len(),
+                self.body.len()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// `bytes` as text, minus a character cut off at the end. `None` for
+/// anything that isn't UTF-8 or has control characters other than
+/// whitespace.
+fn text_prefix(bytes: &[u8]) -> Option<&str> {
+    let text = match std::str::from_utf8(bytes) {
+        Ok(text) => text,
+        // only the last character is incomplete
+        Err(err) if err.error_len().is_none() => {
+            std::str::from_utf8(&bytes[..err.valid_up_to()]).ok()?
+        }
+        Err(_) => return None,
+    };
+    let binary = text
+        .chars()
+        .any(|c| c.is_control() && !matches!(c, '\t' | '\n' | '\r'));
+    (!binary).then_some(text)
+}
+
+/// 1xx, 204 and 304 responses never have a body.
+fn is_bodiless(status: u16) -> bool {
+    (100..200).contains(&status) || status == 204 || status == 304
+}
+
+/// Status line and headers, available before the body arrives.
+pub(crate) struct Head {
+    pub(crate) version: HttpVersion,
+    pub(crate) status: u16,
+    pub(crate) reason: String,
+    pub(crate) headers: Headers,
+}
+
+impl Head {
+    pub(crate) fn parse(head: &[u8], preserve_case: bool) -> Result<Self> {
+        let pre = std::str::from_utf8(head)
+            .map_err(|_| Error::Parse("non-UTF-8 response head".to_string()))?;
+
+        let (status_line, headers) = pre.split_once('\n').unwrap_or((pre, ""));
+
+        let mut parts = status_line.trim_end().splitn(3, ' ');
+        let version = parts.next().unwrap_or_default().parse::<HttpVersion>()?;
+        let status = parts
+            .next()
+            .ok_or_else(|| Error::Parse("malformed status line".to_string()))?
             .parse::<u16>()
-            .context("non-numeric HTTP status")?;
+            .map_err(|_| Error::Parse("non-numeric HTTP status".to_string()))?;
+        let reason = parts.next().unwrap_or_default().to_string();
 
         let headers = {
-            let mut out = HashMap::new();
-            for line in headers.split("\r\n") {
-                let (name, value) = line.split_once(": ").context("malformed header")?;
-                out.insert(name.to_string(), value.to_string());
+            let mut out = Headers::with_capacity(headers.lines().count(), preserve_case);
+            for line in headers.lines().filter(|line| !line.is_empty()) {
+                let (name, value) = line
+                    .split_once(':')
+                    .ok_or_else(|| Error::Parse(format!("malformed header: {line}")))?;
+                out.append(name, value.trim());
             }
             out
         };
 
         Ok(Self {
+            version,
             status,
+            reason,
             headers,
-            body: body.to_string(),
         })
     }
 }
+
+#[cfg(feature = "decompression")]
+fn decompress_body(
+    mut headers: Headers,
+    mut body: Vec<u8>,
+    max: Option<usize>,
+) -> Result<(Headers, Vec<u8>)> {
+    use crate::decompression::{decompress, is_supported};
+
+    // some servers gzip as a transfer coding (`Transfer-Encoding: gzip,
+    // chunked`), it's hop-by-hop and applied last, so it's undone first;
+    // `chunked` itself is gone by now
+    if let Some(codings) = headers.get("Transfer-Encoding")
+        && is_supported(codings)
+    {
+        let codings = headers.remove("Transfer-Encoding").unwrap_or_default();
+        body = decompress(&codings, body, max)?;
+    }
+
+    let Some(encodings) = headers.get("Content-Encoding") else {
+        return Ok((headers, body));
+    };
+
+    if !is_supported(encodings) {
+        return Ok((headers, body));
+    }
+
+    let encodings = headers.remove("Content-Encoding").unwrap_or_default();
+    headers.remove("Content-Length");
+    let body = decompress(&encodings, body, max)?;
+    Ok((headers, body))
+}
+
+/// Looks for the end of the response head as data arrives, line by line,
+/// only scanning what's new since the last call and enforcing `Limits`.
+///
+/// Old HTTP/1.0 servers tend to use bare `\n` line endings and sometimes
+/// close the connection right after the status line.
+pub(crate) struct HeadScanner {
+    scanned: usize,
+    line_start: usize,
+    lines: usize,
+    head_end: Option<(usize, usize)>,
+    limits: Limits,
+}
+
+impl HeadScanner {
+    pub(crate) fn new(limits: Limits) -> Self {
+        Self {
+            scanned: 0,
+            line_start: 0,
+            lines: 0,
+            head_end: None,
+            limits,
+        }
+    }
+
+    pub(crate) fn head_end(&self) -> Option<(usize, usize)> {
+        self.head_end
+    }
+
+    /// `data` is everything received so far.
+    pub(crate) fn feed(&mut self, data: &[u8]) -> Result<()> {
+        if self.head_end.is_some() || self.scanned == data.len() {
+            return Ok(());
+        }
+        if data.len() >= 5 && !data.starts_with(b"HTTP/") {
+            // HTTP/0.9, there's no head
+            self.scanned = data.len();
+            return Ok(());
+        }
+
+        let from = self.scanned;
+        self.scanned = data.len();
+
+        for idx in positions(b'\n', &data[from..]).map(|idx| from + idx) {
+            let line = &data[self.line_start..idx];
+            let line = line.strip_suffix(b"\r").unwrap_or(line);
+
+            if line.is_empty() {
+                self.head_end = Some((self.line_start, idx + 1 - self.line_start));
+                break;
+            }
+            self.check_line(line.len())?;
+
+            self.lines += 1;
+            if self.lines - 1 > self.limits.max_header_count {
+                return Err(Error::LimitExceeded(LimitKind::HeaderCount));
+            }
+            self.line_start = idx + 1;
+        }
+
+        match self.head_end {
+            Some((head_len, _)) if head_len > self.limits.max_head_size => {
+                Err(Error::LimitExceeded(LimitKind::HeadSize))
+            }
+            Some(_) => Ok(()),
+            None if data.len() > self.limits.max_head_size => {
+                Err(Error::LimitExceeded(LimitKind::HeadSize))
+            }
+            // the line that's still arriving can already be too long
+            None => self.check_line(data.len() - self.line_start),
+        }
+    }
+
+    fn check_line(&self, len: usize) -> Result<()> {
+        if self.lines == 0 && len > self.limits.max_status_line_length {
+            return Err(Error::LimitExceeded(LimitKind::StatusLineLength));
+        }
+        if self.lines > 0 && len > self.limits.max_header_size {
+            return Err(Error::LimitExceeded(LimitKind::HeaderSize));
+        }
+        Ok(())
+    }
+}
+
+fn positions(needle: u8, haystack: &[u8]) -> impl Iterator<Item = usize> + '_ {
+    haystack
+        .iter()
+        .enumerate()
+        .filter(move |(_, byte)| **byte == needle)
+        .map(|(idx, _)| idx)
+}
+
+fn status_text(status: u16) -> Option<&'static str> {
+    let text = match status {
+        100 => "Continue",
+        101 => "Switching Protocols",
+        103 => "Early Hints",
+        200 => "OK",
+        201 => "Created",
+        202 => "Accepted",
+        203 => "Non-Authoritative Information",
+        204 => "No Content",
+        205 => "Reset Content",
+        206 => "Partial Content",
+        207 => "Multi-Status",
+        300 => "Multiple Choices",
+        301 => "Moved Permanently",
+        302 => "Found",
+        303 => "See Other",
+        304 => "Not Modified",
+        307 => "Temporary Redirect",
+        308 => "Permanent Redirect",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        402 => "Payment Required",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        406 => "Not Acceptable",
+        407 => "Proxy Authentication Required",
+        408 => "Request Timeout",
+        409 => "Conflict",
+        410 => "Gone",
+        411 => "Length Required",
+        412 => "Precondition Failed",
+        413 => "Content Too Large",
+        414 => "URI Too Long",
+        415 => "Unsupported Media Type",
+        416 => "Range Not Satisfiable",
+        417 => "Expectation Failed",
+        421 => "Misdirected Request",
+        422 => "Unprocessable Content",
+        423 => "Locked",
+        425 => "Too Early",
+        426 => "Upgrade Required",
+        428 => "Precondition Required",
+        429 => "Too Many Requests",
+        431 => "Request Header Fields Too Large",
+        451 => "Unavailable For Legal Reasons",
+        500 => "Internal Server Error",
+        501 => "Not Implemented",
+        502 => "Bad Gateway",
+        503 => "Service Unavailable",
+        504 => "Gateway Timeout",
+        505 => "HTTP Version Not Supported",
+        507 => "Insufficient Storage",
+        511 => "Network Authentication Required",
+        _ => return None,
+    };
+    Some(text)
+}
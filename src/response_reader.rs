@@ -0,0 +1,730 @@
+use crate::{
+    BodySink, Error, Headers, LimitKind, Limits, Response, Result,
+    body_sink::fill,
+    response::{Head, HeadScanner},
+};
+use std::ops::Range;
+
+/// Accumulates a response as it arrives and tells when it's complete,
+/// based on `Content-Length`, chunked framing or the lack of a body.
+///
+/// It lives as long as the connection: `reset` prepares it for the next
+/// request on a kept-alive connection, keeping the receive buffer and the
+/// chunk list allocated (up to `retain` bytes), so that a run of small
+/// requests stops hitting the allocator for them.
+pub(crate) struct ResponseReader {
+    data: Vec<u8>,
+    scanner: HeadScanner,
+    head: Option<(Head, usize)>,
+    framing: Framing,
+    complete: bool,
+    body_too_large: bool,
+    /// The chunk list of the last chunked response, cleared.
+    spare_chunks: Vec<Range<usize>>,
+    /// The body goes to a `BodySink`, see `deliver`.
+    streaming: bool,
+    /// Body bytes (framing included) already handed to the sink
+    /// and dropped from `data`.
+    delivered: usize,
+
+    is_head_request: bool,
+    limits: Limits,
+    retain: usize,
+    preserve_header_case: bool,
+}
+
+enum Framing {
+    Empty,
+    Length(usize),
+    Chunked(Chunked),
+    UntilClose,
+}
+
+impl ResponseReader {
+    pub(crate) fn new(
+        limits: Limits,
+        initial: usize,
+        retain: usize,
+        preserve_header_case: bool,
+    ) -> Self {
+        Self {
+            data: Vec::with_capacity(initial),
+            scanner: HeadScanner::new(limits),
+            head: None,
+            framing: Framing::UntilClose,
+            complete: false,
+            body_too_large: false,
+            spare_chunks: vec![],
+            streaming: false,
+            delivered: 0,
+
+            is_head_request: false,
+            limits,
+            retain,
+            preserve_header_case,
+        }
+    }
+
+    /// Other limits for the responses after the current one, takes effect
+    /// with the next `reset`.
+    pub(crate) fn configure(&mut self, limits: Limits, preserve_header_case: bool) {
+        self.limits = limits;
+        self.preserve_header_case = preserve_header_case;
+    }
+
+    /// Gets ready for the response to the next request.
+    pub(crate) fn reset(&mut self, is_head_request: bool) {
+        self.data.clear();
+        self.data.shrink_to(self.retain);
+        self.scanner = HeadScanner::new(self.limits);
+        self.head = None;
+        if let Framing::Chunked(chunked) = std::mem::replace(&mut self.framing, Framing::UntilClose)
+        {
+            self.recycle(chunked);
+        }
+        self.complete = false;
+        self.body_too_large = false;
+        self.delivered = 0;
+        self.is_head_request = is_head_request;
+    }
+
+    fn recycle(&mut self, chunked: Chunked) {
+        let mut chunks = chunked.chunks;
+        chunks.clear();
+        chunks.shrink_to(self.retain / size_of::<Range<usize>>());
+        self.spare_chunks = chunks;
+    }
+
+    pub(crate) fn feed(&mut self, bytes: &[u8]) -> Result<()> {
+        let fed = self.feed_inner(bytes);
+        self.body_too_large = matches!(fed, Err(Error::LimitExceeded(LimitKind::BodySize)));
+        fed
+    }
+
+    /// `feed` then `deliver`, skipping the parsing space for bodies that
+    /// need no decoding while nothing is held back.
+    pub(crate) fn feed_to(&mut self, mut bytes: &[u8], sink: &mut dyn BodySink) -> Result<()> {
+        if let Some((_, body_start)) = &self.head
+            && self.data.len() == *body_start
+        {
+            let sent = match self.framing {
+                Framing::Length(len) => {
+                    let end = (len - self.delivered).min(bytes.len());
+                    fill(sink, &bytes[..end])
+                }
+                Framing::UntilClose => {
+                    let checked = check_body_size(self.delivered + bytes.len(), self.limits);
+                    self.body_too_large = checked.is_err();
+                    checked?;
+                    fill(sink, bytes)
+                }
+                Framing::Empty | Framing::Chunked(_) => 0,
+            };
+            self.delivered += sent;
+            bytes = &bytes[sent..];
+        }
+
+        self.feed(bytes)?;
+        self.deliver(sink);
+        Ok(())
+    }
+
+    /// The body went over `Limits::max_body_size`, nothing more should be
+    /// read for this response.
+    pub(crate) fn is_body_too_large(&self) -> bool {
+        self.body_too_large
+    }
+
+    fn feed_inner(&mut self, bytes: &[u8]) -> Result<()> {
+        self.data.extend_from_slice(bytes);
+
+        while self.head.is_none() {
+            self.scanner.feed(&self.data)?;
+            let Some((head_len, separator_len)) = self.scanner.head_end() else {
+                return Ok(());
+            };
+            let head = Head::parse(&self.data[..head_len], self.preserve_header_case)?;
+            let body_start = head_len + separator_len;
+
+            if (100..200).contains(&head.status) && head.status != 101 {
+                // interim response (e.g. 100 Continue), the final one follows
+                self.data.drain(..body_start);
+                self.scanner = HeadScanner::new(self.limits);
+                continue;
+            }
+
+            self.framing = Framing::new(
+                &head,
+                self.is_head_request,
+                self.streaming,
+                &mut self.spare_chunks,
+            )?;
+            if let Framing::Length(len) = self.framing {
+                check_body_size(len, self.limits)?;
+            }
+            self.head = Some((head, body_start));
+        }
+
+        if let Some((_, body_start)) = &self.head {
+            let body = &self.data[*body_start..];
+            self.complete = match &mut self.framing {
+                Framing::Empty => true,
+                Framing::Length(len) => self.delivered + body.len() >= *len,
+                Framing::Chunked(chunked) => chunked.feed(body, self.limits)?,
+                Framing::UntilClose => {
+                    check_body_size(self.delivered + body.len(), self.limits)?;
+                    false
+                }
+            };
+        }
+
+        Ok(())
+    }
+
+    /// Sends the body to a `BodySink` from now on, ranges of chunks are
+    /// then recorded as their bytes come in rather than once complete.
+    pub(crate) fn set_streaming(&mut self, streaming: bool) {
+        self.streaming = streaming;
+        if let Framing::Chunked(chunked) = &mut self.framing {
+            chunked.partial = streaming;
+        }
+    }
+
+    /// Moves as much of the decoded body received so far into `sink` as
+    /// it takes and drops it from `data`.
+    pub(crate) fn deliver(&mut self, sink: &mut dyn BodySink) {
+        let Some((_, body_start)) = &self.head else {
+            return;
+        };
+        let body_start = *body_start;
+        let body = &self.data[body_start..];
+
+        let dropped = match &mut self.framing {
+            Framing::Empty => 0,
+            Framing::Length(len) => {
+                let end = (*len - self.delivered).min(body.len());
+                fill(sink, &body[..end])
+            }
+            Framing::UntilClose => fill(sink, body),
+            Framing::Chunked(chunked) => {
+                let mut sent = 0;
+                for chunk in &mut chunked.chunks {
+                    chunk.start += fill(sink, &body[chunk.clone()]);
+                    if chunk.start < chunk.end {
+                        break;
+                    }
+                    sent += 1;
+                }
+                chunked.chunks.drain(..sent);
+
+                if chunked.chunks.is_empty() {
+                    std::mem::take(&mut chunked.pos)
+                } else {
+                    0
+                }
+            }
+        };
+
+        self.data.drain(body_start..body_start + dropped);
+        self.delivered += dropped;
+    }
+
+    /// Received body bytes (framing included) not handed to a sink yet.
+    pub(crate) fn held_back(&self) -> usize {
+        match &self.head {
+            Some((_, body_start)) => self.data.len() - body_start,
+            None => 0,
+        }
+    }
+
+    pub(crate) fn has_head(&self) -> bool {
+        self.head.is_some()
+    }
+
+    /// Of the final response, once its head is in.
+    pub(crate) fn status(&self) -> Option<u16> {
+        self.head.as_ref().map(|(head, _)| head.status)
+    }
+
+    /// Received body bytes (framing included), delivered or not.
+    pub(crate) fn body_received(&self) -> usize {
+        self.delivered + self.held_back()
+    }
+
+    /// How much of the body is left to read at least (the framing of
+    /// chunks that haven't arrived isn't known), `None` if it only ends
+    /// with the connection.
+    pub(crate) fn body_left(&self) -> Option<usize> {
+        match &self.framing {
+            Framing::Length(len) => Some(len.saturating_sub(self.body_received())),
+            Framing::UntilClose => None,
+            Framing::Empty | Framing::Chunked(_) => Some(0),
+        }
+    }
+
+    pub(crate) fn is_complete(&self) -> bool {
+        self.complete
+    }
+
+    /// The body is framed but the connection closed before all of it arrived.
+    pub(crate) fn is_truncated(&self) -> bool {
+        self.head.is_some() && !self.complete && !matches!(self.framing, Framing::UntilClose)
+    }
+
+    /// The body is copied out, the buffer stays for the next response.
+    pub(crate) fn finish(&mut self) -> Result<Response> {
+        let Some((mut head, body_start)) = self.head.take() else {
+            let response = Response::parse(&self.data, self.preserve_header_case);
+            self.data.clear();
+            return response;
+        };
+
+        let body = &self.data[body_start..];
+        let body = match std::mem::replace(&mut self.framing, Framing::UntilClose) {
+            Framing::Empty => vec![],
+            Framing::Length(len) => {
+                // short if the peer closed early
+                let end = len.saturating_sub(self.delivered).min(body.len());
+                body[..end].to_vec()
+            }
+            Framing::Chunked(chunked) => {
+                remove_chunked(&mut head.headers);
+                let decoded = chunked.decode(body);
+                self.recycle(chunked);
+                decoded
+            }
+            Framing::UntilClose => body.to_vec(),
+        };
+        self.data.clear();
+
+        if self.streaming {
+            // the start of the body is in the sink, still encoded
+            return Ok(Response::from_raw_parts(head, body));
+        }
+        Response::from_parts(head, body, self.limits.max_body_size)
+    }
+}
+
+impl Framing {
+    // RFC 9112, section 6.3
+    fn new(
+        head: &Head,
+        is_head_request: bool,
+        streaming: bool,
+        spare_chunks: &mut Vec<Range<usize>>,
+    ) -> Result<Self> {
+        if is_head_request || matches!(head.status, 100..=199 | 204 | 304) {
+            return Ok(Self::Empty);
+        }
+
+        let codings = list(&head.headers, "Transfer-Encoding");
+        if let Some(last) = codings.last() {
+            return Ok(if last.eq_ignore_ascii_case("chunked") {
+                Self::Chunked(Chunked {
+                    chunks: std::mem::take(spare_chunks),
+                    partial: streaming,
+                    ..Chunked::default()
+                })
+            } else {
+                Self::UntilClose
+            });
+        }
+
+        let mut length = None;
+        for value in list(&head.headers, "Content-Length") {
+            let value = value
+                .parse::<usize>()
+                .map_err(|_| Error::Parse(format!("invalid Content-Length: {value}")))?;
+            if length.is_some_and(|length| length != value) {
+                return Err(Error::Parse(
+                    "conflicting Content-Length values".to_string(),
+                ));
+            }
+            length = Some(value);
+        }
+
+        Ok(length.map_or(Self::UntilClose, Self::Length))
+    }
+}
+
+fn list<'a>(headers: &'a Headers, name: &'a str) -> Vec<&'a str> {
+    headers
+        .get_all(name)
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+fn remove_chunked(headers: &mut Headers) {
+    let Some(codings) = headers.remove("Transfer-Encoding") else {
+        return;
+    };
+    let rest = codings
+        .split(',')
+        .map(str::trim)
+        .filter(|coding| !coding.is_empty() && !coding.eq_ignore_ascii_case("chunked"))
+        .collect::<Vec<_>>();
+    if !rest.is_empty() {
+        headers.insert("Transfer-Encoding", rest.join(", "));
+    }
+}
+
+#[derive(Default)]
+struct Chunked {
+    pos: usize,
+    /// Sum of the chunk sizes announced so far.
+    size: usize,
+    /// Of the trailer lines so far.
+    trailers: usize,
+    /// Record the received part of a chunk right away.
+    partial: bool,
+    state: ChunkState,
+    chunks: Vec<Range<usize>>,
+}
+
+#[derive(Default, Clone, Copy)]
+enum ChunkState {
+    #[default]
+    Size,
+    Data(usize),
+    DataEnd,
+    Trailers,
+    Done,
+}
+
+impl Chunked {
+    /// Picks up where the previous call stopped,
+    /// returns `true` once the last chunk and the trailers are in.
+    fn feed(&mut self, body: &[u8], limits: Limits) -> Result<bool> {
+        loop {
+            let rest = &body[self.pos..];
+
+            match self.state {
+                ChunkState::Size => {
+                    let Some((line, len)) = framing_line(rest, limits)? else {
+                        return Ok(false);
+                    };
+                    // chunk extensions are ignored
+                    let size = line.split(|&byte| byte == b';').next().unwrap_or_default();
+                    let size = parse_chunk_size(size)
+                        .ok_or_else(|| Error::Parse("malformed chunk size".to_string()))?;
+                    check_body_size(self.size.saturating_add(size), limits)?;
+                    self.size += size;
+                    self.pos += len;
+                    self.state = if size == 0 {
+                        ChunkState::Trailers
+                    } else {
+                        ChunkState::Data(size)
+                    };
+                }
+                ChunkState::Data(size) => {
+                    if rest.len() < size {
+                        if self.partial && !rest.is_empty() {
+                            self.chunks.push(self.pos..self.pos + rest.len());
+                            self.pos += rest.len();
+                            self.state = ChunkState::Data(size - rest.len());
+                        }
+                        return Ok(false);
+                    }
+                    self.chunks.push(self.pos..self.pos + size);
+                    self.pos += size;
+                    self.state = ChunkState::DataEnd;
+                }
+                ChunkState::DataEnd => {
+                    let Some((line, len)) = framing_line(rest, limits)? else {
+                        return Ok(false);
+                    };
+                    if !line.is_empty() {
+                        return Err(Error::Parse("chunk is longer than its size".to_string()));
+                    }
+                    self.pos += len;
+                    self.state = ChunkState::Size;
+                }
+                ChunkState::Trailers => {
+                    let Some((line, len)) = framing_line(rest, limits)? else {
+                        return Ok(false);
+                    };
+                    self.trailers += len;
+                    if self.trailers > limits.max_head_size {
+                        return Err(Error::LimitExceeded(LimitKind::HeadSize));
+                    }
+                    self.pos += len;
+                    if line.is_empty() {
+                        self.state = ChunkState::Done;
+                    }
+                }
+                ChunkState::Done => return Ok(true),
+            }
+        }
+    }
+
+    fn decode(&self, body: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.chunks.iter().map(|chunk| chunk.len()).sum());
+        for chunk in &self.chunks {
+            out.extend_from_slice(&body[chunk.clone()]);
+        }
+        out
+    }
+}
+
+/// Sizes announced by `Content-Length` or a chunk header are checked
+/// before their bytes are waited for, so they fail without being buffered.
+fn check_body_size(len: usize, limits: Limits) -> Result<()> {
+    if limits.max_body_size.is_some_and(|max| len > max) {
+        return Err(Error::LimitExceeded(LimitKind::BodySize));
+    }
+    Ok(())
+}
+
+/// `1*HEXDIG` with only the whitespace allowed before extensions after
+/// it; `from_str_radix` alone would take e.g. a leading `+`, which a
+/// proxy in front of us may read differently.
+fn parse_chunk_size(size: &[u8]) -> Option<usize> {
+    let size = size.trim_ascii_end();
+    if size.is_empty() || !size.iter().all(u8::is_ascii_hexdigit) {
+        return None;
+    }
+    usize::from_str_radix(std::str::from_utf8(size).ok()?, 16).ok()
+}
+
+/// A chunk size line (extensions included), the end of a chunk or a trailer
+/// line, each held to `max_header_size` whether or not it has ended yet.
+fn framing_line(data: &[u8], limits: Limits) -> Result<Option<(&[u8], usize)>> {
+    let line = next_line(data);
+    let len = line.map_or(data.len(), |(_, len)| len);
+    if len > limits.max_header_size {
+        return Err(Error::LimitExceeded(LimitKind::HeaderSize));
+    }
+    Ok(line)
+}
+
+fn next_line(data: &[u8]) -> Option<(&[u8], usize)> {
+    let idx = data.iter().position(|&byte| byte == b'\n')?;
+    let line = &data[..idx];
+    Some((line.strip_suffix(b"\r").unwrap_or(line), idx + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reader() -> ResponseReader {
+        ResponseReader::new(Limits::default(), 0, 0, false)
+    }
+
+    /// Feeds `pieces` one by one, the reader has to be complete after
+    /// the last one and not before.
+    fn read(reader: &mut ResponseReader, pieces: &[&[u8]]) -> Response {
+        for (idx, piece) in pieces.iter().enumerate() {
+            assert!(!reader.is_complete(), "complete before piece {idx}");
+            reader.feed(piece).unwrap();
+        }
+        assert!(reader.is_complete());
+        reader.finish().unwrap()
+    }
+
+    #[test]
+    fn content_length() {
+        let response = read(
+            &mut reader(),
+            &[b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhe", b"llo"],
+        );
+        assert_eq!(response.body, b"hello");
+    }
+
+    #[test]
+    fn content_length_over_several_responses() {
+        let mut reader = reader();
+        for body in ["first", "second"] {
+            reader.reset(false);
+            let head = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", body.len());
+            let response = read(&mut reader, &[head.as_bytes(), body.as_bytes()]);
+            assert_eq!(response.body, body.as_bytes());
+        }
+    }
+
+    #[test]
+    fn repeated_content_length() {
+        let response = read(
+            &mut reader(),
+            &[b"HTTP/1.1 200 OK\r\nContent-Length: 2, 2\r\nContent-Length: 2\r\n\r\nok"],
+        );
+        assert_eq!(response.body, b"ok");
+    }
+
+    #[test]
+    fn conflicting_content_lengths() {
+        let err = reader()
+            .feed(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\nContent-Length: 3\r\n\r\n")
+            .unwrap_err();
+        assert!(matches!(err, Error::Parse(_)), "{err:?}");
+    }
+
+    #[test]
+    fn invalid_content_length() {
+        let err = reader()
+            .feed(b"HTTP/1.1 200 OK\r\nContent-Length: -1\r\n\r\n")
+            .unwrap_err();
+        assert!(matches!(err, Error::Parse(_)), "{err:?}");
+    }
+
+    #[test]
+    fn chunked_with_extensions_and_trailers() {
+        let response = read(
+            &mut reader(),
+            &[
+                b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n",
+                b"5;name=value\r\nhel",
+                b"lo\r\n6\r\n world\r\n0;last\r\n",
+                b"Checksum: abc\r\n",
+                b"\r\n",
+            ],
+        );
+        assert_eq!(response.body, b"hello world");
+        assert_eq!(response.headers.get("Transfer-Encoding"), None);
+    }
+
+    #[test]
+    fn chunked_wins_over_content_length() {
+        let response = read(
+            &mut reader(),
+            &[b"HTTP/1.1 200 OK\r\nContent-Length: 100\r\nTransfer-Encoding: chunked\r\n\r\n2\r\nok\r\n0\r\n\r\n"],
+        );
+        assert_eq!(response.body, b"ok");
+    }
+
+    #[test]
+    fn malformed_chunk_size() {
+        let err = reader()
+            .feed(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\nzz\r\n")
+            .unwrap_err();
+        assert!(matches!(err, Error::Parse(_)), "{err:?}");
+    }
+
+    #[test]
+    fn chunk_longer_than_its_size() {
+        let err = reader()
+            .feed(b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n2\r\nokay\r\n")
+            .unwrap_err();
+        assert!(matches!(err, Error::Parse(_)), "{err:?}");
+    }
+
+    #[test]
+    fn no_body_for_head_requests() {
+        let mut reader = reader();
+        reader.reset(true);
+        let response = read(
+            &mut reader,
+            &[b"HTTP/1.1 200 OK\r\nContent-Length: 100\r\n\r\n"],
+        );
+        assert!(response.body.is_empty());
+        assert_eq!(response.content_length(), Some(100));
+    }
+
+    #[test]
+    fn no_body_for_204_and_304() {
+        for status in ["204 No Content", "304 Not Modified"] {
+            let head = format!("HTTP/1.1 {status}\r\nContent-Length: 100\r\n\r\n");
+            let response = read(&mut reader(), &[head.as_bytes()]);
+            assert!(response.body.is_empty());
+        }
+    }
+
+    #[test]
+    fn interim_responses_are_skipped() {
+        let response = read(
+            &mut reader(),
+            &[b"HTTP/1.1 100 Continue\r\n\r\nHTTP/1.1 201 Created\r\nContent-Length: 2\r\n\r\nok"],
+        );
+        assert_eq!(response.status, 201);
+        assert_eq!(response.body, b"ok");
+    }
+
+    #[test]
+    fn until_close() {
+        let mut reader = reader();
+        reader.feed(b"HTTP/1.0 200 OK\r\n\r\nhello").unwrap();
+        reader.feed(b" world").unwrap();
+        assert!(!reader.is_complete());
+        assert!(!reader.is_truncated());
+        assert_eq!(reader.finish().unwrap().body, b"hello world");
+    }
+
+    #[test]
+    fn truncated_content_length() {
+        let mut reader = reader();
+        reader
+            .feed(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhe")
+            .unwrap();
+        assert!(reader.is_truncated());
+        assert_eq!(reader.finish().unwrap().body, b"he");
+    }
+
+    #[test]
+    fn body_size_limit() {
+        let mut reader = ResponseReader::new(
+            Limits {
+                max_body_size: Some(4),
+                ..Limits::default()
+            },
+            0,
+            0,
+            false,
+        );
+        let err = reader
+            .feed(b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\n")
+            .unwrap_err();
+        assert!(matches!(err, Error::LimitExceeded(LimitKind::BodySize)));
+        assert!(reader.is_body_too_large());
+    }
+
+    #[test]
+    fn chunk_framing_limits() {
+        let limits = Limits {
+            max_head_size: 64,
+            max_header_size: 32,
+            ..Limits::default()
+        };
+        let head: &[u8] = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n";
+        let cases: [(&[u8], LimitKind); 3] = [
+            // a size line that never ends
+            (b"1;aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa", LimitKind::HeaderSize),
+            (b"1;aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa\r\nx\r\n", LimitKind::HeaderSize),
+            (
+                b"0\r\nA: 1234567890\r\nB: 1234567890\r\nC: 1234567890\r\nD: 1234567890\r\nE: 1234567890\r\n",
+                LimitKind::HeadSize,
+            ),
+        ];
+        for (body, kind) in cases {
+            let mut reader = ResponseReader::new(limits, 0, 0, false);
+            reader.feed(head).unwrap();
+            let err = reader.feed(body).unwrap_err();
+            assert!(
+                matches!(err, Error::LimitExceeded(found) if found == kind),
+                "{err:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn chunk_sizes_are_only_hex_digits() {
+        let head: &[u8] = b"HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n";
+        for size in ["+a", "-1", " a", "0x1", "", ";ext", "a b"] {
+            let mut reader = ResponseReader::new(Limits::default(), 0, 0, false);
+            reader.feed(head).unwrap();
+            let err = reader
+                .feed(format!("{size}\r\n0123456789\r\n0\r\n\r\n").as_bytes())
+                .unwrap_err();
+            assert!(matches!(err, Error::Parse(_)), "{size:?}: {err:?}");
+        }
+
+        let mut reader = ResponseReader::new(Limits::default(), 0, 0, false);
+        reader.feed(head).unwrap();
+        reader
+            .feed(b"A \t;name=value\r\n0123456789\r\n0\r\n\r\n")
+            .unwrap();
+        assert!(reader.is_complete());
+        assert_eq!(reader.finish().unwrap().body, b"0123456789");
+    }
+}
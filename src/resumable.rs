@@ -0,0 +1,685 @@
+//! Resumable uploads: ask the server how much it already has, then send the
+//! rest in chunks starting at that offset. A `Flavor` builds and reads the
+//! requests of one protocol (`Tus`, `Gcs`, and `S3Multipart` with the `s3`
+//! feature), `upload` drives one with the blocking driver and resumes from
+//! the server's offset whenever a chunk doesn't make it.
+
+#[cfg(feature = "s3")]
+use crate::s3::{self, Credentials, MultipartUpload};
+#[cfg(feature = "blocking")]
+use crate::{BlockingConnection, ClientOptions, RetryPolicy, Scheme};
+use crate::{Error, Method, Request, Response, Result};
+#[cfg(feature = "blocking")]
+use std::io::{Read, Seek, SeekFrom};
+#[cfg(feature = "s3")]
+use std::time::SystemTime;
+
+/// The requests of one resumable upload protocol. Chunk requests stream
+/// their body (see `Request::stream_body`), `upload` feeds it.
+pub trait Flavor {
+    /// Bytes sent per chunk request, the last one may be shorter.
+    fn chunk_size(&self) -> u64;
+
+    /// Asks the server how many bytes of the upload it has stored.
+    fn offset_request(&self, total: u64) -> Request;
+
+    /// Extracts the offset to resume from out of the response to
+    /// `offset_request`, it equals `total` once the upload is complete.
+    fn parse_offset(&mut self, response: Response, total: u64) -> Result<u64>;
+
+    /// Sends the `len` bytes starting at `offset` of an upload of `total`.
+    fn chunk_request(&self, offset: u64, len: u64, total: u64) -> Request;
+
+    /// The offset after the chunk at `offset` was answered with `response`.
+    fn chunk_sent(
+        &mut self,
+        _offset: u64,
+        _len: u64,
+        response: Response,
+        total: u64,
+    ) -> Result<u64> {
+        self.parse_offset(response, total)
+    }
+
+    /// Sent once the server has every byte, e.g. to assemble S3 parts.
+    fn complete_request(&self) -> Option<Request> {
+        None
+    }
+
+    /// Checks the response to `complete_request`.
+    fn check_complete(&mut self, response: Response) -> Result<()> {
+        response.error_for_status().map(drop)
+    }
+
+    /// Called on every request right before it's sent, e.g. to sign it.
+    fn prepare(&self, _request: &mut Request) {}
+}
+
+/// tus 1.0 (`HEAD` + `Upload-Offset`, chunks sent with `PATCH`) to an
+/// upload created beforehand with its `Upload-Length`.
+#[derive(Debug, Clone)]
+pub struct Tus {
+    pub path: String,
+    pub chunk_size: u64,
+}
+
+impl Tus {
+    pub fn new(path: impl Into<String>, chunk_size: u64) -> Self {
+        Self {
+            path: path.into(),
+            chunk_size,
+        }
+    }
+}
+
+impl Flavor for Tus {
+    fn chunk_size(&self) -> u64 {
+        self.chunk_size
+    }
+
+    fn offset_request(&self, _total: u64) -> Request {
+        let mut request = Request::head(self.path.clone());
+        request.add_header("Tus-Resumable", TUS_VERSION);
+        request
+    }
+
+    fn parse_offset(&mut self, response: Response, _total: u64) -> Result<u64> {
+        if !response.is_success() {
+            return Err(Error::Status(Box::new(response)));
+        }
+        let offset = response
+            .headers
+            .get("Upload-Offset")
+            .ok_or_else(|| Error::Protocol("missing Upload-Offset".to_string()))?;
+        offset
+            .trim()
+            .parse()
+            .map_err(|_| Error::Parse(format!("invalid Upload-Offset: {offset}")))
+    }
+
+    fn chunk_request(&self, offset: u64, len: u64, _total: u64) -> Request {
+        let mut request = Request::new(Method::Patch, self.path.clone());
+        request.add_header("Tus-Resumable", TUS_VERSION);
+        request.add_header("Upload-Offset", offset.to_string());
+        request.add_header("Content-Type", "application/offset+octet-stream");
+        request.stream_body(Some(len));
+        request
+    }
+}
+
+/// `PUT` with `Content-Range` to a Google Cloud Storage resumable session
+/// URI. Unfinished uploads answer with 308 and a `Range` header, every
+/// chunk but the last has to be a multiple of 256 KiB.
+#[derive(Debug, Clone)]
+pub struct Gcs {
+    pub path: String,
+    pub chunk_size: u64,
+}
+
+impl Gcs {
+    pub fn new(path: impl Into<String>, chunk_size: u64) -> Self {
+        Self {
+            path: path.into(),
+            chunk_size,
+        }
+    }
+}
+
+impl Flavor for Gcs {
+    fn chunk_size(&self) -> u64 {
+        self.chunk_size
+    }
+
+    fn offset_request(&self, total: u64) -> Request {
+        let mut request = Request::put(self.path.clone(), vec![]);
+        request.add_header("Content-Range", format!("bytes */{total}"));
+        request
+    }
+
+    fn parse_offset(&mut self, response: Response, total: u64) -> Result<u64> {
+        match response.status {
+            200 | 201 => Ok(total),
+            308 => {
+                let Some(range) = response.headers.get("Range") else {
+                    return Ok(0);
+                };
+                range
+                    .trim()
+                    .strip_prefix("bytes=0-")
+                    .and_then(|last| last.parse::<u64>().ok())
+                    .map(|last| last + 1)
+                    .ok_or_else(|| Error::Parse(format!("invalid Range: {range}")))
+            }
+            _ => Err(Error::Status(Box::new(response))),
+        }
+    }
+
+    fn chunk_request(&self, offset: u64, len: u64, total: u64) -> Request {
+        let mut request = Request::new(Method::Put, self.path.clone());
+        if len > 0 {
+            let last = offset + len - 1;
+            request.add_header("Content-Range", format!("bytes {offset}-{last}/{total}"));
+        }
+        request.stream_body(Some(len));
+        request
+    }
+}
+
+/// The parts of an S3 multipart upload started with
+/// `MultipartUpload::create_request`, each `part_size` bytes (at least
+/// 5 MiB). The offset is what `ListParts` reports in consecutive parts
+/// from the first, completing assembles them. Requests are signed for
+/// `host`, the `Host` they're sent to.
+#[cfg(feature = "s3")]
+#[derive(Debug, Clone)]
+pub struct S3Multipart {
+    pub upload: MultipartUpload,
+    pub part_size: u64,
+    pub credentials: Credentials,
+    pub host: String,
+}
+
+#[cfg(feature = "s3")]
+impl S3Multipart {
+    pub fn new(
+        upload: MultipartUpload,
+        part_size: u64,
+        credentials: Credentials,
+        host: impl Into<String>,
+    ) -> Self {
+        Self {
+            upload,
+            part_size,
+            credentials,
+            host: host.into(),
+        }
+    }
+
+    fn part_number(&self, offset: u64) -> u32 {
+        (offset / self.part_size.max(1) + 1) as u32
+    }
+}
+
+#[cfg(feature = "s3")]
+impl Flavor for S3Multipart {
+    fn chunk_size(&self) -> u64 {
+        self.part_size
+    }
+
+    fn offset_request(&self, _total: u64) -> Request {
+        self.upload.list_parts_request()
+    }
+
+    fn parse_offset(&mut self, response: Response, total: u64) -> Result<u64> {
+        let mut offset = 0;
+        for (number, size) in self.upload.record_listed_parts(response)? {
+            // a gap, or a short part that isn't the last one
+            if number != self.part_number(offset)
+                || (size != self.part_size && offset + size != total)
+            {
+                break;
+            }
+            offset += size;
+        }
+        Ok(offset)
+    }
+
+    fn chunk_request(&self, offset: u64, len: u64, _total: u64) -> Request {
+        let mut request = self.upload.part_request(self.part_number(offset), vec![]);
+        request.stream_body(Some(len));
+        request
+    }
+
+    fn chunk_sent(
+        &mut self,
+        offset: u64,
+        len: u64,
+        response: Response,
+        _total: u64,
+    ) -> Result<u64> {
+        self.upload
+            .record_part(self.part_number(offset), response)?;
+        Ok(offset + len)
+    }
+
+    fn complete_request(&self) -> Option<Request> {
+        Some(self.upload.complete_request())
+    }
+
+    fn check_complete(&mut self, response: Response) -> Result<()> {
+        s3::check_complete(response).map(drop)
+    }
+
+    fn prepare(&self, request: &mut Request) {
+        self.credentials
+            .sign(request, &self.host, SystemTime::now());
+    }
+}
+
+/// Uploads the `total` bytes of `body` with `flavor`: asks for the offset,
+/// seeks `body` to it and sends the rest chunk by chunk. A failed attempt
+/// starts over from the offset query after the wait `options.retry` asks
+/// for, attempts are counted anew whenever the server got further. Once
+/// every byte is stored the flavor's `complete_request` is sent, if any.
+#[cfg(feature = "blocking")]
+pub fn upload(
+    flavor: &mut impl Flavor,
+    scheme: Scheme,
+    hostname: &str,
+    port: u16,
+    body: &mut (impl Read + Seek),
+    total: u64,
+    options: &ClientOptions,
+) -> Result<()> {
+    // repeated here as a whole, not request by request
+    let once = ClientOptions {
+        retry: RetryPolicy::attempts(1),
+        ..options.clone()
+    };
+    // what's repeated starts with the offset query, which is safe to repeat
+    let method = flavor.offset_request(total).method().clone();
+
+    let mut attempt = 1;
+    let mut stored = 0;
+    loop {
+        let before = stored;
+        let Err(err) = send_rest(
+            flavor,
+            scheme,
+            hostname,
+            port,
+            body,
+            total,
+            &once,
+            &mut stored,
+        ) else {
+            break;
+        };
+        if stored > before {
+            attempt = 1;
+        }
+        let outcome = Err(err);
+        match options.retry.delay(attempt, &method, &outcome) {
+            Some(delay) => std::thread::sleep(delay),
+            None => return outcome.map(drop),
+        }
+        attempt += 1;
+    }
+
+    if let Some(mut request) = flavor.complete_request() {
+        flavor.prepare(&mut request);
+        let response = BlockingConnection::send(scheme, hostname, port, request, options)?;
+        flavor.check_complete(response)?;
+    }
+    Ok(())
+}
+
+/// One attempt: the offset query and then chunks until the server has
+/// everything, `stored` follows what it confirmed.
+#[cfg(feature = "blocking")]
+#[allow(clippy::too_many_arguments)]
+fn send_rest(
+    flavor: &mut impl Flavor,
+    scheme: Scheme,
+    hostname: &str,
+    port: u16,
+    body: &mut (impl Read + Seek),
+    total: u64,
+    options: &ClientOptions,
+    stored: &mut u64,
+) -> Result<()> {
+    let mut request = flavor.offset_request(total);
+    flavor.prepare(&mut request);
+    let response = BlockingConnection::send(scheme, hostname, port, request, options)?;
+    let mut offset = flavor.parse_offset(response, total)?;
+    *stored = offset;
+
+    while offset < total {
+        let len = flavor.chunk_size().clamp(1, total - offset);
+        body.seek(SeekFrom::Start(offset))?;
+        let mut request = flavor.chunk_request(offset, len, total);
+        flavor.prepare(&mut request);
+        let response = BlockingConnection::upload(
+            scheme,
+            hostname,
+            port,
+            request,
+            &mut body.by_ref().take(len),
+            options,
+        )?;
+
+        let next = flavor.chunk_sent(offset, len, response, total)?;
+        if next <= offset {
+            return Err(Error::Protocol(format!(
+                "the server stored nothing of the chunk at {offset}"
+            )));
+        }
+        offset = next.min(total);
+        *stored = offset;
+    }
+    Ok(())
+}
+
+const TUS_VERSION: &str = "1.0.0";
+
+#[cfg(all(test, feature = "blocking"))]
+mod tests {
+    use super::*;
+    use crate::testing::read_request;
+    use std::{
+        io::{Cursor, Write},
+        net::{TcpListener, TcpStream},
+        sync::{Arc, Mutex},
+        thread,
+        time::Duration,
+    };
+
+    const BODY: &[u8] = b"abcdefghijklmnopqrstuvwxyz";
+
+    /// Hands every connection's request head to `handle`, one connection
+    /// at a time.
+    fn serve(mut handle: impl FnMut(&str, &mut TcpStream) + Send + 'static) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            for conn in listener.incoming() {
+                let mut conn = conn.unwrap();
+                let head = read_request(&mut conn);
+                handle(std::str::from_utf8(&head).unwrap(), &mut conn);
+            }
+        });
+        port
+    }
+
+    fn header<'a>(head: &'a str, name: &str) -> &'a str {
+        head.lines()
+            .find_map(|line| {
+                let (key, value) = line.split_once(':')?;
+                key.eq_ignore_ascii_case(name).then(|| value.trim())
+            })
+            .unwrap()
+    }
+
+    /// Reads the body of a request, or drops the connection after `drop_after` bytes of it.
+    fn read_body(head: &str, conn: &mut TcpStream, drop_after: Option<usize>) -> Vec<u8> {
+        let len: usize = header(head, "Content-Length").parse().unwrap();
+        let mut body = vec![0; drop_after.unwrap_or(len).min(len)];
+        conn.read_exact(&mut body).unwrap();
+        body
+    }
+
+    fn options(max_attempts: u32) -> ClientOptions {
+        ClientOptions {
+            retry: RetryPolicy {
+                max_attempts,
+                initial_backoff: Duration::from_millis(1),
+                ..RetryPolicy::default()
+            },
+            ..ClientOptions::default()
+        }
+    }
+
+    #[test]
+    fn tus_resumes_after_a_dropped_connection() {
+        let stored = Arc::new(Mutex::new(vec![]));
+        let mut dropped = false;
+        let port = serve({
+            let stored = Arc::clone(&stored);
+            move |head, conn| {
+                let mut stored = stored.lock().unwrap();
+                if head.starts_with("HEAD /files/1 ") {
+                    write!(
+                        conn,
+                        "HTTP/1.1 200 OK\r\nUpload-Offset: {}\r\nContent-Length: 0\r\n\r\n",
+                        stored.len()
+                    )
+                    .unwrap();
+                    return;
+                }
+                assert!(head.starts_with("PATCH /files/1 "));
+                assert_eq!(header(head, "Upload-Offset"), stored.len().to_string());
+                // the first chunk only gets halfway
+                let drop_after = (!dropped).then_some(10);
+                dropped = true;
+                stored.extend(read_body(head, conn, drop_after));
+                if drop_after.is_none() {
+                    write!(
+                        conn,
+                        "HTTP/1.1 204 No Content\r\nUpload-Offset: {}\r\n\r\n",
+                        stored.len()
+                    )
+                    .unwrap();
+                }
+            }
+        });
+
+        let mut flavor = Tus::new("/files/1", 16);
+        let mut body = Cursor::new(BODY);
+        upload(
+            &mut flavor,
+            Scheme::Http,
+            "127.0.0.1",
+            port,
+            &mut body,
+            BODY.len() as u64,
+            &options(2),
+        )
+        .unwrap();
+        assert_eq!(*stored.lock().unwrap(), BODY);
+    }
+
+    #[test]
+    fn gcs_resumes_from_the_reported_range() {
+        let stored = Arc::new(Mutex::new(vec![]));
+        let mut dropped = false;
+        let port = serve({
+            let stored = Arc::clone(&stored);
+            move |head, conn| {
+                let mut stored = stored.lock().unwrap();
+                assert!(head.starts_with("PUT /session "));
+                let range = header(head, "Content-Range");
+                if range == format!("bytes */{}", BODY.len()) {
+                    let status = match stored.len() {
+                        0 => "308 Resume Incomplete\r\n".to_string(),
+                        len if len == BODY.len() => "200 OK\r\n".to_string(),
+                        len => format!("308 Resume Incomplete\r\nRange: bytes=0-{}\r\n", len - 1),
+                    };
+                    write!(conn, "HTTP/1.1 {status}Content-Length: 0\r\n\r\n").unwrap();
+                    return;
+                }
+                let first = stored.len();
+                assert!(range.starts_with(&format!("bytes {first}-")));
+                let drop_after = (!dropped && first > 0).then_some(3);
+                dropped |= drop_after.is_some();
+                stored.extend(read_body(head, conn, drop_after));
+                if drop_after.is_none() {
+                    let status = match stored.len() {
+                        len if len == BODY.len() => "200 OK\r\n".to_string(),
+                        len => format!("308 Resume Incomplete\r\nRange: bytes=0-{}\r\n", len - 1),
+                    };
+                    write!(conn, "HTTP/1.1 {status}Content-Length: 0\r\n\r\n").unwrap();
+                }
+            }
+        });
+
+        let mut flavor = Gcs::new("/session", 8);
+        let mut body = Cursor::new(BODY);
+        upload(
+            &mut flavor,
+            Scheme::Http,
+            "127.0.0.1",
+            port,
+            &mut body,
+            BODY.len() as u64,
+            &options(2),
+        )
+        .unwrap();
+        assert_eq!(*stored.lock().unwrap(), BODY);
+    }
+
+    #[test]
+    fn gives_up_when_attempts_make_no_progress() {
+        let queries = Arc::new(Mutex::new(0));
+        let port = serve({
+            let queries = Arc::clone(&queries);
+            move |head, conn| {
+                if head.starts_with("HEAD ") {
+                    *queries.lock().unwrap() += 1;
+                    write!(
+                        conn,
+                        "HTTP/1.1 200 OK\r\nUpload-Offset: 0\r\nContent-Length: 0\r\n\r\n"
+                    )
+                    .unwrap();
+                } else {
+                    read_body(head, conn, Some(1));
+                }
+            }
+        });
+
+        let mut flavor = Tus::new("/files/1", 16);
+        let mut body = Cursor::new(BODY);
+        let uploaded = upload(
+            &mut flavor,
+            Scheme::Http,
+            "127.0.0.1",
+            port,
+            &mut body,
+            BODY.len() as u64,
+            &options(3),
+        );
+        assert!(matches!(uploaded, Err(Error::Io(_) | Error::Protocol(_))));
+        assert_eq!(*queries.lock().unwrap(), 3);
+    }
+
+    #[cfg(feature = "s3")]
+    fn ok(body: &str) -> Response {
+        let head = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n", body.len());
+        let mut response = Response::parse(head.as_bytes(), false).unwrap();
+        response.body = body.as_bytes().to_vec();
+        response
+    }
+
+    #[cfg(feature = "s3")]
+    fn s3_flavor() -> S3Multipart {
+        let created = ok("<UploadId>up</UploadId>");
+        let credentials = Credentials {
+            access_key_id: "id".to_string(),
+            secret_access_key: "secret".to_string(),
+            session_token: None,
+            region: "us-east-1".to_string(),
+        };
+        S3Multipart::new(
+            MultipartUpload::from_create_response("key", created).unwrap(),
+            10,
+            credentials,
+            "127.0.0.1",
+        )
+    }
+
+    #[cfg(feature = "s3")]
+    #[test]
+    fn s3_offset_counts_consecutive_parts() {
+        let listed = |parts: &str| ok(&format!("<ListPartsResult>{parts}</ListPartsResult>"));
+        let part = |number: u32, size: u64| {
+            format!(
+                "<Part><PartNumber>{number}</PartNumber><ETag>e{number}</ETag><Size>{size}</Size></Part>"
+            )
+        };
+
+        let mut flavor = s3_flavor();
+        assert_eq!(flavor.parse_offset(listed(""), 26).unwrap(), 0);
+        let parts = part(2, 10) + &part(1, 10);
+        assert_eq!(flavor.parse_offset(listed(&parts), 26).unwrap(), 20);
+        // part 2 is missing
+        let parts = part(1, 10) + &part(3, 6);
+        assert_eq!(flavor.parse_offset(listed(&parts), 26).unwrap(), 10);
+        // a short part can only be the last one
+        let parts = part(1, 4) + &part(2, 10);
+        assert_eq!(flavor.parse_offset(listed(&parts), 26).unwrap(), 0);
+        let parts = part(1, 10) + &part(2, 10) + &part(3, 6);
+        assert_eq!(flavor.parse_offset(listed(&parts), 26).unwrap(), 26);
+    }
+
+    #[cfg(feature = "s3")]
+    #[test]
+    fn s3_resumes_after_the_listed_parts() {
+        let parts = Arc::new(Mutex::new(vec![(1, b"abcdefghij".to_vec())]));
+        let completed = Arc::new(Mutex::new(String::new()));
+        let mut dropped = false;
+        let port = serve({
+            let (parts, completed) = (Arc::clone(&parts), Arc::clone(&completed));
+            move |head, conn| {
+                let mut parts = parts.lock().unwrap();
+                assert!(head.contains("uploadId=up"));
+                assert!(header(head, "Authorization").starts_with("AWS4-HMAC-SHA256 "));
+                let body = if head.starts_with("GET /key?") {
+                    let mut body = String::from("<ListPartsResult>");
+                    for (number, data) in parts.iter() {
+                        body.push_str(&format!(
+                            "<Part><PartNumber>{number}</PartNumber><ETag>e{number}</ETag><Size>{}</Size></Part>",
+                            data.len()
+                        ));
+                    }
+                    body + "</ListPartsResult>"
+                } else if head.starts_with("PUT /key?") {
+                    let number = head.split("partNumber=").nth(1).unwrap()[..1]
+                        .parse()
+                        .unwrap();
+                    let drop_after = (!dropped && number == 2).then_some(5);
+                    dropped |= drop_after.is_some();
+                    let data = read_body(head, conn, drop_after);
+                    if drop_after.is_some() {
+                        return;
+                    }
+                    parts.push((number, data));
+                    write!(
+                        conn,
+                        "HTTP/1.1 200 OK\r\nETag: e{number}\r\nContent-Length: 0\r\n\r\n"
+                    )
+                    .unwrap();
+                    return;
+                } else {
+                    assert!(head.starts_with("POST /key?"));
+                    let body = read_body(head, conn, None);
+                    *completed.lock().unwrap() = String::from_utf8(body).unwrap();
+                    "<CompleteMultipartUploadResult/>".to_string()
+                };
+                write!(
+                    conn,
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{body}",
+                    body.len()
+                )
+                .unwrap();
+            }
+        });
+
+        let mut flavor = s3_flavor();
+        flavor.host = format!("127.0.0.1:{port}");
+        let mut body = Cursor::new(BODY);
+        upload(
+            &mut flavor,
+            Scheme::Http,
+            "127.0.0.1",
+            port,
+            &mut body,
+            BODY.len() as u64,
+            &options(2),
+        )
+        .unwrap();
+
+        let parts = parts.lock().unwrap();
+        let stored = parts
+            .iter()
+            .flat_map(|(_, data)| data.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(stored, BODY);
+        assert_eq!(
+            *completed.lock().unwrap(),
+            "<CompleteMultipartUpload>\
+             <Part><PartNumber>1</PartNumber><ETag>e1</ETag></Part>\
+             <Part><PartNumber>2</PartNumber><ETag>e2</ETag></Part>\
+             <Part><PartNumber>3</PartNumber><ETag>e3</ETag></Part>\
+             </CompleteMultipartUpload>"
+        );
+    }
+}
@@ -0,0 +1,212 @@
+use crate::{Error, Method, Response, Result, TimeoutKind, headers};
+use std::time::{Duration, SystemTime};
+
+/// When and how soon to repeat a request that failed with a transient
+/// error. Drivers that send requests themselves apply it through
+/// `ClientOptions::retry`, others can ask `delay` after every attempt.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Attempts in total including the first one, 1 disables retries.
+    pub max_attempts: u32,
+    /// Wait before the first retry, doubled for every next one.
+    pub initial_backoff: Duration,
+    /// Cap on the wait; a `Retry-After` asking for longer is not honoured
+    /// and the response is returned instead.
+    pub max_backoff: Duration,
+    /// Responses to retry, `Retry-After` is respected for all of them.
+    pub retry_statuses: Vec<u16>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 1,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+            retry_statuses: vec![429, 502, 503, 504],
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Retries up to `max_attempts` times in total with the default backoff.
+    pub fn attempts(max_attempts: u32) -> Self {
+        Self {
+            max_attempts,
+            ..Self::default()
+        }
+    }
+
+    /// How long to wait before attempt number `attempt + 1`, `None` means
+    /// `outcome` is final. Requests with a non-idempotent method are only
+    /// repeated if they never reached the server (failed DNS or connect).
+    pub fn delay(
+        &self,
+        attempt: u32,
+        method: &Method,
+        outcome: &Result<Response>,
+    ) -> Option<Duration> {
+        if attempt >= self.max_attempts {
+            return None;
+        }
+        let backoff = self.backoff(attempt);
+
+        let response = match outcome {
+            Ok(response) => response,
+            Err(Error::Status(response)) => response,
+            Err(Error::Dns(_) | Error::Timeout(TimeoutKind::Connect)) => return Some(backoff),
+            Err(
+                Error::Io(_)
+                | Error::Protocol(_)
+                | Error::Timeout(TimeoutKind::Read | TimeoutKind::Write),
+            ) if method.is_idempotent() => return Some(backoff),
+            Err(_) => return None,
+        };
+
+        if !self.retry_statuses.contains(&response.status) || !method.is_idempotent() {
+            return None;
+        }
+        match response.headers.get("Retry-After") {
+            Some(value) => retry_after(value).filter(|wait| *wait <= self.max_backoff),
+            None => Some(backoff),
+        }
+    }
+
+    fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 1u32
+            .checked_shl(attempt.saturating_sub(1))
+            .unwrap_or(u32::MAX);
+        self.initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff)
+    }
+}
+
+/// `Retry-After` is either delay-seconds or an HTTP-date.
+fn retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+
+    let at = headers::date::parse(value)?;
+    // a date in the past means "now"
+    Some(at.duration_since(SystemTime::now()).unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 10,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(1),
+            ..RetryPolicy::default()
+        }
+    }
+
+    fn response(status: u16, headers: &str) -> Result<Response> {
+        let head = format!("HTTP/1.1 {status} Status\r\n{headers}\r\n");
+        Ok(Response::parse(head.as_bytes(), false).unwrap())
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let outcome = response(503, "");
+        let delays = (1..=6)
+            .map(|attempt| policy().delay(attempt, &Method::Get, &outcome))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            delays,
+            [100, 200, 400, 800, 1000, 1000].map(|ms| Some(Duration::from_millis(ms)))
+        );
+        // no overflow however many attempts are allowed
+        let unlimited = RetryPolicy {
+            max_attempts: u32::MAX,
+            ..policy()
+        };
+        assert_eq!(
+            unlimited.delay(u32::MAX - 1, &Method::Get, &outcome),
+            Some(Duration::from_secs(1))
+        );
+    }
+
+    #[test]
+    fn stops_after_max_attempts() {
+        let outcome = response(503, "");
+        assert!(policy().delay(9, &Method::Get, &outcome).is_some());
+        assert_eq!(policy().delay(10, &Method::Get, &outcome), None);
+        assert_eq!(
+            RetryPolicy::default().delay(1, &Method::Get, &outcome),
+            None
+        );
+    }
+
+    #[test]
+    fn only_retries_listed_statuses() {
+        for status in [200, 404, 500] {
+            assert_eq!(policy().delay(1, &Method::Get, &response(status, "")), None);
+        }
+        let status = Err(Error::Status(Box::new(response(429, "").unwrap())));
+        assert_eq!(
+            policy().delay(1, &Method::Get, &status),
+            Some(Duration::from_millis(100))
+        );
+    }
+
+    #[test]
+    fn honours_retry_after() {
+        let seconds = response(503, "Retry-After: 1\r\n");
+        assert_eq!(
+            policy().delay(1, &Method::Get, &seconds),
+            Some(Duration::from_secs(1))
+        );
+        // longer than max_backoff: the response is final
+        let too_long = response(503, "Retry-After: 120\r\n");
+        assert_eq!(policy().delay(1, &Method::Get, &too_long), None);
+
+        let past = response(503, "Retry-After: Wed, 21 Oct 2015 07:28:00 GMT\r\n");
+        assert_eq!(policy().delay(1, &Method::Get, &past), Some(Duration::ZERO));
+        let patient = RetryPolicy {
+            max_backoff: Duration::from_secs(10),
+            ..policy()
+        };
+        // HTTP-dates have whole seconds
+        let soon = headers::date::format(SystemTime::now() + Duration::from_secs(5));
+        let date = response(503, &format!("Retry-After: {soon}\r\n"));
+        let wait = patient.delay(1, &Method::Get, &date).unwrap();
+        assert!(
+            wait > Duration::from_secs(3) && wait <= Duration::from_secs(5),
+            "{wait:?}"
+        );
+        let later = headers::date::format(SystemTime::now() + Duration::from_secs(60));
+        let date = response(503, &format!("Retry-After: {later}\r\n"));
+        assert_eq!(policy().delay(1, &Method::Get, &date), None);
+
+        // unparseable: not retried rather than retried right away
+        let garbage = response(503, "Retry-After: soon\r\n");
+        assert_eq!(policy().delay(1, &Method::Get, &garbage), None);
+    }
+
+    #[test]
+    fn non_idempotent_requests_are_only_retried_before_reaching_the_server() {
+        let backoff = Some(Duration::from_millis(100));
+        let io = || Err(Error::Io(std::io::ErrorKind::ConnectionReset.into()));
+
+        assert_eq!(policy().delay(1, &Method::Post, &response(503, "")), None);
+        assert_eq!(policy().delay(1, &Method::Post, &io()), None);
+        let read = Err(Error::Timeout(TimeoutKind::Read));
+        assert_eq!(policy().delay(1, &Method::Post, &read), None);
+        let dns = Err(Error::Dns("no such host".to_string()));
+        assert_eq!(policy().delay(1, &Method::Post, &dns), backoff);
+        let connect = Err(Error::Timeout(TimeoutKind::Connect));
+        assert_eq!(policy().delay(1, &Method::Post, &connect), backoff);
+
+        assert_eq!(policy().delay(1, &Method::Put, &io()), backoff);
+        assert_eq!(policy().delay(1, &Method::Get, &read), backoff);
+        let invalid = Err(Error::InvalidRequest("bad".to_string()));
+        assert_eq!(policy().delay(1, &Method::Get, &invalid), None);
+    }
+}
@@ -0,0 +1,94 @@
+use crate::{Error, Result, client_config::ROOT_CERT_STORE};
+use rustls::{
+    RootCertStore,
+    pki_types::{CertificateDer, pem::PemObject},
+};
+use std::{
+    hash::{Hash, Hasher},
+    path::Path,
+    sync::Arc,
+};
+
+/// The trust anchors server chains are verified against, see
+/// `ClientOptions::roots`. Starts with the built-in Mozilla roots,
+/// e.g. to add an internal CA, or empty to trust only the added ones.
+#[derive(Debug, Clone)]
+pub struct RootCerts {
+    /// Shared by clones until one of them adds a root.
+    store: Arc<RootCertStore>,
+}
+
+impl Default for RootCerts {
+    fn default() -> Self {
+        Self {
+            store: Arc::clone(&*ROOT_CERT_STORE),
+        }
+    }
+}
+
+impl RootCerts {
+    /// The built-in roots.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn empty() -> Self {
+        Self {
+            store: Arc::new(RootCertStore::empty()),
+        }
+    }
+
+    /// Fails if the certificate can't be used as a trust anchor.
+    pub fn add(&mut self, cert: CertificateDer<'static>) -> Result<()> {
+        Arc::make_mut(&mut self.store)
+            .add(cert)
+            .map_err(|err| Error::InvalidRequest(format!("invalid root certificate: {err}")))
+    }
+
+    /// Every certificate of a PEM bundle, fails if there's none.
+    pub fn add_pem(&mut self, pem: &[u8]) -> Result<()> {
+        let certs = CertificateDer::pem_slice_iter(pem)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|err| Error::InvalidRequest(format!("invalid root certificate: {err}")))?;
+        if certs.is_empty() {
+            return Err(Error::InvalidRequest(
+                "no root certificate in the PEM".to_string(),
+            ));
+        }
+        for cert in certs {
+            self.add(cert)?;
+        }
+        Ok(())
+    }
+
+    pub fn add_pem_file(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        self.add_pem(&std::fs::read(path)?)
+    }
+
+    pub fn len(&self) -> usize {
+        self.store.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.store.is_empty()
+    }
+
+    pub(crate) fn store(&self) -> &Arc<RootCertStore> {
+        &self.store
+    }
+}
+
+/// Sets of roots are the same if they're clones of each other.
+impl PartialEq for RootCerts {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.store, &other.store)
+    }
+}
+
+impl Eq for RootCerts {}
+
+impl Hash for RootCerts {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Arc::as_ptr(&self.store).hash(state);
+    }
+}
@@ -0,0 +1,38 @@
+use crate::{Error, Result};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Scheme {
+    Http,
+    Https,
+}
+
+impl Scheme {
+    pub fn default_port(self) -> u16 {
+        match self {
+            Self::Http => 80,
+            Self::Https => 443,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Http => "http",
+            Self::Https => "https",
+        }
+    }
+}
+
+impl FromStr for Scheme {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s.eq_ignore_ascii_case("http") {
+            Ok(Self::Http)
+        } else if s.eq_ignore_ascii_case("https") {
+            Ok(Self::Https)
+        } else {
+            Err(Error::InvalidUrl(format!("unsupported scheme: {s}")))
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use libc::{
+    AF_INET, AF_INET6, in_addr, in6_addr, sa_family_t, sockaddr, sockaddr_in, sockaddr_in6,
+};
+use std::{mem::MaybeUninit, net::SocketAddr};
+
+/// Socket address in the form `connect(2)` takes, for io_uring
+/// it has to stay put while the connect SQE is in flight.
+pub(crate) enum SockAddr {
+    V4(sockaddr_in),
+    V6(sockaddr_in6),
+}
+
+impl SockAddr {
+    pub(crate) fn new(addr: SocketAddr) -> Self {
+        match addr {
+            SocketAddr::V4(addr) => {
+                let mut raw = unsafe { MaybeUninit::<sockaddr_in>::zeroed().assume_init() };
+                raw.sin_family = AF_INET as sa_family_t;
+                raw.sin_port = addr.port().to_be();
+                raw.sin_addr = in_addr {
+                    s_addr: u32::from(*addr.ip()).to_be(),
+                };
+                Self::V4(raw)
+            }
+            SocketAddr::V6(addr) => {
+                let mut raw = unsafe { MaybeUninit::<sockaddr_in6>::zeroed().assume_init() };
+                raw.sin6_family = AF_INET6 as sa_family_t;
+                raw.sin6_port = addr.port().to_be();
+                raw.sin6_flowinfo = addr.flowinfo();
+                raw.sin6_addr = in6_addr {
+                    s6_addr: addr.ip().octets(),
+                };
+                raw.sin6_scope_id = addr.scope_id();
+                Self::V6(raw)
+            }
+        }
+    }
+
+    pub(crate) fn domain(&self) -> i32 {
+        match self {
+            Self::V4(_) => AF_INET,
+            Self::V6(_) => AF_INET6,
+        }
+    }
+
+    pub(crate) fn as_ptr(&self) -> *const sockaddr {
+        match self {
+            Self::V4(addr) => (addr as *const sockaddr_in).cast(),
+            Self::V6(addr) => (addr as *const sockaddr_in6).cast(),
+        }
+    }
+
+    pub(crate) fn len(&self) -> u32 {
+        match self {
+            Self::V4(_) => std::mem::size_of::<sockaddr_in>() as u32,
+            Self::V6(_) => std::mem::size_of::<sockaddr_in6>() as u32,
+        }
+    }
+}
@@ -0,0 +1,100 @@
+#[cfg(feature = "poll")]
+use crate::sock_addr::SockAddr;
+use libc::{F_GETFL, F_SETFL, MSG_NOSIGNAL, O_NONBLOCK};
+use std::{
+    io::{self, Read, Write},
+    os::fd::{AsRawFd, OwnedFd},
+};
+#[cfg(feature = "poll")]
+use std::{net::SocketAddr, os::fd::FromRawFd};
+
+/// A connected stream socket of any family,
+/// `send` is used instead of `write` to get EPIPE rather than SIGPIPE.
+pub(crate) struct Socket(pub(crate) OwnedFd);
+
+impl Socket {
+    pub(crate) fn set_nonblocking(&self) -> io::Result<()> {
+        let fd = self.0.as_raw_fd();
+        let flags = unsafe { libc::fcntl(fd, F_GETFL) };
+        if flags < 0 || unsafe { libc::fcntl(fd, F_SETFL, flags | O_NONBLOCK) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// A non-blocking socket with a connect to `addr` in progress,
+    /// it's done when the socket becomes writable, see `take_error`.
+    #[cfg(feature = "poll")]
+    pub(crate) fn connect_nonblocking(addr: SocketAddr) -> io::Result<Self> {
+        let addr = SockAddr::new(addr);
+        let fd = unsafe { libc::socket(addr.domain(), libc::SOCK_STREAM, 0) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let sock = Self(unsafe { OwnedFd::from_raw_fd(fd) });
+        if unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        sock.set_nonblocking()?;
+
+        if unsafe { libc::connect(fd, addr.as_ptr(), addr.len()) } < 0 {
+            let err = io::Error::last_os_error();
+            if err.raw_os_error() != Some(libc::EINPROGRESS) {
+                return Err(err);
+            }
+        }
+        Ok(sock)
+    }
+
+    /// The pending error of the socket (`SO_ERROR`),
+    /// for a finished non-blocking connect it's its outcome.
+    #[cfg(feature = "poll")]
+    pub(crate) fn take_error(&self) -> io::Result<Option<io::Error>> {
+        let mut errno: libc::c_int = 0;
+        let mut len = std::mem::size_of::<libc::c_int>() as libc::socklen_t;
+        let res = unsafe {
+            libc::getsockopt(
+                self.0.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_ERROR,
+                (&mut errno as *mut libc::c_int).cast(),
+                &mut len,
+            )
+        };
+        if res < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok((errno != 0).then(|| io::Error::from_raw_os_error(errno)))
+    }
+}
+
+impl Read for Socket {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = unsafe { libc::recv(self.0.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len(), 0) };
+        if read < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(read as usize)
+    }
+}
+
+impl Write for Socket {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = unsafe {
+            libc::send(
+                self.0.as_raw_fd(),
+                buf.as_ptr().cast(),
+                buf.len(),
+                MSG_NOSIGNAL,
+            )
+        };
+        if written < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(written as usize)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
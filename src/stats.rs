@@ -0,0 +1,16 @@
+/// What it took to get a response, for tuning buffer sizes and drivers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Stats {
+    /// Calls to `FSM::wants`.
+    pub wants_calls: u64,
+    /// Completed reads, i.e. calls to `FSM::done_reading`.
+    pub reads: u64,
+    /// Completed writes, i.e. calls to `FSM::done_writing`.
+    pub writes: u64,
+    pub bytes_read: u64,
+    pub bytes_written: u64,
+    /// Length the incoming TLS buffer had grown to by the end.
+    pub incoming_buffer: usize,
+    /// Length the outgoing TLS buffer had grown to by the end.
+    pub outgoing_buffer: usize,
+}
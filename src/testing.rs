@@ -0,0 +1,297 @@
+//! In-memory servers that unit tests drive an `FSM` against.
+
+use crate::{ClientOptions, FSM, Response, Result, RootCerts, Wants};
+use rustls::{
+    ServerConfig, ServerConnection,
+    pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+};
+use std::{
+    collections::VecDeque,
+    io::{self, Read, Write},
+    net::TcpStream,
+    sync::Arc,
+};
+
+const CA: &[u8] = include_bytes!("../tests/data/ca.pem");
+const CERT: &[u8] = include_bytes!("../tests/data/localhost.pem");
+const KEY: &[u8] = include_bytes!("../tests/data/localhost.key");
+/// A self-signed CA that has nothing to do with the test CA.
+pub(crate) const UNRELATED_CA: &[u8] = include_bytes!("../tests/data/other.pem");
+
+/// What the FSM's `Wants::Read` and `Wants::Write` are served with.
+pub(crate) trait Peer {
+    /// 0 is EOF.
+    fn read(&mut self, buf: &mut [u8]) -> usize;
+
+    /// An error is a rejected write, e.g. the peer closed the connection.
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize>;
+}
+
+/// Runs the exchange to its response, aborting the write on rejection.
+/// `Wants::Body` is filled with `body` until it runs out.
+pub(crate) fn drive<B: crate::Buffer>(
+    fsm: &mut FSM<B>,
+    peer: &mut impl Peer,
+    body: &mut &[u8],
+) -> Result<Response> {
+    loop {
+        match fsm.wants()? {
+            Wants::Read(buf) => {
+                let read = peer.read(buf);
+                fsm.done_reading(read);
+            }
+            Wants::Write(bytes) => match peer.write(bytes) {
+                Ok(written) => fsm.done_writing(written),
+                Err(err) => {
+                    if !fsm.abort_write() {
+                        return Err(err.into());
+                    }
+                }
+            },
+            Wants::Body(buf) => {
+                let len = buf.len().min(body.len());
+                buf[..len].copy_from_slice(&body[..len]);
+                *body = &body[len..];
+                fsm.done_body(len)?;
+            }
+            Wants::SinkFull => panic!("the FSM waits for room in its body sink"),
+            Wants::Done(response) => return Ok(response),
+        }
+    }
+}
+
+/// Answers every request head it receives with the next response of the
+/// script, one piece per read. Reads after the script ran out are EOF.
+pub(crate) struct Script {
+    responses: VecDeque<VecDeque<Vec<u8>>>,
+    received: Vec<u8>,
+    answered: usize,
+}
+
+impl Script {
+    /// Each response is sent in the given pieces.
+    pub(crate) fn new(responses: &[&[&[u8]]]) -> Self {
+        Self {
+            responses: responses
+                .iter()
+                .map(|pieces| pieces.iter().map(|piece| piece.to_vec()).collect())
+                .collect(),
+            received: vec![],
+            answered: 0,
+        }
+    }
+
+    pub(crate) fn received(&self) -> &[u8] {
+        &self.received
+    }
+
+    fn receive(&mut self, bytes: &[u8]) {
+        self.received.extend_from_slice(bytes);
+    }
+
+    /// The next piece once the head of the request it answers is in.
+    fn next_piece(&mut self) -> Option<Vec<u8>> {
+        let heads = self
+            .received
+            .windows(4)
+            .filter(|w| w == b"\r\n\r\n")
+            .count();
+        if heads <= self.answered {
+            return None;
+        }
+        let response = self.responses.front_mut()?;
+        let piece = response.pop_front();
+        if response.is_empty() {
+            self.responses.pop_front();
+            self.answered += 1;
+        }
+        piece
+    }
+}
+
+/// Plain HTTP, `reject_after` bytes of the request are taken before
+/// writes fail as if the server had closed its side.
+pub(crate) struct PlainPeer {
+    pub(crate) script: Script,
+    pub(crate) reject_after: Option<usize>,
+    pending: VecDeque<u8>,
+}
+
+impl PlainPeer {
+    pub(crate) fn new(script: Script) -> Self {
+        Self {
+            script,
+            reject_after: None,
+            pending: VecDeque::new(),
+        }
+    }
+}
+
+impl Peer for PlainPeer {
+    fn read(&mut self, buf: &mut [u8]) -> usize {
+        if self.pending.is_empty() {
+            match self.script.next_piece() {
+                Some(piece) => self.pending.extend(piece),
+                None if self.script.responses.is_empty() => return 0,
+                None => panic!("the FSM reads before the request is complete"),
+            }
+        }
+        self.pending.read(buf).unwrap()
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        let len = match self.reject_after {
+            Some(0) => return Err(io::ErrorKind::BrokenPipe.into()),
+            Some(left) => {
+                let len = left.min(bytes.len());
+                self.reject_after = Some(left - len);
+                len
+            }
+            None => bytes.len(),
+        };
+        self.script.receive(&bytes[..len]);
+        Ok(len)
+    }
+}
+
+/// A rustls server for `localhost` with a certificate from the test CA,
+/// see `TlsPeer::options`.
+pub(crate) struct TlsPeer {
+    pub(crate) script: Script,
+    conn: ServerConnection,
+    /// Send close_notify once the script ran out.
+    pub(crate) close: bool,
+    /// Writes fail once this much of the request was decrypted.
+    pub(crate) reject_after: Option<usize>,
+}
+
+impl TlsPeer {
+    pub(crate) fn new(script: Script) -> Self {
+        Self::appending(script, &[])
+    }
+
+    /// Sends the certificates of `pem` after its own, as a server can
+    /// send any it likes.
+    pub(crate) fn appending(script: Script, pem: &[u8]) -> Self {
+        Self {
+            script,
+            conn: ServerConnection::new(server_config(pem)).unwrap(),
+            close: false,
+            reject_after: None,
+        }
+    }
+
+    /// Trusts the test CA only.
+    pub(crate) fn options() -> ClientOptions {
+        let mut roots = RootCerts::empty();
+        roots.add_pem(CA).unwrap();
+        ClientOptions {
+            roots: Some(roots),
+            ..ClientOptions::default()
+        }
+    }
+}
+
+fn server_config(pem: &[u8]) -> Arc<ServerConfig> {
+    let chain = CertificateDer::pem_slice_iter(CERT)
+        .chain(CertificateDer::pem_slice_iter(pem))
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    let key = PrivateKeyDer::from_pem_slice(KEY).unwrap();
+    let config = ServerConfig::builder()
+        .with_no_client_auth()
+        .with_single_cert(chain, key)
+        .unwrap();
+    Arc::new(config)
+}
+
+impl Peer for TlsPeer {
+    fn read(&mut self, mut buf: &mut [u8]) -> usize {
+        if !self.conn.wants_write() {
+            match self.script.next_piece() {
+                Some(piece) => self.conn.writer().write_all(&piece).unwrap(),
+                None if self.script.responses.is_empty() && self.close => {
+                    self.conn.send_close_notify();
+                }
+                None if self.script.responses.is_empty() => return 0,
+                None => panic!("the FSM reads before the request is complete"),
+            }
+        }
+        self.conn.write_tls(&mut buf).unwrap()
+    }
+
+    fn write(&mut self, mut bytes: &[u8]) -> io::Result<usize> {
+        if self
+            .reject_after
+            .is_some_and(|limit| self.script.received().len() >= limit)
+        {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        }
+        let len = bytes.len();
+        while !bytes.is_empty() {
+            self.conn.read_tls(&mut bytes)?;
+            self.conn.process_new_packets().map_err(io::Error::other)?;
+        }
+        let mut plaintext = vec![];
+        match self.conn.reader().read_to_end(&mut plaintext) {
+            Ok(_) => {}
+            Err(err) if err.kind() == io::ErrorKind::WouldBlock => {}
+            Err(err) => return Err(err),
+        }
+        self.script.receive(&plaintext);
+        Ok(len)
+    }
+}
+
+/// Reads one request head off a socket, for tests against a local server.
+#[cfg_attr(not(feature = "blocking"), allow(dead_code))]
+pub(crate) fn read_request(conn: &mut TcpStream) -> Vec<u8> {
+    let mut head = vec![];
+    let mut byte = [0];
+    while !head.ends_with(b"\r\n\r\n") {
+        conn.read_exact(&mut byte).unwrap();
+        head.push(byte[0]);
+    }
+    head
+}
+
+/// `TlsPeer`'s server on a local port, for drivers that connect by
+/// themselves: `handle` answers every request (head and `Content-Length`
+/// body) with a whole response, connections stay open for as long as the
+/// client keeps them. Trust it with `TlsPeer::options`.
+#[cfg(all(feature = "s3", feature = "blocking"))]
+pub(crate) fn serve_tls(handle: impl Fn(&[u8], &[u8]) -> Vec<u8> + Send + Sync + 'static) -> u16 {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    let handle = Arc::new(handle);
+    std::thread::spawn(move || {
+        for conn in listener.incoming() {
+            let (conn, handle) = (conn.unwrap(), Arc::clone(&handle));
+            std::thread::spawn(move || {
+                let server = ServerConnection::new(server_config(&[])).unwrap();
+                let mut stream = rustls::StreamOwned::new(server, conn);
+                let mut head = vec![];
+                let mut byte = [0];
+                while stream.read_exact(&mut byte).is_ok() {
+                    head.push(byte[0]);
+                    if !head.ends_with(b"\r\n\r\n") {
+                        continue;
+                    }
+                    let text = String::from_utf8_lossy(&head).to_ascii_lowercase();
+                    let len = text
+                        .lines()
+                        .find_map(|line| line.strip_prefix("content-length:"))
+                        .map_or(0, |len| len.trim().parse().unwrap());
+                    let mut body = vec![0; len];
+                    stream.read_exact(&mut body).unwrap();
+                    let response = handle(&head, &body);
+                    if stream.write_all(&response).is_err() {
+                        return;
+                    }
+                    head.clear();
+                }
+            });
+        }
+    });
+    port
+}
@@ -0,0 +1,9 @@
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Timeouts {
+    pub connect: Option<Duration>,
+    pub read: Option<Duration>,
+    pub write: Option<Duration>,
+    pub total: Option<Duration>,
+}
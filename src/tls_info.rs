@@ -0,0 +1,28 @@
+use rustls::{CipherSuite, CommonState, ProtocolVersion, pki_types::CertificateDer};
+
+/// What the TLS handshake settled on, see `FSM::tls_info` and
+/// `Response::tls`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TlsInfo {
+    pub version: ProtocolVersion,
+    pub cipher_suite: CipherSuite,
+    /// `None` if the server didn't pick any of `ClientOptions::alpn_protocols`.
+    pub alpn_protocol: Option<Vec<u8>>,
+    /// The server's certificate first, then the intermediates it sent.
+    pub peer_certificates: Vec<CertificateDer<'static>>,
+}
+
+impl TlsInfo {
+    /// `None` until the handshake is done.
+    pub(crate) fn of(state: &CommonState) -> Option<Self> {
+        if state.is_handshaking() {
+            return None;
+        }
+        Some(Self {
+            version: state.protocol_version()?,
+            cipher_suite: state.negotiated_cipher_suite()?.suite(),
+            alpn_protocol: state.alpn_protocol().map(<[u8]>::to_vec),
+            peer_certificates: state.peer_certificates().unwrap_or_default().to_vec(),
+        })
+    }
+}
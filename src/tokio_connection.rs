@@ -0,0 +1,397 @@
+use crate::{
+    ClientOptions, Error, FSM, Request, Response, Result, Scheme, TimeoutKind, Timeouts, TlsInfo,
+    Url, Wants,
+    deadline::{deadline, remaining},
+    fsm::{is_write_rejected, sink_full},
+    net_event::{emit_connect, emit_resolve},
+    url::host_header,
+};
+use std::{
+    future::Future,
+    io::{self, ErrorKind},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncRead, AsyncReadExt as _, AsyncWrite, AsyncWriteExt as _},
+    net::{TcpStream, lookup_host},
+    time::{timeout, timeout_at},
+};
+
+/// Every await point of these futures is a single read or write whose
+/// result is handed to the FSM before the next one starts, and everything
+/// the exchange needs lives in the future. Dropping one mid-await (a
+/// `select!` losing, an outer timeout) closes the socket it opened, nothing
+/// is left half-sent on a connection that could be reused. `send_over`
+/// can't close the caller's stream, see `AsyncExchange` to resume instead.
+pub struct AsyncConnection;
+
+impl AsyncConnection {
+    pub async fn get(
+        hostname: &str,
+        port: u16,
+        path: &str,
+        options: &ClientOptions,
+    ) -> Result<Response> {
+        Self::send(Scheme::Https, hostname, port, Request::get(path), options).await
+    }
+
+    pub async fn get_url(url: &str, options: &ClientOptions) -> Result<Response> {
+        let url = Url::parse(url)?;
+        Self::send(url.scheme, &url.host, url.port, url.get_request(), options).await
+    }
+
+    /// Sends an arbitrary request, `Host` is added unless the request
+    /// already sets it. Repeated as `options.retry` allows.
+    pub async fn send(
+        scheme: Scheme,
+        hostname: &str,
+        port: u16,
+        request: Request,
+        options: &ClientOptions,
+    ) -> Result<Response> {
+        if options.retry.max_attempts <= 1 {
+            return send_once(scheme, hostname, port, request, NO_BODY, options).await;
+        }
+
+        let mut attempt = 1;
+        loop {
+            let outcome =
+                send_once(scheme, hostname, port, request.clone(), NO_BODY, options).await;
+            match options.retry.delay(attempt, request.method(), &outcome) {
+                Some(delay) => tokio::time::sleep(delay).await,
+                None => return outcome,
+            }
+            attempt += 1;
+        }
+    }
+
+    /// Sends `request` over a stream the caller has connected (e.g. a
+    /// `UnixStream`), `hostname` and `port` are only used for SNI, certificate
+    /// verification and the `Host` header.
+    pub async fn send_over<S: AsyncRead + AsyncWrite + Unpin>(
+        stream: &mut S,
+        scheme: Scheme,
+        hostname: &str,
+        port: u16,
+        request: Request,
+        options: &ClientOptions,
+    ) -> Result<Response> {
+        AsyncExchange::new(stream, scheme, hostname, port, request, options)?
+            .response()
+            .await
+    }
+
+    /// Sends a request set up with `Request::stream_body`, reading the body
+    /// from `body` as it goes out. Not retried, the body can't be replayed.
+    pub async fn upload<B: AsyncRead + Unpin>(
+        scheme: Scheme,
+        hostname: &str,
+        port: u16,
+        request: Request,
+        body: &mut B,
+        options: &ClientOptions,
+    ) -> Result<Response> {
+        send_once(scheme, hostname, port, request, Some(body), options).await
+    }
+}
+
+/// A request in flight over a caller-owned stream. The FSM lives here rather
+/// than in the future returned by `response`, so dropping that future only
+/// abandons the read or write it was waiting on (tokio's are cancel safe)
+/// and calling `response` again picks the exchange up where it stopped,
+/// still bounded by the total timeout set when it was created. Give up on
+/// it instead and the stream is mid-exchange, close it.
+pub struct AsyncExchange<S> {
+    stream: S,
+    fsm: FSM,
+    timeouts: Timeouts,
+    deadline: Option<Instant>,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncExchange<S> {
+    /// `hostname` and `port` are only used for SNI, certificate verification
+    /// and the `Host` header.
+    pub fn new(
+        stream: S,
+        scheme: Scheme,
+        hostname: &str,
+        port: u16,
+        request: Request,
+        options: &ClientOptions,
+    ) -> Result<Self> {
+        Ok(Self {
+            stream,
+            fsm: fsm(scheme, hostname, port, request, options)?,
+            timeouts: options.timeouts,
+            deadline: deadline(&options.timeouts),
+        })
+    }
+
+    /// Resolves once per exchange, polling it again after that is a misuse.
+    pub async fn response(&mut self) -> Result<Response> {
+        exchange(
+            &mut self.stream,
+            &mut self.fsm,
+            NO_BODY,
+            &self.timeouts,
+            self.deadline,
+        )
+        .await
+    }
+
+    /// See `FSM::tls_info`, available before the response is.
+    pub fn tls_info(&self) -> Option<Arc<TlsInfo>> {
+        self.fsm.tls_info()
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.stream
+    }
+
+    pub fn into_inner(self) -> S {
+        self.stream
+    }
+}
+
+/// For requests without a streamed body.
+const NO_BODY: Option<&mut tokio::io::Empty> = None;
+
+async fn send_once<B: AsyncRead + Unpin>(
+    scheme: Scheme,
+    hostname: &str,
+    port: u16,
+    request: Request,
+    body: Option<&mut B>,
+    options: &ClientOptions,
+) -> Result<Response> {
+    let mut fsm = fsm(scheme, hostname, port, request, options)?;
+    let deadline = deadline(&options.timeouts);
+
+    let mut sock = connect(
+        options.connect_host(hostname),
+        port,
+        remaining(options.timeouts.connect, deadline)?,
+        options,
+    )
+    .await?;
+
+    exchange(&mut sock, &mut fsm, body, &options.timeouts, deadline).await
+}
+
+fn fsm(
+    scheme: Scheme,
+    hostname: &str,
+    port: u16,
+    mut request: Request,
+    options: &ClientOptions,
+) -> Result<FSM> {
+    request.add_default_header("Host", &host_header(scheme, hostname, port));
+
+    FSM::with_scheme(scheme, hostname, request, options)
+}
+
+async fn exchange<S: AsyncRead + AsyncWrite + Unpin, B: AsyncRead + Unpin>(
+    sock: &mut S,
+    fsm: &mut FSM,
+    mut body: Option<&mut B>,
+    timeouts: &Timeouts,
+    deadline: Option<Instant>,
+) -> Result<Response> {
+    loop {
+        match fsm.wants()? {
+            Wants::Read(buf) => {
+                let read = with_timeout(
+                    remaining(timeouts.read, deadline)?,
+                    TimeoutKind::Read,
+                    async { sock.read(buf).await.map_err(Error::Io) },
+                )
+                .await?;
+                fsm.done_reading(read);
+            }
+            Wants::Write(buf) => {
+                let written = with_timeout(
+                    remaining(timeouts.write, deadline)?,
+                    TimeoutKind::Write,
+                    async { Ok(sock.write(buf).await) },
+                )
+                .await?;
+                match written {
+                    Ok(written) => fsm.done_writing(written),
+                    Err(err) if is_write_rejected(&err) && fsm.abort_write() => {}
+                    Err(err) => return Err(Error::Io(err)),
+                }
+            }
+            Wants::Body(buf) => {
+                let Some(body) = body.as_mut() else {
+                    return Err(Error::InvalidRequest(
+                        "the request streams its body, send it with upload".to_string(),
+                    ));
+                };
+                let filled = body.read(buf).await.map_err(Error::Io)?;
+                fsm.done_body(filled)?;
+            }
+            Wants::SinkFull => return Err(sink_full()),
+            Wants::Done(response) => {
+                return Ok(response);
+            }
+        }
+    }
+}
+
+/// `timeout` bounds resolving and all the connect attempts together.
+async fn connect(
+    hostname: &str,
+    port: u16,
+    timeout: Option<Duration>,
+    options: &ClientOptions,
+) -> Result<TcpStream> {
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let observer = options.net_observer.as_ref();
+
+    let started = Instant::now();
+    let addrs = before(deadline, lookup_host((hostname, port)))
+        .await
+        .map(|addrs| options.ip_preference.order(addrs))
+        .map_err(|err| match err.kind() {
+            ErrorKind::TimedOut => Error::Timeout(TimeoutKind::Connect),
+            _ => Error::Dns(format!("failed to resolve {hostname}: {err}")),
+        });
+    emit_resolve(observer, hostname, port, started, &addrs);
+
+    let mut last_err = None;
+    for addr in addrs? {
+        let started = Instant::now();
+        let sock = before(deadline, TcpStream::connect(addr)).await;
+        emit_connect(observer, addr, started, &sock);
+
+        match sock {
+            Ok(sock) => return Ok(sock),
+            Err(err) if err.kind() == ErrorKind::TimedOut => {
+                return Err(Error::Timeout(TimeoutKind::Connect));
+            }
+            Err(err) => last_err = Some(err),
+        }
+    }
+
+    Err(last_err.map_or_else(
+        || Error::Dns(format!("failed to resolve DNS name: {hostname}")),
+        Error::Io,
+    ))
+}
+
+/// Fails with `TimedOut` once `deadline` has passed.
+async fn before<T>(
+    deadline: Option<Instant>,
+    fut: impl Future<Output = io::Result<T>>,
+) -> io::Result<T> {
+    match deadline {
+        Some(deadline) => timeout_at(deadline.into(), fut)
+            .await
+            .unwrap_or_else(|_| Err(ErrorKind::TimedOut.into())),
+        None => fut.await,
+    }
+}
+
+async fn with_timeout<T>(
+    duration: Option<Duration>,
+    kind: TimeoutKind,
+    fut: impl Future<Output = Result<T>>,
+) -> Result<T> {
+    match duration {
+        Some(duration) => timeout(duration, fut)
+            .await
+            .map_err(|_| Error::Timeout(kind))?,
+        None => fut.await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{DuplexStream, duplex};
+
+    /// Reads a request head a few bytes at a time, so the client's writes
+    /// have to wait for room.
+    async fn read_request(server: &mut DuplexStream) -> Vec<u8> {
+        let mut head = vec![];
+        let mut buf = [0; 4];
+        while !head.ends_with(b"\r\n\r\n") {
+            tokio::time::sleep(Duration::from_millis(2)).await;
+            let read = server.read(&mut buf).await.unwrap();
+            head.extend_from_slice(&buf[..read]);
+        }
+        head
+    }
+
+    async fn respond(server: &mut DuplexStream, pieces: &[&[u8]]) {
+        for piece in pieces {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+            server.write_all(piece).await.unwrap();
+        }
+    }
+
+    /// Drops the `response` future every millisecond until it resolves.
+    async fn response_with_drops<S: AsyncRead + AsyncWrite + Unpin>(
+        exchange: &mut AsyncExchange<S>,
+    ) -> (Response, usize) {
+        let mut dropped = 0;
+        loop {
+            tokio::select! {
+                response = exchange.response() => return (response.unwrap(), dropped),
+                () = tokio::time::sleep(Duration::from_millis(1)) => dropped += 1,
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn resumes_after_the_response_future_is_dropped() {
+        // small enough that the request head takes several writes
+        let (client, mut server) = duplex(16);
+        let server = tokio::spawn(async move {
+            let first = read_request(&mut server).await;
+            respond(
+                &mut server,
+                &[
+                    b"HTTP/1.1 200 OK\r\nContent-",
+                    b"Length: 5\r\n\r\nhel",
+                    b"lo",
+                ],
+            )
+            .await;
+            let second = read_request(&mut server).await;
+            respond(
+                &mut server,
+                &[b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\n", b"world"],
+            )
+            .await;
+            (first, second)
+        });
+        let options = ClientOptions {
+            keep_alive: true,
+            ..ClientOptions::default()
+        };
+
+        let request = Request::get("/first");
+        let mut exchange =
+            AsyncExchange::new(client, Scheme::Http, "localhost", 8080, request, &options).unwrap();
+        let (response, dropped) = response_with_drops(&mut exchange).await;
+        assert_eq!(response.body, b"hello");
+        assert!(dropped > 0);
+
+        // the stream is where the first exchange left it
+        let client = exchange.into_inner();
+        let request = Request::get("/second");
+        let mut exchange =
+            AsyncExchange::new(client, Scheme::Http, "localhost", 8080, request, &options).unwrap();
+        let (response, dropped) = response_with_drops(&mut exchange).await;
+        assert_eq!(response.body, b"world");
+        assert!(dropped > 0);
+
+        let (first, second) = server.await.unwrap();
+        let first = String::from_utf8(first).unwrap().to_lowercase();
+        assert!(first.starts_with("get /first http/1.1\r\n"));
+        assert!(first.contains("host: localhost:8080\r\n"));
+        assert!(second.starts_with(b"GET /second HTTP/1.1\r\n"));
+    }
+}
@@ -0,0 +1,72 @@
+use std::fmt;
+
+/// One step of an exchange as the FSM saw it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceEvent {
+    /// `Wants::Read` with a buffer of this many bytes.
+    WantsRead(usize),
+    /// `Wants::Write` of this many bytes.
+    WantsWrite(usize),
+    /// `Wants::Body` with a buffer of this many bytes.
+    WantsBody(usize),
+    /// `Wants::SinkFull`.
+    WantsSinkFull,
+    /// `Wants::Done` with a response of this status and body length.
+    WantsDone {
+        status: u16,
+        body: usize,
+    },
+    DoneReading(usize),
+    DoneWriting(usize),
+    DoneBody(usize),
+    AbortWrite,
+    AbortResponse,
+}
+
+/// The `Wants` an FSM returned and the byte counts reported back to it, in
+/// order, recorded when `ClientOptions::trace` is on. Its `Display` output
+/// (one event per line) is kept stable across versions, so that traces of
+/// the same exchange can be diffed to pin down where a new version behaves
+/// differently. What a trace contains also depends on the driver: some ask
+/// for `wants` again after every would-block, and read sizes depend on the
+/// network.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Trace {
+    events: Vec<TraceEvent>,
+}
+
+impl Trace {
+    pub fn events(&self) -> &[TraceEvent] {
+        &self.events
+    }
+
+    pub(crate) fn push(&mut self, event: TraceEvent) {
+        self.events.push(event);
+    }
+}
+
+impl fmt::Display for TraceEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::WantsRead(len) => write!(f, "wants read {len}"),
+            Self::WantsWrite(len) => write!(f, "wants write {len}"),
+            Self::WantsBody(len) => write!(f, "wants body {len}"),
+            Self::WantsSinkFull => f.write_str("wants sink room"),
+            Self::WantsDone { status, body } => write!(f, "wants done {status} {body}"),
+            Self::DoneReading(len) => write!(f, "done reading {len}"),
+            Self::DoneWriting(len) => write!(f, "done writing {len}"),
+            Self::DoneBody(len) => write!(f, "done body {len}"),
+            Self::AbortWrite => f.write_str("abort write"),
+            Self::AbortResponse => f.write_str("abort response"),
+        }
+    }
+}
+
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for event in &self.events {
+            writeln!(f, "{event}")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,88 @@
+use crate::{Error, Result};
+use std::{fmt, ops::Range};
+
+/// One problem with a request or `ClientOptions`, validation reports all of
+/// them at once in `Error::Validation`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValidationError {
+    pub location: Location,
+    /// The offending bytes of the value at `location`, if it's down to them.
+    pub span: Option<Range<usize>>,
+    pub message: String,
+}
+
+/// What a `ValidationError` is about.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Location {
+    Method,
+    /// The path (request target).
+    Target,
+    /// The name of the header at this index of `Request::headers`.
+    HeaderName(usize),
+    /// The value of the header at this index of `Request::headers`.
+    HeaderValue(usize),
+    Body,
+    /// A field of `ClientOptions`, e.g. `buffers.max_incoming`.
+    Option(&'static str),
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.span {
+            Some(span) => write!(
+                f,
+                "{} (bytes {}..{}): {}",
+                self.location, span.start, span.end, self.message
+            ),
+            None => write!(f, "{}: {}", self.location, self.message),
+        }
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Method => write!(f, "method"),
+            Self::Target => write!(f, "target"),
+            Self::HeaderName(idx) => write!(f, "header #{idx} name"),
+            Self::HeaderValue(idx) => write!(f, "header #{idx} value"),
+            Self::Body => write!(f, "body"),
+            Self::Option(name) => write!(f, "ClientOptions::{name}"),
+        }
+    }
+}
+
+/// Collects `ValidationError`s instead of returning on the first one.
+#[derive(Debug, Default)]
+pub(crate) struct Problems(Vec<ValidationError>);
+
+impl Problems {
+    pub(crate) fn push(&mut self, location: Location, message: impl Into<String>) {
+        self.0.push(ValidationError {
+            location,
+            span: None,
+            message: message.into(),
+        });
+    }
+
+    pub(crate) fn push_span(
+        &mut self,
+        location: Location,
+        span: Range<usize>,
+        message: impl Into<String>,
+    ) {
+        self.0.push(ValidationError {
+            location,
+            span: Some(span),
+            message: message.into(),
+        });
+    }
+
+    pub(crate) fn into_result(self) -> Result<()> {
+        if self.0.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Validation(self.0))
+        }
+    }
+}
@@ -0,0 +1,84 @@
+//! Request builders for the common WebDAV (RFC 4918) operations.
+
+use crate::{Method, Request};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Depth {
+    /// Only the resource itself.
+    Zero,
+    /// The resource and its immediate children.
+    One,
+    /// The whole subtree, servers often refuse it for PROPFIND.
+    Infinity,
+}
+
+impl Depth {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Depth::Zero => "0",
+            Depth::One => "1",
+            Depth::Infinity => "infinity",
+        }
+    }
+}
+
+/// Asks for the given properties in the `DAV:` namespace
+/// (e.g. `getetag`, `getlastmodified`), or for all of them if `props` is empty.
+pub fn propfind(path: impl Into<String>, depth: Depth, props: &[&str]) -> Request {
+    let mut body = String::from(XML_PROLOG);
+    body.push_str(r#"<D:propfind xmlns:D="DAV:">"#);
+    if props.is_empty() {
+        body.push_str("<D:allprop/>");
+    } else {
+        body.push_str("<D:prop>");
+        for prop in props {
+            body.push_str(&format!("<D:{prop}/>"));
+        }
+        body.push_str("</D:prop>");
+    }
+    body.push_str("</D:propfind>");
+
+    let mut request = dav_request("PROPFIND", path);
+    request.add_header("Depth", depth.as_str());
+    request.add_header("Content-Type", XML_CONTENT_TYPE);
+    request.set_body(body);
+    request
+}
+
+pub fn mkcol(path: impl Into<String>) -> Request {
+    dav_request("MKCOL", path)
+}
+
+/// `destination` must be an absolute URL on the same server.
+pub fn move_to(path: impl Into<String>, destination: &str, overwrite: bool) -> Request {
+    let mut request = dav_request("MOVE", path);
+    request.add_header("Destination", destination);
+    request.add_header("Overwrite", overwrite_flag(overwrite));
+    request
+}
+
+/// `destination` must be an absolute URL on the same server,
+/// collections are copied with `Depth::Infinity` or `Depth::Zero` only.
+pub fn copy_to(
+    path: impl Into<String>,
+    destination: &str,
+    depth: Depth,
+    overwrite: bool,
+) -> Request {
+    let mut request = dav_request("COPY", path);
+    request.add_header("Destination", destination);
+    request.add_header("Depth", depth.as_str());
+    request.add_header("Overwrite", overwrite_flag(overwrite));
+    request
+}
+
+fn dav_request(method: &str, path: impl Into<String>) -> Request {
+    Request::new(Method::Custom(method.to_string()), path)
+}
+
+fn overwrite_flag(overwrite: bool) -> &'static str {
+    if overwrite { "T" } else { "F" }
+}
+
+const XML_PROLOG: &str = r#"<?xml version="1.0" encoding="utf-8"?>"#;
+const XML_CONTENT_TYPE: &str = "application/xml; charset=utf-8";